@@ -2,14 +2,204 @@ use anchor_lang::prelude::*;
 
 #[error_code]
 pub enum FlowError {
-    #[msg("Insufficient funds in vault for settlement.")]
+    #[msg("Amount exceeds the vault's available balance.")]
     InsufficientFunds,
-    #[msg("Invalid signature provided for settlement.")]
-    InvalidSignature,
-    #[msg("Nonce has already been used or is invalid.")]
+    #[msg("Settlement voucher is missing or was not signed by the vault owner.")]
+    InvalidVoucher,
+    #[msg("Nonce is zero or too far below the highest settled nonce to be tracked.")]
     InvalidNonce,
-    #[msg("The provided amount for settlement is zero.")]
+    #[msg("The provided amount is zero.")]
     ZeroAmount,
     #[msg("Arithmetic overflow occurred.")]
-    Overflow,
-}
\ No newline at end of file
+    MathOverflow,
+    #[msg("Nonce has already been used.")]
+    NonceReused,
+    #[msg("Vault still holds tokens; withdraw them before closing.")]
+    VaultNotEmpty,
+    #[msg("Token-2022 mints with this extension are not supported.")]
+    UnsupportedMintExtension,
+    #[msg("This instruction is paused.")]
+    InstructionPaused,
+    #[msg("Fee destination is not owned by the configured fee recipient.")]
+    InvalidFeeDestination,
+    #[msg("Fee basis points must not exceed 10000.")]
+    InvalidFeeBps,
+    #[msg("The provider has paused settlements.")]
+    ProviderPaused,
+    #[msg("Deposit is outside the configured minimum and maximum.")]
+    DepositOutOfBounds,
+    #[msg("Minimum deposit must not exceed the maximum deposit.")]
+    InvalidDepositLimits,
+    #[msg("Batch exceeds the maximum number of settlements.")]
+    InputTooLarge,
+    #[msg("Batch amounts, nonces and accounts do not line up.")]
+    BatchLengthMismatch,
+    #[msg("Merkle proof does not match the committed root.")]
+    InvalidProof,
+    #[msg("This settlement leaf has already been claimed.")]
+    LeafAlreadyClaimed,
+    #[msg("The vault has expired.")]
+    VaultExpired,
+    #[msg("The vault has not expired yet.")]
+    VaultNotExpired,
+    #[msg("The vault already has a settlement pending.")]
+    SettlementPending,
+    #[msg("The vault has no pending settlement.")]
+    NoPendingSettlement,
+    #[msg("The dispute window has not elapsed yet.")]
+    DisputeWindowActive,
+    #[msg("The dispute window has already elapsed.")]
+    DisputeWindowElapsed,
+    #[msg("The dispute window must not be negative.")]
+    InvalidDisputeWindow,
+    #[msg("Provider name or URI exceeds the maximum length.")]
+    MetadataTooLong,
+    #[msg("Signer is not the pending provider authority.")]
+    InvalidPendingAuthority,
+    #[msg("Missing the config authority's signatures.")]
+    Unauthorized,
+    #[msg("Multisig signers or threshold are invalid.")]
+    InvalidMultisig,
+    #[msg("Fee splits must have nonzero shares summing to 10000 basis points.")]
+    InvalidFeeSplits,
+    #[msg("Settlement arrived before the vault's cooldown elapsed.")]
+    SettleTooSoon,
+    #[msg("Settlement interval cannot be negative.")]
+    InvalidSettleInterval,
+    #[msg("Destination is not on the provider's whitelist.")]
+    DestinationNotWhitelisted,
+    #[msg("Provider already has the maximum number of destinations.")]
+    DestinationListFull,
+    #[msg("Config change is still inside its timelock.")]
+    TimelockNotElapsed,
+    #[msg("This config change has to go through the timelock.")]
+    TimelockRequired,
+    #[msg("No config change is pending.")]
+    NoPendingConfigChange,
+    #[msg("Timelock cannot be negative.")]
+    InvalidTimelock,
+    #[msg("Destination token account is not for the vault's mint.")]
+    MintMismatch,
+    #[msg("Net amount to the provider is below the requested minimum.")]
+    SlippageExceeded,
+    #[msg("Signer is not the pending vault owner.")]
+    InvalidPendingOwner,
+    #[msg("Vault tracks nonces in a bitmap that was not passed.")]
+    NonceBitmapRequired,
+    #[msg("Nonce is beyond the last slot of the nonce bitmap.")]
+    NonceOutOfRange,
+    #[msg("Provider has no accrued earnings to claim.")]
+    NoAccruedEarnings,
+    #[msg("Settlement memo is too long.")]
+    MemoTooLong,
+    #[msg("Account was written by an older program version; run migrate_account.")]
+    StaleAccountVersion,
+    #[msg("Withdrawal was not requested or is still timelocked.")]
+    WithdrawalLocked,
+    #[msg("No withdrawal request is pending.")]
+    NoPendingWithdrawal,
+    #[msg("Withdrawal timelock must not be negative.")]
+    InvalidWithdrawTimelock,
+    #[msg("Settlement threshold must be greater than zero.")]
+    InvalidThreshold,
+    #[msg("Settlement amount is below the config's settle threshold.")]
+    BelowSettleThreshold,
+    #[msg("Referrer account does not match the provider's referrer.")]
+    InvalidReferrer,
+    #[msg("Pause flags contain unknown bits.")]
+    InvalidPauseFlags,
+    #[msg("The provider still has unclaimed earnings.")]
+    UnclaimedEarnings,
+    #[msg("The settlement's deadline has passed.")]
+    DeadlineExceeded,
+    #[msg("A destination change is pending.")]
+    DestinationChangePending,
+    #[msg("No destination change is pending.")]
+    NoPendingDestinationChange,
+    #[msg("Shared vault funds only leave through settlements and expiry refunds.")]
+    SharedVault,
+    #[msg("The vault is not shared.")]
+    NotSharedVault,
+    #[msg("Shared vaults need the depositor's contribution account.")]
+    ContributionRequired,
+    #[msg("Destination account does not match the requested destination.")]
+    DestinationMismatch,
+    #[msg("The vault has been emergency drained.")]
+    VaultDrained,
+    #[msg("Emergency drains need withdrawals paused.")]
+    WithdrawalsNotPaused,
+    #[msg("Recovery account is not owned by the configured recovery destination.")]
+    InvalidRecoveryDestination,
+    #[msg("The vault already has a transfer in progress.")]
+    Reentrancy,
+    #[msg("The config cannot be closed while vaults are still open.")]
+    VaultsStillActive,
+    #[msg("The subscription interval has not elapsed yet.")]
+    IntervalNotElapsed,
+    #[msg("Scheduled pull is larger than the subscription's amount per interval.")]
+    SubscriptionAmountExceeded,
+    #[msg("A subscription needs a non-negative interval and a non-zero amount.")]
+    InvalidSubscription,
+    #[msg("Settlement exceeds the amount the owner has committed.")]
+    CommitmentExceeded,
+    #[msg("Fee tiers must be sorted by amount and at most the base fee.")]
+    InvalidFeeTiers,
+    #[msg("The provider's destination token account is frozen.")]
+    DestinationUnavailable,
+    #[msg("The vault has reached its maximum number of settlements.")]
+    SettlementLimitReached,
+    #[msg("The payer cannot cover the provider registration fee.")]
+    InsufficientRegistrationFee,
+    #[msg("The provider's fee account cannot cover the settlement fee.")]
+    InsufficientFeeFunds,
+    #[msg("The token account belongs to a different token program.")]
+    TokenProgramMismatch,
+    #[msg("The owner already holds the maximum number of open vaults.")]
+    TooManyVaults,
+    #[msg("The cluster clock is unset.")]
+    ClockUnavailable,
+    #[msg("The clawback window has closed or the funds have left the provider's earnings.")]
+    ClawbackWindowClosed,
+    #[msg("The token program does not match the one the vault was created with.")]
+    InvalidTokenProgram,
+    #[msg("Only expired vaults with nothing pending and a balance below the dust threshold can be swept.")]
+    VaultNotSweepable,
+    #[msg("No ed25519 voucher instruction directly precedes this one.")]
+    VoucherMissing,
+    #[msg("Pay either the provider's earnings account or its associated token account, not both.")]
+    ConflictingPayees,
+    #[msg("A downstream vault is only credited when the settlement pays its token account.")]
+    DownstreamVaultNotPaid,
+    #[msg("Provider-paid fees need the provider's fee account.")]
+    ProviderFeeAccountRequired,
+    #[msg("The provider's fee account has not approved the provider to spend the fee.")]
+    FeeAccountNotApproved,
+    #[msg("Expected one recipient token account per fee split in the remaining accounts.")]
+    FeeSplitAccountsMissing,
+    #[msg("The provider has a referrer, so its token account must be passed.")]
+    ReferrerAccountRequired,
+    #[msg("Only shared vaults take a contribution account.")]
+    UnexpectedContribution,
+    #[msg("The provider's fee account is not owned by the provider authority.")]
+    InvalidFeeAccountOwner,
+    #[msg("A settlement cannot credit the vault it is paid from.")]
+    DownstreamVaultIsSource,
+    #[msg("The downstream vault does not own the destination token account.")]
+    DownstreamVaultMismatch,
+    #[msg("The provider authority does not match the provider's registered authority.")]
+    ProviderAuthorityMismatch,
+    #[msg("A memo needs the SPL Memo program account.")]
+    MemoProgramRequired,
+    #[msg("The destination was whitelisted too recently; its delay is not over.")]
+    DestinationNotYetAllowed,
+    #[msg("The settlement root was committed for an earlier vault at this address.")]
+    StaleSettlementRoot,
+    #[msg("The receipt is for a different provider.")]
+    ReceiptProviderMismatch,
+    #[msg("The receipt is for a different vault.")]
+    ReceiptVaultMismatch,
+    #[msg("Only settlements accrued to the provider's earnings account can be clawed back.")]
+    ReceiptNotAccrued,
+    #[msg("The provider has claimed its earnings since this settlement.")]
+    ReceiptAlreadyClaimed,
+}
@@ -1,4 +1,60 @@
+//! Events emitted by every state-changing instruction.
+//!
+//! Field order is part of the IDL: new fields are only ever appended so
+//! existing indexers keep decoding older fields at the same offsets.
+//!
+//! Every event ends with `seq`, taken from `GlobalConfig::event_seq` as it is
+//! emitted. Consecutive events differ by exactly one, so an indexer that sees
+//! a jump knows how many it missed.
+
 use anchor_lang::prelude::*;
+use crate::state::{FeeRounding, FeeSource, FeeSplit, FeeTier, PaymentProtocol};
+
+#[event]
+pub struct ConfigInitialized {
+    pub config: Pubkey,
+    pub admin: Pubkey,
+    pub settle_threshold: u64,
+    pub fee_bps: u16,
+    pub timestamp: i64,
+    pub fee_destination: Pubkey,
+    pub min_deposit: u64,
+    pub max_deposit: u64,
+    pub dispute_seconds: i64,
+    pub min_settle_interval: i64,
+    pub timelock_seconds: i64,
+    pub max_fee: u64,
+    pub referral_bps: u16,
+    pub fee_rounding: FeeRounding,
+    pub registration_fee: u64,
+    pub max_vaults_per_owner: u16,
+    pub clawback_seconds: i64,
+    pub dust_threshold: u64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ProviderRegistered {
+    pub provider: Pubkey,
+    pub authority: Pubkey,
+    pub destination: Pubkey,
+    pub protocol: PaymentProtocol,
+    pub visa_merchant_id: Option<String>,
+    pub timestamp: i64,
+    pub name: String,
+    pub uri: String,
+    pub referrer: Option<Pubkey>,
+    pub registration_fee: u64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ProviderFeeUpdated {
+    pub provider: Pubkey,
+    pub fee_bps_override: Option<u16>,
+    pub timestamp: i64,
+    pub seq: u64,
+}
 
 #[event]
 pub struct VaultCreated {
@@ -6,6 +62,21 @@ pub struct VaultCreated {
     pub agent: Pubkey,
     pub token_mint: Pubkey,
     pub initial_deposit: u64,
+    pub timestamp: i64,
+    pub shared: bool,
+    pub max_settlements: u32,
+    pub session_id: [u8; 16],
+    pub seq: u64,
+}
+
+#[event]
+pub struct VaultToppedUp {
+    pub vault: Pubkey,
+    pub agent: Pubkey,
+    pub amount: u64,
+    pub new_balance: u64,
+    pub timestamp: i64,
+    pub seq: u64,
 }
 
 #[event]
@@ -13,11 +84,26 @@ pub struct Settlement {
     pub vault: Pubkey,
     pub provider: Pubkey,
     pub agent: Pubkey,
+    /// Same as `gross_amount`; kept for existing consumers.
     pub amount: u64,
     pub nonce: u64,
 
     /// [BOUNTY: Visa TAP]
     pub visa_merchant_id: Option<String>,
+
+    pub gross_amount: u64,
+    pub fee_amount: u64,
+    pub net_to_provider: u64,
+    pub timestamp: i64,
+    /// Token account (or wallet, for native vaults) the net amount went to.
+    pub destination: Pubkey,
+    /// Vault balance left after this settlement.
+    pub remaining_balance: u64,
+    /// How much the next settlement could take: the remaining balance, or
+    /// zero once it is below `settle_threshold`.
+    pub remaining_before_cap: u64,
+    pub fee_from: FeeSource,
+    pub seq: u64,
 }
 
 #[event]
@@ -25,4 +111,489 @@ pub struct Withdrawn {
     pub vault: Pubkey,
     pub agent: Pubkey,
     pub amount: u64,
-}
\ No newline at end of file
+    pub timestamp: i64,
+    /// Token account (or wallet, for native vaults) the funds went to.
+    pub recipient: Pubkey,
+    /// Fees the vault's settlements have paid over its lifetime.
+    pub lifetime_fees_paid: u64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct VaultClosed {
+    pub vault: Pubkey,
+    pub agent: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct PauseUpdated {
+    pub config: Pubkey,
+    pub admin: Pubkey,
+    pub paused: bool,
+    pub timestamp: i64,
+    pub pause_flags: u8,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ProviderDestinationAdded {
+    pub provider: Pubkey,
+    pub destination: Pubkey,
+    /// Unix time from which settlements may pay it.
+    pub allowed_after: i64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ProviderDestinationRemoved {
+    pub provider: Pubkey,
+    pub destination: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ProviderPauseUpdated {
+    pub provider: Pubkey,
+    pub authority: Pubkey,
+    pub paused: bool,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ProviderClosed {
+    pub provider: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct RootCommitted {
+    pub settlement_root: Pubkey,
+    pub vault: Pubkey,
+    pub provider: Pubkey,
+    pub root: [u8; 32],
+    pub leaf_count: u16,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct LeafClaimed {
+    pub vault: Pubkey,
+    pub provider: Pubkey,
+    pub root: [u8; 32],
+    pub index: u16,
+    pub amount: u64,
+    pub fee_amount: u64,
+    pub net_to_provider: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct VaultRefunded {
+    pub vault: Pubkey,
+    pub agent: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct VaultOwnershipProposed {
+    pub vault: Pubkey,
+    pub agent: Pubkey,
+    pub pending_agent: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct VaultOwnershipTransferred {
+    pub vault: Pubkey,
+    pub previous_agent: Pubkey,
+    pub new_agent: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct VaultDelegateUpdated {
+    pub vault: Pubkey,
+    pub agent: Pubkey,
+    pub delegate: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct SettlementProposed {
+    pub vault: Pubkey,
+    pub provider: Pubkey,
+    pub amount: u64,
+    pub nonce: u64,
+    pub settle_after: i64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct SettlementCancelled {
+    pub vault: Pubkey,
+    pub provider: Pubkey,
+    pub amount: u64,
+    pub nonce: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ProviderMetadataUpdated {
+    pub provider: Pubkey,
+    pub name: String,
+    pub uri: String,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ProviderAuthorityProposed {
+    pub provider: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ProviderAuthorityTransferred {
+    pub provider: Pubkey,
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ConfigUpdated {
+    pub config: Pubkey,
+    pub authority: Pubkey,
+    pub settle_threshold: u64,
+    pub fee_bps: u16,
+    pub fee_destination: Pubkey,
+    pub min_deposit: u64,
+    pub max_deposit: u64,
+    pub dispute_seconds: i64,
+    pub timestamp: i64,
+    pub min_settle_interval: i64,
+    pub timelock_seconds: i64,
+    pub max_fee: u64,
+    pub referral_bps: u16,
+    pub fee_rounding: FeeRounding,
+    pub registration_fee: u64,
+    pub max_vaults_per_owner: u16,
+    pub clawback_seconds: i64,
+    pub dust_threshold: u64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ConfigSignersUpdated {
+    pub config: Pubkey,
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct FeeSplitsUpdated {
+    pub config: Pubkey,
+    pub splits: Vec<FeeSplit>,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct GuardianUpdated {
+    pub config: Pubkey,
+    pub guardian: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct FeeIncreaseProposed {
+    pub config: Pubkey,
+    pub fee_bps: u16,
+    pub effective_after: i64,
+    pub max_fee: u64,
+    pub fee_rounding: FeeRounding,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct FeeIncreaseApplied {
+    pub config: Pubkey,
+    pub fee_bps: u16,
+    pub max_fee: u64,
+    pub fee_rounding: FeeRounding,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct UnpauseProposed {
+    pub config: Pubkey,
+    pub effective_after: i64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct NonceBitmapCreated {
+    pub vault: Pubkey,
+    pub nonce_bitmap: Pubkey,
+    pub carried_nonce: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct EarningsClaimed {
+    pub provider: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct AccountMigrated {
+    pub account: Pubkey,
+    pub from_version: u8,
+    pub to_version: u8,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct WithdrawalRequested {
+    pub vault: Pubkey,
+    pub agent: Pubkey,
+    pub amount: u64,
+    pub unlock_at: i64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct WithdrawalCancelled {
+    pub vault: Pubkey,
+    pub agent: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct DestinationChangeProposed {
+    pub provider: Pubkey,
+    pub destination: Pubkey,
+    pub effective_after: i64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct DestinationChangeApplied {
+    pub provider: Pubkey,
+    pub old_destination: Pubkey,
+    pub destination: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct DestinationChangeVetoed {
+    pub provider: Pubkey,
+    pub destination: Pubkey,
+    pub vetoed_by: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ContributionAdded {
+    pub vault: Pubkey,
+    pub depositor: Pubkey,
+    pub amount: u64,
+    pub contributed: u64,
+    pub total_contributed: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ContributionRefunded {
+    pub vault: Pubkey,
+    pub depositor: Pubkey,
+    pub contributed: u64,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct RecoveryDestinationUpdated {
+    pub config: Pubkey,
+    pub recovery_destination: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct EmergencyDrain {
+    pub vault: Pubkey,
+    pub agent: Pubkey,
+    pub authority: Pubkey,
+    pub recovery_account: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ConfigClosed {
+    pub config: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct SubscriptionUpdated {
+    pub vault: Pubkey,
+    pub agent: Pubkey,
+    pub provider: Pubkey,
+    pub interval_seconds: i64,
+    pub amount_per_interval: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct AmountCommitted {
+    pub vault: Pubkey,
+    pub agent: Pubkey,
+    pub amount: u64,
+    pub committed: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct FeeTiersUpdated {
+    pub config: Pubkey,
+    pub tiers: Vec<FeeTier>,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ConfigAuthorityProposed {
+    pub config: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ConfigAuthorityTransferred {
+    pub config: Pubkey,
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ConfigAuthorityProposalCancelled {
+    pub config: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct VouchersAccrued {
+    pub vault: Pubkey,
+    pub agent: Pubkey,
+    pub amount: u64,
+    pub accumulated_vouchers: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct PaymentSettled {
+    pub payer: Pubkey,
+    pub provider: Pubkey,
+    pub destination: Pubkey,
+    pub gross_amount: u64,
+    pub fee_amount: u64,
+    pub net_to_provider: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct SettlementClawedBack {
+    pub vault: Pubkey,
+    pub provider: Pubkey,
+    pub authority: Pubkey,
+    pub nonce: u64,
+    pub amount: u64,
+    /// Whether the provider returned the funds itself rather than the
+    /// config authority.
+    pub by_provider: bool,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct VaultSwept {
+    pub vault: Pubkey,
+    pub agent: Pubkey,
+    pub keeper: Pubkey,
+    /// Tokens left in the vault token account, sent to the fee destination.
+    pub residual: u64,
+    /// Rent lamports paid to the keeper; the rest went to the treasury.
+    pub keeper_reward: u64,
+    pub treasury_rent: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct FeeWaived {
+    pub vault: Pubkey,
+    pub provider: Pubkey,
+    /// Config authority that signed the waiver.
+    pub authority: Pubkey,
+    pub nonce: u64,
+    pub amount: u64,
+    /// Fee the settlement would otherwise have paid.
+    pub waived_fee: u64,
+    pub timestamp: i64,
+    pub seq: u64,
+}
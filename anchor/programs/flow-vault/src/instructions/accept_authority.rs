@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+use crate::state::GlobalConfig;
+use crate::errors::FlowError;
+use crate::events::ConfigAuthorityTransferred;
+
+pub fn handler(ctx: Context<AcceptAuthority>) -> Result<()> {
+    let config = &mut ctx.accounts.global_config;
+    let previous_authority = config.accept_admin()?;
+
+    emit!(ConfigAuthorityTransferred {
+        config: config.key(),
+        previous_authority,
+        new_authority: config.admin,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    pub new_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump,
+        constraint = global_config.pending_admin == Some(new_authority.key()) @ FlowError::InvalidPendingAuthority
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
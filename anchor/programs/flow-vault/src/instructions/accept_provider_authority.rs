@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Provider};
+use crate::errors::FlowError;
+use crate::events::ProviderAuthorityTransferred;
+
+pub fn handler(ctx: Context<AcceptProviderAuthority>) -> Result<()> {
+    let provider = &mut ctx.accounts.provider;
+    let previous_authority = provider.authority;
+    provider.authority = ctx.accounts.new_authority.key();
+    provider.pending_authority = None;
+
+    emit!(ProviderAuthorityTransferred {
+        provider: provider.key(),
+        previous_authority,
+        new_authority: provider.authority,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct AcceptProviderAuthority<'info> {
+    pub new_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = provider.pending_authority == Some(new_authority.key()) @ FlowError::InvalidPendingAuthority
+    )]
+    pub provider: Account<'info, Provider>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,37 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Vault};
+use crate::errors::FlowError;
+use crate::events::VaultOwnershipTransferred;
+
+/// Completes a vault handover. The previous owner's delegate is cleared so it
+/// cannot keep settling for the new owner.
+pub fn handler(ctx: Context<AcceptVaultOwnership>) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    let previous_agent = vault.agent;
+    vault.agent = ctx.accounts.new_agent.key();
+    vault.pending_agent = None;
+    vault.delegate = Pubkey::default();
+
+    emit!(VaultOwnershipTransferred {
+        vault: vault.key(),
+        previous_agent,
+        new_agent: vault.agent,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct AcceptVaultOwnership<'info> {
+    pub new_agent: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = vault.pending_agent == Some(new_agent.key()) @ FlowError::InvalidPendingOwner
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
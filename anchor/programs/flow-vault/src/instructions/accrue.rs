@@ -0,0 +1,38 @@
+use anchor_lang::prelude::*;
+use crate::state::{require_current_version, GlobalConfig, Vault};
+use crate::events::VouchersAccrued;
+
+/// Reports vouchers the owner has issued since the last call. From the first
+/// accrual on, `settle_batch` waits until enough has accrued beyond what was
+/// already settled to reach the config's `settle_threshold`.
+pub fn handler(ctx: Context<Accrue>, amount: u64) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    require_current_version(vault.version, Vault::VERSION)?;
+    let accumulated_vouchers = vault.accrue(amount)?;
+
+    emit!(VouchersAccrued {
+        vault: vault.key(),
+        agent: vault.agent,
+        amount,
+        accumulated_vouchers,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct Accrue<'info> {
+    pub agent: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
+        has_one = agent
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
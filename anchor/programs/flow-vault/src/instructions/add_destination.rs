@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, GlobalConfig, Provider};
+use crate::events::ProviderDestinationAdded;
+
+/// Approves another token account `settle_batch` may pay this provider into.
+/// Like `propose_destination`, the entry only takes effect once the config
+/// timelock has passed, and can be removed by the guardian or the config
+/// authority meanwhile.
+pub fn handler(ctx: Context<AddDestination>, destination: Pubkey) -> Result<()> {
+    let now = checked_clock()?.unix_timestamp;
+    let provider = &mut ctx.accounts.provider;
+    let allowed_after =
+        provider.add_destination(destination, ctx.accounts.global_config.timelock_seconds, now)?;
+
+    emit!(ProviderDestinationAdded {
+        provider: provider.key(),
+        destination,
+        allowed_after,
+        timestamp: now,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct AddDestination<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
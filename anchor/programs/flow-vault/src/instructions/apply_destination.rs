@@ -0,0 +1,30 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, GlobalConfig, Provider};
+use crate::events::DestinationChangeApplied;
+
+/// Makes the proposed destination the provider's own once its delay is over.
+pub fn handler(ctx: Context<ApplyDestination>) -> Result<()> {
+    let now = checked_clock()?.unix_timestamp;
+    let provider = &mut ctx.accounts.provider;
+    let old_destination = provider.apply_destination(now)?;
+
+    emit!(DestinationChangeApplied {
+        provider: provider.key(),
+        old_destination,
+        destination: provider.destination,
+        timestamp: now,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ApplyDestination<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,37 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, GlobalConfig};
+use crate::events::FeeIncreaseApplied;
+use super::update_config::signer_keys;
+
+/// Makes a proposed fee increase effective once its timelock has passed.
+pub fn handler(ctx: Context<ApplyFeeIncrease>) -> Result<()> {
+    ctx.accounts
+        .global_config
+        .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+
+    let now = checked_clock()?.unix_timestamp;
+    let config = &mut ctx.accounts.global_config;
+    let fee_bps = config.apply_fee_increase(now)?;
+
+    emit!(FeeIncreaseApplied {
+        config: config.key(),
+        fee_bps,
+        max_fee: config.max_fee,
+        fee_rounding: config.fee_rounding,
+        timestamp: now,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ApplyFeeIncrease<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,37 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, GlobalConfig};
+use crate::events::PauseUpdated;
+use super::update_config::signer_keys;
+
+/// Resumes the protocol once a proposed unpause has waited out the timelock.
+pub fn handler(ctx: Context<ApplyUnpause>) -> Result<()> {
+    ctx.accounts
+        .global_config
+        .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+
+    let now = checked_clock()?.unix_timestamp;
+    let config = &mut ctx.accounts.global_config;
+    config.apply_unpause(now)?;
+
+    emit!(PauseUpdated {
+        config: config.key(),
+        admin: ctx.accounts.authority.key(),
+        paused: false,
+        timestamp: now,
+        pause_flags: 0,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ApplyUnpause<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::state::GlobalConfig;
+use crate::events::ConfigAuthorityProposalCancelled;
+use super::update_config::signer_keys;
+
+/// Withdraws a pending `update_authority` proposal before it is accepted.
+pub fn handler(ctx: Context<CancelAuthorityUpdate>) -> Result<()> {
+    ctx.accounts
+        .global_config
+        .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+
+    let config = &mut ctx.accounts.global_config;
+    let pending_authority = config.cancel_admin_proposal()?;
+
+    emit!(ConfigAuthorityProposalCancelled {
+        config: config.key(),
+        authority: config.admin,
+        pending_authority,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CancelAuthorityUpdate<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
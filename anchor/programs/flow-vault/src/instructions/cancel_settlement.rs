@@ -0,0 +1,51 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, GlobalConfig, Vault};
+use crate::errors::FlowError;
+use crate::events::SettlementCancelled;
+
+/// Lets the vault owner contest a proposed settlement while its dispute
+/// window is still open. The reserved amount returns to the balance; the
+/// nonce stays used.
+pub fn handler(ctx: Context<CancelSettlement>) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    let clock = checked_clock()?;
+
+    if !vault.has_pending_settlement() {
+        return err!(FlowError::NoPendingSettlement);
+    }
+    if clock.unix_timestamp >= vault.settle_after {
+        return err!(FlowError::DisputeWindowElapsed);
+    }
+
+    let provider = vault.pending_provider;
+    let amount = vault.pending_amount;
+    let nonce = vault.pending_nonce;
+    vault.clear_pending();
+
+    emit!(SettlementCancelled {
+        vault: vault.key(),
+        provider,
+        amount,
+        nonce,
+        timestamp: clock.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CancelSettlement<'info> {
+    pub agent: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
+        has_one = agent
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Vault};
+use crate::events::WithdrawalCancelled;
+
+/// Drops a pending withdrawal request, e.g. one made with a leaked owner key.
+pub fn handler(ctx: Context<CancelWithdraw>) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    let amount = vault.pending_withdraw;
+    vault.cancel_withdraw()?;
+
+    emit!(WithdrawalCancelled {
+        vault: vault.key(),
+        agent: vault.agent,
+        amount,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CancelWithdraw<'info> {
+    pub agent: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
+        has_one = agent
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,58 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::{GlobalConfig, Provider};
+use crate::events::EarningsClaimed;
+
+/// Pays the provider's whole accrued balance out to its destination.
+pub fn handler(ctx: Context<ClaimEarnings>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let amount = ctx.accounts.provider.take_accrued(now)?;
+
+    let seeds = &[b"config".as_ref(), &[ctx.accounts.global_config.bump]];
+    let signer_seeds = &[&seeds[..]];
+
+    let cpi_accounts = TransferChecked {
+        from: ctx.accounts.earnings.to_account_info(),
+        mint: ctx.accounts.token_mint.to_account_info(),
+        to: ctx.accounts.destination.to_account_info(),
+        authority: ctx.accounts.global_config.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+    token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+    emit!(EarningsClaimed {
+        provider: ctx.accounts.provider.key(),
+        destination: ctx.accounts.destination.key(),
+        amount,
+        timestamp: now,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ClaimEarnings<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority, has_one = destination)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(
+        mut,
+        seeds = [b"provider_earnings", provider.key().as_ref()],
+        bump
+    )]
+    pub earnings: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = earnings.mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
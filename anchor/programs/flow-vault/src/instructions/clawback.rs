@@ -0,0 +1,108 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::{checked_clock, GlobalConfig, Provider, SettlementReceipt, Vault};
+use crate::errors::FlowError;
+use crate::events::SettlementClawedBack;
+use super::update_config::signer_keys;
+
+/// Returns `amount` of a receipted settlement from the provider's earnings
+/// to the vault it came from, reversing that much of the settlement. The
+/// provider authority may do so at any time; the config authority only
+/// within `clawback_seconds` of the settlement. Either way the provider must
+/// not have claimed its earnings since, so nothing paid out can be pulled
+/// back.
+pub fn handler(ctx: Context<Clawback>, amount: u64) -> Result<()> {
+    let now = checked_clock()?.unix_timestamp;
+    let by_provider = ctx.accounts.authority.key() == ctx.accounts.provider.authority;
+    if !by_provider {
+        ctx.accounts
+            .global_config
+            .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+    }
+
+    let clawback_seconds = ctx.accounts.global_config.clawback_seconds;
+    ctx.accounts
+        .receipt
+        .clawback(amount, by_provider, clawback_seconds, now)?;
+    let settled_at = ctx.accounts.receipt.timestamp;
+    ctx.accounts.provider.return_accrued(amount, settled_at)?;
+    ctx.accounts.vault.refund_settled(amount)?;
+    ctx.accounts.global_config.record_clawback(amount)?;
+
+    let seeds = &[b"config".as_ref(), &[ctx.accounts.global_config.bump]];
+    let signer_seeds = &[&seeds[..]];
+    let cpi_accounts = TransferChecked {
+        from: ctx.accounts.earnings.to_account_info(),
+        mint: ctx.accounts.token_mint.to_account_info(),
+        to: ctx.accounts.vault_token_account.to_account_info(),
+        authority: ctx.accounts.global_config.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+    token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+    emit!(SettlementClawedBack {
+        vault: ctx.accounts.vault.key(),
+        provider: ctx.accounts.provider.key(),
+        authority: ctx.accounts.authority.key(),
+        nonce: ctx.accounts.receipt.nonce,
+        amount,
+        by_provider,
+        timestamp: now,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct Clawback<'info> {
+    /// The provider authority, or the config authority with any multisig
+    /// co-signers passed as remaining accounts.
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(
+        mut,
+        seeds = [b"provider_earnings", provider.key().as_ref()],
+        bump
+    )]
+    pub earnings: InterfaceAccount<'info, TokenAccount>,
+
+    /// Receipt of the settlement being reversed; names the vault it came from.
+    #[account(
+        mut,
+        seeds = [b"receipt", vault.key().as_ref(), &receipt.nonce.to_le_bytes()],
+        bump,
+        constraint = receipt.vault == vault.key() @ FlowError::ReceiptVaultMismatch,
+        constraint = receipt.provider == provider.key() @ FlowError::ReceiptProviderMismatch
+    )]
+    pub receipt: Account<'info, SettlementReceipt>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_token_account", vault.creator.as_ref(), vault.session_seed()],
+        bump,
+        constraint = vault_token_account.key() == vault.vault_token_account
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = earnings.mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        constraint = vault.uses_token_program(&token_program.key()) @ FlowError::InvalidTokenProgram
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+}
@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+use crate::state::GlobalConfig;
+use crate::events::ConfigClosed;
+use super::update_config::signer_keys;
+
+/// Closes the config PDA and returns its rent to the signing authority. Every
+/// vault must be closed first, since their handlers need the config.
+pub fn handler(ctx: Context<CloseConfig>) -> Result<()> {
+    let config = &ctx.accounts.global_config;
+    config.require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+    config.require_no_active_vaults()?;
+
+    emit!(ConfigClosed {
+        config: config.key(),
+        authority: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CloseConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
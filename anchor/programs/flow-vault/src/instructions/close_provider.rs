@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Provider};
+use crate::errors::FlowError;
+use crate::events::ProviderClosed;
+
+/// Closes the provider PDA and returns its rent to the authority. Earnings
+/// still accrued to the provider must be claimed first, since the claim
+/// needs this account.
+pub fn handler(ctx: Context<CloseProvider>) -> Result<()> {
+    let provider = &ctx.accounts.provider;
+    if provider.accrued > 0 {
+        return err!(FlowError::UnclaimedEarnings);
+    }
+
+    emit!(ProviderClosed {
+        provider: provider.key(),
+        authority: provider.authority,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CloseProvider<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut, close = authority, has_one = authority)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
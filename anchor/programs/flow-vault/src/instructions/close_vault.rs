@@ -0,0 +1,77 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, CloseAccount, TokenAccount, TokenInterface};
+use crate::state::{GlobalConfig, OwnerVaultCount, Vault};
+use crate::errors::FlowError;
+use crate::events::VaultClosed;
+
+pub fn handler(ctx: Context<CloseVault>) -> Result<()> {
+  if ctx.accounts.vault_token_account.amount > 0 {
+    return err!(FlowError::VaultNotEmpty);
+  }
+
+  let vault = &ctx.accounts.vault;
+  let seeds = &[
+    b"vault",
+    vault.creator.as_ref(),
+    vault.session_seed(),
+    &[vault.bump],
+  ];
+  let signer_seeds = &[&seeds[..]];
+
+  let cpi_accounts = CloseAccount {
+    account: ctx.accounts.vault_token_account.to_account_info(),
+    destination: ctx.accounts.agent.to_account_info(),
+    authority: vault.to_account_info(),
+  };
+  let cpi_program = ctx.accounts.token_program.to_account_info();
+  let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+  token_interface::close_account(cpi_ctx)?;
+  ctx.accounts.global_config.vault_closed();
+  OwnerVaultCount::release(&ctx.accounts.owner_vault_count)?;
+
+  emit!(VaultClosed {
+    vault: vault.key(),
+    agent: vault.agent,
+    timestamp: Clock::get()?.unix_timestamp,
+    seq: ctx.accounts.global_config.next_event_seq()?,
+  });
+
+  Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CloseVault<'info> {
+  #[account(mut)]
+  pub agent: Signer<'info>,
+
+  #[account(
+    mut,
+    close = agent,
+    seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+    bump = vault.bump,
+    has_one = agent
+  )]
+  pub vault: Account<'info, Vault>,
+
+  #[account(
+    mut,
+    seeds = [b"vault_token_account", vault.creator.as_ref(), vault.session_seed()],
+    bump,
+    constraint = vault_token_account.key() == vault.vault_token_account
+  )]
+  pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+  #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+  pub global_config: Account<'info, GlobalConfig>,
+
+  /// CHECK: The creator's open-vault count, freeing a slot under the
+  /// config's cap. Checked by `OwnerVaultCount::release`, which skips it
+  /// only for creators whose vaults predate the count.
+  #[account(mut, seeds = [b"owner_vaults", vault.creator.as_ref()], bump)]
+  pub owner_vault_count: UncheckedAccount<'info>,
+
+  #[account(
+    constraint = vault.uses_token_program(&token_program.key()) @ FlowError::InvalidTokenProgram
+  )]
+  pub token_program: Interface<'info, TokenInterface>,
+}
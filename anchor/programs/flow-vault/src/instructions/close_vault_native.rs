@@ -0,0 +1,49 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, OwnerVaultCount, Vault};
+use crate::errors::FlowError;
+use crate::events::VaultClosed;
+
+/// Native counterpart of `close_vault`. The balance has to be withdrawn
+/// first; closing returns the vault's rent to the owner and frees its slot
+/// under the config's caps.
+pub fn handler(ctx: Context<CloseVaultNative>) -> Result<()> {
+  if ctx.accounts.vault.balance()? > 0 {
+    return err!(FlowError::VaultNotEmpty);
+  }
+
+  ctx.accounts.global_config.vault_closed();
+  OwnerVaultCount::release(&ctx.accounts.owner_vault_count)?;
+
+  emit!(VaultClosed {
+    vault: ctx.accounts.vault.key(),
+    agent: ctx.accounts.vault.agent,
+    timestamp: Clock::get()?.unix_timestamp,
+    seq: ctx.accounts.global_config.next_event_seq()?,
+  });
+
+  Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CloseVaultNative<'info> {
+  #[account(mut)]
+  pub agent: Signer<'info>,
+
+  #[account(
+    mut,
+    close = agent,
+    seeds = [b"native_vault", vault.creator.as_ref()],
+    bump = vault.bump,
+    has_one = agent
+  )]
+  pub vault: Account<'info, Vault>,
+
+  #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+  pub global_config: Account<'info, GlobalConfig>,
+
+  /// CHECK: The creator's open-vault count, freeing a slot under the
+  /// config's cap. Checked by `OwnerVaultCount::release`, which skips it
+  /// only for creators whose vaults predate the count.
+  #[account(mut, seeds = [b"owner_vaults", vault.creator.as_ref()], bump)]
+  pub owner_vault_count: UncheckedAccount<'info>,
+}
@@ -0,0 +1,37 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Vault};
+use crate::events::AmountCommitted;
+
+/// Raises how much the owner has authorized providers to settle in total.
+/// Once a vault has a commitment, no settlement path, delegate included, can
+/// take it past the committed amount.
+pub fn handler(ctx: Context<CommitAmount>, amount: u64) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    let committed = vault.commit(amount)?;
+
+    emit!(AmountCommitted {
+        vault: vault.key(),
+        agent: vault.agent,
+        amount,
+        committed,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CommitAmount<'info> {
+    pub agent: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
+        has_one = agent
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,60 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Provider, SettlementRoot, Vault, MAX_ROOT_LEAVES};
+use crate::errors::FlowError;
+use crate::events::RootCommitted;
+
+/// The vault owner commits to many micro-payments to one provider at once;
+/// the provider then claims each leaf with `settle_with_proof`.
+pub fn handler(ctx: Context<CommitSettlementRoot>, root: [u8; 32], leaf_count: u16) -> Result<()> {
+    if leaf_count as usize > MAX_ROOT_LEAVES {
+        return err!(FlowError::InputTooLarge);
+    }
+
+    let settlement_root = &mut ctx.accounts.settlement_root;
+    settlement_root.vault = ctx.accounts.vault.key();
+    settlement_root.provider = ctx.accounts.provider.key();
+    settlement_root.root = root;
+    settlement_root.leaf_count = leaf_count;
+    settlement_root.vault_created_slot = ctx.accounts.vault.created_slot;
+
+    emit!(RootCommitted {
+        settlement_root: settlement_root.key(),
+        vault: settlement_root.vault,
+        provider: settlement_root.provider,
+        root,
+        leaf_count,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(root: [u8; 32])]
+pub struct CommitSettlementRoot<'info> {
+    #[account(mut)]
+    pub agent: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
+        has_one = agent
+    )]
+    pub vault: Account<'info, Vault>,
+
+    pub provider: Account<'info, Provider>,
+
+    #[account(
+        init,
+        payer = agent,
+        space = SettlementRoot::LEN,
+        seeds = [b"settlement_root", vault.key().as_ref(), root.as_ref()],
+        bump
+    )]
+    pub settlement_root: Account<'info, SettlementRoot>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,94 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::{checked_clock, Contribution, GlobalConfig, Vault, PAUSE_DEPOSIT};
+use crate::errors::FlowError;
+use crate::events::ContributionAdded;
+
+/// Adds a depositor's funds to a shared vault's pooled balance and records
+/// them in the depositor's `Contribution`, which sizes their expiry refund.
+pub fn handler(ctx: Context<Contribute>, amount: u64) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(PAUSE_DEPOSIT)?;
+    let now = checked_clock()?.unix_timestamp;
+    if ctx.accounts.vault.is_expired(now) {
+        return err!(FlowError::VaultExpired);
+    }
+
+    let cpi_accounts = TransferChecked {
+        from: ctx.accounts.depositor_token_account.to_account_info(),
+        mint: ctx.accounts.token_mint.to_account_info(),
+        to: ctx.accounts.vault_token_account.to_account_info(),
+        authority: ctx.accounts.depositor.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+    token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+    let vault = &mut ctx.accounts.vault;
+    vault.contribute(amount)?;
+
+    let contribution = &mut ctx.accounts.contribution;
+    contribution.vault = vault.key();
+    contribution.depositor = ctx.accounts.depositor.key();
+    contribution.amount = contribution.amount.checked_add(amount).ok_or(FlowError::MathOverflow)?;
+    contribution.bump = ctx.bumps.contribution;
+
+    emit!(ContributionAdded {
+        vault: vault.key(),
+        depositor: contribution.depositor,
+        amount,
+        contributed: contribution.amount,
+        total_contributed: vault.total_contributed,
+        timestamp: now,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct Contribute<'info> {
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
+        constraint = vault.shared @ FlowError::NotSharedVault
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        space = Contribution::LEN,
+        seeds = [b"contribution", vault.key().as_ref(), depositor.key().as_ref()],
+        bump
+    )]
+    pub contribution: Account<'info, Contribution>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_token_account", vault.creator.as_ref(), vault.session_seed()],
+        bump,
+        constraint = vault_token_account.key() == vault.vault_token_account
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub depositor_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = vault.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        constraint = vault.uses_token_program(&token_program.key()) @ FlowError::InvalidTokenProgram
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
@@ -1,27 +1,63 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
-use crate::state::Vault;
+use anchor_spl::token_2022::spl_token_2022::{
+  self,
+  extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::{session_seed, Contribution, GlobalConfig, OwnerVaultCount, Vault, PAUSE_CREATE};
+use crate::errors::FlowError;
 use crate::events::VaultCreated;
 
-pub fn handler(ctx: Context<CreateVault>, deposit_amount: u64) -> Result<()> {
-  let vault = &mut ctx.accounts.vault;
+#[allow(clippy::too_many_arguments)]
+pub fn handler(
+  ctx: Context<CreateVault>,
+  deposit_amount: u64,
+  expiry_unix: i64,
+  withdraw_timelock: i64,
+  shared: bool,
+  max_settlements: u32,
+  session_id: [u8; 16],
+  emit_events: bool,
+) -> Result<()> {
+  let params = VaultParams {
+    deposit_amount,
+    expiry_unix,
+    withdraw_timelock,
+    shared,
+    max_settlements,
+    session_id,
+    emit_events,
+  };
+  params.check(&ctx.accounts.global_config, ctx.accounts.contribution.is_some(), &ctx.accounts.token_mint)?;
 
-  vault.agent = ctx.accounts.agent.key();
-  vault.token_mint = ctx.accounts.token_mint.key();
-  vault.vault_token_account = ctx.accounts.vault_token_account.key();
-  vault.deposit_amount = deposit_amount;
-  vault.total_settled = 0;
-  vault.last_settlement_slot = 0;
-  vault.nonce = 0;
+  let owner_vaults = &mut ctx.accounts.owner_vault_count;
+  owner_vaults.owner = ctx.accounts.agent.key();
+  owner_vaults.bump = ctx.bumps.owner_vault_count;
+  owner_vaults.vault_opened(ctx.accounts.global_config.max_vaults_per_owner)?;
+
+  let vault = &mut ctx.accounts.vault;
+  params.init_vault(
+    vault,
+    ctx.bumps.vault,
+    ctx.accounts.agent.key(),
+    ctx.accounts.token_mint.key(),
+    ctx.accounts.vault_token_account.key(),
+    ctx.accounts.token_program.key(),
+  )?;
+  if let Some(contribution) = ctx.accounts.contribution.as_mut() {
+    params.init_contribution(vault, contribution, ctx.bumps.contribution)?;
+  }
 
-  let cpi_accounts = Transfer {
+  let cpi_accounts = TransferChecked {
     from: ctx.accounts.agent_token_account.to_account_info(),
+    mint: ctx.accounts.token_mint.to_account_info(),
     to: ctx.accounts.vault_token_account.to_account_info(),
     authority: ctx.accounts.agent.to_account_info(),
   };
   let cpi_program = ctx.accounts.token_program.to_account_info();
   let cpi_ctx = CpiContext::new(cpi_program,cpi_accounts);
-  token::transfer(cpi_ctx, deposit_amount)?;
+  token_interface::transfer_checked(cpi_ctx, deposit_amount, ctx.accounts.token_mint.decimals)?;
+  ctx.accounts.global_config.vault_opened()?;
 
 
   emit!(VaultCreated {
@@ -29,13 +65,132 @@ pub fn handler(ctx: Context<CreateVault>, deposit_amount: u64) -> Result<()> {
     agent: vault.agent,
     token_mint: vault.token_mint,
     initial_deposit: deposit_amount,
+    timestamp: Clock::get()?.unix_timestamp,
+    shared,
+    max_settlements,
+    session_id,
+    seq: ctx.accounts.global_config.next_event_seq()?,
   });
   Ok(())
 
 }
 
+/// The creation arguments shared by `create_vault` and
+/// `create_vault_idempotent`, so both open identical vaults.
+pub(crate) struct VaultParams {
+  pub deposit_amount: u64,
+  pub expiry_unix: i64,
+  pub withdraw_timelock: i64,
+  pub shared: bool,
+  pub max_settlements: u32,
+  pub session_id: [u8; 16],
+  pub emit_events: bool,
+}
+
+impl VaultParams {
+  pub(crate) fn check(
+    &self,
+    config: &GlobalConfig,
+    has_contribution: bool,
+    mint: &InterfaceAccount<Mint>,
+  ) -> Result<()> {
+    config.require_not_paused(PAUSE_CREATE)?;
+    require_gte!(self.withdraw_timelock, 0, FlowError::InvalidWithdrawTimelock);
+    // A shared vault's own deposit is the creator's first contribution.
+    match (self.shared, has_contribution) {
+      (true, false) => return err!(FlowError::ContributionRequired),
+      (false, true) => return err!(FlowError::UnexpectedContribution),
+      _ => {}
+    }
+    config.check_deposit(self.deposit_amount)?;
+    ensure_supported_mint(mint)
+  }
+
+  pub(crate) fn init_vault(
+    &self,
+    vault: &mut Vault,
+    bump: u8,
+    agent: Pubkey,
+    token_mint: Pubkey,
+    vault_token_account: Pubkey,
+    token_program: Pubkey,
+  ) -> Result<()> {
+    vault.agent = agent;
+    vault.creator = agent;
+    vault.token_mint = token_mint;
+    vault.vault_token_account = vault_token_account;
+    vault.deposit_amount = self.deposit_amount;
+    vault.total_settled = 0;
+    vault.last_settlement_slot = 0;
+    vault.nonce = 0;
+    vault.expiry_unix = self.expiry_unix;
+    vault.withdraw_timelock = self.withdraw_timelock;
+    vault.version = Vault::VERSION;
+    vault.bump = bump;
+    vault.shared = self.shared;
+    vault.max_settlements = self.max_settlements;
+    vault.session_id = self.session_id;
+    vault.token_program = token_program;
+    vault.events_disabled = !self.emit_events;
+    vault.created_slot = Clock::get()?.slot;
+    Ok(())
+  }
+
+  pub(crate) fn init_contribution(
+    &self,
+    vault: &mut Account<Vault>,
+    contribution: &mut Contribution,
+    bump: Option<u8>,
+  ) -> Result<()> {
+    contribution.vault = vault.key();
+    contribution.depositor = vault.agent;
+    contribution.amount = self.deposit_amount;
+    contribution.bump = bump.ok_or(FlowError::ContributionRequired)?;
+    vault.total_contributed = self.deposit_amount;
+    Ok(())
+  }
+}
+
+/// Token-2022 mints whose transfers this program can't carry out as settled
+/// are rejected up front: a transfer fee delivers less than the settled
+/// amount, a transfer hook needs extra accounts no transfer here forwards, a
+/// non-transferable mint can't move at all and a permanent delegate can pull
+/// funds out of the vault behind its accounting.
+pub(crate) fn ensure_supported_mint(mint: &InterfaceAccount<Mint>) -> Result<()> {
+  let mint_info = mint.to_account_info();
+  if *mint_info.owner != spl_token_2022::ID {
+    return Ok(());
+  }
+
+  let mint_data = mint_info.try_borrow_data()?;
+  let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+  let unsupported = mint_state.get_extension_types()?.into_iter().any(|extension| {
+    matches!(
+      extension,
+      ExtensionType::TransferFeeConfig
+        | ExtensionType::TransferHook
+        | ExtensionType::NonTransferable
+        | ExtensionType::PermanentDelegate
+    )
+  });
+  if unsupported {
+    return err!(FlowError::UnsupportedMintExtension);
+  }
+  Ok(())
+}
 
+
+/// A non-zero `session_id` gives the owner an extra vault addressed by that
+/// id; the all-zero id is the owner's default vault.
 #[derive(Accounts)]
+#[instruction(
+  deposit_amount: u64,
+  expiry_unix: i64,
+  withdraw_timelock: i64,
+  shared: bool,
+  max_settlements: u32,
+  session_id: [u8; 16]
+)]
 pub struct CreateVault<'info> {
   #[account(mut)]
   pub agent: Signer<'info>,
@@ -44,28 +199,55 @@ pub struct CreateVault<'info> {
     init, 
     payer = agent,
     space = Vault::LEN,
-    seeds = [b"vault", agent.key().as_ref()],
+    seeds = [b"vault", agent.key().as_ref(), session_seed(&session_id)],
     bump
   )]
   pub vault: Account<'info, Vault>,
 
+  /// Program-derived and owned by the vault PDA. Every handler that moves
+  /// funds re-derives it from these seeds, so a look-alike account is refused.
   #[account(
     init, 
     payer = agent,
     token::mint = token_mint,
     token::authority = vault,
-    seeds = [b"vault_token_account", agent.key().as_ref()],
+    token::token_program = token_program,
+    seeds = [b"vault_token_account", agent.key().as_ref(), session_seed(&session_id)],
     bump
   )]
-  pub vault_token_account: Account<'info, TokenAccount>,
+  pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
 
   #[account(
     mut, 
     constraint = agent_token_account.mint == token_mint.key()
   )]
-  pub agent_token_account: Account<'info, TokenAccount>,
+  pub agent_token_account: InterfaceAccount<'info, TokenAccount>,
+
+  pub token_mint: InterfaceAccount<'info, Mint>,
+
+  #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+  pub global_config: Account<'info, GlobalConfig>,
+
+  /// The creator's contribution record; passed only for shared vaults.
+  #[account(
+    init,
+    payer = agent,
+    space = Contribution::LEN,
+    seeds = [b"contribution", vault.key().as_ref(), agent.key().as_ref()],
+    bump
+  )]
+  pub contribution: Option<Account<'info, Contribution>>,
+
+  /// Open vaults held by the agent, checked against the config's cap.
+  #[account(
+    init_if_needed,
+    payer = agent,
+    space = OwnerVaultCount::LEN,
+    seeds = [b"owner_vaults", agent.key().as_ref()],
+    bump
+  )]
+  pub owner_vault_count: Account<'info, OwnerVaultCount>,
 
-  pub token_mint: Account<'info, Mint>,
-  pub token_program: Program<'info, Token>,
+  pub token_program: Interface<'info, TokenInterface>,
   pub system_program: Program<'info, System>,
 }
\ No newline at end of file
@@ -0,0 +1,181 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::{checked_clock, session_seed, Contribution, GlobalConfig, OwnerVaultCount, Vault, PAUSE_DEPOSIT};
+use crate::errors::FlowError;
+use crate::events::{VaultCreated, VaultToppedUp};
+use super::create_vault::VaultParams;
+
+/// `create_vault` that is safe to retry. If the vault already exists for this
+/// owner and mint, only the shortfall needed to bring its deposits up to
+/// `deposit_amount` is transferred, so a retry of a call that did land is a
+/// no-op. That top-up is held to the same checks as `deposit`.
+#[allow(clippy::too_many_arguments)]
+pub fn handler(
+    ctx: Context<CreateVaultIdempotent>,
+    deposit_amount: u64,
+    expiry_unix: i64,
+    withdraw_timelock: i64,
+    shared: bool,
+    max_settlements: u32,
+    session_id: [u8; 16],
+    emit_events: bool,
+) -> Result<()> {
+    let params = VaultParams {
+        deposit_amount,
+        expiry_unix,
+        withdraw_timelock,
+        shared,
+        max_settlements,
+        session_id,
+        emit_events,
+    };
+    params.check(&ctx.accounts.global_config, ctx.accounts.contribution.is_some(), &ctx.accounts.token_mint)?;
+
+    let agent = ctx.accounts.agent.key();
+    let token_mint = ctx.accounts.token_mint.key();
+    let vault = &mut ctx.accounts.vault;
+    let created = vault.creator == Pubkey::default();
+    let transfer_amount = if created {
+        let owner_vaults = &mut ctx.accounts.owner_vault_count;
+        owner_vaults.owner = agent;
+        owner_vaults.bump = ctx.bumps.owner_vault_count;
+        owner_vaults.vault_opened(ctx.accounts.global_config.max_vaults_per_owner)?;
+        params.init_vault(
+            vault,
+            ctx.bumps.vault,
+            agent,
+            token_mint,
+            ctx.accounts.vault_token_account.key(),
+            ctx.accounts.token_program.key(),
+        )?;
+        if let Some(contribution) = ctx.accounts.contribution.as_mut() {
+            params.init_contribution(vault, contribution, ctx.bumps.contribution)?;
+        }
+        deposit_amount
+    } else {
+        require_keys_eq!(vault.agent, agent, FlowError::Unauthorized);
+        require_keys_eq!(vault.token_mint, token_mint, FlowError::MintMismatch);
+        if !vault.uses_token_program(&ctx.accounts.token_program.key()) {
+            return err!(FlowError::InvalidTokenProgram);
+        }
+        match (vault.shared, shared) {
+            (true, false) => return err!(FlowError::ContributionRequired),
+            (false, true) => return err!(FlowError::UnexpectedContribution),
+            _ => {}
+        }
+        let shortfall = deposit_amount.saturating_sub(vault.deposit_amount);
+        if shortfall > 0 {
+            ctx.accounts.global_config.require_not_paused(PAUSE_DEPOSIT)?;
+            vault.check_top_up(checked_clock()?.unix_timestamp)?;
+        }
+        vault.deposit_amount += shortfall;
+        shortfall
+    };
+
+    if transfer_amount > 0 {
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.agent_token_account.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.vault_token_account.to_account_info(),
+            authority: ctx.accounts.agent.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, transfer_amount, ctx.accounts.token_mint.decimals)?;
+    }
+
+    let timestamp = Clock::get()?.unix_timestamp;
+    if created {
+        ctx.accounts.global_config.vault_opened()?;
+        emit!(VaultCreated {
+            vault: vault.key(),
+            agent,
+            token_mint,
+            initial_deposit: deposit_amount,
+            timestamp,
+            shared,
+            max_settlements,
+            session_id,
+            seq: ctx.accounts.global_config.next_event_seq()?,
+        });
+    } else if transfer_amount > 0 {
+        emit!(VaultToppedUp {
+            vault: vault.key(),
+            agent,
+            amount: transfer_amount,
+            new_balance: vault.balance()?,
+            timestamp,
+            seq: ctx.accounts.global_config.next_event_seq()?,
+        });
+    }
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(
+    deposit_amount: u64,
+    expiry_unix: i64,
+    withdraw_timelock: i64,
+    shared: bool,
+    max_settlements: u32,
+    session_id: [u8; 16]
+)]
+pub struct CreateVaultIdempotent<'info> {
+    #[account(mut)]
+    pub agent: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = agent,
+        space = Vault::LEN,
+        seeds = [b"vault", agent.key().as_ref(), session_seed(&session_id)],
+        bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init_if_needed,
+        payer = agent,
+        token::mint = token_mint,
+        token::authority = vault,
+        token::token_program = token_program,
+        seeds = [b"vault_token_account", agent.key().as_ref(), session_seed(&session_id)],
+        bump
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = agent_token_account.mint == token_mint.key()
+    )]
+    pub agent_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// The creator's contribution record; passed only for shared vaults.
+    #[account(
+        init_if_needed,
+        payer = agent,
+        space = Contribution::LEN,
+        seeds = [b"contribution", vault.key().as_ref(), agent.key().as_ref()],
+        bump
+    )]
+    pub contribution: Option<Account<'info, Contribution>>,
+
+    /// Open vaults held by the agent, checked against the config's cap when
+    /// the vault is created.
+    #[account(
+        init_if_needed,
+        payer = agent,
+        space = OwnerVaultCount::LEN,
+        seeds = [b"owner_vaults", agent.key().as_ref()],
+        bump
+    )]
+    pub owner_vault_count: Account<'info, OwnerVaultCount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
@@ -0,0 +1,85 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Transfer};
+use crate::state::{GlobalConfig, OwnerVaultCount, Vault, PAUSE_CREATE};
+use crate::events::VaultCreated;
+
+/// Native SOL vaults hold the deposit as lamports on the vault PDA itself and
+/// leave the token fields zeroed. The deposit bounds, per-owner cap and
+/// expiry work as for `create_vault`.
+pub fn handler(ctx: Context<CreateVaultNative>, deposit_amount: u64, expiry_unix: i64) -> Result<()> {
+  ctx.accounts.global_config.require_not_paused(PAUSE_CREATE)?;
+  ctx.accounts.global_config.check_deposit(deposit_amount)?;
+
+  let owner_vaults = &mut ctx.accounts.owner_vault_count;
+  owner_vaults.owner = ctx.accounts.agent.key();
+  owner_vaults.bump = ctx.bumps.owner_vault_count;
+  owner_vaults.vault_opened(ctx.accounts.global_config.max_vaults_per_owner)?;
+
+  let vault = &mut ctx.accounts.vault;
+
+  vault.agent = ctx.accounts.agent.key();
+  vault.creator = vault.agent;
+  vault.token_mint = Pubkey::default();
+  vault.vault_token_account = Pubkey::default();
+  vault.deposit_amount = deposit_amount;
+  vault.total_settled = 0;
+  vault.last_settlement_slot = 0;
+  vault.nonce = 0;
+  vault.expiry_unix = expiry_unix;
+  vault.version = Vault::VERSION;
+  vault.bump = ctx.bumps.vault;
+  vault.created_slot = Clock::get()?.slot;
+
+  let cpi_accounts = Transfer {
+    from: ctx.accounts.agent.to_account_info(),
+    to: vault.to_account_info(),
+  };
+  let cpi_program = ctx.accounts.system_program.to_account_info();
+  let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+  system_program::transfer(cpi_ctx, deposit_amount)?;
+  ctx.accounts.global_config.vault_opened()?;
+
+  emit!(VaultCreated {
+    vault: vault.key(),
+    agent: vault.agent,
+    token_mint: vault.token_mint,
+    initial_deposit: deposit_amount,
+    timestamp: Clock::get()?.unix_timestamp,
+    shared: false,
+    max_settlements: 0,
+    session_id: [0; 16],
+    seq: ctx.accounts.global_config.next_event_seq()?,
+  });
+  Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateVaultNative<'info> {
+  #[account(mut)]
+  pub agent: Signer<'info>,
+
+  #[account(
+    init,
+    payer = agent,
+    space = Vault::LEN,
+    seeds = [b"native_vault", agent.key().as_ref()],
+    bump
+  )]
+  pub vault: Account<'info, Vault>,
+
+  #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+  pub global_config: Account<'info, GlobalConfig>,
+
+  /// Open vaults held by the agent, shared with its SPL vaults and checked
+  /// against the config's cap.
+  #[account(
+    init_if_needed,
+    payer = agent,
+    space = OwnerVaultCount::LEN,
+    seeds = [b"owner_vaults", agent.key().as_ref()],
+    bump
+  )]
+  pub owner_vault_count: Account<'info, OwnerVaultCount>,
+
+  pub system_program: Program<'info, System>,
+}
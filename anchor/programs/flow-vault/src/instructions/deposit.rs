@@ -0,0 +1,77 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::{checked_clock, GlobalConfig, Vault, PAUSE_DEPOSIT};
+use crate::errors::FlowError;
+use crate::events::VaultToppedUp;
+
+pub fn handler(ctx: Context<Deposit>, amount: u64) -> Result<()> {
+  ctx.accounts.global_config.require_not_paused(PAUSE_DEPOSIT)?;
+  if amount == 0 {
+    return err!(FlowError::ZeroAmount);
+  }
+  let now = checked_clock()?.unix_timestamp;
+  ctx.accounts.vault.check_top_up(now)?;
+
+  let cpi_accounts = TransferChecked {
+    from: ctx.accounts.agent_token_account.to_account_info(),
+    mint: ctx.accounts.token_mint.to_account_info(),
+    to: ctx.accounts.vault_token_account.to_account_info(),
+    authority: ctx.accounts.agent.to_account_info(),
+  };
+  let cpi_program = ctx.accounts.token_program.to_account_info();
+  let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+  token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+  let vault = &mut ctx.accounts.vault;
+  vault.deposit_amount = vault.deposit_amount.checked_add(amount).ok_or(FlowError::MathOverflow)?;
+
+  emit!(VaultToppedUp {
+    vault: vault.key(),
+    agent: vault.agent,
+    amount,
+    new_balance: vault.balance()?,
+    timestamp: now,
+    seq: ctx.accounts.global_config.next_event_seq()?,
+  });
+
+  Ok(())
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+  pub agent: Signer<'info>,
+
+  #[account(
+    mut,
+    seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+    bump = vault.bump,
+    has_one = agent
+  )]
+  pub vault: Account<'info, Vault>,
+
+  #[account(
+    mut,
+    seeds = [b"vault_token_account", vault.creator.as_ref(), vault.session_seed()],
+    bump,
+    constraint = vault_token_account.key() == vault.vault_token_account
+  )]
+  pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+  #[account(mut)]
+  pub agent_token_account: InterfaceAccount<'info, TokenAccount>,
+
+  #[account(address = vault.token_mint)]
+  pub token_mint: InterfaceAccount<'info, Mint>,
+
+  #[account(
+    mut,
+    seeds = [b"config"],
+    bump = global_config.bump
+  )]
+  pub global_config: Account<'info, GlobalConfig>,
+
+  #[account(
+    constraint = vault.uses_token_program(&token_program.key()) @ FlowError::InvalidTokenProgram
+  )]
+  pub token_program: Interface<'info, TokenInterface>,
+}
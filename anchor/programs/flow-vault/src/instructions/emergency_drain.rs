@@ -0,0 +1,98 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::{session_seed, GlobalConfig, Vault, PAUSE_WITHDRAW};
+use crate::errors::FlowError;
+use crate::events::EmergencyDrain;
+use super::update_config::signer_keys;
+
+/// Incident response: sweeps everything left in an SPL vault to a token
+/// account owned by the config's recovery destination, e.g. when the owner
+/// key is known to be compromised. Only the config authority or the
+/// guardian may drain, and only while withdrawals are paused so the owner
+/// cannot race it. The vault is marked drained afterwards.
+pub fn handler(ctx: Context<EmergencyDrainVault>) -> Result<()> {
+    let config = &ctx.accounts.global_config;
+    if !config.is_guardian(ctx.accounts.authority.key) {
+        config.require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+    }
+    if !config.is_paused(PAUSE_WITHDRAW) {
+        return err!(FlowError::WithdrawalsNotPaused);
+    }
+    if config.recovery_destination == Pubkey::default() {
+        return err!(FlowError::InvalidRecoveryDestination);
+    }
+
+    let vault = &mut ctx.accounts.vault;
+    let amount = vault.drain()?;
+
+    let creator = vault.creator;
+    let session_id = vault.session_id;
+    let seeds = &[b"vault", creator.as_ref(), session_seed(&session_id), &[vault.bump]];
+    let signer_seeds = &[&seeds[..]];
+    let cpi_accounts = TransferChecked {
+        from: ctx.accounts.vault_token_account.to_account_info(),
+        mint: ctx.accounts.token_mint.to_account_info(),
+        to: ctx.accounts.recovery_account.to_account_info(),
+        authority: vault.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        cpi_accounts,
+        signer_seeds,
+    );
+    token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+    emit!(EmergencyDrain {
+        vault: vault.key(),
+        agent: vault.agent,
+        authority: ctx.accounts.authority.key(),
+        recovery_account: ctx.accounts.recovery_account.key(),
+        amount,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct EmergencyDrainVault<'info> {
+    /// The config authority, a multisig member, or the guardian.
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_token_account", vault.creator.as_ref(), vault.session_seed()],
+        bump,
+        constraint = vault_token_account.key() == vault.vault_token_account
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = recovery_account.owner == global_config.recovery_destination @ FlowError::InvalidRecoveryDestination,
+        constraint = recovery_account.mint == vault.token_mint @ FlowError::MintMismatch
+    )]
+    pub recovery_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = vault.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = vault.uses_token_program(&token_program.key()) @ FlowError::InvalidTokenProgram
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+}
@@ -1,21 +1,43 @@
 use anchor_lang::prelude::*;
 use crate::state::GlobalConfig;
+use crate::events::PauseUpdated;
+use super::update_config::signer_keys;
 
-pub fn handler(_ctx: Context<EmergencyPause>, _paused: bool) -> Result<()> {
-    msg!("Emergency pause triggered by admin");
+/// Pauses the instruction groups in `flags` (`PAUSE_*` bits) on top of any
+/// already paused, leaving the rest running.
+pub fn handler(ctx: Context<EmergencyPause>, flags: u8) -> Result<()> {
+    // The guardian can only halt the protocol; resuming needs the authority.
+    let config = &ctx.accounts.global_config;
+    if !(flags != 0 && config.is_guardian(ctx.accounts.admin.key)) {
+        config.require_authority(&signer_keys(&ctx.accounts.admin, ctx.remaining_accounts))?;
+    }
+
+    // Unpausing waits out the timelock via `propose_unpause`.
+    let config = &mut ctx.accounts.global_config;
+    config.pause(flags)?;
+
+    emit!(PauseUpdated {
+        config: config.key(),
+        admin: ctx.accounts.admin.key(),
+        paused: true,
+        timestamp: Clock::get()?.unix_timestamp,
+        pause_flags: config.pause_flags,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
     Ok(())
 }
 
 #[derive(Accounts)]
 pub struct EmergencyPause<'info> {
+    /// The admin, or one of the multisig signers when a multisig is set. The
+    /// guardian may also sign to pause.
     #[account(mut)]
     pub admin: Signer<'info>,
 
     #[account(
         mut,
         seeds = [b"config"],
-        bump,
-        has_one = admin
+        bump = global_config.bump
     )]
     pub global_config: Account<'info, GlobalConfig>,
-}
\ No newline at end of file
+}
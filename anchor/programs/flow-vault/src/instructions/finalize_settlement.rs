@@ -0,0 +1,156 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::{checked_clock, FeeSource, GlobalConfig, Provider, Vault};
+use crate::errors::FlowError;
+use crate::events::Settlement;
+use super::settle_batch::fee_shares;
+
+/// Second phase of a disputable settlement. Anyone can finalize once the
+/// dispute window is over; the funds only ever go to the proposed provider.
+/// With fee splits configured, `remaining_accounts` carries one token account
+/// per split, and a provider registered with a referrer needs its token
+/// account passed as `referrer`, as for `settle_batch`.
+pub fn handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, FinalizeSettlement<'info>>,
+) -> Result<()> {
+    // 1. Business logic checks
+    let vault = &mut ctx.accounts.vault;
+    let clock = checked_clock()?;
+    let split = vault.check_finalize(
+        &ctx.accounts.global_config,
+        &ctx.accounts.provider,
+        clock.unix_timestamp,
+    )?;
+    let amount = vault.pending_amount;
+    let nonce = vault.pending_nonce;
+
+    // 2. Perform the token transfers
+    let seeds = &[
+        b"vault",
+        vault.creator.as_ref(),
+        vault.session_seed(),
+        &[vault.bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    let cpi_accounts = TransferChecked {
+        from: ctx.accounts.vault_token_account.to_account_info(),
+        mint: ctx.accounts.token_mint.to_account_info(),
+        to: ctx.accounts.destination.to_account_info(),
+        authority: vault.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+    token_interface::transfer_checked(cpi_ctx, split.net_to_provider, ctx.accounts.token_mint.decimals)?;
+
+    // Micro-payments can round a fee share down to zero; skip the empty CPI.
+    for (to, fee) in fee_shares(
+        &ctx.accounts.global_config,
+        &ctx.accounts.provider,
+        split.fee_amount,
+        ctx.accounts.referrer.as_ref().map(|referrer| referrer.to_account_info()),
+        ctx.accounts.fee_destination.to_account_info(),
+        ctx.remaining_accounts,
+        false,
+    )? {
+        if fee == 0 {
+            continue;
+        }
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.vault_token_account.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to,
+            authority: vault.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+        token_interface::transfer_checked(cpi_ctx, fee, ctx.accounts.token_mint.decimals)?;
+    }
+
+    // 3. Update vault state and protocol totals
+    vault.finalize_settlement(split.fee_amount, &clock)?;
+    ctx.accounts.global_config.record_settlement(amount, split.fee_amount)?;
+
+    // 4. Emit event
+    if vault.emits_events() {
+        emit!(Settlement {
+            vault: vault.key(),
+            provider: ctx.accounts.provider.key(),
+            agent: vault.agent,
+            amount,
+            nonce,
+            visa_merchant_id: ctx.accounts.provider.visa_merchant_id.clone(),
+            gross_amount: amount,
+            fee_amount: split.fee_amount,
+            net_to_provider: split.net_to_provider,
+            timestamp: clock.unix_timestamp,
+            destination: ctx.accounts.destination.key(),
+            remaining_balance: vault.balance()?,
+            remaining_before_cap: vault.settle_capacity(&ctx.accounts.global_config)?,
+            fee_from: FeeSource::NetFromAmount,
+            seq: ctx.accounts.global_config.next_event_seq()?,
+        });
+    }
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct FinalizeSettlement<'info> {
+    #[account(mut)]
+    pub facilitator: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        address = vault.vault_token_account
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = vault.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+      mut,
+      seeds = [b"config"],
+      bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        address = vault.pending_provider,
+        has_one = destination
+    )]
+    pub provider: Account<'info, Provider>,
+
+    #[account(
+        mut,
+        constraint = destination.mint == vault.token_mint @ FlowError::MintMismatch
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = fee_destination.owner == global_config.fee_destination @ FlowError::InvalidFeeDestination
+    )]
+    pub fee_destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = vault.uses_token_program(&token_program.key()) @ FlowError::InvalidTokenProgram
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// The provider's referrer, paid `referral_bps` of the fee.
+    #[account(
+        mut,
+        constraint = provider.referrer == Some(referrer.key()) @ FlowError::InvalidReferrer,
+        constraint = referrer.mint == vault.token_mint @ FlowError::MintMismatch
+    )]
+    pub referrer: Option<InterfaceAccount<'info, TokenAccount>>,
+}
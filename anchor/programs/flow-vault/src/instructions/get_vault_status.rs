@@ -0,0 +1,21 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, GlobalConfig, Vault, VaultStatus};
+
+/// View instruction: returns the vault's balance and status through the
+/// transaction's return data. Works for SPL and native vaults alike.
+pub fn handler(ctx: Context<GetVaultStatus>) -> Result<VaultStatus> {
+    ctx.accounts
+        .vault
+        .status(&ctx.accounts.global_config, checked_clock()?.unix_timestamp)
+}
+
+#[derive(Accounts)]
+pub struct GetVaultStatus<'info> {
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -1,16 +1,36 @@
 use anchor_lang::prelude::*;
-use crate::state::GlobalConfig;
+use crate::state::{ConfigParams, GlobalConfig};
+use crate::events::ConfigInitialized;
 
-
-pub fn handler(
-  ctx: Context<InitializeConfig>,
-  settle_threshold: u64,
-  fee_bps: u16,
-) -> Result<()> {
+pub fn handler(ctx: Context<InitializeConfig>, params: ConfigParams) -> Result<()> {
   let config = &mut ctx.accounts.global_config;
+  config.apply(&params)?;
   config.admin = ctx.accounts.admin.key();
-  config.settle_threshold = settle_threshold;
-  config.fee_bps = fee_bps;
+  config.pause_flags = 0;
+  config.version = GlobalConfig::VERSION;
+  config.bump = ctx.bumps.global_config;
+
+  emit!(ConfigInitialized {
+    config: config.key(),
+    admin: config.admin,
+    settle_threshold: params.settle_threshold,
+    fee_bps: params.fee_bps,
+    fee_destination: params.fee_destination,
+    min_deposit: params.min_deposit,
+    max_deposit: params.max_deposit,
+    dispute_seconds: params.dispute_seconds,
+    min_settle_interval: params.min_settle_interval,
+    timelock_seconds: params.timelock_seconds,
+    max_fee: params.max_fee,
+    referral_bps: params.referral_bps,
+    fee_rounding: params.fee_rounding,
+    registration_fee: params.registration_fee,
+    max_vaults_per_owner: params.max_vaults_per_owner,
+    clawback_seconds: params.clawback_seconds,
+    dust_threshold: params.dust_threshold,
+    timestamp: Clock::get()?.unix_timestamp,
+    seq: ctx.accounts.global_config.next_event_seq()?,
+  });
   Ok(())
 }
 
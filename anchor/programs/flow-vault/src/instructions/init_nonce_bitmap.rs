@@ -0,0 +1,50 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, NonceBitmap, Vault};
+use crate::events::NonceBitmapCreated;
+
+/// Switches a vault from its 128-nonce replay window to a `NonceBitmap`.
+/// Every nonce up to the vault's current one is carried over as used.
+pub fn handler(ctx: Context<InitNonceBitmap>) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    let mut bitmap = ctx.accounts.nonce_bitmap.load_init()?;
+    bitmap.vault = vault.key();
+    bitmap.mark_used_through(vault.nonce);
+    vault.uses_nonce_bitmap = true;
+
+    emit!(NonceBitmapCreated {
+        vault: vault.key(),
+        nonce_bitmap: ctx.accounts.nonce_bitmap.key(),
+        carried_nonce: vault.nonce,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitNonceBitmap<'info> {
+    #[account(mut)]
+    pub agent: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
+        has_one = agent
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init,
+        payer = agent,
+        space = NonceBitmap::LEN,
+        seeds = [b"nonce_bitmap", vault.key().as_ref()],
+        bump
+    )]
+    pub nonce_bitmap: AccountLoader<'info, NonceBitmap>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
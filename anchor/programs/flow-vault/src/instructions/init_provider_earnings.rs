@@ -0,0 +1,38 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::state::{GlobalConfig, Provider};
+
+/// Opens the token account `settle_batch` can accrue a provider's net
+/// proceeds into. It is owned by the config PDA, so only `claim_earnings`
+/// can move funds out, and only to the provider's destination.
+pub fn handler(_ctx: Context<InitProviderEarnings>) -> Result<()> {
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitProviderEarnings<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(has_one = authority)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::mint = token_mint,
+        token::authority = global_config,
+        token::token_program = token_program,
+        seeds = [b"provider_earnings", provider.key().as_ref()],
+        bump
+    )]
+    pub earnings: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
@@ -0,0 +1,109 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Transfer};
+use anchor_lang::Discriminator;
+use crate::state::{GlobalConfig, Provider, Vault};
+use crate::events::AccountMigrated;
+
+/// Upgrades a `GlobalConfig`, `Provider` or `Vault` written by an older
+/// program to its type's current `VERSION`. Anyone may call it, since migration only
+/// fills in defaults. Accounts from before a layout grew are resized first,
+/// with `payer` covering the extra rent; the added bytes are zero, as is the
+/// reserved space they used to follow. Accounts that are already current are
+/// left untouched. Migrating a vault or provider takes the current
+/// `global_config` for the event `seq`; the config migrates on its own, so it
+/// is left out when it is the account being migrated.
+pub fn handler(ctx: Context<MigrateAccount>) -> Result<()> {
+    let info = ctx.accounts.account.to_account_info();
+    let discriminator = info
+        .try_borrow_data()?
+        .get(..8)
+        .map(<[u8]>::to_vec)
+        .ok_or(ErrorCode::AccountDiscriminatorNotFound)?;
+
+    let (from_version, to_version) = if discriminator == Vault::DISCRIMINATOR {
+        (migrate(&ctx, Vault::LEN, Vault::migrate)?, Vault::VERSION)
+    } else if discriminator == Provider::DISCRIMINATOR {
+        (
+            migrate(&ctx, Provider::LEN, |provider: &mut Provider| provider.migrate(info.key))?,
+            Provider::VERSION,
+        )
+    } else if discriminator == GlobalConfig::DISCRIMINATOR {
+        (migrate(&ctx, GlobalConfig::LEN, GlobalConfig::migrate)?, GlobalConfig::VERSION)
+    } else {
+        return err!(ErrorCode::AccountDiscriminatorMismatch);
+    };
+
+    if let Some(from_version) = from_version {
+        let seq = if discriminator == GlobalConfig::DISCRIMINATOR {
+            next_migrated_config_seq(&info)?
+        } else {
+            ctx.accounts
+                .global_config
+                .as_mut()
+                .ok_or(ErrorCode::AccountNotEnoughKeys)?
+                .next_event_seq()?
+        };
+        emit!(AccountMigrated {
+            account: info.key(),
+            from_version,
+            to_version,
+            timestamp: Clock::get()?.unix_timestamp,
+            seq,
+        });
+    }
+    Ok(())
+}
+
+/// Takes the event `seq` from the config that was just migrated, since it
+/// cannot also be passed as `global_config`.
+fn next_migrated_config_seq(info: &AccountInfo) -> Result<u64> {
+    let mut config = GlobalConfig::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    let seq = config.next_event_seq()?;
+    config.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    Ok(seq)
+}
+
+/// Grows the account to `len`, decodes it, runs `upgrade` on it and writes
+/// it back if it changed.
+fn migrate<T: AccountSerialize + AccountDeserialize>(
+    ctx: &Context<MigrateAccount>,
+    len: usize,
+    upgrade: impl FnOnce(&mut T) -> Option<u8>,
+) -> Result<Option<u8>> {
+    let info = ctx.accounts.account.to_account_info();
+    if info.data_len() < len {
+        let shortfall = Rent::get()?.minimum_balance(len).saturating_sub(info.lamports());
+        if shortfall > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.payer.to_account_info(),
+                to: info.clone(),
+            };
+            let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+            system_program::transfer(cpi_ctx, shortfall)?;
+        }
+        info.resize(len)?;
+    }
+
+    let mut account = T::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    let from_version = upgrade(&mut account);
+    if from_version.is_some() {
+        account.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    }
+    Ok(from_version)
+}
+
+#[derive(Accounts)]
+pub struct MigrateAccount<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: Decoded by discriminator in the handler; must be owned by this
+    /// program.
+    #[account(mut, owner = crate::ID)]
+    pub account: UncheckedAccount<'info>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Option<Account<'info, GlobalConfig>>,
+
+    pub system_program: Program<'info, System>,
+}
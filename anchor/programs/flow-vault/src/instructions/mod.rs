@@ -1,13 +1,135 @@
+#![allow(ambiguous_glob_reexports)]
+
 pub mod create_vault;
+pub mod create_vault_idempotent;
 pub mod settle_batch;
+pub mod settle_batch_many;
+pub mod pay_once;
+pub mod propose_settlement;
+pub mod finalize_settlement;
+pub mod cancel_settlement;
 pub mod withdraw;
+pub mod relay_withdraw;
+pub mod request_withdraw;
+pub mod cancel_withdraw;
+pub mod refund_expired;
 pub mod emergency_pause;
+pub mod emergency_drain;
+pub mod propose_fee_increase;
+pub mod apply_fee_increase;
+pub mod propose_unpause;
+pub mod apply_unpause;
 pub mod init_config;
+pub mod update_config;
+pub mod set_config_signers;
+pub mod set_fee_splits;
+pub mod set_fee_tiers;
+pub mod set_guardian;
+pub mod set_recovery_destination;
+pub mod close_config;
+pub mod update_authority;
+pub mod accept_authority;
+pub mod cancel_authority_update;
 pub mod register_provider;
+pub mod register_providers_batch;
+pub mod close_vault;
+pub mod sweep_dust;
+pub mod get_vault_status;
+pub mod simulate_settle;
+pub mod create_vault_native;
+pub mod settle_batch_native;
+pub mod withdraw_native;
+pub mod close_vault_native;
+pub mod deposit;
+pub mod contribute;
+pub mod set_delegate;
+pub mod set_subscription;
+pub mod commit_amount;
+pub mod accrue;
+pub mod transfer_vault_ownership;
+pub mod accept_vault_ownership;
+pub mod init_nonce_bitmap;
+pub mod init_provider_earnings;
+pub mod claim_earnings;
+pub mod clawback;
+pub mod migrate_account;
+pub mod set_provider_fee;
+pub mod pause_provider;
+pub mod close_provider;
+pub mod update_provider_metadata;
+pub mod add_destination;
+pub mod remove_destination;
+pub mod propose_destination;
+pub mod apply_destination;
+pub mod veto_destination;
+pub mod transfer_provider_authority;
+pub mod accept_provider_authority;
+pub mod commit_settlement_root;
+pub mod settle_with_proof;
 
 pub use create_vault::*;
+pub use create_vault_idempotent::*;
 pub use settle_batch::*;
+pub use settle_batch_many::*;
+pub use pay_once::*;
+pub use propose_settlement::*;
+pub use finalize_settlement::*;
+pub use cancel_settlement::*;
 pub use withdraw::*;
+pub use relay_withdraw::*;
+pub use request_withdraw::*;
+pub use cancel_withdraw::*;
+pub use refund_expired::*;
 pub use emergency_pause::*;
+pub use emergency_drain::*;
+pub use propose_fee_increase::*;
+pub use apply_fee_increase::*;
+pub use propose_unpause::*;
+pub use apply_unpause::*;
 pub use init_config::*;
+pub use update_config::*;
+pub use set_config_signers::*;
+pub use set_fee_splits::*;
+pub use set_fee_tiers::*;
+pub use set_guardian::*;
+pub use set_recovery_destination::*;
+pub use close_config::*;
+pub use update_authority::*;
+pub use accept_authority::*;
+pub use cancel_authority_update::*;
 pub use register_provider::*;
+pub use register_providers_batch::*;
+pub use close_vault::*;
+pub use sweep_dust::*;
+pub use get_vault_status::*;
+pub use simulate_settle::*;
+pub use create_vault_native::*;
+pub use settle_batch_native::*;
+pub use withdraw_native::*;
+pub use close_vault_native::*;
+pub use deposit::*;
+pub use contribute::*;
+pub use set_delegate::*;
+pub use set_subscription::*;
+pub use commit_amount::*;
+pub use accrue::*;
+pub use transfer_vault_ownership::*;
+pub use accept_vault_ownership::*;
+pub use init_nonce_bitmap::*;
+pub use init_provider_earnings::*;
+pub use claim_earnings::*;
+pub use clawback::*;
+pub use migrate_account::*;
+pub use set_provider_fee::*;
+pub use pause_provider::*;
+pub use close_provider::*;
+pub use update_provider_metadata::*;
+pub use add_destination::*;
+pub use remove_destination::*;
+pub use propose_destination::*;
+pub use apply_destination::*;
+pub use veto_destination::*;
+pub use transfer_provider_authority::*;
+pub use accept_provider_authority::*;
+pub use commit_settlement_root::*;
+pub use settle_with_proof::*;
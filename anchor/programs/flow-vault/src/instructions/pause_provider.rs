@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Provider};
+use crate::events::ProviderPauseUpdated;
+
+/// Lets a provider halt settlements to itself (e.g. during a key rotation)
+/// without touching the protocol-wide emergency pause.
+pub fn handler(ctx: Context<PauseProvider>, paused: bool) -> Result<()> {
+    let provider = &mut ctx.accounts.provider;
+    provider.paused = paused;
+    msg!("Provider pause set to {} by authority", paused);
+
+    emit!(ProviderPauseUpdated {
+        provider: provider.key(),
+        authority: provider.authority,
+        paused,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct PauseProvider<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,109 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::{GlobalConfig, Provider};
+use crate::errors::FlowError;
+use crate::events::PaymentSettled;
+use super::create_vault::ensure_supported_mint;
+use super::settle_batch::fee_shares;
+
+/// Pays a provider straight from the payer's token account, for one-off
+/// payments that don't justify opening a vault. The fee is shared out as for
+/// `settle_batch`, between the provider's `referrer` and the fee destination
+/// or the fee splits' token accounts in `remaining_accounts`, and the rest
+/// goes to the provider's registered destination; no account is created
+/// along the way, so nothing is left to close.
+pub fn handler<'info>(ctx: Context<'_, '_, 'info, 'info, PayOnce<'info>>, amount: u64) -> Result<()> {
+    let split = ctx
+        .accounts
+        .global_config
+        .check_direct_payment(&ctx.accounts.provider, amount)?;
+    ensure_supported_mint(&ctx.accounts.token_mint)?;
+    if ctx.accounts.destination.is_frozen() {
+        return err!(FlowError::DestinationUnavailable);
+    }
+
+    transfer(&ctx, ctx.accounts.destination.to_account_info(), split.net_to_provider)?;
+    for (to, fee) in fee_shares(
+        &ctx.accounts.global_config,
+        &ctx.accounts.provider,
+        split.fee_amount,
+        ctx.accounts.referrer.as_ref().map(|referrer| referrer.to_account_info()),
+        ctx.accounts.fee_destination.to_account_info(),
+        ctx.remaining_accounts,
+        false,
+    )? {
+        if fee > 0 {
+            transfer(&ctx, to, fee)?;
+        }
+    }
+
+    let config = &mut ctx.accounts.global_config;
+    config.record_settlement(amount, split.fee_amount)?;
+    emit!(PaymentSettled {
+        payer: ctx.accounts.payer.key(),
+        provider: ctx.accounts.provider.key(),
+        destination: ctx.accounts.destination.key(),
+        gross_amount: amount,
+        fee_amount: split.fee_amount,
+        net_to_provider: split.net_to_provider,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+fn transfer<'info>(
+    ctx: &Context<'_, '_, 'info, 'info, PayOnce<'info>>,
+    to: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = TransferChecked {
+        from: ctx.accounts.payer_token_account.to_account_info(),
+        mint: ctx.accounts.token_mint.to_account_info(),
+        to,
+        authority: ctx.accounts.payer.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+    token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)
+}
+
+#[derive(Accounts)]
+pub struct PayOnce<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = payer_token_account.mint == token_mint.key() @ FlowError::MintMismatch
+    )]
+    pub payer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(has_one = destination)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(
+        mut,
+        constraint = destination.mint == token_mint.key() @ FlowError::MintMismatch
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = fee_destination.owner == global_config.fee_destination @ FlowError::InvalidFeeDestination
+    )]
+    pub fee_destination: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// The provider's referrer, paid `referral_bps` of the fee.
+    #[account(
+        mut,
+        constraint = provider.referrer == Some(referrer.key()) @ FlowError::InvalidReferrer,
+        constraint = referrer.mint == token_mint.key() @ FlowError::MintMismatch
+    )]
+    pub referrer: Option<InterfaceAccount<'info, TokenAccount>>,
+}
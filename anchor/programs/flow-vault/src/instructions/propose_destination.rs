@@ -0,0 +1,41 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::TokenAccount;
+use crate::state::{checked_clock, GlobalConfig, Provider};
+use crate::events::DestinationChangeProposed;
+
+/// Queues a new provider destination behind the config timelock, so a
+/// compromised authority key cannot redirect settlements at once. The
+/// provider, the guardian or the config authority can veto it meanwhile.
+pub fn handler(ctx: Context<ProposeDestination>) -> Result<()> {
+    let now = checked_clock()?.unix_timestamp;
+    let destination = ctx.accounts.destination.key();
+    let provider = &mut ctx.accounts.provider;
+    let effective_after =
+        provider.propose_destination(destination, ctx.accounts.global_config.timelock_seconds, now)?;
+
+    emit!(DestinationChangeProposed {
+        provider: provider.key(),
+        destination,
+        effective_after,
+        timestamp: now,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ProposeDestination<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub provider: Account<'info, Provider>,
+
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,45 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, FeeRounding, GlobalConfig};
+use crate::events::FeeIncreaseProposed;
+use super::update_config::signer_keys;
+
+/// Queues a higher protocol fee, a higher or removed `max_fee` cap, or a
+/// rounding that takes more, behind the config timelock. Lowering any of them
+/// stays immediate through `update_config`.
+pub fn handler(
+    ctx: Context<ProposeFeeIncrease>,
+    fee_bps: u16,
+    max_fee: u64,
+    fee_rounding: FeeRounding,
+) -> Result<()> {
+    ctx.accounts
+        .global_config
+        .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+
+    let now = checked_clock()?.unix_timestamp;
+    let config = &mut ctx.accounts.global_config;
+    let effective_after = config.propose_fee_increase(fee_bps, max_fee, fee_rounding, now)?;
+
+    emit!(FeeIncreaseProposed {
+        config: config.key(),
+        fee_bps,
+        effective_after,
+        max_fee,
+        fee_rounding,
+        timestamp: now,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ProposeFeeIncrease<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,83 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions;
+use crate::state::{checked_clock, GlobalConfig, Provider, Vault};
+use crate::errors::FlowError;
+use crate::events::SettlementProposed;
+use super::settle_batch::authorize_settlement;
+
+/// First phase of a disputable settlement: validates it exactly like
+/// `settle_batch` but only reserves the amount until the config's dispute
+/// window has passed.
+pub fn handler(ctx: Context<ProposeSettlement>, amount: u64, nonce: u64) -> Result<()> {
+    // 1. Check the owner or delegate signed the transaction, or a voucher for it
+    authorize_settlement(
+        &ctx.accounts.agent,
+        &ctx.accounts.facilitator,
+        &ctx.accounts.instructions,
+        &ctx.accounts.vault,
+        &ctx.accounts.provider.key(),
+        amount,
+        nonce,
+    )?;
+
+    // 2. Business logic checks
+    let vault = &mut ctx.accounts.vault;
+    let clock = checked_clock()?;
+    vault.check_settlement(
+        &ctx.accounts.global_config,
+        &ctx.accounts.provider,
+        amount,
+        nonce,
+        clock.unix_timestamp,
+    )?;
+
+    // 3. Reserve the amount until the window closes
+    let settle_after = clock
+        .unix_timestamp
+        .checked_add(ctx.accounts.global_config.dispute_seconds)
+        .ok_or(FlowError::MathOverflow)?;
+    vault.propose_settlement(ctx.accounts.provider.key(), amount, nonce, settle_after)?;
+
+    emit!(SettlementProposed {
+        vault: vault.key(),
+        provider: ctx.accounts.provider.key(),
+        amount,
+        nonce,
+        settle_after,
+        timestamp: clock.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ProposeSettlement<'info> {
+    #[account(mut)]
+    pub facilitator: Signer<'info>,
+
+    /// CHECK: The vault owner. Either signs the transaction or has its
+    /// voucher checked against the ed25519 pre-instruction.
+    pub agent: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
+        has_one = agent
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+      mut,
+      seeds = [b"config"],
+      bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub provider: Account<'info, Provider>,
+
+    /// CHECK: The instructions sysvar is used to verify the ed25519 signature.
+    #[account(address = instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+}
@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, GlobalConfig};
+use crate::events::UnpauseProposed;
+use super::update_config::signer_keys;
+
+/// Starts the timelock after which `apply_unpause` can resume the protocol.
+pub fn handler(ctx: Context<ProposeUnpause>) -> Result<()> {
+    ctx.accounts
+        .global_config
+        .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+
+    let now = checked_clock()?.unix_timestamp;
+    let config = &mut ctx.accounts.global_config;
+    let effective_after = config.propose_unpause(now)?;
+
+    emit!(UnpauseProposed {
+        config: config.key(),
+        effective_after,
+        timestamp: now,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ProposeUnpause<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,123 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::{checked_clock, Contribution, GlobalConfig, Vault};
+use crate::errors::FlowError;
+use crate::events::{ContributionRefunded, VaultRefunded};
+
+/// Returns the whole remaining balance of an expired vault to its owner.
+/// A shared vault instead pays one contributor, into `agent_token_account`,
+/// their proportional share of what was left at expiry; anyone may crank
+/// it.
+pub fn handler(ctx: Context<RefundExpired>) -> Result<()> {
+  let clock = checked_clock()?;
+
+  if !ctx.accounts.vault.is_expired(clock.unix_timestamp) {
+    return err!(FlowError::VaultNotExpired);
+  }
+  let contributed = match ctx.accounts.contribution.as_mut() {
+    Some(contribution) => {
+      require_keys_eq!(
+        ctx.accounts.agent_token_account.owner,
+        contribution.depositor,
+        FlowError::Unauthorized
+      );
+      Some(std::mem::take(&mut contribution.amount))
+    }
+    None if ctx.accounts.vault.shared => return err!(FlowError::ContributionRequired),
+    None => None,
+  };
+  let amount = match contributed {
+    Some(contributed) => ctx.accounts.vault.refund_share(contributed)?,
+    None => ctx.accounts.vault.balance()?,
+  };
+  if amount == 0 {
+    return err!(FlowError::ZeroAmount);
+  }
+
+  let vault = &ctx.accounts.vault;
+  let seeds = &[
+    b"vault",
+    vault.creator.as_ref(),
+    vault.session_seed(),
+    &[vault.bump],
+  ];
+  let signer_seeds = &[&seeds[..]];
+
+  let cpi_accounts = TransferChecked {
+    from: ctx.accounts.vault_token_account.to_account_info(),
+    mint: ctx.accounts.token_mint.to_account_info(),
+    to: ctx.accounts.agent_token_account.to_account_info(),
+    authority: vault.to_account_info(),
+  };
+  let cpi_program = ctx.accounts.token_program.to_account_info();
+  let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+  token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+  let vault = &mut ctx.accounts.vault;
+  vault.deposit_amount = vault.deposit_amount.checked_sub(amount).ok_or(FlowError::MathOverflow)?;
+
+  match (contributed, ctx.accounts.contribution.as_ref()) {
+    (Some(contributed), Some(contribution)) => emit!(ContributionRefunded {
+      vault: vault.key(),
+      depositor: contribution.depositor,
+      contributed,
+      amount,
+      timestamp: clock.unix_timestamp,
+      seq: ctx.accounts.global_config.next_event_seq()?,
+    }),
+    _ => emit!(VaultRefunded {
+      vault: vault.key(),
+      agent: vault.agent,
+      amount,
+      timestamp: clock.unix_timestamp,
+      seq: ctx.accounts.global_config.next_event_seq()?,
+    }),
+  }
+
+  Ok(())
+}
+
+#[derive(Accounts)]
+pub struct RefundExpired<'info> {
+  #[account(mut)]
+  pub agent: Signer<'info>,
+
+  #[account(
+    mut,
+    seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+    bump = vault.bump,
+    constraint = vault.shared || vault.agent == agent.key() @ FlowError::Unauthorized
+  )]
+  pub vault: Account<'info, Vault>,
+
+  /// The contributor being refunded; required for shared vaults.
+  #[account(
+    mut,
+    seeds = [b"contribution", vault.key().as_ref(), contribution.depositor.as_ref()],
+    bump = contribution.bump,
+    has_one = vault
+  )]
+  pub contribution: Option<Account<'info, Contribution>>,
+
+  #[account(
+    mut,
+    seeds = [b"vault_token_account", vault.creator.as_ref(), vault.session_seed()],
+    bump,
+    constraint = vault_token_account.key() == vault.vault_token_account
+  )]
+  pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+  #[account(mut)]
+  pub agent_token_account: InterfaceAccount<'info, TokenAccount>,
+
+  #[account(address = vault.token_mint)]
+  pub token_mint: InterfaceAccount<'info, Mint>,
+
+  #[account(
+    constraint = vault.uses_token_program(&token_program.key()) @ FlowError::InvalidTokenProgram
+  )]
+  pub token_program: Interface<'info, TokenInterface>,
+
+  #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+  pub global_config: Account<'info, GlobalConfig>,
+}
@@ -1,16 +1,76 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::TokenAccount;
-use crate::state::{PaymentProtocol, Provider};
+use anchor_lang::system_program;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use crate::state::{GlobalConfig, PaymentProtocol, Provider};
+use crate::errors::FlowError;
+use crate::events::ProviderRegistered;
+
+pub fn handler(
+  ctx: Context<RegisterProvider>,
+  visa_merchant_id: Option<String>,
+  protocol: PaymentProtocol,
+  name: String,
+  uri: String,
+  referrer: Option<Pubkey>,
+) -> Result<()> {
+  Provider::validate_metadata(&name, &uri)?;
+  let registration_fee = ctx.accounts.global_config.registration_fee;
+  collect_registration_fee(
+    registration_fee,
+    &ctx.accounts.authority,
+    &ctx.accounts.fee_destination,
+    &ctx.accounts.system_program,
+  )?;
 
-pub fn handler(ctx: Context<RegisterProvider>, visa_merchant_id: Option<String>, protocol: PaymentProtocol) -> Result<()> {
   let provider = &mut ctx.accounts.provider;
   provider.authority = ctx.accounts.authority.key();
   provider.destination = ctx.accounts.destination.key();
   provider.protocol = protocol;
   provider.visa_merchant_id = visa_merchant_id;
+  provider.name = name;
+  provider.uri = uri;
+  provider.referrer = referrer;
+  provider.version = Provider::VERSION;
+  provider.bump = ctx.bumps.provider;
+
+  emit!(ProviderRegistered {
+    provider: provider.key(),
+    authority: provider.authority,
+    destination: provider.destination,
+    protocol: provider.protocol.clone(),
+    visa_merchant_id: provider.visa_merchant_id.clone(),
+    name: provider.name.clone(),
+    uri: provider.uri.clone(),
+    referrer,
+    timestamp: Clock::get()?.unix_timestamp,
+    registration_fee,
+    seq: ctx.accounts.global_config.next_event_seq()?,
+  });
   Ok(())
 }
 
+/// Moves `fee` lamports from `payer` to the config's fee destination.
+pub(crate) fn collect_registration_fee<'info>(
+  fee: u64,
+  payer: &Signer<'info>,
+  fee_destination: &UncheckedAccount<'info>,
+  system_program: &Program<'info, System>,
+) -> Result<()> {
+  if fee == 0 {
+    return Ok(());
+  }
+  if payer.lamports() < fee {
+    return err!(FlowError::InsufficientRegistrationFee);
+  }
+
+  let cpi_accounts = system_program::Transfer {
+    from: payer.to_account_info(),
+    to: fee_destination.to_account_info(),
+  };
+  let cpi_ctx = CpiContext::new(system_program.to_account_info(), cpi_accounts);
+  system_program::transfer(cpi_ctx, fee)
+}
+
 #[derive(Accounts)]
 pub struct RegisterProvider<'info> {
   #[account(mut)]
@@ -25,7 +85,24 @@ pub struct RegisterProvider<'info> {
   )]
   pub provider: Account<'info, Provider>,
 
-  pub destination: Account<'info, TokenAccount>,
+  #[account(
+    owner = token_program.key() @ FlowError::TokenProgramMismatch
+  )]
+  pub destination: InterfaceAccount<'info, TokenAccount>,
+
+  /// Token program the provider will be settled under; `destination` must
+  /// belong to it.
+  pub token_program: Interface<'info, TokenInterface>,
+
+  #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+  pub global_config: Account<'info, GlobalConfig>,
+
+  /// CHECK: Only receives lamports; pinned to the config's fee destination.
+  #[account(
+    mut,
+    address = global_config.fee_destination @ FlowError::InvalidFeeDestination
+  )]
+  pub fee_destination: UncheckedAccount<'info>,
 
   pub system_program: Program<'info, System>,
-}
\ No newline at end of file
+}
@@ -0,0 +1,164 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Allocate, Assign, CreateAccount, Transfer};
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use crate::state::{GlobalConfig, PaymentProtocol, Provider};
+use crate::errors::FlowError;
+use crate::events::ProviderRegistered;
+use super::register_provider::collect_registration_fee;
+
+/// Upper bound on providers per call, keeping the batch inside the compute
+/// budget. Also the width of the returned bitmap.
+pub const MAX_BATCH_PROVIDERS: usize = 8;
+
+/// Accounts each provider takes from `remaining_accounts`, in order:
+/// authority (signer), provider PDA, destination token account.
+const ACCOUNTS_PER_PROVIDER: usize = 3;
+
+/// Registers several providers at once, with `payer` covering the rent and
+/// the registration fee of each one created. Each authority still signs for
+/// its own provider. Providers that already exist are skipped rather than
+/// aborting the batch; malformed accounts still abort. Metadata starts empty
+/// and can be filled in with `update_provider_metadata`. Returns a bitmap with
+/// bit `i` set when provider `i` was created.
+pub fn handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, RegisterProvidersBatch<'info>>,
+    protocol: PaymentProtocol,
+) -> Result<u8> {
+    let accounts = ctx.remaining_accounts;
+    if accounts.len() > MAX_BATCH_PROVIDERS * ACCOUNTS_PER_PROVIDER {
+        return err!(FlowError::InputTooLarge);
+    }
+    if accounts.len() % ACCOUNTS_PER_PROVIDER != 0 {
+        return err!(FlowError::BatchLengthMismatch);
+    }
+
+    let timestamp = Clock::get()?.unix_timestamp;
+    let registration_fee = ctx.accounts.global_config.registration_fee;
+    let mut registered: u8 = 0;
+
+    for (i, accounts) in accounts.chunks(ACCOUNTS_PER_PROVIDER).enumerate() {
+        let (authority, provider_info, destination) = (&accounts[0], &accounts[1], &accounts[2]);
+        if !authority.is_signer {
+            return err!(ErrorCode::AccountNotSigner);
+        }
+        let (provider_key, bump) =
+            Pubkey::find_program_address(&[b"provider", authority.key.as_ref()], ctx.program_id);
+        require_keys_eq!(provider_info.key(), provider_key, ErrorCode::ConstraintSeeds);
+        if !provider_info.is_writable {
+            return err!(ErrorCode::ConstraintMut);
+        }
+        require_keys_eq!(
+            *destination.owner,
+            ctx.accounts.token_program.key(),
+            FlowError::TokenProgramMismatch
+        );
+        InterfaceAccount::<TokenAccount>::try_from(destination)?;
+
+        if provider_info.owner == ctx.program_id {
+            msg!("Provider {} already registered; skipped", i);
+            continue;
+        }
+        create_provider_account(&ctx, provider_info, authority.key, bump)?;
+
+        let provider = Provider {
+            authority: authority.key(),
+            destination: destination.key(),
+            protocol: protocol.clone(),
+            version: Provider::VERSION,
+            bump,
+            ..Default::default()
+        };
+        provider.try_serialize(&mut &mut provider_info.try_borrow_mut_data()?[..])?;
+        registered |= 1 << i;
+
+        emit!(ProviderRegistered {
+            provider: provider_key,
+            authority: provider.authority,
+            destination: provider.destination,
+            protocol: provider.protocol,
+            visa_merchant_id: None,
+            timestamp,
+            name: String::new(),
+            uri: String::new(),
+            referrer: None,
+            registration_fee,
+            seq: ctx.accounts.global_config.next_event_seq()?,
+        });
+    }
+
+    let total_fee = registration_fee
+        .checked_mul(u64::from(registered.count_ones()))
+        .ok_or(FlowError::MathOverflow)?;
+    collect_registration_fee(
+        total_fee,
+        &ctx.accounts.payer,
+        &ctx.accounts.fee_destination,
+        &ctx.accounts.system_program,
+    )?;
+    Ok(registered)
+}
+
+/// Allocates the provider PDA the way `init` would, topping up any lamports
+/// already sent to the address instead of failing on them.
+fn create_provider_account<'info>(
+    ctx: &Context<'_, '_, 'info, 'info, RegisterProvidersBatch<'info>>,
+    provider_info: &AccountInfo<'info>,
+    authority: &Pubkey,
+    bump: u8,
+) -> Result<()> {
+    let seeds: &[&[u8]] = &[b"provider", authority.as_ref(), &[bump]];
+    let signer = &[seeds];
+    let payer = ctx.accounts.payer.to_account_info();
+    let system_program = ctx.accounts.system_program.to_account_info();
+    let rent = Rent::get()?.minimum_balance(Provider::LEN);
+
+    if provider_info.lamports() == 0 {
+        let cpi_accounts = CreateAccount {
+            from: payer,
+            to: provider_info.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(system_program, cpi_accounts, signer);
+        return system_program::create_account(cpi_ctx, rent, Provider::LEN as u64, ctx.program_id);
+    }
+
+    let shortfall = rent.saturating_sub(provider_info.lamports());
+    if shortfall > 0 {
+        let cpi_accounts = Transfer {
+            from: payer,
+            to: provider_info.clone(),
+        };
+        let cpi_ctx = CpiContext::new(system_program.clone(), cpi_accounts);
+        system_program::transfer(cpi_ctx, shortfall)?;
+    }
+    let cpi_accounts = Allocate {
+        account_to_allocate: provider_info.clone(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(system_program.clone(), cpi_accounts, signer);
+    system_program::allocate(cpi_ctx, Provider::LEN as u64)?;
+    let cpi_accounts = Assign {
+        account_to_assign: provider_info.clone(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(system_program, cpi_accounts, signer);
+    system_program::assign(cpi_ctx, ctx.program_id)
+}
+
+#[derive(Accounts)]
+pub struct RegisterProvidersBatch<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// CHECK: Only receives lamports; pinned to the config's fee destination.
+    #[account(
+        mut,
+        address = global_config.fee_destination @ FlowError::InvalidFeeDestination
+    )]
+    pub fee_destination: UncheckedAccount<'info>,
+
+    /// Token program every provider in the batch will be settled under.
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
@@ -0,0 +1,93 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::state::{GlobalConfig, Vault};
+use crate::errors::FlowError;
+use super::settle_batch::verify_preceding_signature;
+use super::withdraw::transfer_out;
+
+/// Withdraws on the owner's behalf so a relayer can pay the transaction fee.
+/// The owner signs `(vault, created_slot, amount, recipient, nonce)`
+/// off-chain and the relayer submits it behind an ed25519 verification of
+/// that approval. The nonce comes from the same window as settlement vouchers, so an approval
+/// can only be used once.
+pub fn handler(ctx: Context<RelayWithdraw>, amount: u64, nonce: u64) -> Result<()> {
+    let accounts = ctx.accounts;
+    let message = withdraw_message(&accounts.vault, amount, &accounts.recipient.key(), nonce);
+    verify_preceding_signature(&accounts.instructions, &accounts.agent.key(), &message)?;
+    accounts.vault.check_nonce(nonce)?;
+    accounts.vault.use_nonce(nonce);
+
+    transfer_out(
+        &mut accounts.vault,
+        &mut accounts.global_config,
+        &accounts.vault_token_account,
+        accounts.recipient.to_account_info(),
+        &accounts.token_mint,
+        &accounts.token_program,
+        amount,
+    )
+}
+
+fn withdraw_message(vault: &Account<Vault>, amount: u64, recipient: &Pubkey, nonce: u64) -> Vec<u8> {
+    let mut message = Vec::new();
+    message.extend_from_slice(b"X402_FLOW_WITHDRAW");
+    message.extend_from_slice(&vault.key().to_bytes());
+    message.extend_from_slice(&vault.created_slot.to_le_bytes());
+    message.extend_from_slice(&amount.to_le_bytes());
+    message.extend_from_slice(&recipient.to_bytes());
+    message.extend_from_slice(&nonce.to_le_bytes());
+    message
+}
+
+#[derive(Accounts)]
+pub struct RelayWithdraw<'info> {
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+
+    /// CHECK: The vault owner; its approval is checked against the ed25519
+    /// pre-instruction.
+    pub agent: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
+        has_one = agent
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_token_account", vault.creator.as_ref(), vault.session_seed()],
+        bump,
+        constraint = vault_token_account.key() == vault.vault_token_account
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token account named in the owner's approval.
+    #[account(
+        mut,
+        constraint = recipient.mint == vault.token_mint @ FlowError::MintMismatch
+    )]
+    pub recipient: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = vault.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = vault.uses_token_program(&token_program.key()) @ FlowError::InvalidTokenProgram
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// CHECK: The instructions sysvar is used to verify the ed25519 signature.
+    #[account(address = instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+}
@@ -0,0 +1,46 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, GlobalConfig, Provider};
+use crate::errors::FlowError;
+use crate::events::ProviderDestinationRemoved;
+use super::update_config::signer_keys;
+
+/// Withdraws a previously approved settlement destination. While an entry is
+/// still waiting out its delay, the guardian or the config authority may
+/// remove it too, as with `veto_destination`.
+pub fn handler(ctx: Context<RemoveDestination>, destination: Pubkey) -> Result<()> {
+    let now = checked_clock()?.unix_timestamp;
+    let signer = ctx.accounts.signer.key();
+    let config = &ctx.accounts.global_config;
+    if signer != ctx.accounts.provider.authority {
+        if !ctx.accounts.provider.is_pending_destination(&destination, now) {
+            return err!(FlowError::Unauthorized);
+        }
+        if !config.is_guardian(&signer) {
+            config.require_authority(&signer_keys(&ctx.accounts.signer, ctx.remaining_accounts))?;
+        }
+    }
+
+    let provider = &mut ctx.accounts.provider;
+    provider.remove_destination(&destination)?;
+
+    emit!(ProviderDestinationRemoved {
+        provider: provider.key(),
+        destination,
+        timestamp: now,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct RemoveDestination<'info> {
+    /// The provider authority, or for a pending entry the guardian or config
+    /// authority.
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
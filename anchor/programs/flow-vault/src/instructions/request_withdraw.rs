@@ -0,0 +1,37 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, GlobalConfig, Vault};
+use crate::events::WithdrawalRequested;
+
+/// Starts the vault's withdrawal timelock; `withdraw` can take up to `amount`
+/// once it has elapsed.
+pub fn handler(ctx: Context<RequestWithdraw>, amount: u64) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    let now = checked_clock()?.unix_timestamp;
+    let unlock_at = vault.request_withdraw(amount, now)?;
+
+    emit!(WithdrawalRequested {
+        vault: vault.key(),
+        agent: vault.agent,
+        amount,
+        unlock_at,
+        timestamp: now,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct RequestWithdraw<'info> {
+    pub agent: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
+        has_one = agent
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,36 @@
+use anchor_lang::prelude::*;
+use crate::state::GlobalConfig;
+use crate::events::ConfigSignersUpdated;
+use super::update_config::signer_keys;
+
+/// Moves the config between single-admin and N-of-M multisig control. The
+/// current authority, not the new one, has to approve the change.
+pub fn handler(ctx: Context<SetConfigSigners>, signers: Vec<Pubkey>, threshold: u8) -> Result<()> {
+    ctx.accounts
+        .global_config
+        .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+
+    let config = &mut ctx.accounts.global_config;
+    config.set_signers(&signers, threshold)?;
+
+    emit!(ConfigSignersUpdated {
+        config: config.key(),
+        signers,
+        threshold,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetConfigSigners<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Vault};
+use crate::events::VaultDelegateUpdated;
+
+/// Lets the owner name a key that may settle from the vault without a voucher.
+/// Passing the default pubkey revokes it.
+pub fn handler(ctx: Context<SetDelegate>, delegate: Pubkey) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    vault.delegate = delegate;
+
+    emit!(VaultDelegateUpdated {
+        vault: vault.key(),
+        agent: vault.agent,
+        delegate,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetDelegate<'info> {
+    pub agent: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
+        has_one = agent
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
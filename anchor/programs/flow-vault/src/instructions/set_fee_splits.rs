@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::state::{FeeSplit, GlobalConfig};
+use crate::events::FeeSplitsUpdated;
+use super::update_config::signer_keys;
+
+/// Replaces the recipients `settle_batch` divides the protocol fee across.
+/// An empty list routes fees back to the single `fee_destination`.
+pub fn handler(ctx: Context<SetFeeSplits>, splits: Vec<FeeSplit>) -> Result<()> {
+    ctx.accounts
+        .global_config
+        .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+
+    let config = &mut ctx.accounts.global_config;
+    config.set_fee_splits(&splits)?;
+
+    emit!(FeeSplitsUpdated {
+        config: config.key(),
+        splits,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetFeeSplits<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
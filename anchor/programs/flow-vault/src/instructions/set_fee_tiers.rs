@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::state::{FeeTier, GlobalConfig};
+use crate::events::FeeTiersUpdated;
+use super::update_config::signer_keys;
+
+/// Replaces the size tiers that discount the protocol fee for larger
+/// settlements. An empty list charges `fee_bps` on every amount.
+pub fn handler(ctx: Context<SetFeeTiers>, tiers: Vec<FeeTier>) -> Result<()> {
+    ctx.accounts
+        .global_config
+        .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+
+    let config = &mut ctx.accounts.global_config;
+    config.set_fee_tiers(&tiers)?;
+
+    emit!(FeeTiersUpdated {
+        config: config.key(),
+        tiers,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetFeeTiers<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::state::GlobalConfig;
+use crate::events::GuardianUpdated;
+use super::update_config::signer_keys;
+
+/// Names the key allowed to pause, but not unpause, the protocol. The
+/// default pubkey removes the guardian.
+pub fn handler(ctx: Context<SetGuardian>, guardian: Pubkey) -> Result<()> {
+    ctx.accounts
+        .global_config
+        .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+
+    let config = &mut ctx.accounts.global_config;
+    config.guardian = guardian;
+
+    emit!(GuardianUpdated {
+        config: config.key(),
+        guardian,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetGuardian<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
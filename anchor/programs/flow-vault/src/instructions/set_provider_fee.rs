@@ -0,0 +1,38 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Provider};
+use crate::events::ProviderFeeUpdated;
+use super::update_config::signer_keys;
+
+/// Fee overrides are negotiated with the protocol, so only the config
+/// authority can set or clear them, and only below `fee_bps`.
+pub fn handler(ctx: Context<SetProviderFee>, fee_bps_override: Option<u16>) -> Result<()> {
+    let config = &ctx.accounts.global_config;
+    config.require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+    config.check_fee_override(fee_bps_override)?;
+
+    let provider = &mut ctx.accounts.provider;
+    provider.fee_bps_override = fee_bps_override;
+
+    emit!(ProviderFeeUpdated {
+        provider: provider.key(),
+        fee_bps_override,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetProviderFee<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub provider: Account<'info, Provider>,
+}
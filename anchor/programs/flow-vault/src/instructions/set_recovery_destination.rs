@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::state::GlobalConfig;
+use crate::events::RecoveryDestinationUpdated;
+use super::update_config::signer_keys;
+
+/// Names the wallet `emergency_drain` sweeps vaults to. The default pubkey
+/// disables drains.
+pub fn handler(ctx: Context<SetRecoveryDestination>, recovery_destination: Pubkey) -> Result<()> {
+    ctx.accounts
+        .global_config
+        .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+
+    let config = &mut ctx.accounts.global_config;
+    config.recovery_destination = recovery_destination;
+
+    emit!(RecoveryDestinationUpdated {
+        config: config.key(),
+        recovery_destination,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetRecoveryDestination<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,44 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, GlobalConfig, Vault};
+use crate::events::SubscriptionUpdated;
+
+/// Lets the owner turn the vault into a metered subscription: `provider` may
+/// settle up to `amount_per_interval` once every `interval_seconds` without a
+/// voucher. A zero interval cancels it.
+pub fn handler(
+    ctx: Context<SetSubscription>,
+    provider: Pubkey,
+    interval_seconds: i64,
+    amount_per_interval: u64,
+) -> Result<()> {
+    let timestamp = checked_clock()?.unix_timestamp;
+    let vault = &mut ctx.accounts.vault;
+    vault.set_subscription(provider, interval_seconds, amount_per_interval, timestamp)?;
+
+    emit!(SubscriptionUpdated {
+        vault: vault.key(),
+        agent: vault.agent,
+        provider: vault.subscription_provider,
+        interval_seconds,
+        amount_per_interval: vault.amount_per_interval,
+        timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetSubscription<'info> {
+    pub agent: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
+        has_one = agent
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
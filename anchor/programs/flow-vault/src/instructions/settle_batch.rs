@@ -1,144 +1,697 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::memo::{self, BuildMemo, Memo};
+use anchor_spl::token_interface::{
+    self, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
 use anchor_lang::solana_program::{
     sysvar::instructions::{self, load_instruction_at_checked},
 };
 use solana_program::ed25519_program;
-use crate::state::{GlobalConfig, Provider, Vault};
+use crate::state::{
+    check_deadline, checked_clock, session_seed, FeeSource, GlobalConfig, NonceBitmap,
+    OwnerVaultCount, Provider, SettlementReceipt, Vault, PAUSE_DEPOSIT,
+};
 use crate::errors::FlowError;
-use crate::events::Settlement;
+use crate::events::{FeeWaived, Settlement, VaultClosed, VaultToppedUp};
+use super::update_config::signer_keys;
 
-pub fn handler(ctx: Context<SettleBatch>, amount: u64, nonce: u64) -> Result<()> {
-    // 1. Verify the ed25519 signature from the pre-instruction
-    verify_signature(&ctx, amount, nonce)?;
+/// Longest memo `settle_batch` will record, in bytes.
+pub const MAX_SETTLEMENT_MEMO_LEN: usize = 64;
 
-    // 2. Business logic checks
-    let vault = &mut ctx.accounts.vault;
-    let clock = Clock::get()?;
-    let config = &ctx.accounts.global_config;
-
-    if amount < config.settle_threshold {
-        return err!(FlowError::ZeroAmount);
+/// Pays `amount` from the vault to the provider's `destination`, less the
+/// protocol fee. The optional accounts redirect the payment or change how the
+/// fee is taken; see their docs. With fee splits configured,
+/// `remaining_accounts` carries one token account per split, in config order,
+/// followed by any multisig co-signers of `fee_waiver_authority`.
+#[allow(clippy::too_many_arguments)]
+pub fn handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SettleBatch<'info>>,
+    amount: u64,
+    nonce: u64,
+    // Guards against a fee change landing first; zero accepts any net amount.
+    min_net_to_provider: u64,
+    // Written through the SPL Memo program so it shows up in the provider's
+    // transaction history.
+    memo: Option<String>,
+    // Stops a transaction that sat unconfirmed from landing late; zero
+    // disables it.
+    deadline_unix: i64,
+    // Another whitelisted destination of the provider's, for just this
+    // settlement.
+    destination_override: Option<Pubkey>,
+    // Under `FeeSource::ProviderPays` the provider gets the whole amount and
+    // the fee is pulled from `provider_fee_account`.
+    fee_from: FeeSource,
+) -> Result<()> {
+    let now = checked_clock()?.unix_timestamp;
+    check_deadline(deadline_unix, now)?;
+    let destination = ctx.accounts.destination.key();
+    require_keys_eq!(
+        destination,
+        destination_override.unwrap_or(ctx.accounts.provider.destination),
+        FlowError::DestinationMismatch
+    );
+    if ctx.accounts.provider.is_pending_destination(&destination, now) {
+        return err!(FlowError::DestinationNotYetAllowed);
     }
-    if nonce != vault.nonce.checked_add(1).ok_or(FlowError::Overflow)? {
-        return err!(FlowError::InvalidNonce);
+    require!(
+        ctx.accounts.provider.is_allowed_destination(&destination, now),
+        FlowError::DestinationNotWhitelisted
+    );
+    // A closed destination is already refused when the accounts load; a
+    // frozen one would only fail inside the transfer CPI. The provider's
+    // earnings account still takes the payment.
+    let redirected = ctx.accounts.provider_earnings.is_some() || ctx.accounts.provider_ata.is_some();
+    require!(
+        redirected || !ctx.accounts.destination.is_frozen(),
+        FlowError::DestinationUnavailable
+    );
+    require!(
+        ctx.accounts.provider_earnings.is_none() || ctx.accounts.provider_ata.is_none(),
+        FlowError::ConflictingPayees
+    );
+    // A downstream vault is only credited when it is actually paid.
+    if ctx.accounts.downstream_vault.is_some() {
+        require!(!redirected, FlowError::DownstreamVaultNotPaid);
+        ctx.accounts.global_config.require_not_paused(PAUSE_DEPOSIT)?;
     }
-    let available_balance = vault.deposit_amount.checked_sub(vault.total_settled).ok_or(FlowError::Overflow)?;
-    if available_balance < amount {
-        return err!(FlowError::InsufficientFunds);
+    require!(
+        memo.as_ref().is_none_or(|memo| memo.len() <= MAX_SETTLEMENT_MEMO_LEN),
+        FlowError::MemoTooLong
+    );
+
+    // 1. Check the owner or delegate signed the transaction, or a voucher for
+    // it. Failing that, the vault's subscription provider may take its
+    // scheduled pull.
+    let clock = checked_clock()?;
+    let provider_key = ctx.accounts.provider.key();
+    let scheduled = match authorize_settlement(
+        &ctx.accounts.agent,
+        &ctx.accounts.facilitator,
+        &ctx.accounts.instructions,
+        &ctx.accounts.vault,
+        &provider_key,
+        amount,
+        nonce,
+    ) {
+        Ok(()) => false,
+        Err(_) if ctx.accounts.vault.has_subscription_for(&provider_key) => {
+            ctx.accounts.vault.check_subscription_pull(amount, clock.unix_timestamp)?;
+            true
+        }
+        Err(err) => return Err(err),
+    };
+
+    // 2. Business logic checks
+    let vault = &mut ctx.accounts.vault;
+    let mut split = match ctx.accounts.nonce_bitmap.as_ref() {
+        Some(bitmap) => {
+            bitmap.load()?.check(nonce)?;
+            vault.check_bitmap_settlement(
+                &ctx.accounts.global_config,
+                &ctx.accounts.provider,
+                amount,
+                clock.unix_timestamp,
+            )?
+        }
+        None => vault.check_settlement(
+            &ctx.accounts.global_config,
+            &ctx.accounts.provider,
+            amount,
+            nonce,
+            clock.unix_timestamp,
+        )?,
+    };
+    let waived_fee = match ctx.accounts.fee_waiver_authority.as_ref() {
+        Some(waiver) => {
+            ctx.accounts
+                .global_config
+                .require_authority(&signer_keys(waiver, ctx.remaining_accounts))?;
+            split.waive_fee()
+        }
+        None => 0,
+    };
+    require_gte!(split.net_to_provider, min_net_to_provider, FlowError::SlippageExceeded);
+    let payout = split.payout(amount, fee_from);
+    if fee_from == FeeSource::ProviderPays && split.fee_amount > 0 {
+        let fee_account = ctx
+            .accounts
+            .provider_fee_account
+            .as_ref()
+            .ok_or(FlowError::ProviderFeeAccountRequired)?;
+        require_gte!(fee_account.amount, split.fee_amount, FlowError::InsufficientFeeFunds);
+        require!(
+            fee_account.delegate == Some(provider_key).into()
+                && fee_account.delegated_amount >= split.fee_amount,
+            FlowError::FeeAccountNotApproved
+        );
     }
 
-    // 3. Perform the token transfer
+    // 3. Perform the token transfer. The guard is written to the account
+    // first so a transfer hook re-entering on this vault sees it.
+    vault.begin_transfer()?;
+    vault.exit(&crate::ID)?;
+    let creator = vault.creator;
+    let session_id = vault.session_id;
     let seeds = &[
         b"vault",
-        vault.agent.as_ref(),
-        &[ctx.bumps.vault],
+        creator.as_ref(),
+        session_seed(&session_id),
+        &[vault.bump],
     ];
     let signer_seeds = &[&seeds[..]];
 
-    let cpi_accounts = Transfer {
+    let payee = match (ctx.accounts.provider_earnings.as_ref(), ctx.accounts.provider_ata.as_ref()) {
+        (Some(earnings), _) => {
+            ctx.accounts.provider.accrue(payout)?;
+            earnings.to_account_info()
+        }
+        (None, Some(ata)) => {
+            require!(!ata.is_frozen(), FlowError::DestinationUnavailable);
+            ata.to_account_info()
+        }
+        (None, None) => ctx.accounts.destination.to_account_info(),
+    };
+    let vault_token_account_key = ctx.accounts.vault_token_account.key();
+    let config = &ctx.accounts.global_config;
+    let mut fee_payouts = fee_shares(
+        config,
+        &ctx.accounts.provider,
+        split.fee_amount,
+        ctx.accounts.referrer.as_ref().map(|referrer| referrer.to_account_info()),
+        ctx.accounts.fee_destination.to_account_info(),
+        ctx.remaining_accounts,
+        false,
+    )?;
+    // The provider PDA spends its fee account as the approved delegate.
+    let provider = &ctx.accounts.provider;
+    let provider_seeds = &[b"provider", provider.authority.as_ref(), &[provider.bump]];
+    let provider_signer_seeds = &[&provider_seeds[..]];
+    let (fee_from_account, fee_authority, fee_signer_seeds) =
+        match ctx.accounts.provider_fee_account.as_ref() {
+            Some(fee_account) if fee_from == FeeSource::ProviderPays => (
+                fee_account.to_account_info(),
+                provider.to_account_info(),
+                provider_signer_seeds,
+            ),
+            _ => (
+                ctx.accounts.vault_token_account.to_account_info(),
+                vault.to_account_info(),
+                signer_seeds,
+            ),
+        };
+    // Fee shares owed to the payee itself, e.g. when the provider's
+    // destination is also the fee destination, join the payout in a single
+    // transfer so each account is credited once.
+    let mut payee_amount = payout;
+    if *fee_from_account.key == vault_token_account_key {
+        let mut other_payouts = Vec::with_capacity(fee_payouts.len());
+        for (to, fee) in fee_payouts {
+            if to.key == payee.key {
+                payee_amount = payee_amount.checked_add(fee).ok_or(FlowError::MathOverflow)?;
+            } else {
+                other_payouts.push((to, fee));
+            }
+        }
+        fee_payouts = other_payouts;
+    }
+    let cpi_accounts = TransferChecked {
         from: ctx.accounts.vault_token_account.to_account_info(),
-        to: ctx.accounts.destination.to_account_info(),
+        mint: ctx.accounts.token_mint.to_account_info(),
+        to: payee.clone(),
         authority: vault.to_account_info(),
     };
     let cpi_program = ctx.accounts.token_program.to_account_info();
     let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
-    token::transfer(cpi_ctx, amount)?;
-
-    // 4. Update vault state
-    vault.total_settled = vault.total_settled.checked_add(amount).ok_or(FlowError::Overflow)?;
-    vault.nonce = nonce;
-    vault.last_settlement_slot = clock.slot;
-
-    // 5. Emit event
-    emit!(Settlement {
-        vault: vault.key(),
-        provider: ctx.accounts.provider.key(),
-        agent: vault.agent,
-        amount,
-        nonce,
-        visa_merchant_id: ctx.accounts.provider.visa_merchant_id.clone(),
-    });
+    token_interface::transfer_checked(cpi_ctx, payee_amount, ctx.accounts.token_mint.decimals)?;
+
+    // Micro-payments can round a fee share down to zero; skip the empty CPI.
+    for (to, fee) in fee_payouts {
+        if fee == 0 {
+            continue;
+        }
+        let cpi_accounts = TransferChecked {
+            from: fee_from_account.clone(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to,
+            authority: fee_authority.clone(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, fee_signer_seeds);
+        token_interface::transfer_checked(cpi_ctx, fee, ctx.accounts.token_mint.decimals)?;
+    }
+    vault.end_transfer();
+
+    // 4. Update vault state and protocol totals
+    let vault_fee = amount.checked_sub(payout).ok_or(FlowError::MathOverflow)?;
+    match ctx.accounts.nonce_bitmap.as_ref() {
+        Some(bitmap) => {
+            vault.record_claim(amount, vault_fee, &clock)?;
+            bitmap.load_mut()?.mark_used(nonce);
+        }
+        None => vault.record_settlement(amount, vault_fee, nonce, &clock)?,
+    }
+    if scheduled {
+        vault.record_subscription_pull(clock.unix_timestamp)?;
+    }
+    if let Some(downstream) = ctx.accounts.downstream_vault.as_mut() {
+        downstream.credit(payout, clock.unix_timestamp)?;
+        emit!(VaultToppedUp {
+            vault: downstream.key(),
+            agent: downstream.agent,
+            amount: payout,
+            new_balance: downstream.balance()?,
+            timestamp: clock.unix_timestamp,
+            seq: ctx.accounts.global_config.next_event_seq()?,
+        });
+    }
+    ctx.accounts.global_config.record_settlement(amount, split.fee_amount)?;
+
+    if let Some(receipt) = ctx.accounts.receipt.as_mut() {
+        receipt.vault = vault.key();
+        receipt.provider = ctx.accounts.provider.key();
+        receipt.nonce = nonce;
+        receipt.amount = amount;
+        receipt.fee = split.fee_amount;
+        receipt.timestamp = clock.unix_timestamp;
+        receipt.accrued = ctx.accounts.provider_earnings.is_some();
+        receipt.payout = payout;
+    }
+
+    if let Some(memo) = memo {
+        let memo_program = ctx
+            .accounts
+            .memo_program
+            .as_ref()
+            .ok_or(FlowError::MemoProgramRequired)?;
+        let cpi_ctx = CpiContext::new(memo_program.to_account_info(), BuildMemo {});
+        memo::build_memo(cpi_ctx, memo.as_bytes())?;
+    }
+
+    // 5. Emit events
+    if let Some(waiver) = ctx.accounts.fee_waiver_authority.as_ref() {
+        emit!(FeeWaived {
+            vault: vault.key(),
+            provider: ctx.accounts.provider.key(),
+            authority: waiver.key(),
+            nonce,
+            amount,
+            waived_fee,
+            timestamp: clock.unix_timestamp,
+            seq: ctx.accounts.global_config.next_event_seq()?,
+        });
+    }
+    if vault.emits_events() {
+        emit!(Settlement {
+            vault: vault.key(),
+            provider: ctx.accounts.provider.key(),
+            agent: vault.agent,
+            amount,
+            nonce,
+            visa_merchant_id: ctx.accounts.provider.visa_merchant_id.clone(),
+            gross_amount: amount,
+            fee_amount: split.fee_amount,
+            net_to_provider: split.net_to_provider,
+            timestamp: clock.unix_timestamp,
+            destination: payee.key(),
+            remaining_balance: vault.balance()?,
+            remaining_before_cap: vault.settle_capacity(&ctx.accounts.global_config)?,
+            fee_from,
+            seq: ctx.accounts.global_config.next_event_seq()?,
+        });
+    }
+
+    // 6. Return the rent of a drained vault to its owner. A pending
+    // settlement still needs the vault, and tokens sent to the vault token
+    // account outside a deposit would otherwise block the close.
+    ctx.accounts.vault_token_account.reload()?;
+    if vault.balance()? == 0
+        && !vault.has_pending_settlement()
+        && ctx.accounts.vault_token_account.amount == 0
+    {
+        let cpi_accounts = CloseAccount {
+            account: ctx.accounts.vault_token_account.to_account_info(),
+            destination: ctx.accounts.agent.to_account_info(),
+            authority: vault.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+        token_interface::close_account(cpi_ctx)?;
+        vault.close(ctx.accounts.agent.to_account_info())?;
+        ctx.accounts.global_config.vault_closed();
+        OwnerVaultCount::release(&ctx.accounts.owner_vault_count)?;
+
+        emit!(VaultClosed {
+            vault: vault.key(),
+            agent: vault.agent,
+            timestamp: clock.unix_timestamp,
+            seq: ctx.accounts.global_config.next_event_seq()?,
+        });
+    }
 
     Ok(())
 }
 
-fn verify_signature(ctx: &Context<SettleBatch>, amount: u64, nonce: u64) -> Result<()> {
-    let ix_sysvar = &ctx.accounts.instructions;
+/// The vault owner authorizes a settlement either by co-signing the
+/// transaction or, so facilitators can settle without them, with an ed25519
+/// voucher verified in the preceding instruction. A facilitator that is the
+/// vault's delegate needs neither. Shared by the SPL and native settlement
+/// paths.
+pub(crate) fn authorize_settlement(
+    agent: &AccountInfo,
+    facilitator: &Signer,
+    ix_sysvar: &AccountInfo,
+    vault: &Account<Vault>,
+    provider: &Pubkey,
+    amount: u64,
+    nonce: u64,
+) -> Result<()> {
+    if agent.is_signer || vault.is_delegate(facilitator.key) {
+        return Ok(());
+    }
+    verify_settlement_signature(ix_sysvar, agent.key, vault, provider, amount, nonce)
+}
+
+/// Where a settlement's fee goes. A provider registered with a referrer pays
+/// it `referral_bps` of the fee first, through the `referrer` account. The
+/// rest goes to `fee_destination`, or with fee splits configured, each
+/// split's share to its account in `recipients`, in config order. SPL
+/// settlements pass token accounts owned by the split recipients, `native`
+/// ones the recipients' wallets. Shared by every settlement path.
+pub(crate) fn fee_shares<'info>(
+    config: &GlobalConfig,
+    provider: &Provider,
+    fee: u64,
+    referrer: Option<AccountInfo<'info>>,
+    fee_destination: AccountInfo<'info>,
+    recipients: &'info [AccountInfo<'info>],
+    native: bool,
+) -> Result<Vec<(AccountInfo<'info>, u64)>> {
+    let (referral_fee, protocol_fee) = match provider.referrer {
+        Some(_) => config.split_referral(fee),
+        None => (0, fee),
+    };
+    let mut shares = Vec::new();
+    if referral_fee > 0 {
+        let referrer = referrer.ok_or(FlowError::ReferrerAccountRequired)?;
+        shares.push((referrer, referral_fee));
+    }
+    if config.fee_splits().is_empty() {
+        shares.push((fee_destination, protocol_fee));
+        return Ok(shares);
+    }
+    let recipients = recipients
+        .get(..config.fee_splits().len())
+        .ok_or(FlowError::FeeSplitAccountsMissing)?;
+    for ((entry, share), account) in config
+        .fee_splits()
+        .iter()
+        .zip(config.split_fee(protocol_fee))
+        .zip(recipients)
+    {
+        let owner = if native {
+            account.key()
+        } else {
+            InterfaceAccount::<TokenAccount>::try_from(account)?.owner
+        };
+        require_keys_eq!(owner, entry.recipient, FlowError::InvalidFeeDestination);
+        shares.push((account.clone(), share));
+    }
+    Ok(shares)
+}
+
+/// Size of the ed25519 instruction header: the signature count and padding.
+const ED25519_HEADER_LEN: usize = 2;
+/// Size of each signature's offsets entry: seven `u16` fields.
+const ED25519_OFFSETS_LEN: usize = 7 * 2;
+
+/// Checks that the instruction immediately preceding the current one is an
+/// ed25519 verification of `agent` signing the settlement message.
+pub(crate) fn verify_settlement_signature(
+    ix_sysvar: &AccountInfo,
+    agent: &Pubkey,
+    vault: &Account<Vault>,
+    provider: &Pubkey,
+    amount: u64,
+    nonce: u64,
+) -> Result<()> {
+    verify_preceding_signature(ix_sysvar, agent, &settlement_message(vault, provider, amount, nonce))
+}
+
+/// Checks that the instruction immediately preceding the current one is an
+/// ed25519 verification of `agent` signing `message`.
+pub(crate) fn verify_preceding_signature(
+    ix_sysvar: &AccountInfo,
+    agent: &Pubkey,
+    message: &[u8],
+) -> Result<()> {
     let current_ix_index = instructions::load_current_index_checked(ix_sysvar)? as usize;
 
     // The ed25519 instruction must be the immediate preceding instruction
-    if current_ix_index == 0 {
-        return err!(FlowError::InvalidSignature);
-    }
+    require!(current_ix_index > 0, FlowError::VoucherMissing);
     let ed25519_ix_index = current_ix_index - 1;
     let ed25519_ix = load_instruction_at_checked(ed25519_ix_index, ix_sysvar)?;
 
     // Check that the instruction is for the ed25519 program
-    if ed25519_ix.program_id != ed25519_program::ID {
-        return err!(FlowError::InvalidSignature);
-    }
+    require_keys_eq!(ed25519_ix.program_id, ed25519_program::ID, FlowError::VoucherMissing);
 
-    let signed_pubkey = &ed25519_ix.data[16..48];
-    if signed_pubkey != ctx.accounts.agent.key.as_ref() {
-        return err!(FlowError::InvalidSignature);
-    }
+    require!(ed25519_ix_signs(&ed25519_ix.data, agent, message), FlowError::InvalidVoucher);
+    Ok(())
+}
 
-    let message = &ed25519_ix.data[112..];
-    let mut expected_message = Vec::new();
-    expected_message.extend_from_slice(b"X402_FLOW_SETTLE");
-    expected_message.extend_from_slice(&ctx.accounts.vault.key().to_bytes());
-    expected_message.extend_from_slice(&ctx.accounts.provider.key().to_bytes());
-    expected_message.extend_from_slice(&amount.to_le_bytes());
-    expected_message.extend_from_slice(&nonce.to_le_bytes());
+/// Like `verify_settlement_signature`, but accepts a voucher from any ed25519
+/// instruction earlier in the transaction, so one transaction can carry
+/// vouchers for several vaults.
+pub(crate) fn find_settlement_voucher(
+    ix_sysvar: &AccountInfo,
+    agent: &Pubkey,
+    vault: &Account<Vault>,
+    provider: &Pubkey,
+    amount: u64,
+    nonce: u64,
+) -> Result<()> {
+    let current_ix_index = instructions::load_current_index_checked(ix_sysvar)? as usize;
+    let message = settlement_message(vault, provider, amount, nonce);
 
-    if message != expected_message {
-        return err!(FlowError::InvalidSignature);
+    for index in 0..current_ix_index {
+        let ix = load_instruction_at_checked(index, ix_sysvar)?;
+        if ix.program_id == ed25519_program::ID && ed25519_ix_signs(&ix.data, agent, &message) {
+            return Ok(());
+        }
     }
+    err!(FlowError::InvalidVoucher)
+}
 
-    Ok(())
+/// What a voucher signs. The vault's creation slot follows its address, so a
+/// voucher for a closed vault cannot be replayed once the address is reused.
+fn settlement_message(vault: &Account<Vault>, provider: &Pubkey, amount: u64, nonce: u64) -> Vec<u8> {
+    let mut message = Vec::new();
+    message.extend_from_slice(b"X402_FLOW_SETTLE");
+    message.extend_from_slice(&vault.key().to_bytes());
+    message.extend_from_slice(&vault.created_slot.to_le_bytes());
+    message.extend_from_slice(&provider.to_bytes());
+    message.extend_from_slice(&amount.to_le_bytes());
+    message.extend_from_slice(&nonce.to_le_bytes());
+    message
+}
+
+/// Whether ed25519 instruction `data` verifies `agent` signing `message`.
+/// The offsets in the instruction header are followed rather than assumed, so
+/// a verification of some other key or message cannot be passed off as ours.
+fn ed25519_ix_signs(data: &[u8], agent: &Pubkey, message: &[u8]) -> bool {
+    let Some(&num_signatures) = data.first() else {
+        return false;
+    };
+
+    (0..num_signatures as usize).any(|i| {
+        let entry = ED25519_HEADER_LEN + i * ED25519_OFFSETS_LEN;
+        let field = |n: usize| read_u16(data, entry + n * 2);
+        let (
+            Some(signature_ix_index),
+            Some(public_key_offset),
+            Some(public_key_ix_index),
+            Some(message_offset),
+            Some(message_size),
+            Some(message_ix_index),
+        ) = (field(1), field(2), field(3), field(4), field(5), field(6))
+        else {
+            return false;
+        };
+
+        // Every part must live in the ed25519 instruction itself, otherwise
+        // the bytes checked here are not the ones the precompile verified.
+        if [signature_ix_index, public_key_ix_index, message_ix_index]
+            .iter()
+            .any(|&index| index != u16::MAX)
+        {
+            return false;
+        }
+
+        let public_key_offset = public_key_offset as usize;
+        let message_offset = message_offset as usize;
+        data.get(public_key_offset..public_key_offset + 32) == Some(agent.as_ref())
+            && data.get(message_offset..message_offset + message_size as usize) == Some(message)
+    })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
 }
 
 #[derive(Accounts)]
+#[instruction(amount: u64, nonce: u64)]
 pub struct SettleBatch<'info> {
     #[account(mut)]
     pub facilitator: Signer<'info>,
 
-    /// CHECK: This is the agent's public key, not a signer.
-    /// Its signature is checked against the ed25519 pre-instruction.
+    /// CHECK: The vault owner. Either signs the transaction or has its
+    /// voucher checked against the ed25519 pre-instruction. Writable so a
+    /// drained vault's rent can be refunded to it.
+    #[account(mut)]
     pub agent: AccountInfo<'info>,
 
     #[account(
         mut,
-        seeds = [b"vault", agent.key().as_ref()],
-        bump,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump,
         has_one = agent
     )]
     pub vault: Account<'info, Vault>,
 
     #[account(
         mut,
-        seeds = [b"vault_token_account", agent.key().as_ref()],
+        seeds = [b"vault_token_account", vault.creator.as_ref(), vault.session_seed()],
         bump,
         constraint = vault_token_account.key() == vault.vault_token_account
     )]
-    pub vault_token_account: Account<'info, TokenAccount>,
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = vault.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
 
     #[account(
+      mut,
       seeds = [b"config"],
-      bump
+      bump = global_config.bump
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
-    #[account(has_one = destination)]
+    #[account(mut)]
     pub provider: Account<'info, Provider>,
 
-    #[account(mut)]
-    pub destination: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = destination.mint == vault.token_mint @ FlowError::MintMismatch
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
 
-    pub token_program: Program<'info, Token>,
+    #[account(
+        mut,
+        constraint = fee_destination.owner == global_config.fee_destination @ FlowError::InvalidFeeDestination
+    )]
+    pub fee_destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = vault.uses_token_program(&token_program.key()) @ FlowError::InvalidTokenProgram
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
 
     /// CHECK: The instructions sysvar is used to verify the ed25519 signature.
     #[account(address = instructions::ID)]
     pub instructions: AccountInfo<'info>,
+
+    /// Optional on-chain receipt, paid for by the facilitator.
+    #[account(
+        init,
+        payer = facilitator,
+        space = SettlementReceipt::LEN,
+        seeds = [b"receipt", vault.key().as_ref(), &nonce.to_le_bytes()],
+        bump
+    )]
+    pub receipt: Option<Account<'info, SettlementReceipt>>,
+
+    pub system_program: Option<Program<'info, System>>,
+
+    /// Required once the vault has switched to a nonce bitmap.
+    #[account(
+        mut,
+        seeds = [b"nonce_bitmap", vault.key().as_ref()],
+        bump
+    )]
+    pub nonce_bitmap: Option<AccountLoader<'info, NonceBitmap>>,
+
+    /// The provider's earnings account, to accrue the net amount for a later
+    /// `claim_earnings` instead of paying out. Also the way to keep settling
+    /// while `destination` is frozen.
+    #[account(
+        mut,
+        seeds = [b"provider_earnings", provider.key().as_ref()],
+        bump,
+        constraint = provider_earnings.mint == vault.token_mint @ FlowError::MintMismatch
+    )]
+    pub provider_earnings: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Required when a memo is passed.
+    pub memo_program: Option<Program<'info, Memo>>,
+
+    /// The provider's referrer, paid `referral_bps` of the fee ahead of the
+    /// fee destination or splits. Required once the provider has one.
+    #[account(
+        mut,
+        constraint = provider.referrer == Some(referrer.key()) @ FlowError::InvalidReferrer,
+        constraint = referrer.mint == vault.token_mint @ FlowError::MintMismatch
+    )]
+    pub referrer: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Vault that owns `destination`, credited with the net amount so the
+    /// settlement flows straight into it.
+    #[account(
+        mut,
+        seeds = [b"vault", downstream_vault.creator.as_ref(), downstream_vault.session_seed()],
+        bump = downstream_vault.bump,
+        constraint = downstream_vault.key() != vault.key() @ FlowError::DownstreamVaultIsSource,
+        constraint = downstream_vault.vault_token_account == destination.key() @ FlowError::DownstreamVaultMismatch
+    )]
+    pub downstream_vault: Option<Box<Account<'info, Vault>>>,
+
+    /// Provider-owned account the fee is pulled from under
+    /// `FeeSource::ProviderPays`, with the provider PDA approved as delegate.
+    #[account(
+        mut,
+        constraint = provider_fee_account.owner == provider.authority @ FlowError::InvalidFeeAccountOwner,
+        constraint = provider_fee_account.mint == vault.token_mint @ FlowError::MintMismatch
+    )]
+    pub provider_fee_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: The creator's open-vault count, freed up if this settlement
+    /// drains and closes the vault. Checked by `OwnerVaultCount::release`,
+    /// which skips it only for creators whose vaults predate the count.
+    #[account(mut, seeds = [b"owner_vaults", vault.creator.as_ref()], bump)]
+    pub owner_vault_count: UncheckedAccount<'info>,
+
+    /// CHECK: Only used to derive `provider_ata`; pinned to the provider's
+    /// authority.
+    #[account(address = provider.authority @ FlowError::ProviderAuthorityMismatch)]
+    pub provider_authority: Option<UncheckedAccount<'info>>,
+
+    /// The provider authority's associated token account for the vault mint,
+    /// paid instead of `destination`. Its address is derived from the
+    /// authority, mint and token program, so it cannot point anywhere else.
+    /// Created at the facilitator's expense if needed.
+    #[account(
+        init_if_needed,
+        payer = facilitator,
+        associated_token::mint = token_mint,
+        associated_token::authority = provider_authority,
+        associated_token::token_program = token_program
+    )]
+    pub provider_ata: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+
+    /// Config authority waiving this settlement's fee. A multisig
+    /// authority's co-signers follow any fee split accounts.
+    pub fee_waiver_authority: Option<Signer<'info>>,
 }
\ No newline at end of file
@@ -0,0 +1,216 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::{checked_clock, FeeSource, GlobalConfig, Provider, Vault};
+use crate::errors::FlowError;
+use crate::events::Settlement;
+use super::settle_batch::{fee_shares, find_settlement_voucher};
+
+/// Upper bound on settlements per call, keeping the batch inside the compute
+/// budget. Also the width of the returned bitmap.
+pub const MAX_BATCH_SETTLEMENTS: usize = 16;
+
+/// Accounts each settlement takes from `remaining_accounts`, in order:
+/// agent, vault, vault token account. With fee splits configured, one token
+/// account per split follows the last settlement's, as for `settle_batch`.
+const ACCOUNTS_PER_SETTLEMENT: usize = 3;
+
+/// Settles several vaults to one provider. A vault that fails its voucher,
+/// pause, threshold, nonce or balance check is skipped rather than aborting the
+/// batch; malformed accounts still abort. Returns a bitmap with bit `i` set
+/// when settlement `i` went through. A provider registered with a referrer
+/// needs its token account passed as `referrer`, as for `settle_batch`.
+pub fn handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SettleBatchMany<'info>>,
+    amounts: Vec<u64>,
+    nonces: Vec<u64>,
+) -> Result<u16> {
+    if amounts.len() > MAX_BATCH_SETTLEMENTS {
+        return err!(FlowError::InputTooLarge);
+    }
+    let settlement_accounts = amounts.len() * ACCOUNTS_PER_SETTLEMENT;
+    if nonces.len() != amounts.len()
+        || ctx.remaining_accounts.len()
+            != settlement_accounts + ctx.accounts.global_config.fee_splits().len()
+    {
+        return err!(FlowError::BatchLengthMismatch);
+    }
+    let (settlements, fee_recipients) = ctx.remaining_accounts.split_at(settlement_accounts);
+
+    let clock = checked_clock()?;
+    let provider = &ctx.accounts.provider;
+    let mut settled: u16 = 0;
+
+    for (i, accounts) in settlements.chunks(ACCOUNTS_PER_SETTLEMENT).enumerate() {
+        let (agent, vault_info, vault_token_info) = (&accounts[0], &accounts[1], &accounts[2]);
+        let (amount, nonce) = (amounts[i], nonces[i]);
+
+        let mut vault = Account::<Vault>::try_from(vault_info)?;
+        let bump = vault.bump;
+        let vault_key =
+            Pubkey::create_program_address(
+                &[b"vault", vault.creator.as_ref(), vault.session_seed(), &[bump]],
+                ctx.program_id,
+            )
+                .map_err(|_| ErrorCode::ConstraintSeeds)?;
+        require_keys_eq!(vault.key(), vault_key, ErrorCode::ConstraintSeeds);
+        require_keys_eq!(vault.agent, agent.key(), ErrorCode::ConstraintHasOne);
+        require_keys_eq!(vault.token_mint, ctx.accounts.token_mint.key(), ErrorCode::ConstraintAddress);
+        require_keys_eq!(
+            vault.vault_token_account,
+            vault_token_info.key(),
+            ErrorCode::ConstraintAddress
+        );
+        if !vault_info.is_writable || !vault_token_info.is_writable {
+            return err!(ErrorCode::ConstraintMut);
+        }
+        let vault_token_account = InterfaceAccount::<TokenAccount>::try_from(vault_token_info)?;
+        if !vault.uses_token_program(&ctx.accounts.token_program.key()) {
+            return err!(FlowError::InvalidTokenProgram);
+        }
+
+        let checked = if agent.is_signer || vault.is_delegate(ctx.accounts.facilitator.key) {
+            Ok(())
+        } else {
+            find_settlement_voucher(
+                &ctx.accounts.instructions,
+                agent.key,
+                &vault,
+                &provider.key(),
+                amount,
+                nonce,
+            )
+        }
+        .and_then(|_| {
+            vault.check_settlement(
+                &ctx.accounts.global_config,
+                provider,
+                amount,
+                nonce,
+                clock.unix_timestamp,
+            )
+        });
+        let split = match checked {
+            Ok(split) => split,
+            Err(err) => {
+                msg!("Skipping settlement {} for vault {}: {}", i, vault.key(), err);
+                continue;
+            }
+        };
+
+        let seeds = &[b"vault", vault.creator.as_ref(), vault.session_seed(), &[bump]];
+        let signer_seeds = &[&seeds[..]];
+        transfer_from_vault(
+            &ctx,
+            &vault_token_account,
+            vault_info,
+            ctx.accounts.destination.to_account_info(),
+            split.net_to_provider,
+            signer_seeds,
+        )?;
+        // Micro-payments can round a fee share down to zero; skip the empty CPI.
+        for (to, fee) in fee_shares(
+            &ctx.accounts.global_config,
+            provider,
+            split.fee_amount,
+            ctx.accounts.referrer.as_ref().map(|referrer| referrer.to_account_info()),
+            ctx.accounts.fee_destination.to_account_info(),
+            fee_recipients,
+            false,
+        )? {
+            if fee > 0 {
+                transfer_from_vault(&ctx, &vault_token_account, vault_info, to, fee, signer_seeds)?;
+            }
+        }
+
+        vault.record_settlement(amount, split.fee_amount, nonce, &clock)?;
+        vault.exit(ctx.program_id)?;
+        ctx.accounts.global_config.record_settlement(amount, split.fee_amount)?;
+
+        if vault.emits_events() {
+            emit!(Settlement {
+                vault: vault.key(),
+                provider: provider.key(),
+                agent: vault.agent,
+                amount,
+                nonce,
+                visa_merchant_id: provider.visa_merchant_id.clone(),
+                gross_amount: amount,
+                fee_amount: split.fee_amount,
+                net_to_provider: split.net_to_provider,
+                timestamp: clock.unix_timestamp,
+                destination: ctx.accounts.destination.key(),
+                remaining_balance: vault.balance()?,
+                remaining_before_cap: vault.settle_capacity(&ctx.accounts.global_config)?,
+                fee_from: FeeSource::NetFromAmount,
+                seq: ctx.accounts.global_config.next_event_seq()?,
+            });
+        }
+        settled |= 1 << i;
+    }
+
+    Ok(settled)
+}
+
+fn transfer_from_vault<'info>(
+    ctx: &Context<'_, '_, 'info, 'info, SettleBatchMany<'info>>,
+    vault_token_account: &InterfaceAccount<'info, TokenAccount>,
+    vault: &AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let cpi_accounts = TransferChecked {
+        from: vault_token_account.to_account_info(),
+        mint: ctx.accounts.token_mint.to_account_info(),
+        to,
+        authority: vault.clone(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+    token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)
+}
+
+#[derive(Accounts)]
+pub struct SettleBatchMany<'info> {
+    #[account(mut)]
+    pub facilitator: Signer<'info>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(has_one = destination)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(
+        mut,
+        constraint = destination.mint == token_mint.key() @ FlowError::MintMismatch
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = fee_destination.owner == global_config.fee_destination @ FlowError::InvalidFeeDestination
+    )]
+    pub fee_destination: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// CHECK: The instructions sysvar is used to find each vault's voucher.
+    #[account(address = instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+
+    /// The provider's referrer, paid `referral_bps` of the fee.
+    #[account(
+        mut,
+        constraint = provider.referrer == Some(referrer.key()) @ FlowError::InvalidReferrer,
+        constraint = referrer.mint == token_mint.key() @ FlowError::MintMismatch
+    )]
+    pub referrer: Option<InterfaceAccount<'info, TokenAccount>>,
+}
@@ -0,0 +1,144 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions;
+use crate::state::{checked_clock, FeeSource, GlobalConfig, Provider, Vault};
+use crate::errors::FlowError;
+use crate::events::Settlement;
+use super::settle_batch::{authorize_settlement, fee_shares};
+
+/// With fee splits configured, `remaining_accounts` carries each split
+/// recipient's wallet, in config order. A provider registered with a referrer
+/// needs it passed as `referrer`, as for `settle_batch`.
+pub fn handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SettleBatchNative<'info>>,
+    amount: u64,
+    nonce: u64,
+) -> Result<()> {
+    // 1. Check the owner or delegate signed the transaction, or a voucher for it
+    authorize_settlement(
+        &ctx.accounts.agent,
+        &ctx.accounts.facilitator,
+        &ctx.accounts.instructions,
+        &ctx.accounts.vault,
+        &ctx.accounts.provider.key(),
+        amount,
+        nonce,
+    )?;
+
+    // 2. Business logic checks
+    let vault = &mut ctx.accounts.vault;
+    let clock = checked_clock()?;
+    let split = vault.check_settlement(
+        &ctx.accounts.global_config,
+        &ctx.accounts.provider,
+        amount,
+        nonce,
+        clock.unix_timestamp,
+    )?;
+
+    // 3. Move lamports straight off the program-owned vault PDA
+    transfer_lamports(
+        &vault.to_account_info(),
+        &ctx.accounts.destination.to_account_info(),
+        split.net_to_provider,
+    )?;
+    for (to, fee) in fee_shares(
+        &ctx.accounts.global_config,
+        &ctx.accounts.provider,
+        split.fee_amount,
+        ctx.accounts.referrer.as_ref().map(|referrer| referrer.to_account_info()),
+        ctx.accounts.fee_destination.to_account_info(),
+        ctx.remaining_accounts,
+        true,
+    )? {
+        if fee > 0 {
+            transfer_lamports(&vault.to_account_info(), &to, fee)?;
+        }
+    }
+
+    // 4. Update vault state and protocol totals
+    vault.record_settlement(amount, split.fee_amount, nonce, &clock)?;
+    ctx.accounts.global_config.record_settlement(amount, split.fee_amount)?;
+
+    // 5. Emit event
+    if vault.emits_events() {
+        emit!(Settlement {
+            vault: vault.key(),
+            provider: ctx.accounts.provider.key(),
+            agent: vault.agent,
+            amount,
+            nonce,
+            visa_merchant_id: ctx.accounts.provider.visa_merchant_id.clone(),
+            gross_amount: amount,
+            fee_amount: split.fee_amount,
+            net_to_provider: split.net_to_provider,
+            timestamp: clock.unix_timestamp,
+            destination: ctx.accounts.destination.key(),
+            remaining_balance: vault.balance()?,
+            remaining_before_cap: vault.settle_capacity(&ctx.accounts.global_config)?,
+            fee_from: FeeSource::NetFromAmount,
+            seq: ctx.accounts.global_config.next_event_seq()?,
+        });
+    }
+
+    Ok(())
+}
+
+/// Moves lamports out of a program-owned account. Native vaults cannot use a
+/// system transfer because the program, not the system program, owns them.
+pub(crate) fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    let from_balance = from.lamports().checked_sub(amount).ok_or(FlowError::MathOverflow)?;
+    let to_balance = to.lamports().checked_add(amount).ok_or(FlowError::MathOverflow)?;
+    **from.try_borrow_mut_lamports()? = from_balance;
+    **to.try_borrow_mut_lamports()? = to_balance;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SettleBatchNative<'info> {
+    #[account(mut)]
+    pub facilitator: Signer<'info>,
+
+    /// CHECK: The vault owner. Either signs the transaction or has its
+    /// voucher checked against the ed25519 pre-instruction.
+    pub agent: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"native_vault", vault.creator.as_ref()],
+        bump = vault.bump,
+        has_one = agent
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+      mut,
+      seeds = [b"config"],
+      bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub provider: Account<'info, Provider>,
+
+    /// CHECK: Only receives lamports; pinned to the provider's registered
+    /// destination, as on the SPL path. A wrapped-SOL destination takes them
+    /// into its balance with `sync_native`.
+    #[account(mut, address = provider.destination @ FlowError::DestinationMismatch)]
+    pub destination: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        address = global_config.fee_destination @ FlowError::InvalidFeeDestination
+    )]
+    pub fee_destination: SystemAccount<'info>,
+
+    /// CHECK: The instructions sysvar is used to verify the ed25519 signature.
+    #[account(address = instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+
+    /// The provider's referrer, paid `referral_bps` of the fee in lamports.
+    #[account(
+        mut,
+        constraint = provider.referrer == Some(referrer.key()) @ FlowError::InvalidReferrer
+    )]
+    pub referrer: Option<SystemAccount<'info>>,
+}
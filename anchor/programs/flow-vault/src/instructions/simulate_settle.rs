@@ -0,0 +1,45 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, GlobalConfig, NonceBitmap, Provider, SettlePreview, Vault};
+
+/// View instruction: runs the checks `settle_batch` would for `amount` and
+/// `nonce` and returns the resulting split and balance through the
+/// transaction's return data. The owner's authorization is not checked, and
+/// nothing is transferred or recorded.
+pub fn handler(ctx: Context<SimulateSettle>, amount: u64, nonce: u64) -> Result<SettlePreview> {
+    let now = checked_clock()?.unix_timestamp;
+    let vault = &ctx.accounts.vault;
+    let config = &ctx.accounts.global_config;
+    let provider = &ctx.accounts.provider;
+    let split = match ctx.accounts.nonce_bitmap.as_ref() {
+        Some(bitmap) => {
+            bitmap.load()?.check(nonce)?;
+            vault.check_bitmap_settlement(config, provider, amount, now)?
+        }
+        None => vault.check_settlement(config, provider, amount, nonce, now)?,
+    };
+    vault.preview_settlement(config, amount, &split)
+}
+
+#[derive(Accounts)]
+pub struct SimulateSettle<'info> {
+    #[account(
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub provider: Account<'info, Provider>,
+
+    /// Required once the vault has switched to a nonce bitmap.
+    #[account(
+        seeds = [b"nonce_bitmap", vault.key().as_ref()],
+        bump
+    )]
+    pub nonce_bitmap: Option<AccountLoader<'info, NonceBitmap>>,
+}
@@ -0,0 +1,140 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{
+    self, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+use crate::state::{checked_clock, GlobalConfig, OwnerVaultCount, Vault, BPS_DENOMINATOR};
+use crate::errors::FlowError;
+use crate::events::VaultSwept;
+
+/// Share of the reclaimed rent paid to whoever cranks `sweep_dust`, in basis
+/// points; the rest goes to the fee destination.
+pub const SWEEP_KEEPER_BPS: u64 = 1_000;
+
+/// Closes an abandoned vault: one past its expiry whose balance has fallen
+/// below the config's `dust_threshold`, with no settlement or withdrawal
+/// pending. Anyone may crank it. Whatever is left in the vault token
+/// account goes to the fee destination's token account, and the rent of both
+/// accounts is split between the keeper and the fee destination.
+pub fn handler(ctx: Context<SweepDust>) -> Result<()> {
+    let now = checked_clock()?.unix_timestamp;
+    ctx.accounts
+        .vault
+        .sweepable_balance(ctx.accounts.global_config.dust_threshold, now)?;
+
+    let vault = &ctx.accounts.vault;
+    let seeds = &[
+        b"vault",
+        vault.creator.as_ref(),
+        vault.session_seed(),
+        &[vault.bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    // Tokens sent straight to the vault token account are swept along with
+    // the tracked balance, or the account could not be closed.
+    let residual = ctx.accounts.vault_token_account.amount;
+    if residual > 0 {
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.vault_token_account.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.fee_token_account.to_account_info(),
+            authority: vault.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        token_interface::transfer_checked(cpi_ctx, residual, ctx.accounts.token_mint.decimals)?;
+    }
+
+    let cpi_accounts = CloseAccount {
+        account: ctx.accounts.vault_token_account.to_account_info(),
+        destination: vault.to_account_info(),
+        authority: vault.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        cpi_accounts,
+        signer_seeds,
+    );
+    token_interface::close_account(cpi_ctx)?;
+
+    let vault_info = vault.to_account_info();
+    let rent = vault_info.lamports();
+    let keeper_reward = rent
+        .checked_mul(SWEEP_KEEPER_BPS)
+        .ok_or(FlowError::MathOverflow)?
+        / BPS_DENOMINATOR;
+    **vault_info.try_borrow_mut_lamports()? -= keeper_reward;
+    **ctx.accounts.keeper.to_account_info().try_borrow_mut_lamports()? += keeper_reward;
+
+    let (vault_key, agent) = (vault.key(), vault.agent);
+    ctx.accounts.vault.close(ctx.accounts.fee_destination.to_account_info())?;
+    ctx.accounts.global_config.vault_closed();
+    OwnerVaultCount::release(&ctx.accounts.owner_vault_count)?;
+
+    emit!(VaultSwept {
+        vault: vault_key,
+        agent,
+        keeper: ctx.accounts.keeper.key(),
+        residual,
+        keeper_reward,
+        treasury_rent: rent - keeper_reward,
+        timestamp: now,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SweepDust<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_token_account", vault.creator.as_ref(), vault.session_seed()],
+        bump,
+        constraint = vault_token_account.key() == vault.vault_token_account
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = vault.token_mint @ FlowError::MintMismatch)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        constraint = fee_token_account.owner == global_config.fee_destination @ FlowError::InvalidFeeDestination,
+        constraint = fee_token_account.mint == token_mint.key() @ FlowError::MintMismatch
+    )]
+    pub fee_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Only receives lamports; pinned to the config's fee destination.
+    #[account(
+        mut,
+        address = global_config.fee_destination @ FlowError::InvalidFeeDestination
+    )]
+    pub fee_destination: UncheckedAccount<'info>,
+
+    /// CHECK: The creator's open-vault count, freeing a slot under the
+    /// config's cap. Checked by `OwnerVaultCount::release`, which skips it
+    /// only for creators whose vaults predate the count.
+    #[account(mut, seeds = [b"owner_vaults", vault.creator.as_ref()], bump)]
+    pub owner_vault_count: UncheckedAccount<'info>,
+
+    #[account(
+        constraint = vault.uses_token_program(&token_program.key()) @ FlowError::InvalidTokenProgram
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+}
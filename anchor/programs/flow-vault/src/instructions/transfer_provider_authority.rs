@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Provider};
+use crate::events::ProviderAuthorityProposed;
+
+/// First step of an authority rotation. Nothing changes until the new key
+/// accepts, so a mistyped address cannot lock the provider out. Proposing
+/// again replaces the pending key.
+pub fn handler(ctx: Context<TransferProviderAuthority>) -> Result<()> {
+    let provider = &mut ctx.accounts.provider;
+    let new_authority = ctx.accounts.new_authority.key();
+    provider.pending_authority = Some(new_authority);
+
+    emit!(ProviderAuthorityProposed {
+        provider: provider.key(),
+        authority: provider.authority,
+        pending_authority: new_authority,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct TransferProviderAuthority<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub provider: Account<'info, Provider>,
+
+    /// CHECK: Only recorded as the pending authority; it must sign to accept.
+    pub new_authority: UncheckedAccount<'info>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Vault};
+use crate::events::VaultOwnershipProposed;
+
+/// First step of handing a vault to another wallet. The current owner keeps
+/// full control until the new one accepts; proposing again replaces the
+/// pending key.
+pub fn handler(ctx: Context<TransferVaultOwnership>) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    let new_agent = ctx.accounts.new_agent.key();
+    vault.pending_agent = Some(new_agent);
+
+    emit!(VaultOwnershipProposed {
+        vault: vault.key(),
+        agent: vault.agent,
+        pending_agent: new_agent,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct TransferVaultOwnership<'info> {
+    pub agent: Signer<'info>,
+
+    #[account(mut, has_one = agent)]
+    pub vault: Account<'info, Vault>,
+
+    /// CHECK: Only recorded as the pending owner; it must sign to accept.
+    pub new_agent: UncheckedAccount<'info>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
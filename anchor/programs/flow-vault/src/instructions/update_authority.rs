@@ -0,0 +1,42 @@
+use anchor_lang::prelude::*;
+use crate::state::GlobalConfig;
+use crate::events::ConfigAuthorityProposed;
+use super::update_config::signer_keys;
+
+/// First step of rotating the config admin. The current authority keeps full
+/// control until the new key signs `accept_authority`, and can withdraw the
+/// proposal with `cancel_authority_update`. Proposing again replaces the
+/// pending key.
+pub fn handler(ctx: Context<UpdateAuthority>) -> Result<()> {
+    ctx.accounts
+        .global_config
+        .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+
+    let config = &mut ctx.accounts.global_config;
+    let new_authority = ctx.accounts.new_authority.key();
+    config.pending_admin = Some(new_authority);
+
+    emit!(ConfigAuthorityProposed {
+        config: config.key(),
+        authority: config.admin,
+        pending_authority: new_authority,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateAuthority<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// CHECK: Only recorded as the pending authority; it must sign to accept.
+    pub new_authority: UncheckedAccount<'info>,
+}
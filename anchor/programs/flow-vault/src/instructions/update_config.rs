@@ -0,0 +1,62 @@
+use anchor_lang::prelude::*;
+use crate::state::{ConfigParams, GlobalConfig};
+use crate::events::ConfigUpdated;
+
+pub fn handler(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+    ctx.accounts
+        .global_config
+        .require_authority(&signer_keys(&ctx.accounts.authority, ctx.remaining_accounts))?;
+
+    params.validate()?;
+    let config = &mut ctx.accounts.global_config;
+    config.check_update(&params)?;
+    config.apply(&params)?;
+
+    emit!(ConfigUpdated {
+        config: config.key(),
+        authority: ctx.accounts.authority.key(),
+        settle_threshold: params.settle_threshold,
+        fee_bps: params.fee_bps,
+        fee_destination: params.fee_destination,
+        min_deposit: params.min_deposit,
+        max_deposit: params.max_deposit,
+        dispute_seconds: params.dispute_seconds,
+        min_settle_interval: params.min_settle_interval,
+        timelock_seconds: params.timelock_seconds,
+        max_fee: params.max_fee,
+        referral_bps: params.referral_bps,
+        fee_rounding: params.fee_rounding,
+        registration_fee: params.registration_fee,
+        max_vaults_per_owner: params.max_vaults_per_owner,
+        clawback_seconds: params.clawback_seconds,
+        dust_threshold: params.dust_threshold,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+/// Keys that signed the transaction: the submitting authority plus any
+/// co-signing multisig members passed as remaining accounts.
+pub(crate) fn signer_keys(authority: &Signer, remaining_accounts: &[AccountInfo]) -> Vec<Pubkey> {
+    std::iter::once(authority.key())
+        .chain(
+            remaining_accounts
+                .iter()
+                .filter(|account| account.is_signer)
+                .map(|account| account.key()),
+        )
+        .collect()
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Provider};
+use crate::events::ProviderMetadataUpdated;
+
+pub fn handler(ctx: Context<UpdateProviderMetadata>, name: String, uri: String) -> Result<()> {
+    Provider::validate_metadata(&name, &uri)?;
+
+    let provider = &mut ctx.accounts.provider;
+    provider.name = name;
+    provider.uri = uri;
+
+    emit!(ProviderMetadataUpdated {
+        provider: provider.key(),
+        name: provider.name.clone(),
+        uri: provider.uri.clone(),
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateProviderMetadata<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(mut, seeds = [b"config"], bump = global_config.bump)]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -0,0 +1,41 @@
+use anchor_lang::prelude::*;
+use crate::state::{GlobalConfig, Provider};
+use crate::events::DestinationChangeVetoed;
+use super::update_config::signer_keys;
+
+/// Drops a pending destination change. The provider authority, the guardian
+/// or the config authority may veto.
+pub fn handler(ctx: Context<VetoDestination>) -> Result<()> {
+    let signer = ctx.accounts.signer.key();
+    let config = &ctx.accounts.global_config;
+    if !(signer == ctx.accounts.provider.authority || config.is_guardian(&signer)) {
+        config.require_authority(&signer_keys(&ctx.accounts.signer, ctx.remaining_accounts))?;
+    }
+
+    let provider = &mut ctx.accounts.provider;
+    let destination = provider.cancel_destination()?;
+
+    emit!(DestinationChangeVetoed {
+        provider: provider.key(),
+        destination,
+        vetoed_by: signer,
+        timestamp: Clock::get()?.unix_timestamp,
+        seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct VetoDestination<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
@@ -1,51 +1,78 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, CloseAccount, Token, TokenAccount, Transfer};
-use crate::state::Vault;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::{checked_clock, GlobalConfig, Vault, PAUSE_WITHDRAW};
+use crate::errors::FlowError;
 use crate::events::Withdrawn;
 
-pub fn handler(ctx: Context<Withdraw>) -> Result<()>{
-  let vault = &ctx.accounts.vault;
-  let remaining_amount = ctx.accounts.vault_token_account.amount;
-
-  if remaining_amount > 0 {
-    let seeds = &[
-      b"vault",
-      vault.agent.as_ref(),
-      &[ctx.bumps.vault],
-    ];
-    let signer_seeds = &[&seeds[..]];
-
-    let cpi_accounts = Transfer {
-      from: ctx.accounts.vault_token_account.to_account_info(),
-      to: ctx.accounts.agent_token_account.to_account_info(),
-      authority: vault.to_account_info(),
-    };
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
-    token::transfer(cpi_ctx, remaining_amount)?;
-  }
+pub fn handler(ctx: Context<Withdraw>, amount: u64) -> Result<()>{
+  let accounts = ctx.accounts;
+  transfer_out(
+    &mut accounts.vault,
+    &mut accounts.global_config,
+    &accounts.vault_token_account,
+    accounts.agent_token_account.to_account_info(),
+    &accounts.token_mint,
+    &accounts.token_program,
+    amount,
+  )
+}
+
+/// Pays `amount` of the owner's balance to `to` once the caller has checked
+/// the owner authorized it. Shared with `relay_withdraw`.
+pub(crate) fn transfer_out<'info>(
+  vault: &mut Account<'info, Vault>,
+  config: &mut GlobalConfig,
+  vault_token_account: &InterfaceAccount<'info, TokenAccount>,
+  to: AccountInfo<'info>,
+  token_mint: &InterfaceAccount<'info, Mint>,
+  token_program: &Interface<'info, TokenInterface>,
+  amount: u64,
+) -> Result<()> {
+  config.require_not_paused(PAUSE_WITHDRAW)?;
+  let clock = checked_clock()?;
+
+  vault.require_not_drained()?;
+  require_neq!(amount, 0, FlowError::ZeroAmount);
+  require_gte!(vault.balance()?, amount, FlowError::InsufficientFunds);
+  vault.consume_withdrawal(amount, clock.unix_timestamp)?;
+  vault.begin_transfer()?;
+  vault.exit(&crate::ID)?;
 
   let seeds = &[
     b"vault",
-    vault.agent.as_ref(),
-    &[ctx.bumps.vault],
+    vault.creator.as_ref(),
+    vault.session_seed(),
+    &[vault.bump],
   ];
-
   let signer_seeds = &[&seeds[..]];
-  let cpi_accounts = CloseAccount {
-    account: ctx.accounts.vault_token_account.to_account_info(),
-    destination: ctx.accounts.agent.to_account_info(),
+
+  let recipient = to.key();
+  let cpi_accounts = TransferChecked {
+    from: vault_token_account.to_account_info(),
+    mint: token_mint.to_account_info(),
+    to,
     authority: vault.to_account_info(),
   };
-  let cpi_program = ctx.accounts.token_program.to_account_info();
-  let cpi_ctx=  CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
-  token::close_account(cpi_ctx)?;
+  let cpi_program = token_program.to_account_info();
+  let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+  token_interface::transfer_checked(cpi_ctx, amount, token_mint.decimals)?;
+  vault.end_transfer();
 
-  emit!(Withdrawn {
-    vault: vault.key(),
-    agent: vault.agent,
-    amount: remaining_amount,
-  });
+  // The accounts stay open, even when emptied, until `close_vault`
+  // reclaims their rent.
+  vault.deposit_amount = vault.deposit_amount.checked_sub(amount).ok_or(FlowError::MathOverflow)?;
+
+  if vault.emits_events() {
+    emit!(Withdrawn {
+      vault: vault.key(),
+      agent: vault.agent,
+      amount,
+      timestamp: clock.unix_timestamp,
+      recipient,
+      lifetime_fees_paid: vault.lifetime_fees_paid,
+      seq: config.next_event_seq()?,
+    });
+  }
 
   Ok(())
 
@@ -58,23 +85,35 @@ pub struct Withdraw<'info> {
 
   #[account(
     mut,
-    close = agent,
-    seeds = [b"vault", agent.key().as_ref()],
-    bump,
+    seeds = [b"vault", vault.creator.as_ref(), vault.session_seed()],
+    bump = vault.bump,
     has_one = agent
   )]
   pub vault: Account<'info, Vault>,
 
   #[account(
     mut,
-    seeds = [b"vault_token_account", agent.key().as_ref()],
+    seeds = [b"config"],
+    bump = global_config.bump
+  )]
+  pub global_config: Account<'info, GlobalConfig>,
+
+  #[account(
+    mut,
+    seeds = [b"vault_token_account", vault.creator.as_ref(), vault.session_seed()],
     bump,
     constraint = vault_token_account.key() == vault.vault_token_account
   )]
-  pub vault_token_account: Account<'info, TokenAccount>,
+  pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
 
   #[account(mut)]
-  pub agent_token_account: Account<'info, TokenAccount>,
+  pub agent_token_account: InterfaceAccount<'info, TokenAccount>,
+
+  #[account(address = vault.token_mint)]
+  pub token_mint: InterfaceAccount<'info, Mint>,
 
-  pub token_program: Program<'info, Token>,
+  #[account(
+    constraint = vault.uses_token_program(&token_program.key()) @ FlowError::InvalidTokenProgram
+  )]
+  pub token_program: Interface<'info, TokenInterface>,
 }
\ No newline at end of file
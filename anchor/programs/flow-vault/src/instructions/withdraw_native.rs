@@ -0,0 +1,61 @@
+use anchor_lang::prelude::*;
+use crate::state::{checked_clock, GlobalConfig, Vault, PAUSE_WITHDRAW};
+use crate::errors::FlowError;
+use crate::events::Withdrawn;
+use super::settle_batch_native::transfer_lamports;
+
+/// Native counterpart of `withdraw`, with the same drain and timelock checks
+/// ahead of the lamport transfer.
+pub fn handler(ctx: Context<WithdrawNative>, amount: u64) -> Result<()> {
+  ctx.accounts.global_config.require_not_paused(PAUSE_WITHDRAW)?;
+  let clock = checked_clock()?;
+  let vault = &mut ctx.accounts.vault;
+
+  vault.require_not_drained()?;
+  require_neq!(amount, 0, FlowError::ZeroAmount);
+  require_gte!(vault.balance()?, amount, FlowError::InsufficientFunds);
+  vault.consume_withdrawal(amount, clock.unix_timestamp)?;
+
+  transfer_lamports(
+    &vault.to_account_info(),
+    &ctx.accounts.agent.to_account_info(),
+    amount,
+  )?;
+
+  vault.deposit_amount = vault.deposit_amount.checked_sub(amount).ok_or(FlowError::MathOverflow)?;
+
+  if vault.emits_events() {
+    emit!(Withdrawn {
+      vault: vault.key(),
+      agent: vault.agent,
+      amount,
+      timestamp: clock.unix_timestamp,
+      recipient: ctx.accounts.agent.key(),
+      lifetime_fees_paid: vault.lifetime_fees_paid,
+      seq: ctx.accounts.global_config.next_event_seq()?,
+    });
+  }
+
+  Ok(())
+}
+
+#[derive(Accounts)]
+pub struct WithdrawNative<'info> {
+  #[account(mut)]
+  pub agent: Signer<'info>,
+
+  #[account(
+    mut,
+    seeds = [b"native_vault", vault.creator.as_ref()],
+    bump = vault.bump,
+    has_one = agent
+  )]
+  pub vault: Account<'info, Vault>,
+
+  #[account(
+    mut,
+    seeds = [b"config"],
+    bump = global_config.bump
+  )]
+  pub global_config: Account<'info, GlobalConfig>,
+}
@@ -6,7 +6,10 @@ pub mod instructions;
 pub mod state;
 
 use instructions::*;
-use state::PaymentProtocol;
+use state::{
+    ConfigParams, FeeRounding, FeeSource, FeeSplit, FeeTier, PaymentProtocol, SettlePreview,
+    VaultStatus,
+};
 
 declare_id!("Ca5JKghY5ECswAfm3NkvxeEXFmCongnnfkvpFyr5Yirg");
 
@@ -14,12 +17,55 @@ declare_id!("Ca5JKghY5ECswAfm3NkvxeEXFmCongnnfkvpFyr5Yirg");
 pub mod flow_vault {
     use super::*;
 
-    pub fn initialize_config(
-        ctx: Context<InitializeConfig>,
-        settle_threshold: u64,
-        fee_bps: u16,
+    pub fn initialize_config(ctx: Context<InitializeConfig>, params: ConfigParams) -> Result<()> {
+        init_config::handler(ctx, params)
+    }
+
+    pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+        update_config::handler(ctx, params)
+    }
+
+    pub fn set_config_signers(
+        ctx: Context<SetConfigSigners>,
+        signers: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        set_config_signers::handler(ctx, signers, threshold)
+    }
+
+    pub fn set_fee_splits(ctx: Context<SetFeeSplits>, splits: Vec<FeeSplit>) -> Result<()> {
+        set_fee_splits::handler(ctx, splits)
+    }
+
+    pub fn set_fee_tiers(ctx: Context<SetFeeTiers>, tiers: Vec<FeeTier>) -> Result<()> {
+        set_fee_tiers::handler(ctx, tiers)
+    }
+
+    pub fn set_guardian(ctx: Context<SetGuardian>, guardian: Pubkey) -> Result<()> {
+        set_guardian::handler(ctx, guardian)
+    }
+
+    pub fn set_recovery_destination(
+        ctx: Context<SetRecoveryDestination>,
+        recovery_destination: Pubkey,
     ) -> Result<()> {
-        init_config::handler(ctx, settle_threshold, fee_bps)
+        set_recovery_destination::handler(ctx, recovery_destination)
+    }
+
+    pub fn close_config(ctx: Context<CloseConfig>) -> Result<()> {
+        close_config::handler(ctx)
+    }
+
+    pub fn update_authority(ctx: Context<UpdateAuthority>) -> Result<()> {
+        update_authority::handler(ctx)
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        accept_authority::handler(ctx)
+    }
+
+    pub fn cancel_authority_update(ctx: Context<CancelAuthorityUpdate>) -> Result<()> {
+        cancel_authority_update::handler(ctx)
     }
 
     /// [BOUNTY] Updated function signature
@@ -27,23 +73,338 @@ pub mod flow_vault {
         ctx: Context<RegisterProvider>,
         visa_merchant_id: Option<String>,
         protocol: PaymentProtocol,
+        name: String,
+        uri: String,
+        referrer: Option<Pubkey>,
+    ) -> Result<()> {
+        register_provider::handler(ctx, visa_merchant_id, protocol, name, uri, referrer)
+    }
+
+    pub fn register_providers_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RegisterProvidersBatch<'info>>,
+        protocol: PaymentProtocol,
+    ) -> Result<u8> {
+        register_providers_batch::handler(ctx, protocol)
+    }
+
+    pub fn update_provider_metadata(
+        ctx: Context<UpdateProviderMetadata>,
+        name: String,
+        uri: String,
+    ) -> Result<()> {
+        update_provider_metadata::handler(ctx, name, uri)
+    }
+
+    pub fn add_destination(ctx: Context<AddDestination>, destination: Pubkey) -> Result<()> {
+        add_destination::handler(ctx, destination)
+    }
+
+    pub fn remove_destination(ctx: Context<RemoveDestination>, destination: Pubkey) -> Result<()> {
+        remove_destination::handler(ctx, destination)
+    }
+
+    pub fn propose_destination(ctx: Context<ProposeDestination>) -> Result<()> {
+        propose_destination::handler(ctx)
+    }
+
+    pub fn apply_destination(ctx: Context<ApplyDestination>) -> Result<()> {
+        apply_destination::handler(ctx)
+    }
+
+    pub fn veto_destination(ctx: Context<VetoDestination>) -> Result<()> {
+        veto_destination::handler(ctx)
+    }
+
+    pub fn transfer_provider_authority(ctx: Context<TransferProviderAuthority>) -> Result<()> {
+        transfer_provider_authority::handler(ctx)
+    }
+
+    pub fn accept_provider_authority(ctx: Context<AcceptProviderAuthority>) -> Result<()> {
+        accept_provider_authority::handler(ctx)
+    }
+
+    pub fn set_provider_fee(
+        ctx: Context<SetProviderFee>,
+        fee_bps_override: Option<u16>,
+    ) -> Result<()> {
+        set_provider_fee::handler(ctx, fee_bps_override)
+    }
+
+    pub fn pause_provider(ctx: Context<PauseProvider>, paused: bool) -> Result<()> {
+        pause_provider::handler(ctx, paused)
+    }
+
+    pub fn close_provider(ctx: Context<CloseProvider>) -> Result<()> {
+        close_provider::handler(ctx)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_vault(
+        ctx: Context<CreateVault>,
+        deposit_amount: u64,
+        expiry_unix: i64,
+        withdraw_timelock: i64,
+        shared: bool,
+        max_settlements: u32,
+        session_id: [u8; 16],
+        emit_events: bool,
+    ) -> Result<()> {
+        create_vault::handler(
+            ctx,
+            deposit_amount,
+            expiry_unix,
+            withdraw_timelock,
+            shared,
+            max_settlements,
+            session_id,
+            emit_events,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_vault_idempotent(
+        ctx: Context<CreateVaultIdempotent>,
+        deposit_amount: u64,
+        expiry_unix: i64,
+        withdraw_timelock: i64,
+        shared: bool,
+        max_settlements: u32,
+        session_id: [u8; 16],
+        emit_events: bool,
+    ) -> Result<()> {
+        create_vault_idempotent::handler(
+            ctx,
+            deposit_amount,
+            expiry_unix,
+            withdraw_timelock,
+            shared,
+            max_settlements,
+            session_id,
+            emit_events,
+        )
+    }
+
+    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
+        deposit::handler(ctx, amount)
+    }
+
+    pub fn contribute(ctx: Context<Contribute>, amount: u64) -> Result<()> {
+        contribute::handler(ctx, amount)
+    }
+
+    pub fn set_delegate(ctx: Context<SetDelegate>, delegate: Pubkey) -> Result<()> {
+        set_delegate::handler(ctx, delegate)
+    }
+
+    pub fn set_subscription(
+        ctx: Context<SetSubscription>,
+        provider: Pubkey,
+        interval_seconds: i64,
+        amount_per_interval: u64,
+    ) -> Result<()> {
+        set_subscription::handler(ctx, provider, interval_seconds, amount_per_interval)
+    }
+
+    pub fn commit_amount(ctx: Context<CommitAmount>, amount: u64) -> Result<()> {
+        commit_amount::handler(ctx, amount)
+    }
+
+    pub fn accrue(ctx: Context<Accrue>, amount: u64) -> Result<()> {
+        accrue::handler(ctx, amount)
+    }
+
+    pub fn transfer_vault_ownership(ctx: Context<TransferVaultOwnership>) -> Result<()> {
+        transfer_vault_ownership::handler(ctx)
+    }
+
+    pub fn accept_vault_ownership(ctx: Context<AcceptVaultOwnership>) -> Result<()> {
+        accept_vault_ownership::handler(ctx)
+    }
+
+    pub fn init_nonce_bitmap(ctx: Context<InitNonceBitmap>) -> Result<()> {
+        init_nonce_bitmap::handler(ctx)
+    }
+
+    pub fn init_provider_earnings(ctx: Context<InitProviderEarnings>) -> Result<()> {
+        init_provider_earnings::handler(ctx)
+    }
+
+    pub fn claim_earnings(ctx: Context<ClaimEarnings>) -> Result<()> {
+        claim_earnings::handler(ctx)
+    }
+
+    pub fn clawback(ctx: Context<Clawback>, amount: u64) -> Result<()> {
+        clawback::handler(ctx, amount)
+    }
+
+    pub fn migrate_account(ctx: Context<MigrateAccount>) -> Result<()> {
+        migrate_account::handler(ctx)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn settle_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SettleBatch<'info>>,
+        amount: u64,
+        nonce: u64,
+        min_net_to_provider: u64,
+        memo: Option<String>,
+        deadline_unix: i64,
+        destination_override: Option<Pubkey>,
+        fee_from: FeeSource,
+    ) -> Result<()> {
+        settle_batch::handler(
+            ctx,
+            amount,
+            nonce,
+            min_net_to_provider,
+            memo,
+            deadline_unix,
+            destination_override,
+            fee_from,
+        )
+    }
+
+    pub fn propose_settlement(
+        ctx: Context<ProposeSettlement>,
+        amount: u64,
+        nonce: u64,
+    ) -> Result<()> {
+        propose_settlement::handler(ctx, amount, nonce)
+    }
+
+    pub fn finalize_settlement<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FinalizeSettlement<'info>>,
+    ) -> Result<()> {
+        finalize_settlement::handler(ctx)
+    }
+
+    pub fn cancel_settlement(ctx: Context<CancelSettlement>) -> Result<()> {
+        cancel_settlement::handler(ctx)
+    }
+
+    pub fn settle_batch_many<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SettleBatchMany<'info>>,
+        amounts: Vec<u64>,
+        nonces: Vec<u64>,
+    ) -> Result<u16> {
+        settle_batch_many::handler(ctx, amounts, nonces)
+    }
+
+    pub fn pay_once<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PayOnce<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        pay_once::handler(ctx, amount)
+    }
+
+    pub fn commit_settlement_root(
+        ctx: Context<CommitSettlementRoot>,
+        root: [u8; 32],
+        leaf_count: u16,
+    ) -> Result<()> {
+        commit_settlement_root::handler(ctx, root, leaf_count)
+    }
+
+    pub fn settle_with_proof<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SettleWithProof<'info>>,
+        root: [u8; 32],
+        amount: u64,
+        index: u16,
+        proof: Vec<[u8; 32]>,
     ) -> Result<()> {
-        register_provider::handler(ctx, visa_merchant_id, protocol)
+        settle_with_proof::handler(ctx, root, amount, index, proof)
     }
 
-    pub fn create_vault(ctx: Context<CreateVault>, deposit_amount: u64) -> Result<()> {
-        create_vault::handler(ctx, deposit_amount)
+    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        withdraw::handler(ctx, amount)
+    }
+
+    pub fn relay_withdraw(ctx: Context<RelayWithdraw>, amount: u64, nonce: u64) -> Result<()> {
+        relay_withdraw::handler(ctx, amount, nonce)
+    }
+
+    pub fn request_withdraw(ctx: Context<RequestWithdraw>, amount: u64) -> Result<()> {
+        request_withdraw::handler(ctx, amount)
+    }
+
+    pub fn cancel_withdraw(ctx: Context<CancelWithdraw>) -> Result<()> {
+        cancel_withdraw::handler(ctx)
+    }
+
+    pub fn refund_expired(ctx: Context<RefundExpired>) -> Result<()> {
+        refund_expired::handler(ctx)
+    }
+
+    pub fn close_vault(ctx: Context<CloseVault>) -> Result<()> {
+        close_vault::handler(ctx)
+    }
+
+    pub fn sweep_dust(ctx: Context<SweepDust>) -> Result<()> {
+        sweep_dust::handler(ctx)
+    }
+
+    pub fn get_vault_status(ctx: Context<GetVaultStatus>) -> Result<VaultStatus> {
+        get_vault_status::handler(ctx)
+    }
+
+    pub fn simulate_settle(
+        ctx: Context<SimulateSettle>,
+        amount: u64,
+        nonce: u64,
+    ) -> Result<SettlePreview> {
+        simulate_settle::handler(ctx, amount, nonce)
+    }
+
+    pub fn create_vault_native(
+        ctx: Context<CreateVaultNative>,
+        deposit_amount: u64,
+        expiry_unix: i64,
+    ) -> Result<()> {
+        create_vault_native::handler(ctx, deposit_amount, expiry_unix)
+    }
+
+    pub fn settle_batch_native<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SettleBatchNative<'info>>,
+        amount: u64,
+        nonce: u64,
+    ) -> Result<()> {
+        settle_batch_native::handler(ctx, amount, nonce)
+    }
+
+    pub fn withdraw_native(ctx: Context<WithdrawNative>, amount: u64) -> Result<()> {
+        withdraw_native::handler(ctx, amount)
+    }
+
+    pub fn close_vault_native(ctx: Context<CloseVaultNative>) -> Result<()> {
+        close_vault_native::handler(ctx)
+    }
+
+    pub fn emergency_pause(ctx: Context<EmergencyPause>, flags: u8) -> Result<()> {
+        emergency_pause::handler(ctx, flags)
+    }
+
+    pub fn emergency_drain(ctx: Context<EmergencyDrainVault>) -> Result<()> {
+        emergency_drain::handler(ctx)
+    }
+
+    pub fn propose_fee_increase(
+        ctx: Context<ProposeFeeIncrease>,
+        fee_bps: u16,
+        max_fee: u64,
+        fee_rounding: FeeRounding,
+    ) -> Result<()> {
+        propose_fee_increase::handler(ctx, fee_bps, max_fee, fee_rounding)
     }
 
-    pub fn settle_batch(ctx: Context<SettleBatch>, amount: u64, nonce: u64) -> Result<()> {
-        settle_batch::handler(ctx, amount, nonce)
+    pub fn apply_fee_increase(ctx: Context<ApplyFeeIncrease>) -> Result<()> {
+        apply_fee_increase::handler(ctx)
     }
 
-    pub fn withdraw(ctx: Context<Withdraw>) -> Result<()> {
-        withdraw::handler(ctx)
+    pub fn propose_unpause(ctx: Context<ProposeUnpause>) -> Result<()> {
+        propose_unpause::handler(ctx)
     }
 
-    pub fn emergency_pause(ctx: Context<EmergencyPause>, paused: bool) -> Result<()> {
-        emergency_pause::handler(ctx, paused)
+    pub fn apply_unpause(ctx: Context<ApplyUnpause>) -> Result<()> {
+        apply_unpause::handler(ctx)
     }
 }
\ No newline at end of file
@@ -0,0 +1,17 @@
+use anchor_lang::prelude::*;
+
+/// One depositor's stake in a shared vault. Seeded by `(vault, depositor)`,
+/// so repeat contributions add to the same record. `amount` drops to zero
+/// once the expiry refund has paid the depositor out.
+#[account]
+pub struct Contribution {
+    pub vault: Pubkey,
+    pub depositor: Pubkey,
+    pub amount: u64,
+    pub bump: u8,
+}
+
+impl Contribution {
+    // discriminator + vault + depositor + amount + bump
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 1;
+}
@@ -1,11 +1,210 @@
 use anchor_lang::prelude::*;
+use crate::errors::FlowError;
+use crate::state::{require_current_version, Provider, SettlementSplit};
+
+/// Denominator for basis-point fee math.
+pub const BPS_DENOMINATOR: u64 = 10_000;
+/// Most keys the config multisig can hold.
+pub const MAX_CONFIG_SIGNERS: usize = 5;
+/// Most recipients the protocol fee can be split across.
+pub const MAX_FEE_SPLITS: usize = 4;
+/// Most size tiers the fee schedule can hold.
+pub const MAX_FEE_TIERS: usize = 4;
+
+/// `GlobalConfig::pause_flags` bits, one per group of hot-path instructions.
+pub const PAUSE_CREATE: u8 = 1 << 0;
+pub const PAUSE_SETTLE: u8 = 1 << 1;
+pub const PAUSE_WITHDRAW: u8 = 1 << 2;
+pub const PAUSE_DEPOSIT: u8 = 1 << 3;
+pub const PAUSE_ALL: u8 = PAUSE_CREATE | PAUSE_SETTLE | PAUSE_WITHDRAW | PAUSE_DEPOSIT;
+
+/// Tunable config values, shared by `initialize_config` and `update_config`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConfigParams {
+    pub settle_threshold: u64,
+    pub fee_bps: u16,
+    pub fee_destination: Pubkey,
+    pub min_deposit: u64,
+    pub max_deposit: u64,
+    pub dispute_seconds: i64,
+    pub min_settle_interval: i64,
+    pub timelock_seconds: i64,
+    pub max_fee: u64,
+    pub referral_bps: u16,
+    pub fee_rounding: FeeRounding,
+    pub registration_fee: u64,
+    pub max_vaults_per_owner: u16,
+    pub clawback_seconds: i64,
+    pub dust_threshold: u64,
+}
+
+impl ConfigParams {
+    /// Invariants every config must hold, checked by both `init_config` and
+    /// `update_config`.
+    pub fn validate(&self) -> Result<()> {
+        if self.fee_bps as u64 > BPS_DENOMINATOR || self.referral_bps as u64 > BPS_DENOMINATOR {
+            return err!(FlowError::InvalidFeeBps);
+        }
+        if self.settle_threshold == 0 {
+            return err!(FlowError::InvalidThreshold);
+        }
+        if self.max_deposit != 0 && self.min_deposit > self.max_deposit {
+            return err!(FlowError::InvalidDepositLimits);
+        }
+        if self.dispute_seconds < 0 || self.clawback_seconds < 0 {
+            return err!(FlowError::InvalidDisputeWindow);
+        }
+        if self.min_settle_interval < 0 {
+            return err!(FlowError::InvalidSettleInterval);
+        }
+        if self.timelock_seconds < 0 {
+            return err!(FlowError::InvalidTimelock);
+        }
+        Ok(())
+    }
+}
+
+/// Which way a fee that is not a whole number of base units is rounded.
+/// `Nearest` rounds halves up.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, InitSpace)]
+pub enum FeeRounding {
+    #[default]
+    Floor,
+    Ceil,
+    Nearest,
+}
+
+impl FeeRounding {
+    pub fn divide(self, numerator: u128, denominator: u128) -> u128 {
+        match self {
+            FeeRounding::Floor => numerator / denominator,
+            FeeRounding::Ceil => numerator.div_ceil(denominator),
+            FeeRounding::Nearest => (numerator + denominator / 2) / denominator,
+        }
+    }
+
+    /// Whether switching from `previous` to this mode can round some fee up
+    /// that `previous` did not: Floor, Nearest and Ceil each take at least
+    /// as much as the one before.
+    pub fn raises_fees_over(self, previous: FeeRounding) -> bool {
+        let rank = |rounding| match rounding {
+            FeeRounding::Floor => 0,
+            FeeRounding::Nearest => 1,
+            FeeRounding::Ceil => 2,
+        };
+        rank(self) > rank(previous)
+    }
+}
+
+/// One recipient's share of the protocol fee. Like `fee_destination`,
+/// `recipient` is a wallet; fees go to its token account for the vault mint.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, InitSpace)]
+pub struct FeeSplit {
+    pub recipient: Pubkey,
+    pub share_bps: u16,
+}
+
+/// Settlements of at least `min_amount` pay `fee_bps` instead of the base
+/// rate.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, InitSpace)]
+pub struct FeeTier {
+    pub min_amount: u64,
+    pub fee_bps: u16,
+}
+
 #[account]
+#[derive(InitSpace)]
 pub struct GlobalConfig {
 
     pub admin: Pubkey,
     pub settle_threshold: u64,
     pub fee_bps: u16,
-    pub reserved: [u8; 128],
+    /// `PAUSE_*` bits set by `emergency_pause`; each paused instruction
+    /// fails with `InstructionPaused`.
+    pub pause_flags: u8,
+    /// Wallet that receives protocol fees. SPL fees are paid into any token
+    /// account it owns for the vault mint, native fees to the wallet itself.
+    pub fee_destination: Pubkey,
+    /// Smallest initial deposit `create_vault` accepts.
+    pub min_deposit: u64,
+    /// Largest initial deposit `create_vault` accepts; zero means no limit.
+    pub max_deposit: u64,
+    /// How long a proposed settlement can be cancelled by the vault owner
+    /// before it may be finalized.
+    pub dispute_seconds: i64,
+    /// N-of-M keys that replace `admin` for `update_config` and
+    /// `emergency_pause` when `signer_threshold` is nonzero. Only the first
+    /// `signer_count` entries are used.
+    pub signers: [Pubkey; MAX_CONFIG_SIGNERS],
+    pub signer_count: u8,
+    pub signer_threshold: u8,
+    /// When set, `settle_batch` pays the fee across these recipients instead
+    /// of `fee_destination`. Only the first `fee_split_count` entries are used.
+    pub fee_splits: [FeeSplit; MAX_FEE_SPLITS],
+    pub fee_split_count: u8,
+    /// Minimum seconds between payouts from the same vault; zero disables
+    /// the cooldown.
+    pub min_settle_interval: i64,
+    /// Break-glass key that can pause the protocol but not unpause it. The
+    /// default key means no guardian.
+    pub guardian: Pubkey,
+    /// Delay before a fee increase or an unpause can be applied. It can be
+    /// raised with `update_config` but never shortened.
+    pub timelock_seconds: i64,
+    /// Fee increase waiting out the timelock; `fee_effective_after` is zero
+    /// when none is pending.
+    pub pending_fee_bps: u16,
+    pub fee_effective_after: i64,
+    /// Unix time from which a proposed unpause can be applied; zero when
+    /// none is pending.
+    pub unpause_effective_after: i64,
+    /// Running totals over `settle_batch` settlements.
+    pub total_settled: u128,
+    pub total_fees: u128,
+    pub settlement_count: u64,
+    /// Vaults created and not yet closed.
+    pub active_vaults: u64,
+    pub version: u8,
+    /// Canonical bump of the config PDA.
+    pub bump: u8,
+    /// Cap on any one settlement's fee, applied after `fee_bps` or a
+    /// provider override; zero means no cap.
+    pub max_fee: u64,
+    /// Share of a settlement's fee, in bps of the fee, paid to the
+    /// provider's referrer when it has one.
+    pub referral_bps: u16,
+    /// Wallet whose token accounts `emergency_drain` may sweep vaults into.
+    /// The default key disables drains.
+    pub recovery_destination: Pubkey,
+    /// Discounted rates for larger settlements, sorted by `min_amount`. Only
+    /// the first `fee_tier_count` entries are used.
+    pub fee_tiers: [FeeTier; MAX_FEE_TIERS],
+    pub fee_tier_count: u8,
+    /// Rounding applied to the fee in `calculate_fee`; takes the place of
+    /// the last reserved byte, so older configs read as `Floor`.
+    pub fee_rounding: FeeRounding,
+    /// Lamports `register_provider` charges to `fee_destination`; zero
+    /// disables it.
+    pub registration_fee: u64,
+    /// Admin proposed by `update_authority`; takes over once it signs
+    /// `accept_authority`.
+    pub pending_admin: Option<Pubkey>,
+    /// Most open vaults `create_vault` allows one owner; zero means
+    /// unlimited.
+    pub max_vaults_per_owner: u16,
+    /// `seq` of the last event emitted; see `next_event_seq`.
+    pub event_seq: u64,
+    /// Seconds after a settlement during which the config authority may
+    /// `clawback` it from the provider's earnings; zero leaves clawbacks to
+    /// the provider alone.
+    pub clawback_seconds: i64,
+    /// Balance below which `sweep_dust` may close an expired vault; zero
+    /// disables sweeping.
+    pub dust_threshold: u64,
+    /// Fee cap proposed along with `pending_fee_bps`.
+    pub pending_max_fee: u64,
+    /// Fee rounding proposed along with `pending_fee_bps`.
+    pub pending_fee_rounding: FeeRounding,
 }
 
 impl Default for GlobalConfig {
@@ -14,11 +213,1064 @@ impl Default for GlobalConfig {
             admin: Pubkey::default(),
             settle_threshold: 0,
             fee_bps: 0,
-            reserved: [0u8; 128],
+            pause_flags: 0,
+            fee_destination: Pubkey::default(),
+            min_deposit: 0,
+            max_deposit: 0,
+            dispute_seconds: 0,
+            signers: [Pubkey::default(); MAX_CONFIG_SIGNERS],
+            signer_count: 0,
+            signer_threshold: 0,
+            fee_splits: [FeeSplit::default(); MAX_FEE_SPLITS],
+            fee_split_count: 0,
+            min_settle_interval: 0,
+            guardian: Pubkey::default(),
+            timelock_seconds: 0,
+            pending_fee_bps: 0,
+            fee_effective_after: 0,
+            unpause_effective_after: 0,
+            total_settled: 0,
+            total_fees: 0,
+            settlement_count: 0,
+            active_vaults: 0,
+            version: 0,
+            bump: 0,
+            max_fee: 0,
+            referral_bps: 0,
+            recovery_destination: Pubkey::default(),
+            fee_tiers: [FeeTier::default(); MAX_FEE_TIERS],
+            fee_tier_count: 0,
+            fee_rounding: FeeRounding::Floor,
+            registration_fee: 0,
+            pending_admin: None,
+            max_vaults_per_owner: 0,
+            event_seq: 0,
+            clawback_seconds: 0,
+            dust_threshold: 0,
+            pending_max_fee: 0,
+            pending_fee_rounding: FeeRounding::Floor,
         }
     }
 }
 
 impl GlobalConfig {
-    pub const LEN: usize = 8 + 32 + 8 + 2 + 128;
+    /// Layout version; see `require_current_version`.
+    pub const VERSION: u8 = 15;
+    pub const LEN: usize = 8 + Self::INIT_SPACE;
+
+    /// Brings an account written by an older program up to
+    /// `VERSION`, returning the version it had. `None` if it was
+    /// already current.
+    pub fn migrate(&mut self) -> Option<u8> {
+        let from_version = self.version;
+        if from_version >= Self::VERSION {
+            return None;
+        }
+        // v2 stores the PDA bump; every other field added since v0 defaults
+        // to zero, which for v4's `max_fee` means no cap.
+        if from_version < 2 {
+            self.bump = Pubkey::find_program_address(&[b"config"], &crate::ID).1;
+        }
+        // Before v5 the flags byte was a single `paused` bool, which halted
+        // vault creation, settlement and deposits but left withdrawals open.
+        if from_version < 5 && self.pause_flags != 0 {
+            self.pause_flags = PAUSE_CREATE | PAUSE_SETTLE | PAUSE_DEPOSIT;
+        }
+        self.version = Self::VERSION;
+        Some(from_version)
+    }
+
+    /// Claims the `seq` for the event about to be emitted.
+    pub fn next_event_seq(&mut self) -> Result<u64> {
+        self.event_seq = self.event_seq.checked_add(1).ok_or(FlowError::MathOverflow)?;
+        Ok(self.event_seq)
+    }
+
+    /// Validates and stores the tunable values.
+    pub fn apply(&mut self, params: &ConfigParams) -> Result<()> {
+        params.validate()?;
+
+        self.settle_threshold = params.settle_threshold;
+        self.fee_bps = params.fee_bps;
+        self.fee_destination = params.fee_destination;
+        self.min_deposit = params.min_deposit;
+        self.max_deposit = params.max_deposit;
+        self.dispute_seconds = params.dispute_seconds;
+        self.min_settle_interval = params.min_settle_interval;
+        self.timelock_seconds = params.timelock_seconds;
+        self.max_fee = params.max_fee;
+        self.referral_bps = params.referral_bps;
+        self.fee_rounding = params.fee_rounding;
+        self.registration_fee = params.registration_fee;
+        self.max_vaults_per_owner = params.max_vaults_per_owner;
+        self.clawback_seconds = params.clawback_seconds;
+        self.dust_threshold = params.dust_threshold;
+        Ok(())
+    }
+
+    /// Rejects `update_config` changes that have to wait out the timelock:
+    /// raising the fee, its cap or its rounding, or shortening the timelock
+    /// itself.
+    pub fn check_update(&self, params: &ConfigParams) -> Result<()> {
+        if params.fee_bps > self.fee_bps
+            || self.raises_fee_cap(params.max_fee)
+            || params.fee_rounding.raises_fees_over(self.fee_rounding)
+            || params.timelock_seconds < self.timelock_seconds
+        {
+            return err!(FlowError::TimelockRequired);
+        }
+        Ok(())
+    }
+
+    /// Whether `max_fee` would let a settlement pay more than the current
+    /// cap allows: a higher cap, or zero removing it.
+    fn raises_fee_cap(&self, max_fee: u64) -> bool {
+        self.max_fee != 0 && (max_fee == 0 || max_fee > self.max_fee)
+    }
+
+    /// Queues a fee increase, `fee_bps`, `max_fee` and `fee_rounding`
+    /// together, that `apply_fee_increase` can make effective once the
+    /// timelock has passed.
+    pub fn propose_fee_increase(
+        &mut self,
+        fee_bps: u16,
+        max_fee: u64,
+        fee_rounding: FeeRounding,
+        now: i64,
+    ) -> Result<i64> {
+        if fee_bps as u64 > BPS_DENOMINATOR {
+            return err!(FlowError::InvalidFeeBps);
+        }
+        let effective_after = now
+            .checked_add(self.timelock_seconds)
+            .ok_or(FlowError::MathOverflow)?;
+        self.pending_fee_bps = fee_bps;
+        self.pending_max_fee = max_fee;
+        self.pending_fee_rounding = fee_rounding;
+        self.fee_effective_after = effective_after;
+        Ok(effective_after)
+    }
+
+    pub fn apply_fee_increase(&mut self, now: i64) -> Result<u16> {
+        check_timelock(self.fee_effective_after, now)?;
+        self.fee_bps = self.pending_fee_bps;
+        self.max_fee = self.pending_max_fee;
+        self.fee_rounding = self.pending_fee_rounding;
+        self.pending_fee_bps = 0;
+        self.pending_max_fee = 0;
+        self.pending_fee_rounding = FeeRounding::Floor;
+        self.fee_effective_after = 0;
+        Ok(self.fee_bps)
+    }
+
+    pub fn propose_unpause(&mut self, now: i64) -> Result<i64> {
+        let effective_after = now
+            .checked_add(self.timelock_seconds)
+            .ok_or(FlowError::MathOverflow)?;
+        self.unpause_effective_after = effective_after;
+        Ok(effective_after)
+    }
+
+    pub fn is_paused(&self, flag: u8) -> bool {
+        self.pause_flags & flag != 0
+    }
+
+    pub fn require_not_paused(&self, flag: u8) -> Result<()> {
+        if self.is_paused(flag) {
+            return err!(FlowError::InstructionPaused);
+        }
+        Ok(())
+    }
+
+    /// Adds `flags` to the paused set. Clearing flags goes through the
+    /// unpause timelock instead.
+    pub fn pause(&mut self, flags: u8) -> Result<()> {
+        if flags & !PAUSE_ALL != 0 {
+            return err!(FlowError::InvalidPauseFlags);
+        }
+        if flags == 0 {
+            return err!(FlowError::TimelockRequired);
+        }
+        self.pause_flags |= flags;
+        self.unpause_effective_after = 0;
+        Ok(())
+    }
+
+    pub fn apply_unpause(&mut self, now: i64) -> Result<()> {
+        check_timelock(self.unpause_effective_after, now)?;
+        self.pause_flags = 0;
+        self.unpause_effective_after = 0;
+        Ok(())
+    }
+
+    /// Checks that `signed` (the keys that signed the transaction) carry the
+    /// config's authority: the admin alone by default, or `signer_threshold`
+    /// distinct multisig keys once a multisig is set.
+    pub fn require_authority<'a>(&self, signed: impl IntoIterator<Item = &'a Pubkey> + Clone) -> Result<()> {
+        if self.signer_threshold == 0 {
+            if signed.into_iter().any(|key| *key == self.admin) {
+                return Ok(());
+            }
+            return err!(FlowError::Unauthorized);
+        }
+
+        let approvals = self.signers[..self.signer_count as usize]
+            .iter()
+            .filter(|member| signed.clone().into_iter().any(|key| key == *member))
+            .count();
+        if approvals < self.signer_threshold as usize {
+            return err!(FlowError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    /// Hands `admin` to the proposed key, returning the previous admin.
+    pub fn accept_admin(&mut self) -> Result<Pubkey> {
+        let new_admin = self.pending_admin.take().ok_or(FlowError::InvalidPendingAuthority)?;
+        Ok(std::mem::replace(&mut self.admin, new_admin))
+    }
+
+    /// Drops the proposed admin, returning it.
+    pub fn cancel_admin_proposal(&mut self) -> Result<Pubkey> {
+        self.pending_admin
+            .take()
+            .ok_or_else(|| error!(FlowError::InvalidPendingAuthority))
+    }
+
+    pub fn is_guardian(&self, key: &Pubkey) -> bool {
+        self.guardian != Pubkey::default() && self.guardian == *key
+    }
+
+    /// Replaces the multisig. A threshold of zero returns to single-admin
+    /// mode and must come with no signers.
+    pub fn set_signers(&mut self, signers: &[Pubkey], threshold: u8) -> Result<()> {
+        let has_duplicates = signers
+            .iter()
+            .enumerate()
+            .any(|(i, key)| signers[..i].contains(key));
+        if signers.len() > MAX_CONFIG_SIGNERS
+            || threshold as usize > signers.len()
+            || (threshold == 0) != signers.is_empty()
+            || has_duplicates
+        {
+            return err!(FlowError::InvalidMultisig);
+        }
+
+        self.signers = [Pubkey::default(); MAX_CONFIG_SIGNERS];
+        self.signers[..signers.len()].copy_from_slice(signers);
+        self.signer_count = signers.len() as u8;
+        self.signer_threshold = threshold;
+        Ok(())
+    }
+
+    pub fn fee_splits(&self) -> &[FeeSplit] {
+        &self.fee_splits[..self.fee_split_count as usize]
+    }
+
+    /// Replaces the fee splits. Shares must be nonzero and add up to
+    /// `BPS_DENOMINATOR`; an empty list sends fees back to `fee_destination`.
+    pub fn set_fee_splits(&mut self, splits: &[FeeSplit]) -> Result<()> {
+        let total: u64 = splits.iter().map(|split| split.share_bps as u64).sum();
+        if splits.len() > MAX_FEE_SPLITS
+            || splits.iter().any(|split| split.share_bps == 0)
+            || (!splits.is_empty() && total != BPS_DENOMINATOR)
+        {
+            return err!(FlowError::InvalidFeeSplits);
+        }
+
+        self.fee_splits = [FeeSplit::default(); MAX_FEE_SPLITS];
+        self.fee_splits[..splits.len()].copy_from_slice(splits);
+        self.fee_split_count = splits.len() as u8;
+        Ok(())
+    }
+
+    /// Splits a fee into the referrer's `referral_bps` share (floored) and
+    /// the rest, which goes to the fee destination or splits.
+    pub fn split_referral(&self, fee: u64) -> (u64, u64) {
+        let referral = (fee as u128 * self.referral_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        (referral, fee - referral)
+    }
+
+    /// Divides `fee` across the fee splits in order. Each share is floored
+    /// and the rounding dust goes to the first recipient, so the parts always
+    /// sum to `fee`.
+    pub fn split_fee(&self, fee: u64) -> Vec<u64> {
+        let mut shares: Vec<u64> = self
+            .fee_splits()
+            .iter()
+            .map(|split| (fee as u128 * split.share_bps as u128 / BPS_DENOMINATOR as u128) as u64)
+            .collect();
+        let dust = fee - shares.iter().sum::<u64>();
+        if let Some(first) = shares.first_mut() {
+            *first += dust;
+        }
+        shares
+    }
+
+    /// Validates a `pay_once` payment, which has no vault behind it, against
+    /// the same pause, threshold and fee rules as a vault settlement.
+    pub fn check_direct_payment(&self, provider: &Provider, amount: u64) -> Result<SettlementSplit> {
+        require_current_version(self.version, Self::VERSION)?;
+        require_current_version(provider.version, Provider::VERSION)?;
+        self.require_not_paused(PAUSE_SETTLE)?;
+        if provider.paused {
+            return err!(FlowError::ProviderPaused);
+        }
+        if amount < self.settle_threshold {
+            return err!(FlowError::BelowSettleThreshold);
+        }
+        let fee_amount = self.calculate_fee(amount, provider.fee_bps_override)?;
+        let net_to_provider = amount
+            .checked_sub(fee_amount)
+            .ok_or(FlowError::MathOverflow)?;
+        Ok(SettlementSplit {
+            fee_amount,
+            net_to_provider,
+        })
+    }
+
+    pub fn record_settlement(&mut self, amount: u64, fee: u64) -> Result<()> {
+        self.total_settled = self
+            .total_settled
+            .checked_add(amount as u128)
+            .ok_or(FlowError::MathOverflow)?;
+        self.total_fees = self
+            .total_fees
+            .checked_add(fee as u128)
+            .ok_or(FlowError::MathOverflow)?;
+        self.settlement_count = self
+            .settlement_count
+            .checked_add(1)
+            .ok_or(FlowError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Takes a clawed-back `amount` out of `total_settled`. The fee was kept,
+    /// so `total_fees` and the settlement count stay as they are.
+    pub fn record_clawback(&mut self, amount: u64) -> Result<()> {
+        self.total_settled = self
+            .total_settled
+            .checked_sub(amount as u128)
+            .ok_or(FlowError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn vault_opened(&mut self) -> Result<()> {
+        self.active_vaults = self
+            .active_vaults
+            .checked_add(1)
+            .ok_or(FlowError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Saturates so vaults created before the counter existed can still
+    /// close.
+    pub fn vault_closed(&mut self) {
+        self.active_vaults = self.active_vaults.saturating_sub(1);
+    }
+
+    /// `close_config` only runs once no vault still needs the config.
+    pub fn require_no_active_vaults(&self) -> Result<()> {
+        if self.active_vaults > 0 {
+            return err!(FlowError::VaultsStillActive);
+        }
+        Ok(())
+    }
+
+    /// Rejects an initial deposit outside `[min_deposit, max_deposit]`.
+    pub fn check_deposit(&self, amount: u64) -> Result<()> {
+        if amount < self.min_deposit || (self.max_deposit != 0 && amount > self.max_deposit) {
+            return err!(FlowError::DepositOutOfBounds);
+        }
+        Ok(())
+    }
+
+    pub fn fee_tiers(&self) -> &[FeeTier] {
+        &self.fee_tiers[..self.fee_tier_count as usize]
+    }
+
+    /// Replaces the size-tier schedule. Tiers must be in strictly increasing
+    /// `min_amount` order and may only discount the base `fee_bps`, so they
+    /// cannot get around the fee increase timelock.
+    pub fn set_fee_tiers(&mut self, tiers: &[FeeTier]) -> Result<()> {
+        if tiers.len() > MAX_FEE_TIERS
+            || tiers.iter().any(|tier| tier.fee_bps > self.fee_bps)
+            || tiers.windows(2).any(|pair| pair[0].min_amount >= pair[1].min_amount)
+        {
+            return err!(FlowError::InvalidFeeTiers);
+        }
+
+        self.fee_tiers = [FeeTier::default(); MAX_FEE_TIERS];
+        self.fee_tiers[..tiers.len()].copy_from_slice(tiers);
+        self.fee_tier_count = tiers.len() as u8;
+        Ok(())
+    }
+
+    /// A provider's negotiated rate may only discount `fee_bps`, since raising
+    /// a fee has to wait out the timelock.
+    pub fn check_fee_override(&self, fee_bps_override: Option<u16>) -> Result<()> {
+        if fee_bps_override.is_some_and(|fee_bps| fee_bps > self.fee_bps) {
+            return err!(FlowError::InvalidFeeBps);
+        }
+        Ok(())
+    }
+
+    /// Base rate for a settlement of `amount`: the highest tier it reaches,
+    /// never above `fee_bps` even if that has since been lowered.
+    pub fn tier_fee_bps(&self, amount: u64) -> u16 {
+        self.fee_tiers()
+            .iter()
+            .rev()
+            .find(|tier| amount >= tier.min_amount)
+            .map_or(self.fee_bps, |tier| tier.fee_bps.min(self.fee_bps))
+    }
+
+    /// Protocol fee owed on `amount`, using the provider's negotiated rate
+    /// when it has one and the size tier otherwise, neither above `fee_bps`.
+    /// The product is taken in `u128` so it cannot overflow for any `u64`
+    /// amount.
+    pub fn calculate_fee(&self, amount: u64, fee_bps_override: Option<u16>) -> Result<u64> {
+        let fee_bps = fee_bps_override
+            .map_or_else(|| self.tier_fee_bps(amount), |fee_bps| fee_bps.min(self.fee_bps));
+        let fee = self.fee_rounding.divide(
+            (amount as u128)
+                .checked_mul(fee_bps as u128)
+                .ok_or(FlowError::MathOverflow)?,
+            BPS_DENOMINATOR as u128,
+        );
+        let fee = u64::try_from(fee).map_err(|_| error!(FlowError::MathOverflow))?;
+        if self.max_fee == 0 {
+            return Ok(fee);
+        }
+        Ok(fee.min(self.max_fee))
+    }
+}
+
+fn check_timelock(effective_after: i64, now: i64) -> Result<()> {
+    if effective_after == 0 {
+        return err!(FlowError::NoPendingConfigChange);
+    }
+    if now < effective_after {
+        return err!(FlowError::TimelockNotElapsed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(fee_bps: u16) -> GlobalConfig {
+        GlobalConfig {
+            fee_bps,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn single_admin_mode_needs_the_admin() {
+        let admin = Pubkey::new_unique();
+        let config = GlobalConfig {
+            admin,
+            ..Default::default()
+        };
+        assert!(config.require_authority(&[admin]).is_ok());
+        assert_eq!(
+            config.require_authority(&[Pubkey::new_unique()]).unwrap_err(),
+            error!(FlowError::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn two_of_three_needs_two_distinct_members() {
+        let members = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut config = GlobalConfig::default();
+        config.set_signers(&members, 2).unwrap();
+
+        assert_eq!(
+            config.require_authority(&[members[0]]).unwrap_err(),
+            error!(FlowError::Unauthorized)
+        );
+        assert_eq!(
+            config.require_authority(&[members[0], members[0]]).unwrap_err(),
+            error!(FlowError::Unauthorized)
+        );
+        assert!(config.require_authority(&[members[0], members[2]]).is_ok());
+        // The admin alone no longer suffices.
+        assert!(config.require_authority(&[config.admin]).is_err());
+    }
+
+    #[test]
+    fn fee_increase_waits_out_the_timelock() {
+        let mut config = GlobalConfig {
+            fee_bps: 100,
+            timelock_seconds: 60,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.apply_fee_increase(1_000).unwrap_err(),
+            error!(FlowError::NoPendingConfigChange)
+        );
+
+        assert_eq!(config.propose_fee_increase(250, 5_000, FeeRounding::Ceil, 1_000).unwrap(), 1_060);
+        assert_eq!(
+            config.apply_fee_increase(1_059).unwrap_err(),
+            error!(FlowError::TimelockNotElapsed)
+        );
+        assert_eq!(config.fee_bps, 100);
+        assert_eq!(config.apply_fee_increase(1_060).unwrap(), 250);
+        assert_eq!(config.max_fee, 5_000);
+        assert_eq!(config.fee_rounding, FeeRounding::Ceil);
+        assert_eq!(config.fee_effective_after, 0);
+    }
+
+    #[test]
+    fn unpause_waits_out_the_timelock() {
+        let mut config = GlobalConfig {
+            pause_flags: PAUSE_ALL,
+            timelock_seconds: 60,
+            ..Default::default()
+        };
+        config.propose_unpause(1_000).unwrap();
+        assert_eq!(
+            config.apply_unpause(1_030).unwrap_err(),
+            error!(FlowError::TimelockNotElapsed)
+        );
+        assert_eq!(config.pause_flags, PAUSE_ALL);
+        config.apply_unpause(1_060).unwrap();
+        assert_eq!(config.pause_flags, 0);
+    }
+
+    #[test]
+    fn pausing_one_instruction_leaves_the_others_open() {
+        let mut config = GlobalConfig::default();
+        config.pause(PAUSE_SETTLE).unwrap();
+        assert_eq!(
+            config.require_not_paused(PAUSE_SETTLE).unwrap_err(),
+            error!(FlowError::InstructionPaused)
+        );
+        config.require_not_paused(PAUSE_CREATE).unwrap();
+        config.require_not_paused(PAUSE_WITHDRAW).unwrap();
+        config.require_not_paused(PAUSE_DEPOSIT).unwrap();
+
+        config.pause(PAUSE_WITHDRAW).unwrap();
+        assert_eq!(config.pause_flags, PAUSE_SETTLE | PAUSE_WITHDRAW);
+        assert_eq!(config.pause(1 << 4).unwrap_err(), error!(FlowError::InvalidPauseFlags));
+        assert_eq!(config.pause(0).unwrap_err(), error!(FlowError::TimelockRequired));
+    }
+
+    #[test]
+    fn migration_keeps_withdrawals_open_under_a_legacy_pause() {
+        let mut config = GlobalConfig {
+            version: 4,
+            bump: 255,
+            pause_flags: 1,
+            ..Default::default()
+        };
+        assert_eq!(config.migrate(), Some(4));
+        assert_eq!(config.pause_flags, PAUSE_CREATE | PAUSE_SETTLE | PAUSE_DEPOSIT);
+        assert!(!config.is_paused(PAUSE_WITHDRAW));
+    }
+
+    #[test]
+    fn updates_cannot_skip_the_timelock() {
+        let config = GlobalConfig {
+            fee_bps: 100,
+            timelock_seconds: 60,
+            ..Default::default()
+        };
+        let params = ConfigParams {
+            settle_threshold: 0,
+            fee_bps: 50,
+            fee_destination: Pubkey::default(),
+            min_deposit: 0,
+            max_deposit: 0,
+            dispute_seconds: 0,
+            min_settle_interval: 0,
+            timelock_seconds: 60,
+            max_fee: 0,
+            referral_bps: 0,
+            fee_rounding: FeeRounding::Floor,
+            registration_fee: 0,
+            max_vaults_per_owner: 0,
+            clawback_seconds: 0,
+            dust_threshold: 0,
+        };
+        assert!(config.check_update(&params).is_ok());
+        for params in [
+            ConfigParams { fee_bps: 101, ..params.clone() },
+            ConfigParams { timelock_seconds: 59, ..params.clone() },
+        ] {
+            assert_eq!(
+                config.check_update(&params).unwrap_err(),
+                error!(FlowError::TimelockRequired)
+            );
+        }
+    }
+
+    #[test]
+    fn raising_the_fee_cap_needs_the_timelock() {
+        let config = GlobalConfig { max_fee: 1_000, ..Default::default() };
+        let params = ConfigParams {
+            settle_threshold: 1,
+            fee_bps: 0,
+            fee_destination: Pubkey::default(),
+            min_deposit: 0,
+            max_deposit: 0,
+            dispute_seconds: 0,
+            min_settle_interval: 0,
+            timelock_seconds: 0,
+            max_fee: 1_000,
+            referral_bps: 0,
+            fee_rounding: FeeRounding::Floor,
+            registration_fee: 0,
+            max_vaults_per_owner: 0,
+            clawback_seconds: 0,
+            dust_threshold: 0,
+        };
+        assert!(config.check_update(&params).is_ok());
+        assert!(config.check_update(&ConfigParams { max_fee: 999, ..params.clone() }).is_ok());
+        assert_eq!(
+            config.check_update(&ConfigParams { max_fee: 1_001, ..params.clone() }).unwrap_err(),
+            error!(FlowError::TimelockRequired)
+        );
+    }
+
+    #[test]
+    fn removing_the_fee_cap_needs_the_timelock() {
+        let capped = GlobalConfig { max_fee: 1_000, ..Default::default() };
+        let params = ConfigParams {
+            settle_threshold: 1,
+            fee_bps: 0,
+            fee_destination: Pubkey::default(),
+            min_deposit: 0,
+            max_deposit: 0,
+            dispute_seconds: 0,
+            min_settle_interval: 0,
+            timelock_seconds: 0,
+            max_fee: 0,
+            referral_bps: 0,
+            fee_rounding: FeeRounding::Floor,
+            registration_fee: 0,
+            max_vaults_per_owner: 0,
+            clawback_seconds: 0,
+            dust_threshold: 0,
+        };
+        assert_eq!(
+            capped.check_update(&params).unwrap_err(),
+            error!(FlowError::TimelockRequired)
+        );
+        // Capping an uncapped config only lowers what settlements can pay.
+        let uncapped = GlobalConfig::default();
+        assert!(uncapped.check_update(&params).is_ok());
+        assert!(uncapped.check_update(&ConfigParams { max_fee: 500, ..params }).is_ok());
+    }
+
+    #[test]
+    fn rounding_fees_up_needs_the_timelock() {
+        let params = ConfigParams {
+            settle_threshold: 1,
+            fee_bps: 0,
+            fee_destination: Pubkey::default(),
+            min_deposit: 0,
+            max_deposit: 0,
+            dispute_seconds: 0,
+            min_settle_interval: 0,
+            timelock_seconds: 0,
+            max_fee: 0,
+            referral_bps: 0,
+            fee_rounding: FeeRounding::Floor,
+            registration_fee: 0,
+            max_vaults_per_owner: 0,
+            clawback_seconds: 0,
+            dust_threshold: 0,
+        };
+        let modes = [FeeRounding::Floor, FeeRounding::Nearest, FeeRounding::Ceil];
+        for (i, current) in modes.into_iter().enumerate() {
+            let config = GlobalConfig { fee_rounding: current, ..Default::default() };
+            for (j, fee_rounding) in modes.into_iter().enumerate() {
+                let result = config.check_update(&ConfigParams { fee_rounding, ..params.clone() });
+                if j > i {
+                    assert_eq!(result.unwrap_err(), error!(FlowError::TimelockRequired));
+                } else {
+                    assert!(result.is_ok());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn event_seq_advances_by_one_and_never_wraps() {
+        let mut config = GlobalConfig::default();
+        assert_eq!(config.next_event_seq().unwrap(), 1);
+        assert_eq!(config.next_event_seq().unwrap(), 2);
+
+        config.event_seq = u64::MAX;
+        assert_eq!(config.next_event_seq().unwrap_err(), error!(FlowError::MathOverflow));
+        assert_eq!(config.event_seq, u64::MAX);
+    }
+
+    #[test]
+    fn params_are_validated_before_they_are_stored() {
+        let params = ConfigParams {
+            settle_threshold: 1,
+            fee_bps: 10_000,
+            fee_destination: Pubkey::default(),
+            min_deposit: 0,
+            max_deposit: 0,
+            dispute_seconds: 0,
+            min_settle_interval: 0,
+            timelock_seconds: 0,
+            max_fee: 0,
+            referral_bps: 0,
+            fee_rounding: FeeRounding::Floor,
+            registration_fee: 0,
+            max_vaults_per_owner: 0,
+            clawback_seconds: 0,
+            dust_threshold: 0,
+        };
+        let mut config = GlobalConfig::default();
+        config.apply(&params).unwrap();
+
+        for (params, error) in [
+            (ConfigParams { fee_bps: 15_000, ..params.clone() }, FlowError::InvalidFeeBps),
+            (ConfigParams { settle_threshold: 0, ..params.clone() }, FlowError::InvalidThreshold),
+        ] {
+            assert_eq!(config.apply(&params).unwrap_err(), error!(error));
+        }
+        assert_eq!(config.fee_bps, 10_000);
+        assert_eq!(config.settle_threshold, 1);
+    }
+
+    #[test]
+    fn referral_takes_its_share_of_the_fee() {
+        let config = GlobalConfig {
+            fee_bps: 250,
+            referral_bps: 2_000,
+            ..Default::default()
+        };
+        // 250 bps of 1_000_000 is 25_000; 20% of that goes to the referrer.
+        let fee = config.calculate_fee(1_000_000, None).unwrap();
+        assert_eq!(config.split_referral(fee), (5_000, 20_000));
+        assert_eq!(1_000_000 - fee, 975_000);
+        assert_eq!(config.split_referral(7), (1, 6));
+        assert_eq!(GlobalConfig::default().split_referral(7), (0, 7));
+    }
+
+    #[test]
+    fn max_fee_caps_large_settlements_only() {
+        let mut config = GlobalConfig {
+            fee_bps: 100,
+            max_fee: 5_000,
+            ..Default::default()
+        };
+        assert_eq!(config.calculate_fee(100_000, None).unwrap(), 1_000);
+        assert_eq!(config.calculate_fee(500_000, None).unwrap(), 5_000);
+        assert_eq!(config.calculate_fee(100_000_000, None).unwrap(), 5_000);
+        assert_eq!(config.calculate_fee(100_000_000, Some(10)).unwrap(), 5_000);
+
+        config.max_fee = 0;
+        assert_eq!(config.calculate_fee(100_000_000, None).unwrap(), 1_000_000);
+    }
+
+    #[test]
+    fn fee_override_only_discounts() {
+        let mut config = GlobalConfig {
+            fee_bps: 100,
+            ..Default::default()
+        };
+        config.check_fee_override(Some(100)).unwrap();
+        config.check_fee_override(None).unwrap();
+        assert_eq!(
+            config.check_fee_override(Some(101)).unwrap_err(),
+            error!(FlowError::InvalidFeeBps)
+        );
+
+        // An override set before `fee_bps` was lowered is capped by it.
+        config.fee_bps = 50;
+        assert_eq!(config.calculate_fee(10_000, Some(100)).unwrap(), 50);
+        assert_eq!(config.calculate_fee(10_000, Some(10)).unwrap(), 10);
+    }
+
+    #[test]
+    fn direct_payments_follow_the_settlement_rules() {
+        let mut config = GlobalConfig {
+            settle_threshold: 100,
+            fee_bps: 250,
+            max_fee: 10,
+            version: GlobalConfig::VERSION,
+            ..Default::default()
+        };
+        let mut provider = Provider {
+            version: Provider::VERSION,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.check_direct_payment(&provider, 99).unwrap_err(),
+            error!(FlowError::BelowSettleThreshold)
+        );
+        let split = config.check_direct_payment(&provider, 1_000).unwrap();
+        assert_eq!((split.fee_amount, split.net_to_provider), (10, 990));
+
+        provider.paused = true;
+        assert_eq!(
+            config.check_direct_payment(&provider, 1_000).unwrap_err(),
+            error!(FlowError::ProviderPaused)
+        );
+        provider.paused = false;
+        config.pause_flags = PAUSE_SETTLE;
+        assert_eq!(
+            config.check_direct_payment(&provider, 1_000).unwrap_err(),
+            error!(FlowError::InstructionPaused)
+        );
+    }
+
+    #[test]
+    fn stats_accumulate_per_settlement() {
+        let mut config = GlobalConfig::default();
+        config.record_settlement(u64::MAX, 10).unwrap();
+        config.record_settlement(u64::MAX, 5).unwrap();
+        assert_eq!(config.total_settled, 2 * u64::MAX as u128);
+        assert_eq!(config.total_fees, 15);
+        assert_eq!(config.settlement_count, 2);
+        config.record_clawback(u64::MAX).unwrap();
+        assert_eq!(config.total_settled, u64::MAX as u128);
+        assert_eq!(config.total_fees, 15);
+
+        config.vault_opened().unwrap();
+        config.vault_closed();
+        config.vault_closed();
+        assert_eq!(config.active_vaults, 0);
+    }
+
+    #[test]
+    fn len_fits_a_config_with_a_pending_admin() {
+        let config = GlobalConfig {
+            pending_admin: Some(Pubkey::new_unique()),
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        config.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), GlobalConfig::LEN);
+    }
+
+    #[test]
+    fn admin_changes_only_once_the_proposal_is_accepted() {
+        let (admin, proposed) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut config = GlobalConfig {
+            admin,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.accept_admin().unwrap_err(),
+            error!(FlowError::InvalidPendingAuthority)
+        );
+
+        config.pending_admin = Some(proposed);
+        assert_eq!(config.cancel_admin_proposal().unwrap(), proposed);
+        assert_eq!(config.admin, admin);
+        assert_eq!(
+            config.cancel_admin_proposal().unwrap_err(),
+            error!(FlowError::InvalidPendingAuthority)
+        );
+
+        config.pending_admin = Some(proposed);
+        assert_eq!(config.accept_admin().unwrap(), admin);
+        assert_eq!(config.admin, proposed);
+        assert_eq!(config.pending_admin, None);
+    }
+
+    #[test]
+    fn config_closes_only_without_active_vaults() {
+        let mut config = GlobalConfig::default();
+        assert!(config.require_no_active_vaults().is_ok());
+        config.vault_opened().unwrap();
+        assert_eq!(
+            config.require_no_active_vaults().unwrap_err(),
+            error!(FlowError::VaultsStillActive)
+        );
+        config.vault_closed();
+        assert!(config.require_no_active_vaults().is_ok());
+    }
+
+    #[test]
+    fn unset_guardian_matches_no_one() {
+        let mut config = GlobalConfig::default();
+        assert!(!config.is_guardian(&Pubkey::default()));
+
+        config.guardian = Pubkey::new_unique();
+        assert!(config.is_guardian(&config.guardian));
+        assert!(!config.is_guardian(&config.admin));
+    }
+
+    #[test]
+    fn invalid_multisigs_are_rejected() {
+        let key = Pubkey::new_unique();
+        let mut config = GlobalConfig::default();
+        assert!(config.set_signers(&[key], 2).is_err());
+        assert!(config.set_signers(&[key, key], 1).is_err());
+        assert!(config.set_signers(&[key], 0).is_err());
+        assert!(config.set_signers(&[], 0).is_ok());
+    }
+
+    fn splits(shares: &[u16]) -> Vec<FeeSplit> {
+        shares
+            .iter()
+            .map(|&share_bps| FeeSplit {
+                recipient: Pubkey::new_unique(),
+                share_bps,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fee_split_dust_goes_to_first_recipient() {
+        let mut config = GlobalConfig::default();
+        config.set_fee_splits(&splits(&[6_000, 4_000])).unwrap();
+        assert_eq!(config.split_fee(1_000), vec![600, 400]);
+        assert_eq!(config.split_fee(999), vec![600, 399]);
+        assert_eq!(config.split_fee(1), vec![1, 0]);
+        assert_eq!(config.split_fee(0), vec![0, 0]);
+    }
+
+    #[test]
+    fn invalid_fee_splits_are_rejected() {
+        let mut config = GlobalConfig::default();
+        for shares in [&[6_000, 3_000][..], &[10_000, 0], &[2_500, 2_500, 2_500, 2_000, 500]] {
+            assert_eq!(
+                config.set_fee_splits(&splits(shares)).unwrap_err(),
+                error!(FlowError::InvalidFeeSplits)
+            );
+        }
+        config.set_fee_splits(&splits(&[10_000])).unwrap();
+        config.set_fee_splits(&[]).unwrap();
+        assert!(config.fee_splits().is_empty());
+    }
+
+    fn tiers(entries: &[(u64, u16)]) -> Vec<FeeTier> {
+        entries
+            .iter()
+            .map(|&(min_amount, fee_bps)| FeeTier { min_amount, fee_bps })
+            .collect()
+    }
+
+    #[test]
+    fn fee_rounding_picks_where_the_dust_goes() {
+        let mut config = GlobalConfig {
+            fee_bps: 250,
+            ..Default::default()
+        };
+        // 1_001 * 250 / 10_000 = 25.025; 1_020 * 250 / 10_000 = 25.5
+        assert_eq!(config.calculate_fee(1_001, None).unwrap(), 25);
+        config.fee_rounding = FeeRounding::Ceil;
+        assert_eq!(config.calculate_fee(1_001, None).unwrap(), 26);
+        assert_eq!(config.calculate_fee(1_000, None).unwrap(), 25);
+        config.fee_rounding = FeeRounding::Nearest;
+        assert_eq!(config.calculate_fee(1_001, None).unwrap(), 25);
+        assert_eq!(config.calculate_fee(1_020, None).unwrap(), 26);
+    }
+
+    #[test]
+    fn fee_tiers_pick_the_highest_tier_reached() {
+        let mut config = GlobalConfig {
+            fee_bps: 100,
+            ..Default::default()
+        };
+        config.set_fee_tiers(&tiers(&[(1_000, 50), (10_000, 20)])).unwrap();
+        assert_eq!(config.tier_fee_bps(999), 100);
+        assert_eq!(config.tier_fee_bps(1_000), 50);
+        assert_eq!(config.tier_fee_bps(9_999), 50);
+        assert_eq!(config.tier_fee_bps(10_000), 20);
+        assert_eq!(config.calculate_fee(10_000, None).unwrap(), 20);
+        // A negotiated rate still wins over the tiers.
+        assert_eq!(config.calculate_fee(10_000, Some(100)).unwrap(), 100);
+
+        // Lowering the base rate caps every tier.
+        config.fee_bps = 30;
+        assert_eq!(config.tier_fee_bps(1_000), 30);
+    }
+
+    #[test]
+    fn invalid_fee_tiers_are_rejected() {
+        let mut config = GlobalConfig {
+            fee_bps: 100,
+            ..Default::default()
+        };
+        for entries in [
+            &[(1_000, 50), (1_000, 20)][..],
+            &[(10_000, 20), (1_000, 50)],
+            &[(1_000, 101)],
+            &[(1, 90), (2, 80), (3, 70), (4, 60), (5, 50)],
+        ] {
+            assert_eq!(
+                config.set_fee_tiers(&tiers(entries)).unwrap_err(),
+                error!(FlowError::InvalidFeeTiers)
+            );
+        }
+        config.set_fee_tiers(&tiers(&[(0, 100)])).unwrap();
+        config.set_fee_tiers(&[]).unwrap();
+        assert!(config.fee_tiers().is_empty());
+    }
+
+    #[test]
+    fn deposit_limits_are_inclusive() {
+        let config = GlobalConfig {
+            min_deposit: 100,
+            max_deposit: 1_000,
+            ..Default::default()
+        };
+        assert!(config.check_deposit(100).is_ok());
+        assert!(config.check_deposit(1_000).is_ok());
+        assert_eq!(
+            config.check_deposit(99).unwrap_err(),
+            error!(FlowError::DepositOutOfBounds)
+        );
+        assert_eq!(
+            config.check_deposit(1_001).unwrap_err(),
+            error!(FlowError::DepositOutOfBounds)
+        );
+    }
+
+    #[test]
+    fn zero_max_deposit_is_unbounded() {
+        let config = GlobalConfig {
+            min_deposit: 100,
+            ..Default::default()
+        };
+        assert!(config.check_deposit(u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn fee_is_floored_basis_points() {
+        assert_eq!(config(100).calculate_fee(2_000_000, None).unwrap(), 20_000);
+        assert_eq!(config(250).calculate_fee(399, None).unwrap(), 9);
+        assert_eq!(config(100).calculate_fee(0, None).unwrap(), 0);
+    }
+
+    #[test]
+    fn provider_override_replaces_global_rate() {
+        assert_eq!(config(250).calculate_fee(10_000, Some(50)).unwrap(), 50);
+        assert_eq!(config(250).calculate_fee(10_000, Some(0)).unwrap(), 0);
+        assert_eq!(config(250).calculate_fee(10_000, None).unwrap(), 250);
+    }
+
+    #[test]
+    fn fee_does_not_overflow_near_u64_max() {
+        assert_eq!(
+            config(10_000).calculate_fee(u64::MAX, None).unwrap(),
+            u64::MAX
+        );
+        assert_eq!(
+            config(100).calculate_fee(u64::MAX, None).unwrap(),
+            u64::MAX / 100
+        );
+    }
+
+    #[test]
+    fn fee_larger_than_u64_is_a_clean_error() {
+        assert_eq!(
+            config(u16::MAX).calculate_fee(u64::MAX, None).unwrap_err(),
+            error!(FlowError::MathOverflow)
+        );
+    }
 }
\ No newline at end of file
@@ -1,7 +1,64 @@
+use anchor_lang::prelude::*;
+use crate::errors::FlowError;
+
+/// `GlobalConfig`, `Provider` and `Vault` each carry the layout version they
+/// were written with, bumped on its own as that type changes. Handlers reject
+/// older accounts until `migrate_account` has brought them up to `current`.
+pub fn require_current_version(version: u8, current: u8) -> Result<()> {
+    if version < current {
+        return err!(FlowError::StaleAccountVersion);
+    }
+    Ok(())
+}
+
+/// Clock for handlers whose rules depend on the time. Some local harnesses
+/// leave the sysvar unset, reading zero, which would quietly make expiries,
+/// timelocks and cooldowns all resolve one way; such clocks are refused
+/// with `ClockUnavailable` instead.
+pub fn checked_clock() -> Result<Clock> {
+    let clock = Clock::get()?;
+    require_clock_set(clock.unix_timestamp)?;
+    Ok(clock)
+}
+
+pub fn require_clock_set(unix_timestamp: i64) -> Result<()> {
+    if unix_timestamp <= 0 {
+        return err!(FlowError::ClockUnavailable);
+    }
+    Ok(())
+}
+
 pub mod global_config;
 pub mod provider;
 pub mod vault;
+pub mod settlement_root;
+pub mod settlement_receipt;
+pub mod nonce_bitmap;
+pub mod contribution;
+pub mod owner_vault_count;
 
 pub use global_config::*;
 pub use provider::*;
-pub use vault::*;
\ No newline at end of file
+pub use vault::*;
+pub use settlement_root::*;
+pub use settlement_receipt::*;
+pub use nonce_bitmap::*;
+pub use contribution::*;
+pub use owner_vault_count::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unset_clock_is_refused() {
+        for unix_timestamp in [0, -1] {
+            assert_eq!(
+                require_clock_set(unix_timestamp).unwrap_err(),
+                error!(FlowError::ClockUnavailable)
+            );
+        }
+        assert!(require_clock_set(1).is_ok());
+    }
+}
+
@@ -0,0 +1,98 @@
+use anchor_lang::prelude::*;
+use crate::errors::FlowError;
+
+/// Nonce slots a bitmap can track: nonces `1..NONCE_BITMAP_SLOTS`.
+pub const NONCE_BITMAP_SLOTS: usize = 65_536;
+
+/// Used-nonce bitmap for vaults that settle more vouchers than the vault's
+/// replay window can hold. Zero-copy, so `settle_batch` flips a single bit
+/// without deserializing the 8 KB account.
+///
+/// Seeds: `[b"nonce_bitmap", vault]`. Once a vault has one, every nonce-based
+/// settlement must pass it to `settle_batch`.
+#[account(zero_copy)]
+#[repr(C)]
+pub struct NonceBitmap {
+    pub vault: Pubkey,
+    pub bits: [u8; NONCE_BITMAP_SLOTS / 8],
+}
+
+impl NonceBitmap {
+    // discriminator + vault + bits
+    pub const LEN: usize = 8 + 32 + NONCE_BITMAP_SLOTS / 8;
+
+    pub fn is_used(&self, nonce: u64) -> bool {
+        self.bits[nonce as usize / 8] & (1 << (nonce % 8)) != 0
+    }
+
+    /// Rejects nonce zero, nonces past the last slot and nonces already used.
+    pub fn check(&self, nonce: u64) -> Result<()> {
+        if nonce as usize >= NONCE_BITMAP_SLOTS {
+            return err!(FlowError::NonceOutOfRange);
+        }
+        if nonce == 0 {
+            return err!(FlowError::InvalidNonce);
+        }
+        if self.is_used(nonce) {
+            return err!(FlowError::NonceReused);
+        }
+        Ok(())
+    }
+
+    pub fn mark_used(&mut self, nonce: u64) {
+        self.bits[nonce as usize / 8] |= 1 << (nonce % 8);
+    }
+
+    /// Marks every nonce up to `highest` as used, so vouchers the vault's
+    /// window already accepted cannot be replayed through the bitmap.
+    pub fn mark_used_through(&mut self, highest: u64) {
+        let last = (highest as usize).min(NONCE_BITMAP_SLOTS - 1);
+        let full_bytes = (last + 1) / 8;
+        self.bits[..full_bytes].fill(u8::MAX);
+        for nonce in full_bytes * 8..=last {
+            self.mark_used(nonce as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitmap() -> NonceBitmap {
+        NonceBitmap {
+            vault: Pubkey::default(),
+            bits: [0; NONCE_BITMAP_SLOTS / 8],
+        }
+    }
+
+    #[test]
+    fn nonces_are_accepted_once_in_any_order() {
+        let mut bitmap = bitmap();
+        for nonce in [40_000, 2, 65_535] {
+            bitmap.check(nonce).unwrap();
+            bitmap.mark_used(nonce);
+        }
+        for nonce in [2, 40_000, 65_535] {
+            assert_eq!(bitmap.check(nonce).unwrap_err(), error!(FlowError::NonceReused));
+        }
+        assert_eq!(bitmap.check(0).unwrap_err(), error!(FlowError::InvalidNonce));
+        assert!(bitmap.check(3).is_ok());
+        assert_eq!(
+            bitmap.check(NONCE_BITMAP_SLOTS as u64).unwrap_err(),
+            error!(FlowError::NonceOutOfRange)
+        );
+    }
+
+    #[test]
+    fn window_nonces_are_carried_over() {
+        let mut bitmap = bitmap();
+        bitmap.mark_used_through(10);
+        assert!(bitmap.is_used(10));
+        assert!(!bitmap.is_used(11));
+
+        bitmap.mark_used_through(15);
+        assert!((0..=15).all(|nonce| bitmap.is_used(nonce)));
+        assert!(!bitmap.is_used(16));
+    }
+}
@@ -0,0 +1,100 @@
+use anchor_lang::prelude::*;
+use crate::errors::FlowError;
+
+/// How many vaults the `create_vault*` instructions have opened for
+/// one owner that have not been closed yet. Seeded by the owner, so every
+/// vault of theirs shares the record.
+#[account]
+pub struct OwnerVaultCount {
+    pub owner: Pubkey,
+    pub count: u16,
+    pub bump: u8,
+}
+
+impl OwnerVaultCount {
+    // discriminator + owner + count + bump
+    pub const LEN: usize = 8 + 32 + 2 + 1;
+
+    /// Counts a newly opened vault, refusing it once the owner already holds
+    /// `cap` vaults. A cap of zero means unlimited.
+    pub fn vault_opened(&mut self, cap: u16) -> Result<()> {
+        if cap != 0 && self.count >= cap {
+            return err!(FlowError::TooManyVaults);
+        }
+        self.count = self.count.checked_add(1).ok_or(FlowError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Saturates, since vaults opened before the count existed were never
+    /// added to it.
+    pub fn vault_closed(&mut self) {
+        self.count = self.count.saturating_sub(1);
+    }
+
+    /// Frees the slot a closing vault held in its creator's record, passed as
+    /// the PDA at `[b"owner_vaults", creator]`. Only a creator whose vaults
+    /// all predate the count has nothing there, and then nothing to free.
+    pub fn release(record: &AccountInfo) -> Result<()> {
+        if record.owner == &system_program::ID && record.data_is_empty() {
+            return Ok(());
+        }
+        require_keys_eq!(*record.owner, crate::ID, ErrorCode::AccountOwnedByWrongProgram);
+        let mut data = record.try_borrow_mut_data()?;
+        let mut owner_vaults = OwnerVaultCount::try_deserialize(&mut &data[..])?;
+        owner_vaults.vault_closed();
+        owner_vaults.try_serialize(&mut &mut data[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cap_frees_a_slot_once_a_vault_closes() {
+        let mut record = OwnerVaultCount {
+            owner: Pubkey::default(),
+            count: 0,
+            bump: 0,
+        };
+        record.vault_opened(2).unwrap();
+        record.vault_opened(2).unwrap();
+        assert_eq!(record.vault_opened(2).unwrap_err(), error!(FlowError::TooManyVaults));
+
+        record.vault_closed();
+        record.vault_opened(2).unwrap();
+        assert_eq!(record.count, 2);
+
+        record.vault_opened(0).unwrap();
+        assert_eq!(record.count, 3);
+    }
+
+    #[test]
+    fn release_skips_only_a_missing_record() {
+        let key = Pubkey::new_unique();
+        let (mut lamports, mut data) = (0, vec![]);
+        let missing = AccountInfo::new(
+            &key, false, true, &mut lamports, &mut data, &system_program::ID, false, 0,
+        );
+        OwnerVaultCount::release(&missing).unwrap();
+
+        let mut data = vec![];
+        OwnerVaultCount { owner: key, count: 2, bump: 0 }
+            .try_serialize(&mut data)
+            .unwrap();
+        let mut lamports = 1;
+        let record = AccountInfo::new(
+            &key, false, true, &mut lamports, &mut data, &crate::ID, false, 0,
+        );
+        OwnerVaultCount::release(&record).unwrap();
+        let count = OwnerVaultCount::try_deserialize(&mut &record.data.borrow()[..]).unwrap().count;
+        assert_eq!(count, 1);
+
+        let (mut lamports, mut forged) = (1, record.data.borrow().to_vec());
+        let stranger = Pubkey::new_unique();
+        let forged = AccountInfo::new(
+            &key, false, true, &mut lamports, &mut forged, &stranger, false, 0,
+        );
+        assert!(OwnerVaultCount::release(&forged).is_err());
+    }
+}
@@ -1,23 +1,86 @@
 use anchor_lang::prelude::*;
+use crate::errors::FlowError;
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+/// Longest provider display name, in bytes.
+pub const MAX_PROVIDER_NAME_LEN: usize = 32;
+/// Longest provider metadata URI, in bytes.
+pub const MAX_PROVIDER_URI_LEN: usize = 128;
+/// Space reserved for the Visa merchant id, in bytes.
+pub const MAX_VISA_MERCHANT_ID_LEN: usize = 32;
+/// Most destinations a provider can approve besides its own `destination`.
+pub const MAX_PROVIDER_DESTINATIONS: usize = 5;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq, InitSpace)]
 pub enum PaymentProtocol {
   NativeSpl,
   AtxpBridge,
 }
 
 #[account]
+#[derive(InitSpace)]
 pub struct Provider {
   pub authority: Pubkey,
   pub destination: Pubkey,
 
   /// [BOUNTY: Visa TAP]
+  #[max_len(MAX_VISA_MERCHANT_ID_LEN)]
   pub visa_merchant_id: Option<String>,
 
   /// [BOUNTY: ATXP]
   pub protocol: PaymentProtocol,
 
-  pub reserved: [u8; 90],
+  /// Negotiated fee replacing `GlobalConfig::fee_bps`, never above it; set
+  /// by the config authority.
+  pub fee_bps_override: Option<u16>,
+
+  /// Set by the provider authority to stop settlements to this provider only.
+  pub paused: bool,
+
+  /// Display name and metadata URI for provider directories.
+  #[max_len(MAX_PROVIDER_NAME_LEN)]
+  pub name: String,
+  #[max_len(MAX_PROVIDER_URI_LEN)]
+  pub uri: String,
+
+  /// Key proposed by `transfer_provider_authority`; becomes the authority
+  /// once it signs `accept_provider_authority`.
+  pub pending_authority: Option<Pubkey>,
+
+  /// Token accounts `settle_batch` may pay out to in addition to
+  /// `destination`, which is always allowed. Only the first
+  /// `allowed_destination_count` entries are used, each from its
+  /// `allowed_after` time on.
+  pub allowed_destinations: [Pubkey; MAX_PROVIDER_DESTINATIONS],
+  pub allowed_destination_count: u8,
+
+  /// Net settlement proceeds held in the provider's earnings account,
+  /// awaiting `claim_earnings`.
+  pub accrued: u64,
+
+  pub version: u8,
+
+  /// Canonical bump of the provider PDA.
+  pub bump: u8,
+
+  /// Token account of the affiliate that onboarded this provider; paid
+  /// `GlobalConfig::referral_bps` of each settlement fee.
+  pub referrer: Option<Pubkey>,
+
+  /// Destination queued by `propose_destination`, and the Unix time from
+  /// which `apply_destination` can swap it in. Settlements keep paying the
+  /// current `destination` until then.
+  pub pending_destination: Option<Pubkey>,
+  pub destination_effective_after: i64,
+
+  /// Unix time from which the matching `allowed_destinations` entry can be
+  /// paid, so a new entry waits out the same delay as a destination change.
+  pub allowed_after: [i64; MAX_PROVIDER_DESTINATIONS],
+
+  /// Unix time of the last `claim_earnings`. Settlements accrued up to then
+  /// have been paid out, so they can no longer be clawed back.
+  pub last_claimed_at: i64,
+
+  pub reserved: [u8; 10],
 
 }
 
@@ -28,12 +91,352 @@ impl Default for Provider {
       destination: Pubkey::default(),
       visa_merchant_id: None,
       protocol: PaymentProtocol::NativeSpl,
-      reserved: [0u8; 90],
+      fee_bps_override: None,
+      paused: false,
+      name: String::new(),
+      uri: String::new(),
+      pending_authority: None,
+      allowed_destinations: [Pubkey::default(); MAX_PROVIDER_DESTINATIONS],
+      allowed_destination_count: 0,
+      accrued: 0,
+      version: 0,
+      bump: 0,
+      referrer: None,
+      pending_destination: None,
+      destination_effective_after: 0,
+      allowed_after: [0; MAX_PROVIDER_DESTINATIONS],
+      last_claimed_at: 0,
+      reserved: [0u8; 10],
     }
   }
 }
 
 impl Provider {
-    // discriminator + authority + destination + reserved
-     pub const LEN: usize = 8 + 32 + 32 + (1 + 4 + 32) + 1 + 90;
+    /// Layout version; see `require_current_version`.
+    pub const VERSION: u8 = 6;
+    pub const LEN: usize = 8 + Self::INIT_SPACE;
+
+  pub fn validate_metadata(name: &str, uri: &str) -> Result<()> {
+    if name.len() > MAX_PROVIDER_NAME_LEN || uri.len() > MAX_PROVIDER_URI_LEN {
+      return err!(FlowError::MetadataTooLong);
+    }
+    Ok(())
+  }
+
+  pub fn allowed_destinations(&self) -> &[Pubkey] {
+    &self.allowed_destinations[..self.allowed_destination_count as usize]
+  }
+
+  fn allowed_index(&self, key: &Pubkey) -> Option<usize> {
+    self.allowed_destinations().iter().position(|allowed| allowed == key)
+  }
+
+  /// Whether `key` can be paid at `now`: the provider's own destination, or
+  /// a whitelist entry whose delay is over.
+  pub fn is_allowed_destination(&self, key: &Pubkey, now: i64) -> bool {
+    *key == self.destination
+      || self
+        .allowed_index(key)
+        .is_some_and(|index| now >= self.allowed_after[index])
+  }
+
+  /// Approves `key` as a settlement destination from `delay` seconds after
+  /// `now`, returning that time. Adding one that is already listed keeps
+  /// its original time.
+  pub fn add_destination(&mut self, key: Pubkey, delay: i64, now: i64) -> Result<i64> {
+    if key == self.destination {
+      return Ok(now);
+    }
+    if let Some(index) = self.allowed_index(&key) {
+      return Ok(self.allowed_after[index]);
+    }
+    let count = self.allowed_destination_count as usize;
+    if count == MAX_PROVIDER_DESTINATIONS {
+      return err!(FlowError::DestinationListFull);
+    }
+    let allowed_after = now.checked_add(delay).ok_or(FlowError::MathOverflow)?;
+    self.allowed_destinations[count] = key;
+    self.allowed_after[count] = allowed_after;
+    self.allowed_destination_count += 1;
+    Ok(allowed_after)
+  }
+
+  /// Whether `key` is listed but still waiting out its delay.
+  pub fn is_pending_destination(&self, key: &Pubkey, now: i64) -> bool {
+    self
+      .allowed_index(key)
+      .is_some_and(|index| now < self.allowed_after[index])
+  }
+
+  /// Withdraws approval for `key`. The provider's own `destination` cannot be
+  /// removed.
+  pub fn remove_destination(&mut self, key: &Pubkey) -> Result<()> {
+    let index = self.allowed_index(key).ok_or(FlowError::DestinationNotWhitelisted)?;
+    let count = self.allowed_destination_count as usize;
+    self.allowed_destinations.copy_within(index + 1..count, index);
+    self.allowed_after.copy_within(index + 1..count, index);
+    self.allowed_destinations[count - 1] = Pubkey::default();
+    self.allowed_after[count - 1] = 0;
+    self.allowed_destination_count -= 1;
+    Ok(())
+  }
+
+  /// Queues `destination` to replace the provider's own destination once
+  /// `delay` seconds have passed. Returns when it can be applied.
+  pub fn propose_destination(&mut self, destination: Pubkey, delay: i64, now: i64) -> Result<i64> {
+    if self.pending_destination.is_some() {
+      return err!(FlowError::DestinationChangePending);
+    }
+    let effective_after = now.checked_add(delay).ok_or(FlowError::MathOverflow)?;
+    self.pending_destination = Some(destination);
+    self.destination_effective_after = effective_after;
+    Ok(effective_after)
+  }
+
+  /// Swaps in the pending destination once its delay is over, returning the
+  /// destination it replaced.
+  pub fn apply_destination(&mut self, now: i64) -> Result<Pubkey> {
+    let destination = self
+      .pending_destination
+      .ok_or(FlowError::NoPendingDestinationChange)?;
+    if now < self.destination_effective_after {
+      return err!(FlowError::DestinationChangePending);
+    }
+    self.pending_destination = None;
+    self.destination_effective_after = 0;
+    Ok(std::mem::replace(&mut self.destination, destination))
+  }
+
+  /// Drops the pending destination, returning it.
+  pub fn cancel_destination(&mut self) -> Result<Pubkey> {
+    let destination = self
+      .pending_destination
+      .take()
+      .ok_or(FlowError::NoPendingDestinationChange)?;
+    self.destination_effective_after = 0;
+    Ok(destination)
+  }
+
+  /// Brings an account written by an older program up to
+  /// `VERSION`, returning the version it had. `None` if it was
+  /// already current. `key` is the provider's own address.
+  pub fn migrate(&mut self, key: &Pubkey) -> Option<u8> {
+    let from_version = self.version;
+    if from_version >= Self::VERSION {
+      return None;
+    }
+    // v2 stores the PDA bump. It can only be recovered while the authority
+    // that registered the provider still holds it; no handler re-derives the
+    // provider PDA, so an unknown bump is left at zero.
+    if from_version < 2 {
+      let (derived, bump) =
+        Pubkey::find_program_address(&[b"provider", self.authority.as_ref()], &crate::ID);
+      if derived == *key {
+        self.bump = bump;
+      }
+    }
+    // Every other field added since v0 defaults to zero.
+    self.version = Self::VERSION;
+    Some(from_version)
+  }
+
+  pub fn accrue(&mut self, amount: u64) -> Result<()> {
+    self.accrued = self.accrued.checked_add(amount).ok_or(FlowError::MathOverflow)?;
+    Ok(())
+  }
+
+  /// Zeroes the ledger and returns what it held, recording `now` as the
+  /// claim time.
+  pub fn take_accrued(&mut self, now: i64) -> Result<u64> {
+    if self.accrued == 0 {
+      return err!(FlowError::NoAccruedEarnings);
+    }
+    self.last_claimed_at = now;
+    Ok(std::mem::take(&mut self.accrued))
+  }
+
+  /// Takes `amount` of a settlement accrued at `settled_at` back out of the
+  /// ledger for a clawback. A settlement no later than the last claim has
+  /// been paid out, and later accruals belong to other settlements.
+  pub fn return_accrued(&mut self, amount: u64, settled_at: i64) -> Result<()> {
+    if settled_at <= self.last_claimed_at {
+      return err!(FlowError::ReceiptAlreadyClaimed);
+    }
+    self.accrued = self
+      .accrued
+      .checked_sub(amount)
+      .ok_or(FlowError::MathOverflow)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn metadata_at_the_limits_is_accepted() {
+    let name = "n".repeat(MAX_PROVIDER_NAME_LEN);
+    let uri = "u".repeat(MAX_PROVIDER_URI_LEN);
+    assert!(Provider::validate_metadata(&name, &uri).is_ok());
+    assert!(Provider::validate_metadata("", "").is_ok());
+  }
+
+  #[test]
+  fn len_fits_a_provider_at_its_limits() {
+    let provider = Provider {
+      visa_merchant_id: Some("v".repeat(MAX_VISA_MERCHANT_ID_LEN)),
+      fee_bps_override: Some(0),
+      name: "n".repeat(MAX_PROVIDER_NAME_LEN),
+      uri: "u".repeat(MAX_PROVIDER_URI_LEN),
+      pending_authority: Some(Pubkey::new_unique()),
+      referrer: Some(Pubkey::new_unique()),
+      pending_destination: Some(Pubkey::new_unique()),
+      ..Default::default()
+    };
+    let mut data = Vec::new();
+    provider.try_serialize(&mut data).unwrap();
+    assert_eq!(data.len(), Provider::LEN);
+  }
+
+  #[test]
+  fn destination_change_waits_out_the_delay() {
+    let old = Pubkey::new_unique();
+    let new = Pubkey::new_unique();
+    let mut provider = Provider {
+      destination: old,
+      ..Default::default()
+    };
+
+    assert_eq!(provider.propose_destination(new, 60, 1_000).unwrap(), 1_060);
+    assert_eq!(
+      provider.propose_destination(Pubkey::new_unique(), 60, 1_000).unwrap_err(),
+      error!(FlowError::DestinationChangePending)
+    );
+    assert_eq!(
+      provider.apply_destination(1_059).unwrap_err(),
+      error!(FlowError::DestinationChangePending)
+    );
+    assert!(provider.is_allowed_destination(&old, 1_060));
+    assert!(!provider.is_allowed_destination(&new, 1_060));
+
+    assert_eq!(provider.apply_destination(1_060).unwrap(), old);
+    assert_eq!(provider.destination, new);
+    assert_eq!(provider.pending_destination, None);
+    assert_eq!(
+      provider.apply_destination(1_060).unwrap_err(),
+      error!(FlowError::NoPendingDestinationChange)
+    );
+  }
+
+  #[test]
+  fn cancelled_destination_change_cannot_be_applied() {
+    let mut provider = Provider::default();
+    let new = Pubkey::new_unique();
+    provider.propose_destination(new, 60, 1_000).unwrap();
+
+    assert_eq!(provider.cancel_destination().unwrap(), new);
+    assert_eq!(provider.destination_effective_after, 0);
+    assert_eq!(
+      provider.apply_destination(2_000).unwrap_err(),
+      error!(FlowError::NoPendingDestinationChange)
+    );
+    assert_eq!(
+      provider.cancel_destination().unwrap_err(),
+      error!(FlowError::NoPendingDestinationChange)
+    );
+  }
+
+  #[test]
+  fn own_destination_is_always_allowed() {
+    let provider = Provider {
+      destination: Pubkey::new_unique(),
+      ..Default::default()
+    };
+    assert!(provider.is_allowed_destination(&provider.destination, 0));
+    assert!(!provider.is_allowed_destination(&Pubkey::new_unique(), 0));
+  }
+
+  #[test]
+  fn destinations_can_be_added_up_to_the_limit_and_removed() {
+    let mut provider = Provider::default();
+    let keys: Vec<Pubkey> = (0..MAX_PROVIDER_DESTINATIONS).map(|_| Pubkey::new_unique()).collect();
+    for (i, key) in keys.iter().enumerate() {
+      provider.add_destination(*key, 60, i as i64).unwrap();
+    }
+    assert_eq!(
+      provider.add_destination(Pubkey::new_unique(), 60, 0).unwrap_err(),
+      error!(FlowError::DestinationListFull)
+    );
+
+    provider.remove_destination(&keys[1]).unwrap();
+    assert!(!provider.is_allowed_destination(&keys[1], 1_000));
+    assert_eq!(provider.allowed_destinations(), &[keys[0], keys[2], keys[3], keys[4]]);
+    assert_eq!(provider.allowed_after[..4], [60, 62, 63, 64]);
+    assert_eq!(
+      provider.remove_destination(&keys[1]).unwrap_err(),
+      error!(FlowError::DestinationNotWhitelisted)
+    );
+  }
+
+  #[test]
+  fn new_destination_waits_out_the_delay() {
+    let mut provider = Provider::default();
+    let key = Pubkey::new_unique();
+    assert_eq!(provider.add_destination(key, 60, 1_000).unwrap(), 1_060);
+    assert!(provider.is_pending_destination(&key, 1_059));
+    assert!(!provider.is_allowed_destination(&key, 1_059));
+
+    // Re-adding it does not restart or skip the delay.
+    assert_eq!(provider.add_destination(key, 0, 1_050).unwrap(), 1_060);
+    assert!(provider.is_allowed_destination(&key, 1_060));
+    assert!(!provider.is_pending_destination(&key, 1_060));
+  }
+
+  #[test]
+  fn claiming_takes_the_whole_accrued_balance() {
+    let mut provider = Provider::default();
+    provider.accrue(700).unwrap();
+    provider.accrue(300).unwrap();
+    assert_eq!(provider.take_accrued(100).unwrap(), 1_000);
+    assert_eq!(provider.accrued, 0);
+    assert_eq!(provider.last_claimed_at, 100);
+    assert_eq!(
+      provider.take_accrued(101).unwrap_err(),
+      error!(FlowError::NoAccruedEarnings)
+    );
+  }
+
+  #[test]
+  fn claimed_settlements_stay_claimed_after_new_accruals() {
+    let mut provider = Provider::default();
+    provider.accrue(500).unwrap();
+    provider.take_accrued(100).unwrap();
+    provider.accrue(800).unwrap();
+
+    assert_eq!(
+      provider.return_accrued(500, 100).unwrap_err(),
+      error!(FlowError::ReceiptAlreadyClaimed)
+    );
+    provider.return_accrued(300, 101).unwrap();
+    assert_eq!(provider.accrued, 500);
+    assert_eq!(
+      provider.return_accrued(501, 101).unwrap_err(),
+      error!(FlowError::MathOverflow)
+    );
+  }
+
+  #[test]
+  fn metadata_over_the_limits_is_rejected() {
+    let name = "n".repeat(MAX_PROVIDER_NAME_LEN + 1);
+    let uri = "u".repeat(MAX_PROVIDER_URI_LEN + 1);
+    assert_eq!(
+      Provider::validate_metadata(&name, "").unwrap_err(),
+      error!(FlowError::MetadataTooLong)
+    );
+    assert_eq!(
+      Provider::validate_metadata("", &uri).unwrap_err(),
+      error!(FlowError::MetadataTooLong)
+    );
+  }
 }
\ No newline at end of file
@@ -0,0 +1,127 @@
+use anchor_lang::prelude::*;
+use crate::errors::FlowError;
+
+/// Durable record of one settlement, kept for accounting after RPCs have
+/// pruned the `Settlement` event. Seeded by `(vault, nonce)`, so a nonce can
+/// only ever get one receipt.
+#[account]
+pub struct SettlementReceipt {
+    pub vault: Pubkey,
+    pub provider: Pubkey,
+    pub nonce: u64,
+    pub amount: u64,
+    pub fee: u64,
+    pub timestamp: i64,
+    /// Net amount `clawback` has returned to the vault so far.
+    pub clawed_back: u64,
+    /// Set when the net amount accrued to the provider's earnings account
+    /// instead of being paid out; only those settlements can be clawed back.
+    pub accrued: bool,
+    /// What the provider received: `amount - fee`, or all of `amount` when
+    /// it paid the fee itself.
+    pub payout: u64,
+}
+
+impl SettlementReceipt {
+    // discriminator + vault + provider + nonce + amount + fee + timestamp
+    // + clawed_back + accrued + payout
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 1 + 8;
+
+    /// Marks `amount` of the provider's payout as returned. The provider may
+    /// return it at any time; the config authority only for
+    /// `clawback_seconds` after the settlement.
+    pub fn clawback(
+        &mut self,
+        amount: u64,
+        by_provider: bool,
+        clawback_seconds: i64,
+        now: i64,
+    ) -> Result<()> {
+        if amount == 0 {
+            return err!(FlowError::ZeroAmount);
+        }
+        if !self.accrued {
+            return err!(FlowError::ReceiptNotAccrued);
+        }
+        if !by_provider && now >= self.timestamp.saturating_add(clawback_seconds) {
+            return err!(FlowError::ClawbackWindowClosed);
+        }
+        let returnable = self
+            .payout
+            .checked_sub(self.clawed_back)
+            .ok_or(FlowError::MathOverflow)?;
+        if amount > returnable {
+            return err!(FlowError::InsufficientFunds);
+        }
+        self.clawed_back += amount;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt() -> SettlementReceipt {
+        SettlementReceipt {
+            vault: Pubkey::default(),
+            provider: Pubkey::default(),
+            nonce: 1,
+            amount: 1_000,
+            fee: 25,
+            timestamp: 100,
+            clawed_back: 0,
+            accrued: true,
+            payout: 975,
+        }
+    }
+
+    #[test]
+    fn authority_clawback_is_limited_to_the_window() {
+        let mut receipt = receipt();
+        receipt.clawback(500, false, 60, 159).unwrap();
+        assert_eq!(
+            receipt.clawback(100, false, 60, 160).unwrap_err(),
+            error!(FlowError::ClawbackWindowClosed)
+        );
+        assert_eq!(
+            receipt.clawback(100, false, 0, 100).unwrap_err(),
+            error!(FlowError::ClawbackWindowClosed)
+        );
+        // The provider is not bound by the window.
+        receipt.clawback(100, true, 0, 10_000).unwrap();
+        assert_eq!(receipt.clawed_back, 600);
+    }
+
+    #[test]
+    fn clawbacks_never_exceed_the_net_amount() {
+        let mut receipt = receipt();
+        receipt.clawback(975, true, 0, 100).unwrap();
+        assert_eq!(
+            receipt.clawback(1, true, 0, 100).unwrap_err(),
+            error!(FlowError::InsufficientFunds)
+        );
+    }
+
+    #[test]
+    fn provider_paid_fees_leave_the_whole_amount_returnable() {
+        let mut receipt = SettlementReceipt {
+            payout: 1_000,
+            ..receipt()
+        };
+        receipt.clawback(1_000, true, 0, 100).unwrap();
+        assert_eq!(receipt.clawed_back, 1_000);
+    }
+
+    #[test]
+    fn settlements_paid_out_directly_cannot_be_clawed_back() {
+        let mut receipt = SettlementReceipt {
+            accrued: false,
+            ..receipt()
+        };
+        assert_eq!(
+            receipt.clawback(100, true, 60, 100).unwrap_err(),
+            error!(FlowError::ReceiptNotAccrued)
+        );
+    }
+}
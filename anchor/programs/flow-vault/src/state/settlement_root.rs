@@ -0,0 +1,150 @@
+use anchor_lang::prelude::*;
+use solana_keccak_hasher::hashv;
+use crate::errors::FlowError;
+
+/// Most leaves a single root can commit to; sizes the claimed bitmap.
+pub const MAX_ROOT_LEAVES: usize = 256;
+/// Proof length needed for a full tree of `MAX_ROOT_LEAVES`.
+pub const MAX_PROOF_DEPTH: usize = 8;
+
+// Prefixes keep a leaf from being passed off as an interior node.
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+/// A Merkle root over `(vault, index, amount)` leaves, committed by the vault
+/// owner so the provider can claim each leaf with a proof.
+#[account]
+pub struct SettlementRoot {
+    pub vault: Pubkey,
+    pub provider: Pubkey,
+    pub root: [u8; 32],
+    pub leaf_count: u16,
+    /// Bit `i` is set once leaf `i` has been claimed.
+    pub claimed: [u8; MAX_ROOT_LEAVES / 8],
+    /// `Vault::created_slot` of the vault the root was committed for, so its
+    /// leaves cannot be claimed from a vault later created at the same
+    /// address.
+    pub vault_created_slot: u64,
+}
+
+impl SettlementRoot {
+    // discriminator + vault + provider + root + leaf_count + claimed + vault_created_slot
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 2 + MAX_ROOT_LEAVES / 8 + 8;
+
+    /// Leaf hash for `amount` at position `index` of `vault`'s tree.
+    pub fn leaf(vault: &Pubkey, index: u16, amount: u64) -> [u8; 32] {
+        hashv(&[LEAF_PREFIX, vault.as_ref(), &index.to_le_bytes(), &amount.to_le_bytes()]).to_bytes()
+    }
+
+    /// Rebuilds the root from `leaf` and its sibling path. The bits of `index`
+    /// pick which side each sibling sits on, from the bottom level up.
+    pub fn verify(&self, leaf: [u8; 32], index: u16, proof: &[[u8; 32]]) -> Result<()> {
+        if proof.len() > MAX_PROOF_DEPTH {
+            return err!(FlowError::InputTooLarge);
+        }
+        if index >= self.leaf_count {
+            return err!(FlowError::InvalidProof);
+        }
+
+        let mut node = leaf;
+        let mut position = index;
+        for sibling in proof {
+            node = if position & 1 == 0 {
+                hashv(&[NODE_PREFIX, &node, sibling]).to_bytes()
+            } else {
+                hashv(&[NODE_PREFIX, sibling, &node]).to_bytes()
+            };
+            position >>= 1;
+        }
+
+        if position != 0 || node != self.root {
+            return err!(FlowError::InvalidProof);
+        }
+        Ok(())
+    }
+
+    pub fn is_claimed(&self, index: u16) -> bool {
+        self.claimed[index as usize / 8] & (1 << (index % 8)) != 0
+    }
+
+    pub fn mark_claimed(&mut self, index: u16) -> Result<()> {
+        if self.is_claimed(index) {
+            return err!(FlowError::LeafAlreadyClaimed);
+        }
+        self.claimed[index as usize / 8] |= 1 << (index % 8);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        hashv(&[NODE_PREFIX, left, right]).to_bytes()
+    }
+
+    /// A four-leaf tree and its root.
+    fn tree(vault: &Pubkey) -> ([[u8; 32]; 4], SettlementRoot) {
+        let amounts = [10, 20, 30, 40];
+        let leaves: [[u8; 32]; 4] =
+            std::array::from_fn(|i| SettlementRoot::leaf(vault, i as u16, amounts[i]));
+        let root = node(&node(&leaves[0], &leaves[1]), &node(&leaves[2], &leaves[3]));
+        let account = SettlementRoot {
+            vault: *vault,
+            provider: Pubkey::default(),
+            root,
+            leaf_count: 4,
+            claimed: [0; MAX_ROOT_LEAVES / 8],
+            vault_created_slot: 0,
+        };
+        (leaves, account)
+    }
+
+    #[test]
+    fn valid_proof_rebuilds_root() {
+        let vault = Pubkey::new_unique();
+        let (leaves, account) = tree(&vault);
+        let proof = [leaves[3], node(&leaves[0], &leaves[1])];
+        assert!(account.verify(leaves[2], 2, &proof).is_ok());
+    }
+
+    #[test]
+    fn wrong_sibling_or_index_is_rejected() {
+        let vault = Pubkey::new_unique();
+        let (leaves, account) = tree(&vault);
+        let bad_sibling = [leaves[2], node(&leaves[0], &leaves[1])];
+        assert_eq!(
+            account.verify(leaves[2], 2, &bad_sibling).unwrap_err(),
+            error!(FlowError::InvalidProof)
+        );
+
+        let proof = [leaves[3], node(&leaves[0], &leaves[1])];
+        assert_eq!(
+            account.verify(leaves[2], 3, &proof).unwrap_err(),
+            error!(FlowError::InvalidProof)
+        );
+    }
+
+    #[test]
+    fn proof_deeper_than_max_is_rejected() {
+        let (leaves, account) = tree(&Pubkey::new_unique());
+        let proof = [leaves[0]; MAX_PROOF_DEPTH + 1];
+        assert_eq!(
+            account.verify(leaves[2], 2, &proof).unwrap_err(),
+            error!(FlowError::InputTooLarge)
+        );
+    }
+
+    #[test]
+    fn leaves_can_only_be_claimed_once() {
+        let (_, mut account) = tree(&Pubkey::new_unique());
+        account.mark_claimed(9).unwrap();
+        assert!(account.is_claimed(9));
+        assert!(!account.is_claimed(8));
+        assert_eq!(
+            account.mark_claimed(9).unwrap_err(),
+            error!(FlowError::LeafAlreadyClaimed)
+        );
+    }
+}
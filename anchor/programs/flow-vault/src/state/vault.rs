@@ -1,6 +1,97 @@
 use anchor_lang::prelude::*;
+use crate::errors::FlowError;
+use crate::state::{require_current_version, GlobalConfig, Provider, PAUSE_SETTLE};
+
+/// Number of nonces below the highest one that the replay window tracks.
+pub const NONCE_WINDOW_SIZE: u64 = 128;
+
+/// Rejects a settlement landing after `deadline_unix`; zero means no deadline.
+pub fn check_deadline(deadline_unix: i64, now: i64) -> Result<()> {
+    if deadline_unix != 0 && now > deadline_unix {
+        return err!(FlowError::DeadlineExceeded);
+    }
+    Ok(())
+}
+
+/// Extra PDA seed of a vault created for an external session id. The
+/// all-zero id is the owner's default vault and adds nothing, so its
+/// addresses are the same as before session ids existed.
+pub fn session_seed(session_id: &[u8; 16]) -> &[u8] {
+    if *session_id == [0; 16] {
+        &[]
+    } else {
+        session_id
+    }
+}
+
+/// How a settled amount is divided between the protocol and the provider.
+#[derive(Debug)]
+pub struct SettlementSplit {
+    pub fee_amount: u64,
+    pub net_to_provider: u64,
+}
+
+impl SettlementSplit {
+    /// What the vault sends the provider out of a settlement of `amount`.
+    pub fn payout(&self, amount: u64, fee_from: FeeSource) -> u64 {
+        match fee_from {
+            FeeSource::NetFromAmount => self.net_to_provider,
+            FeeSource::ProviderPays => amount,
+        }
+    }
+
+    /// Drops the fee so the provider nets the whole amount, returning the
+    /// fee that was waived.
+    pub fn waive_fee(&mut self) -> u64 {
+        let fee = std::mem::take(&mut self.fee_amount);
+        self.net_to_provider += fee;
+        fee
+    }
+}
+
+/// Where a settlement's fee is taken from.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FeeSource {
+    /// Netted out of the settled amount.
+    #[default]
+    NetFromAmount,
+    /// Pulled from a provider-owned token account that has approved the
+    /// provider PDA as delegate, so the whole amount reaches the provider.
+    ProviderPays,
+}
+
+/// Read-only summary of a vault returned by `get_vault_status`, so clients
+/// don't depend on the raw account layout.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct VaultStatus {
+    /// Funds still available for settlement or withdrawal.
+    pub balance: u64,
+    pub owner: Pubkey,
+    /// Provider of the settlement waiting out the dispute window, if any.
+    /// Vaults are not bound to a single provider.
+    pub provider: Option<Pubkey>,
+    pub expiry_unix: i64,
+    pub expired: bool,
+    /// Whether settlements from the vault are paused protocol-wide.
+    pub paused: bool,
+    pub last_nonce: u64,
+}
+
+/// What a settlement would do, returned by `simulate_settle`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SettlePreview {
+    pub gross: u64,
+    pub fee: u64,
+    pub net: u64,
+    /// Vault balance left after the settlement.
+    pub resulting_balance: u64,
+    /// Whether that balance falls below `settle_threshold`, so no further
+    /// settlement could follow.
+    pub would_cross_threshold: bool,
+}
 
 #[account]
+#[derive(Default, InitSpace)]
 pub struct Vault {
 
     pub agent: Pubkey,
@@ -9,26 +100,1330 @@ pub struct Vault {
     pub deposit_amount: u64,
     pub total_settled: u64,
     pub last_settlement_slot: u64,
+    /// Highest settlement nonce accepted so far; the top of the replay
+    /// window.
     pub nonce: u64,
-    pub reserved: [u8; 64],
+    /// Unix time after which settlements stop and the owner can reclaim the
+    /// balance with `refund_expired`. Zero means the vault never expires.
+    pub expiry_unix: i64,
+    /// Settlement waiting out the dispute window; zero when none is pending.
+    /// Held back from `balance` until it is finalized or cancelled.
+    pub pending_amount: u64,
+    pub pending_nonce: u64,
+    pub pending_provider: Pubkey,
+    /// Unix time from which the pending settlement can be finalized.
+    pub settle_after: i64,
+    /// Replay window below `nonce`: bit `i` is set once nonce `nonce - i`
+    /// has been used. Unused nonces inside the window are still accepted, so
+    /// settlements may land out of order.
+    pub nonce_window: u128,
+    /// Key the owner lets settle on its behalf, e.g. a hosted facilitator.
+    /// It cannot withdraw or change the delegate; the default key means none.
+    pub delegate: Pubkey,
+    /// Unix time of the last payout, for the config's settlement cooldown.
+    pub last_settle_ts: i64,
+    /// Key the vault PDAs are derived from: the `agent` that created it. It
+    /// stays fixed when ownership moves to a new `agent`.
+    pub creator: Pubkey,
+    /// Owner proposed by `transfer_vault_ownership`; becomes `agent` once it
+    /// signs `accept_vault_ownership`.
+    pub pending_agent: Option<Pubkey>,
+    /// Set once a `NonceBitmap` replaces the replay window for this vault.
+    pub uses_nonce_bitmap: bool,
+    pub version: u8,
+    /// Canonical bump of the vault PDA, stored so handlers skip re-deriving it.
+    pub bump: u8,
+    /// Seconds a `request_withdraw` must wait before `withdraw` can take it;
+    /// zero lets the owner withdraw immediately.
+    pub withdraw_timelock: i64,
+    /// Amount requested by `request_withdraw` and the Unix time it unlocks.
+    pub pending_withdraw: u64,
+    pub withdraw_unlock_at: i64,
+    /// Set at creation for vaults co-funded through `contribute`. Their funds
+    /// leave only through settlements and proportional expiry refunds.
+    pub shared: bool,
+    /// Sum of every `Contribution` to a shared vault, the owner's included.
+    pub total_contributed: u64,
+    /// Balance when the first expiry refund of a shared vault ran; each
+    /// contributor's refund is their share of it.
+    pub refund_pool: Option<u64>,
+    /// Contributions already paid out of `refund_pool`.
+    pub refunded_contributions: u64,
+    /// Set once `emergency_drain` has swept the vault; it takes no more
+    /// deposits, settlements or withdrawals.
+    pub drained: bool,
+    /// Set while `settle_batch` or `withdraw` has a token transfer in flight,
+    /// so a transfer hook cannot re-enter either on the same vault.
+    pub in_progress: bool,
+    /// Provider allowed to pull `amount_per_interval` once every
+    /// `interval_seconds` without a voucher; a zero interval means no
+    /// subscription.
+    pub subscription_provider: Pubkey,
+    pub interval_seconds: i64,
+    pub amount_per_interval: u64,
+    /// Earliest Unix time of the next scheduled pull.
+    pub next_allowed_ts: i64,
+    /// Running total the owner has authorized through `commit_amount`,
+    /// settled amounts included. Settlements can never take the vault past
+    /// it; `None` leaves the vault uncapped.
+    pub committed: Option<u64>,
+    /// Settlements the vault allows over its lifetime; zero means unlimited.
+    pub max_settlements: u32,
+    /// Settlements recorded so far, finalized proposals and claims included.
+    pub settlement_count: u32,
+    /// Off-chain session this vault was created for; part of its PDA seeds
+    /// unless all zero.
+    pub session_id: [u8; 16],
+    /// Fees taken out of this vault's settlements over its lifetime.
+    pub lifetime_fees_paid: u64,
+    /// Voucher total the owner has reported through `accrue`. Once non-zero,
+    /// the settlement threshold applies to what has accrued beyond
+    /// `total_settled` rather than to each settlement's size.
+    pub accumulated_vouchers: u64,
+    /// Token program the vault token account was created under; every later
+    /// transfer must go through it. Default for native vaults and vaults
+    /// from before it was recorded.
+    pub token_program: Pubkey,
+    /// Set at creation to skip the per-settlement and withdrawal events,
+    /// saving their compute; state is updated all the same. Takes a reserved
+    /// byte, so older vaults read as emitting.
+    pub events_disabled: bool,
+    /// Slot the vault was created in. Vouchers and relayed withdrawals sign
+    /// it along with the vault address, so ones for a closed vault are no
+    /// good against a vault later created at the same address.
+    pub created_slot: u64,
+    pub reserved: [u8; 2],
 }
 
-impl Default for Vault {
-    fn default() -> Self {
-        Self {
-            agent: Pubkey::default(),
-            token_mint: Pubkey::default(),
-            vault_token_account: Pubkey::default(),
-            deposit_amount: 0,
-            total_settled: 0,
-            last_settlement_slot: 0,
-            nonce: 0,
-            reserved: [0u8; 64],
+impl Vault {
+    /// Layout version; see `require_current_version`.
+    pub const VERSION: u8 = 12;
+    pub const LEN: usize = 8 + Self::INIT_SPACE;
+
+    /// Brings an account written by an older program up to
+    /// `VERSION`, returning the version it had. `None` if it was
+    /// already current.
+    pub fn migrate(&mut self) -> Option<u8> {
+        let from_version = self.version;
+        if from_version >= Self::VERSION {
+            return None;
+        }
+        // v0 vaults predate ownership transfer, so the owner is the creator.
+        if from_version < 1 && self.creator == Pubkey::default() {
+            self.creator = self.agent;
+        }
+        // v2 stores the PDA bump; native vaults have no mint.
+        if from_version < 2 {
+            let prefix: &[u8] = if self.token_mint == Pubkey::default() {
+                b"native_vault"
+            } else {
+                b"vault"
+            };
+            self.bump = Pubkey::find_program_address(&[prefix, self.creator.as_ref()], &crate::ID).1;
+        }
+        self.version = Self::VERSION;
+        Some(from_version)
+    }
+
+    /// Starts the withdrawal timelock for `amount`, replacing any earlier
+    /// request. Returns the unlock time.
+    pub fn request_withdraw(&mut self, amount: u64, now: i64) -> Result<i64> {
+        if self.shared {
+            return err!(FlowError::SharedVault);
+        }
+        if amount == 0 {
+            return err!(FlowError::ZeroAmount);
+        }
+        if amount > self.balance()? {
+            return err!(FlowError::InsufficientFunds);
+        }
+        let unlock_at = now
+            .checked_add(self.withdraw_timelock)
+            .ok_or(FlowError::MathOverflow)?;
+        self.pending_withdraw = amount;
+        self.withdraw_unlock_at = unlock_at;
+        Ok(unlock_at)
+    }
+
+    pub fn cancel_withdraw(&mut self) -> Result<()> {
+        if self.pending_withdraw == 0 {
+            return err!(FlowError::NoPendingWithdrawal);
+        }
+        self.pending_withdraw = 0;
+        self.withdraw_unlock_at = 0;
+        Ok(())
+    }
+
+    /// With a timelock armed, `amount` has to come out of an unlocked
+    /// request. Without one, withdrawals are immediate.
+    pub fn consume_withdrawal(&mut self, amount: u64, now: i64) -> Result<()> {
+        if self.shared {
+            return err!(FlowError::SharedVault);
+        }
+        if self.withdraw_timelock == 0 {
+            return Ok(());
+        }
+        if amount > self.pending_withdraw || now < self.withdraw_unlock_at {
+            return err!(FlowError::WithdrawalLocked);
+        }
+        self.pending_withdraw -= amount;
+        if self.pending_withdraw == 0 {
+            self.withdraw_unlock_at = 0;
+        }
+        Ok(())
+    }
+
+    pub fn require_not_drained(&self) -> Result<()> {
+        if self.drained {
+            return err!(FlowError::VaultDrained);
+        }
+        Ok(())
+    }
+
+    /// Settled plus reserved by a pending settlement.
+    fn spent(&self) -> Result<u64> {
+        self.total_settled
+            .checked_add(self.pending_amount)
+            .ok_or_else(|| error!(FlowError::MathOverflow))
+    }
+
+    /// Raises the committed cap by `amount`, returning the new total. The
+    /// first commitment builds on what has already been settled, so it
+    /// authorizes `amount` more.
+    pub fn commit(&mut self, amount: u64) -> Result<u64> {
+        if amount == 0 {
+            return err!(FlowError::ZeroAmount);
+        }
+        let base = match self.committed {
+            Some(committed) => committed,
+            None => self.spent()?,
+        };
+        let committed = base.checked_add(amount).ok_or(FlowError::MathOverflow)?;
+        self.committed = Some(committed);
+        Ok(committed)
+    }
+
+    /// Adds `amount` of incoming vouchers to the accrued total, returning it.
+    pub fn accrue(&mut self, amount: u64) -> Result<u64> {
+        if amount == 0 {
+            return err!(FlowError::ZeroAmount);
+        }
+        self.accumulated_vouchers = self
+            .accumulated_vouchers
+            .checked_add(amount)
+            .ok_or(FlowError::MathOverflow)?;
+        Ok(self.accumulated_vouchers)
+    }
+
+    /// Whether a settlement of `amount` clears `settle_threshold`: the
+    /// settlement itself until the owner has accrued anything, then the
+    /// vouchers accrued but not yet settled.
+    pub fn meets_settle_threshold(&self, config: &GlobalConfig, amount: u64) -> bool {
+        if self.accumulated_vouchers == 0 {
+            return amount >= config.settle_threshold;
+        }
+        self.accumulated_vouchers.saturating_sub(self.total_settled) >= config.settle_threshold
+    }
+
+    /// Committed but not yet settled; `None` when the vault is uncapped.
+    pub fn uncommitted_headroom(&self) -> Result<Option<u64>> {
+        let spent = self.spent()?;
+        Ok(self.committed.map(|committed| committed.saturating_sub(spent)))
+    }
+
+    /// Starts, replaces or, with a zero interval, cancels the vault's
+    /// subscription. The first pull is allowed straight away.
+    pub fn set_subscription(
+        &mut self,
+        provider: Pubkey,
+        interval_seconds: i64,
+        amount_per_interval: u64,
+        now: i64,
+    ) -> Result<()> {
+        if interval_seconds < 0 || (interval_seconds > 0 && amount_per_interval == 0) {
+            return err!(FlowError::InvalidSubscription);
+        }
+        if interval_seconds == 0 {
+            self.subscription_provider = Pubkey::default();
+            self.amount_per_interval = 0;
+            self.next_allowed_ts = 0;
+        } else {
+            self.subscription_provider = provider;
+            self.amount_per_interval = amount_per_interval;
+            self.next_allowed_ts = now;
+        }
+        self.interval_seconds = interval_seconds;
+        Ok(())
+    }
+
+    pub fn has_subscription_for(&self, provider: &Pubkey) -> bool {
+        self.interval_seconds > 0 && self.subscription_provider == *provider
+    }
+
+    /// Checks a scheduled pull of `amount`: at most `amount_per_interval`,
+    /// and only once the current interval has elapsed.
+    pub fn check_subscription_pull(&self, amount: u64, now: i64) -> Result<()> {
+        if now < self.next_allowed_ts {
+            return err!(FlowError::IntervalNotElapsed);
+        }
+        if amount > self.amount_per_interval {
+            return err!(FlowError::SubscriptionAmountExceeded);
+        }
+        Ok(())
+    }
+
+    /// Starts the next interval after a scheduled pull at `now`.
+    pub fn record_subscription_pull(&mut self, now: i64) -> Result<()> {
+        self.next_allowed_ts = now
+            .checked_add(self.interval_seconds)
+            .ok_or(FlowError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Reverses `amount` of an earlier settlement that a clawback has paid
+    /// back into the vault.
+    pub fn refund_settled(&mut self, amount: u64) -> Result<()> {
+        self.require_not_drained()?;
+        self.total_settled = self
+            .total_settled
+            .checked_sub(amount)
+            .ok_or(FlowError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Checks that the vault can take more funds from its owner: current,
+    /// not drained, live, and not shared, since shared vaults take funds
+    /// through `contribute` so every stake is recorded.
+    pub fn check_top_up(&self, now: i64) -> Result<()> {
+        require_current_version(self.version, Self::VERSION)?;
+        self.require_not_drained()?;
+        if self.shared {
+            return err!(FlowError::SharedVault);
+        }
+        if self.is_expired(now) {
+            return err!(FlowError::VaultExpired);
+        }
+        Ok(())
+    }
+
+    /// Credits `amount` paid in by another vault's settlement, under the
+    /// same checks as a deposit.
+    pub fn credit(&mut self, amount: u64, now: i64) -> Result<()> {
+        self.check_top_up(now)?;
+        self.deposit_amount = self
+            .deposit_amount
+            .checked_add(amount)
+            .ok_or(FlowError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Marks a token transfer out of the vault as started. The caller must
+    /// write the account back before the CPI so a re-entrant call sees it.
+    pub fn begin_transfer(&mut self) -> Result<()> {
+        if self.in_progress {
+            return err!(FlowError::Reentrancy);
+        }
+        self.in_progress = true;
+        Ok(())
+    }
+
+    pub fn end_transfer(&mut self) {
+        self.in_progress = false;
+    }
+
+    /// Empties the vault for `emergency_drain`: everything not yet paid out,
+    /// a pending settlement included, is returned for the sweep and the vault
+    /// is marked drained.
+    pub fn drain(&mut self) -> Result<u64> {
+        self.require_not_drained()?;
+        let amount = self
+            .deposit_amount
+            .checked_sub(self.total_settled)
+            .ok_or(FlowError::MathOverflow)?;
+        self.deposit_amount = self.total_settled;
+        self.pending_amount = 0;
+        self.pending_nonce = 0;
+        self.pending_provider = Pubkey::default();
+        self.settle_after = 0;
+        self.pending_withdraw = 0;
+        self.withdraw_unlock_at = 0;
+        self.drained = true;
+        Ok(amount)
+    }
+
+    /// Adds `amount` from a contributor to the pooled balance.
+    pub fn contribute(&mut self, amount: u64) -> Result<()> {
+        self.require_not_drained()?;
+        if amount == 0 {
+            return err!(FlowError::ZeroAmount);
+        }
+        self.deposit_amount = self.deposit_amount.checked_add(amount).ok_or(FlowError::MathOverflow)?;
+        self.total_contributed = self
+            .total_contributed
+            .checked_add(amount)
+            .ok_or(FlowError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Expiry refund owed for a `contributed` stake in a shared vault: its
+    /// share of the balance left at the first refund, floored, with the last
+    /// contributor taking whatever rounding left behind.
+    pub fn refund_share(&mut self, contributed: u64) -> Result<u64> {
+        let balance = self.balance()?;
+        let pool = *self.refund_pool.get_or_insert(balance);
+        let refunded = self
+            .refunded_contributions
+            .checked_add(contributed)
+            .ok_or(FlowError::MathOverflow)?;
+        let share = if refunded == self.total_contributed {
+            balance
+        } else {
+            (contributed as u128 * pool as u128 / self.total_contributed as u128) as u64
+        };
+        self.refunded_contributions = refunded;
+        Ok(share)
+    }
+
+    pub fn status(&self, config: &GlobalConfig, now: i64) -> Result<VaultStatus> {
+        Ok(VaultStatus {
+            balance: self.balance()?,
+            owner: self.agent,
+            provider: self.has_pending_settlement().then_some(self.pending_provider),
+            expiry_unix: self.expiry_unix,
+            expired: self.is_expired(now),
+            paused: config.is_paused(PAUSE_SETTLE),
+            last_nonce: self.nonce,
+        })
+    }
+
+    /// Outcome of settling `amount`, already validated into `split`.
+    pub fn preview_settlement(
+        &self,
+        config: &GlobalConfig,
+        amount: u64,
+        split: &SettlementSplit,
+    ) -> Result<SettlePreview> {
+        let resulting_balance = self
+            .balance()?
+            .checked_sub(amount)
+            .ok_or(FlowError::InsufficientFunds)?;
+        Ok(SettlePreview {
+            gross: amount,
+            fee: split.fee_amount,
+            net: split.net_to_provider,
+            resulting_balance,
+            would_cross_threshold: resulting_balance < config.settle_threshold,
+        })
+    }
+
+    /// Most the next settlement could take under the limits `check_payout`
+    /// enforces: the balance, capped by any uncommitted headroom, or nothing
+    /// once `max_settlements` is used up or that falls below the settlement
+    /// threshold.
+    pub fn settle_capacity(&self, config: &GlobalConfig) -> Result<u64> {
+        if self.settlement_limit_reached() {
+            return Ok(0);
+        }
+        let mut capacity = self.balance()?;
+        if let Some(headroom) = self.uncommitted_headroom()? {
+            capacity = capacity.min(headroom);
+        }
+        Ok(if capacity < config.settle_threshold { 0 } else { capacity })
+    }
+
+    /// Funds still available for settlement or withdrawal.
+    pub fn balance(&self) -> Result<u64> {
+        self.deposit_amount
+            .checked_sub(self.total_settled)
+            .and_then(|unsettled| unsettled.checked_sub(self.pending_amount))
+            .ok_or_else(|| error!(FlowError::MathOverflow))
+    }
+
+    pub fn is_delegate(&self, key: &Pubkey) -> bool {
+        self.delegate != Pubkey::default() && self.delegate == *key
+    }
+
+    pub fn has_pending_settlement(&self) -> bool {
+        self.pending_amount != 0
+    }
+
+    /// Whether settlements and withdrawals from this vault emit their events.
+    pub fn emits_events(&self) -> bool {
+        !self.events_disabled
+    }
+
+    /// Rejects nonce zero and nonces too far below the highest one to still
+    /// be tracked by the window as invalid, and nonces already used as
+    /// reused.
+    pub fn check_nonce(&self, nonce: u64) -> Result<()> {
+        if nonce == 0 {
+            return err!(FlowError::InvalidNonce);
+        }
+        if nonce > self.nonce {
+            return Ok(());
+        }
+        let offset = self.nonce - nonce;
+        if offset >= NONCE_WINDOW_SIZE {
+            return err!(FlowError::InvalidNonce);
+        }
+        if self.nonce_window & (1 << offset) != 0 {
+            return err!(FlowError::NonceReused);
+        }
+        Ok(())
+    }
+
+    /// Marks a nonce that passed `check_nonce` as used, sliding the window
+    /// up when it is the new highest.
+    pub fn use_nonce(&mut self, nonce: u64) {
+        if nonce > self.nonce {
+            let shift = nonce - self.nonce;
+            self.nonce_window = if shift >= NONCE_WINDOW_SIZE {
+                0
+            } else {
+                self.nonce_window << shift
+            };
+            self.nonce = nonce;
+        }
+        self.nonce_window |= 1 << (self.nonce - nonce);
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expiry_unix != 0 && now >= self.expiry_unix
+    }
+
+    /// Balance `sweep_dust` would take from an abandoned vault: one past its
+    /// expiry with less than `dust_threshold` left and nothing pending.
+    /// Shared vaults are refunded to their contributors, never swept.
+    pub fn sweepable_balance(&self, dust_threshold: u64, now: i64) -> Result<u64> {
+        if !self.is_expired(now) {
+            return err!(FlowError::VaultNotExpired);
+        }
+        if self.shared
+            || self.in_progress
+            || self.has_pending_settlement()
+            || self.pending_withdraw != 0
+        {
+            return err!(FlowError::VaultNotSweepable);
+        }
+        let balance = self.balance()?;
+        if balance >= dust_threshold {
+            return err!(FlowError::VaultNotSweepable);
+        }
+        Ok(balance)
+    }
+
+    /// True until `interval` seconds have passed since the last payout. A
+    /// zero interval disables the cooldown.
+    pub fn in_cooldown(&self, interval: i64, now: i64) -> bool {
+        interval != 0 && self.last_settle_ts != 0 && now < self.last_settle_ts.saturating_add(interval)
+    }
+
+    /// Validates a settlement of `amount` at `nonce` without mutating the
+    /// vault. Every settlement path (SPL and native) goes through here so
+    /// threshold, nonce and fee rules cannot drift apart.
+    pub fn check_settlement(
+        &self,
+        config: &GlobalConfig,
+        provider: &Provider,
+        amount: u64,
+        nonce: u64,
+        now: i64,
+    ) -> Result<SettlementSplit> {
+        if self.uses_nonce_bitmap {
+            return err!(FlowError::NonceBitmapRequired);
+        }
+        self.check_payout(config, provider, amount, Some(nonce), now)
+    }
+
+    /// Validates a settlement whose nonce is checked against the vault's
+    /// `NonceBitmap` instead of the replay window.
+    pub fn check_bitmap_settlement(
+        &self,
+        config: &GlobalConfig,
+        provider: &Provider,
+        amount: u64,
+        now: i64,
+    ) -> Result<SettlementSplit> {
+        self.check_payout(config, provider, amount, None, now)
+    }
+
+    /// Validates a Merkle leaf claim. Claims are guarded by the root's claimed
+    /// bitmap instead of the vault nonce.
+    pub fn check_claim(
+        &self,
+        config: &GlobalConfig,
+        provider: &Provider,
+        amount: u64,
+        now: i64,
+    ) -> Result<SettlementSplit> {
+        self.check_payout(config, provider, amount, None, now)
+    }
+
+    fn check_payout(
+        &self,
+        config: &GlobalConfig,
+        provider: &Provider,
+        amount: u64,
+        nonce: Option<u64>,
+        now: i64,
+    ) -> Result<SettlementSplit> {
+        require_current_version(self.version, Self::VERSION)?;
+        require_current_version(config.version, GlobalConfig::VERSION)?;
+        require_current_version(provider.version, Provider::VERSION)?;
+        self.require_not_drained()?;
+        // The global pause overrides any provider-level setting.
+        config.require_not_paused(PAUSE_SETTLE)?;
+        if provider.paused {
+            return err!(FlowError::ProviderPaused);
+        }
+        if self.is_expired(now) {
+            return err!(FlowError::VaultExpired);
+        }
+        if self.in_cooldown(config.min_settle_interval, now) {
+            return err!(FlowError::SettleTooSoon);
+        }
+        // Micro-payments are batched off-chain until their voucher total
+        // reaches the threshold.
+        if !self.meets_settle_threshold(config, amount) {
+            return err!(FlowError::BelowSettleThreshold);
+        }
+        if let Some(nonce) = nonce {
+            self.check_nonce(nonce)?;
+        }
+        if self.balance()? < amount {
+            return err!(FlowError::InsufficientFunds);
+        }
+        if self.uncommitted_headroom()?.is_some_and(|headroom| headroom < amount) {
+            return err!(FlowError::CommitmentExceeded);
+        }
+        if self.settlement_limit_reached() {
+            return err!(FlowError::SettlementLimitReached);
         }
+
+        let fee_amount = config.calculate_fee(amount, provider.fee_bps_override)?;
+        let net_to_provider = amount
+            .checked_sub(fee_amount)
+            .ok_or(FlowError::MathOverflow)?;
+
+        Ok(SettlementSplit {
+            fee_amount,
+            net_to_provider,
+        })
+    }
+
+    /// Records a settlement that passed `check_settlement`, `fee` of which the
+    /// vault paid.
+    pub fn record_settlement(
+        &mut self,
+        amount: u64,
+        fee: u64,
+        nonce: u64,
+        clock: &Clock,
+    ) -> Result<()> {
+        self.record_claim(amount, fee, clock)?;
+        self.use_nonce(nonce);
+        Ok(())
+    }
+
+    /// Whether `program` may move this vault's tokens. Vaults that never
+    /// recorded one stay pinned by their token account's owner alone.
+    pub fn uses_token_program(&self, program: &Pubkey) -> bool {
+        self.token_program == Pubkey::default() || self.token_program == *program
+    }
+
+    pub fn session_seed(&self) -> &[u8] {
+        session_seed(&self.session_id)
+    }
+
+    /// Whether `max_settlements` is used up. A pending settlement counts
+    /// against it, since it will be recorded when finalized.
+    pub fn settlement_limit_reached(&self) -> bool {
+        let pending = u32::from(self.has_pending_settlement());
+        self.max_settlements != 0
+            && self.settlement_count.saturating_add(pending) >= self.max_settlements
+    }
+
+    /// Records a payout that passed `check_claim`; leaves the nonce alone.
+    pub fn record_claim(&mut self, amount: u64, fee: u64, clock: &Clock) -> Result<()> {
+        self.total_settled = self
+            .total_settled
+            .checked_add(amount)
+            .ok_or(FlowError::MathOverflow)?;
+        self.lifetime_fees_paid = self
+            .lifetime_fees_paid
+            .checked_add(fee)
+            .ok_or(FlowError::MathOverflow)?;
+        self.settlement_count = self
+            .settlement_count
+            .checked_add(1)
+            .ok_or(FlowError::MathOverflow)?;
+        self.last_settlement_slot = clock.slot;
+        self.last_settle_ts = clock.unix_timestamp;
+        Ok(())
+    }
+
+    /// Holds a settlement that passed `check_settlement` until `settle_after`.
+    /// The nonce is consumed now so the voucher cannot also settle instantly.
+    pub fn propose_settlement(
+        &mut self,
+        provider: Pubkey,
+        amount: u64,
+        nonce: u64,
+        settle_after: i64,
+    ) -> Result<()> {
+        if self.has_pending_settlement() {
+            return err!(FlowError::SettlementPending);
+        }
+        self.pending_amount = amount;
+        self.pending_nonce = nonce;
+        self.pending_provider = provider;
+        self.settle_after = settle_after;
+        self.use_nonce(nonce);
+        Ok(())
+    }
+
+    /// Validates finalizing the pending settlement once its window is over.
+    /// Pauses still apply; the amount was reserved when it was proposed.
+    pub fn check_finalize(
+        &self,
+        config: &GlobalConfig,
+        provider: &Provider,
+        now: i64,
+    ) -> Result<SettlementSplit> {
+        if !self.has_pending_settlement() {
+            return err!(FlowError::NoPendingSettlement);
+        }
+        if now < self.settle_after {
+            return err!(FlowError::DisputeWindowActive);
+        }
+        config.require_not_paused(PAUSE_SETTLE)?;
+        if provider.paused {
+            return err!(FlowError::ProviderPaused);
+        }
+
+        let fee_amount = config.calculate_fee(self.pending_amount, provider.fee_bps_override)?;
+        let net_to_provider = self
+            .pending_amount
+            .checked_sub(fee_amount)
+            .ok_or(FlowError::MathOverflow)?;
+        Ok(SettlementSplit {
+            fee_amount,
+            net_to_provider,
+        })
+    }
+
+    /// Moves the pending settlement into `total_settled`.
+    pub fn finalize_settlement(&mut self, fee: u64, clock: &Clock) -> Result<()> {
+        let amount = self.pending_amount;
+        self.clear_pending();
+        self.record_claim(amount, fee, clock)
+    }
+
+    pub fn clear_pending(&mut self) {
+        self.pending_amount = 0;
+        self.pending_nonce = 0;
+        self.pending_provider = Pubkey::default();
+        self.settle_after = 0;
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl Vault {
-    // discriminator + agent + token_mint + vault_token_account + deposit_amount + total_settled + last_settlement_slot + nonce + reserved
-    pub const LEN: usize = 8 + 32 + 32 + 32 + 8 + 8 + 8 + 8 + 64;
-}
\ No newline at end of file
+    fn accept(vault: &mut Vault, nonce: u64) -> Result<()> {
+        vault.check_nonce(nonce)?;
+        vault.use_nonce(nonce);
+        Ok(())
+    }
+
+    #[test]
+    fn status_reports_balance_pending_provider_and_flags() {
+        let provider = Pubkey::new_unique();
+        let vault = Vault {
+            agent: Pubkey::new_unique(),
+            deposit_amount: 1_000,
+            total_settled: 300,
+            pending_amount: 200,
+            pending_provider: provider,
+            expiry_unix: 2_000,
+            nonce: 7,
+            ..Default::default()
+        };
+        let config = GlobalConfig {
+            pause_flags: PAUSE_SETTLE,
+            ..Default::default()
+        };
+
+        let status = vault.status(&config, 1_000).unwrap();
+        assert_eq!(
+            status,
+            VaultStatus {
+                balance: 500,
+                owner: vault.agent,
+                provider: Some(provider),
+                expiry_unix: 2_000,
+                expired: false,
+                paused: true,
+                last_nonce: 7,
+            }
+        );
+        assert!(vault.status(&config, 2_000).unwrap().expired);
+
+        let idle = Vault::default().status(&GlobalConfig::default(), 0).unwrap();
+        assert_eq!(idle.provider, None);
+        assert!(!idle.paused);
+    }
+
+    #[test]
+    fn drain_sweeps_pending_funds_and_locks_the_vault() {
+        let mut vault = Vault {
+            deposit_amount: 1_000,
+            total_settled: 300,
+            pending_amount: 200,
+            pending_provider: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        assert_eq!(vault.drain().unwrap(), 700);
+        assert_eq!(vault.balance().unwrap(), 0);
+        assert!(!vault.has_pending_settlement());
+
+        assert_eq!(vault.drain().unwrap_err(), error!(FlowError::VaultDrained));
+        assert_eq!(vault.require_not_drained().unwrap_err(), error!(FlowError::VaultDrained));
+        assert_eq!(vault.contribute(1).unwrap_err(), error!(FlowError::VaultDrained));
+    }
+
+    #[test]
+    fn capacity_drops_to_zero_below_the_threshold() {
+        let config = GlobalConfig {
+            settle_threshold: 100,
+            ..Default::default()
+        };
+        let mut vault = Vault {
+            deposit_amount: 250,
+            ..Default::default()
+        };
+        assert_eq!(vault.settle_capacity(&config).unwrap(), 250);
+        vault.total_settled = 150;
+        assert_eq!(vault.settle_capacity(&config).unwrap(), 100);
+        vault.total_settled = 151;
+        assert_eq!(vault.settle_capacity(&config).unwrap(), 0);
+    }
+
+    #[test]
+    fn capacity_respects_the_commitment_and_settlement_limit() {
+        let config = GlobalConfig {
+            settle_threshold: 100,
+            ..Default::default()
+        };
+        let mut vault = Vault {
+            deposit_amount: 1_000,
+            total_settled: 200,
+            committed: Some(500),
+            max_settlements: 2,
+            settlement_count: 1,
+            ..Default::default()
+        };
+        assert_eq!(vault.settle_capacity(&config).unwrap(), 300);
+        vault.committed = Some(250);
+        assert_eq!(vault.settle_capacity(&config).unwrap(), 0);
+        vault.committed = None;
+        assert_eq!(vault.settle_capacity(&config).unwrap(), 800);
+        vault.settlement_count = 2;
+        assert_eq!(vault.settle_capacity(&config).unwrap(), 0);
+    }
+
+    #[test]
+    fn subscription_pulls_once_per_interval() {
+        let provider = Pubkey::new_unique();
+        let mut vault = Vault::default();
+        assert!(!vault.has_subscription_for(&provider));
+        assert_eq!(
+            vault.set_subscription(provider, 60, 0, 1_000).unwrap_err(),
+            error!(FlowError::InvalidSubscription)
+        );
+
+        vault.set_subscription(provider, 60, 500, 1_000).unwrap();
+        assert!(vault.has_subscription_for(&provider));
+        assert!(!vault.has_subscription_for(&Pubkey::new_unique()));
+        assert_eq!(
+            vault.check_subscription_pull(501, 1_000).unwrap_err(),
+            error!(FlowError::SubscriptionAmountExceeded)
+        );
+        vault.check_subscription_pull(500, 1_000).unwrap();
+        vault.record_subscription_pull(1_000).unwrap();
+
+        assert_eq!(
+            vault.check_subscription_pull(500, 1_059).unwrap_err(),
+            error!(FlowError::IntervalNotElapsed)
+        );
+        assert!(vault.check_subscription_pull(500, 1_060).is_ok());
+
+        vault.set_subscription(Pubkey::default(), 0, 0, 1_060).unwrap();
+        assert!(!vault.has_subscription_for(&provider));
+    }
+
+    #[test]
+    fn commitments_cap_settlements() {
+        let mut vault = Vault {
+            deposit_amount: 1_000,
+            total_settled: 100,
+            ..Default::default()
+        };
+        assert_eq!(vault.uncommitted_headroom().unwrap(), None);
+        assert_eq!(vault.commit(0).unwrap_err(), error!(FlowError::ZeroAmount));
+
+        // The first commitment authorizes on top of what was settled.
+        assert_eq!(vault.commit(300).unwrap(), 400);
+        assert_eq!(vault.uncommitted_headroom().unwrap(), Some(300));
+        vault.total_settled = 350;
+        vault.pending_amount = 50;
+        assert_eq!(vault.uncommitted_headroom().unwrap(), Some(0));
+
+        assert_eq!(vault.commit(200).unwrap(), 600);
+        assert_eq!(vault.uncommitted_headroom().unwrap(), Some(200));
+    }
+
+    #[test]
+    fn preview_reports_the_split_and_what_is_left() {
+        let vault = Vault {
+            deposit_amount: 1_000,
+            version: Vault::VERSION,
+            ..Default::default()
+        };
+        let config = GlobalConfig {
+            settle_threshold: 100,
+            fee_bps: 1_000,
+            version: GlobalConfig::VERSION,
+            ..Default::default()
+        };
+        let provider = Provider {
+            version: Provider::VERSION,
+            ..Default::default()
+        };
+        let split = vault.check_settlement(&config, &provider, 850, 1, 0).unwrap();
+        assert_eq!(
+            vault.preview_settlement(&config, 850, &split).unwrap(),
+            SettlePreview {
+                gross: 850,
+                fee: 85,
+                net: 765,
+                resulting_balance: 150,
+                would_cross_threshold: false,
+            }
+        );
+
+        let split = vault.check_settlement(&config, &provider, 901, 1, 0).unwrap();
+        assert!(vault.preview_settlement(&config, 901, &split).unwrap().would_cross_threshold);
+    }
+
+    #[test]
+    fn default_session_keeps_the_original_vault_address() {
+        let creator = Pubkey::new_unique();
+        let derive = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, &crate::ID).0;
+        let legacy = derive(&[b"vault", creator.as_ref()]);
+        assert_eq!(derive(&[b"vault", creator.as_ref(), session_seed(&[0; 16])]), legacy);
+
+        let session = derive(&[b"vault", creator.as_ref(), session_seed(&[7; 16])]);
+        assert_ne!(session, legacy);
+        assert_eq!(session, derive(&[b"vault", creator.as_ref(), &[7; 16]]));
+    }
+
+    #[test]
+    fn provider_paid_fees_leave_the_whole_amount_to_the_provider() {
+        let split = SettlementSplit {
+            fee_amount: 25,
+            net_to_provider: 975,
+        };
+        assert_eq!(split.payout(1_000, FeeSource::NetFromAmount), 975);
+        assert_eq!(split.payout(1_000, FeeSource::ProviderPays), 1_000);
+    }
+
+    #[test]
+    fn settlement_limit_counts_pending_proposals() {
+        let mut vault = Vault {
+            max_settlements: 2,
+            ..Default::default()
+        };
+        let clock = Clock::default();
+        vault.record_claim(10, 1, &clock).unwrap();
+        assert_eq!(vault.settlement_count, 1);
+        assert!(!vault.settlement_limit_reached());
+
+        vault.propose_settlement(Pubkey::new_unique(), 10, 1, 0).unwrap();
+        assert!(vault.settlement_limit_reached());
+        vault.finalize_settlement(1, &clock).unwrap();
+        assert_eq!(vault.settlement_count, 2);
+        assert_eq!(vault.lifetime_fees_paid, 2);
+        assert!(vault.settlement_limit_reached());
+
+        vault.max_settlements = 0;
+        assert!(!vault.settlement_limit_reached());
+    }
+
+    #[test]
+    fn len_fits_a_vault_with_every_option_set() {
+        let vault = Vault {
+            pending_agent: Some(Pubkey::new_unique()),
+            refund_pool: Some(0),
+            committed: Some(0),
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        vault.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), Vault::LEN);
+    }
+
+    #[test]
+    fn credits_only_land_in_live_vaults() {
+        let mut vault = Vault {
+            deposit_amount: 100,
+            expiry_unix: 1_000,
+            version: Vault::VERSION,
+            ..Default::default()
+        };
+        vault.credit(50, 999).unwrap();
+        assert_eq!(vault.deposit_amount, 150);
+        assert_eq!(vault.credit(50, 1_000).unwrap_err(), error!(FlowError::VaultExpired));
+
+        vault.shared = true;
+        assert_eq!(vault.credit(50, 0).unwrap_err(), error!(FlowError::SharedVault));
+
+        vault.shared = false;
+        vault.drained = true;
+        assert_eq!(vault.check_top_up(0).unwrap_err(), error!(FlowError::VaultDrained));
+        vault.version = 0;
+        assert_eq!(vault.check_top_up(0).unwrap_err(), error!(FlowError::StaleAccountVersion));
+    }
+
+    #[test]
+    fn reentrant_transfer_is_rejected() {
+        let mut vault = Vault::default();
+        vault.begin_transfer().unwrap();
+        assert_eq!(vault.begin_transfer().unwrap_err(), error!(FlowError::Reentrancy));
+        vault.end_transfer();
+        assert!(vault.begin_transfer().is_ok());
+    }
+
+    #[test]
+    fn shared_refunds_are_proportional_to_contributions() {
+        let mut vault = Vault {
+            shared: true,
+            ..Default::default()
+        };
+        vault.contribute(600).unwrap();
+        vault.contribute(300).unwrap();
+        vault.contribute(100).unwrap();
+        assert_eq!(vault.total_contributed, 1_000);
+        vault.total_settled = 299;
+
+        // 701 is left; the first two stakes get their floored shares.
+        let first = vault.refund_share(600).unwrap();
+        assert_eq!(first, 420);
+        vault.deposit_amount -= first;
+        let second = vault.refund_share(300).unwrap();
+        assert_eq!(second, 210);
+        vault.deposit_amount -= second;
+        assert_eq!(vault.refund_pool, Some(701));
+
+        // The last stake takes its 70 plus the rounding dust.
+        assert_eq!(vault.refund_share(100).unwrap(), 71);
+    }
+
+    #[test]
+    fn shared_vaults_cannot_be_withdrawn_from() {
+        let mut vault = Vault {
+            shared: true,
+            deposit_amount: 1_000,
+            ..Default::default()
+        };
+        assert_eq!(vault.request_withdraw(100, 0).unwrap_err(), error!(FlowError::SharedVault));
+        assert_eq!(vault.consume_withdrawal(100, 0).unwrap_err(), error!(FlowError::SharedVault));
+        assert_eq!(vault.contribute(0).unwrap_err(), error!(FlowError::ZeroAmount));
+    }
+
+    #[test]
+    fn deadline_allows_settling_up_to_and_including_it() {
+        check_deadline(1_000, 999).unwrap();
+        check_deadline(1_000, 1_000).unwrap();
+        assert_eq!(
+            check_deadline(1_000, 1_001).unwrap_err(),
+            error!(FlowError::DeadlineExceeded)
+        );
+        check_deadline(0, i64::MAX).unwrap();
+    }
+
+    #[test]
+    fn cooldown_runs_for_the_interval_after_a_payout() {
+        let mut vault = Vault::default();
+        assert!(!vault.in_cooldown(60, 1_000));
+
+        vault.last_settle_ts = 1_000;
+        assert!(vault.in_cooldown(60, 1_000));
+        assert!(vault.in_cooldown(60, 1_059));
+        assert!(!vault.in_cooldown(60, 1_060));
+        assert!(!vault.in_cooldown(0, 1_000));
+    }
+
+    #[test]
+    fn default_delegate_matches_no_one() {
+        let mut vault = Vault::default();
+        assert!(!vault.is_delegate(&Pubkey::default()));
+
+        let delegate = Pubkey::new_unique();
+        vault.delegate = delegate;
+        assert!(vault.is_delegate(&delegate));
+        assert!(!vault.is_delegate(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn nonces_are_accepted_out_of_order_once() {
+        let mut vault = Vault::default();
+        accept(&mut vault, 3).unwrap();
+        accept(&mut vault, 1).unwrap();
+        accept(&mut vault, 2).unwrap();
+        assert_eq!(vault.nonce, 3);
+        for nonce in [1, 2, 3] {
+            assert_eq!(
+                accept(&mut vault, nonce).unwrap_err(),
+                error!(FlowError::NonceReused)
+            );
+        }
+        assert_eq!(accept(&mut vault, 0).unwrap_err(), error!(FlowError::InvalidNonce));
+    }
+
+    #[test]
+    fn nonces_below_the_window_are_rejected() {
+        let mut vault = Vault::default();
+        accept(&mut vault, NONCE_WINDOW_SIZE + 1).unwrap();
+        // Nonce 1 was never used but sits just below the window.
+        assert_eq!(
+            accept(&mut vault, 1).unwrap_err(),
+            error!(FlowError::InvalidNonce)
+        );
+        // Nonce 2 is the lowest one still tracked.
+        accept(&mut vault, 2).unwrap();
+    }
+
+    #[test]
+    fn window_slides_with_used_bits() {
+        let mut vault = Vault::default();
+        accept(&mut vault, 10).unwrap();
+        accept(&mut vault, 12).unwrap();
+        assert_eq!(
+            accept(&mut vault, 10).unwrap_err(),
+            error!(FlowError::NonceReused)
+        );
+        accept(&mut vault, 11).unwrap();
+    }
+
+    #[test]
+    fn zero_expiry_never_expires() {
+        let vault = Vault::default();
+        assert!(!vault.is_expired(i64::MAX));
+    }
+
+    #[test]
+    fn expiry_is_inclusive() {
+        let vault = Vault {
+            expiry_unix: 1_000,
+            ..Default::default()
+        };
+        assert!(!vault.is_expired(999));
+        assert!(vault.is_expired(1_000));
+    }
+
+    #[test]
+    fn v0_vault_is_migrated_before_it_can_settle() {
+        let agent = Pubkey::new_unique();
+        let mut vault = Vault {
+            agent,
+            deposit_amount: 1_000,
+            ..Default::default()
+        };
+        let config = GlobalConfig {
+            version: GlobalConfig::VERSION,
+            ..Default::default()
+        };
+        let provider = Provider {
+            version: Provider::VERSION,
+            ..Default::default()
+        };
+        assert_eq!(
+            vault.check_settlement(&config, &provider, 100, 1, 0).unwrap_err(),
+            error!(FlowError::StaleAccountVersion)
+        );
+
+        assert_eq!(vault.migrate(), Some(0));
+        assert_eq!(vault.version, Vault::VERSION);
+        assert_eq!(vault.creator, agent);
+        assert_eq!(
+            vault.bump,
+            Pubkey::find_program_address(&[b"native_vault", agent.as_ref()], &crate::ID).1
+        );
+        assert!(vault.check_settlement(&config, &provider, 100, 1, 0).is_ok());
+        assert_eq!(vault.migrate(), None);
+    }
+
+    #[test]
+    fn settlements_start_at_the_threshold() {
+        let vault = Vault {
+            deposit_amount: 1_000,
+            version: Vault::VERSION,
+            ..Default::default()
+        };
+        let config = GlobalConfig {
+            settle_threshold: 100,
+            version: GlobalConfig::VERSION,
+            ..Default::default()
+        };
+        let provider = Provider {
+            version: Provider::VERSION,
+            ..Default::default()
+        };
+        assert_eq!(
+            vault.check_settlement(&config, &provider, 99, 1, 0).unwrap_err(),
+            error!(FlowError::BelowSettleThreshold)
+        );
+        assert!(vault.check_settlement(&config, &provider, 100, 1, 0).is_ok());
+    }
+
+    #[test]
+    fn accrued_vouchers_gate_settlement_once_reported() {
+        let mut vault = Vault {
+            deposit_amount: 1_000,
+            total_settled: 50,
+            version: Vault::VERSION,
+            ..Default::default()
+        };
+        let config = GlobalConfig {
+            settle_threshold: 100,
+            version: GlobalConfig::VERSION,
+            ..Default::default()
+        };
+        let provider = Provider {
+            version: Provider::VERSION,
+            ..Default::default()
+        };
+        assert_eq!(vault.accrue(0).unwrap_err(), error!(FlowError::ZeroAmount));
+
+        // 120 accrued, 50 of it already settled: 70 is below the threshold,
+        // however large this settlement is.
+        assert_eq!(vault.accrue(120).unwrap(), 120);
+        assert_eq!(
+            vault.check_settlement(&config, &provider, 500, 1, 0).unwrap_err(),
+            error!(FlowError::BelowSettleThreshold)
+        );
+
+        // Once 100 is outstanding, even a small settlement goes through.
+        assert_eq!(vault.accrue(30).unwrap(), 150);
+        assert!(vault.check_settlement(&config, &provider, 10, 1, 0).is_ok());
+    }
+
+    #[test]
+    fn recorded_token_program_is_enforced() {
+        let legacy = Vault::default();
+        assert!(legacy.uses_token_program(&anchor_spl::token::ID));
+        assert!(legacy.uses_token_program(&anchor_spl::token_2022::ID));
+
+        let vault = Vault {
+            token_program: anchor_spl::token::ID,
+            ..Default::default()
+        };
+        assert!(vault.uses_token_program(&anchor_spl::token::ID));
+        assert!(!vault.uses_token_program(&anchor_spl::token_2022::ID));
+    }
+
+    #[test]
+    fn timelocked_withdrawals_wait_for_their_request() {
+        let mut vault = Vault {
+            deposit_amount: 1_000,
+            withdraw_timelock: 60,
+            ..Default::default()
+        };
+        assert_eq!(
+            vault.consume_withdrawal(100, 0).unwrap_err(),
+            error!(FlowError::WithdrawalLocked)
+        );
+
+        assert_eq!(vault.request_withdraw(400, 1_000).unwrap(), 1_060);
+        assert_eq!(
+            vault.consume_withdrawal(400, 1_059).unwrap_err(),
+            error!(FlowError::WithdrawalLocked)
+        );
+        assert_eq!(
+            vault.consume_withdrawal(401, 1_060).unwrap_err(),
+            error!(FlowError::WithdrawalLocked)
+        );
+        vault.consume_withdrawal(150, 1_060).unwrap();
+        vault.consume_withdrawal(250, 1_061).unwrap();
+        assert_eq!(vault.withdraw_unlock_at, 0);
+
+        vault.request_withdraw(100, 2_000).unwrap();
+        vault.cancel_withdraw().unwrap();
+        assert_eq!(
+            vault.consume_withdrawal(100, 3_000).unwrap_err(),
+            error!(FlowError::WithdrawalLocked)
+        );
+        assert_eq!(vault.cancel_withdraw().unwrap_err(), error!(FlowError::NoPendingWithdrawal));
+    }
+
+    #[test]
+    fn withdrawals_are_immediate_without_a_timelock() {
+        let mut vault = Vault::default();
+        assert!(vault.consume_withdrawal(100, 0).is_ok());
+    }
+
+    #[test]
+    fn only_expired_dust_can_be_swept() {
+        let vault = Vault {
+            deposit_amount: 1_000,
+            total_settled: 950,
+            expiry_unix: 1_000,
+            ..Default::default()
+        };
+        assert_eq!(vault.sweepable_balance(100, 1_000).unwrap(), 50);
+        assert_eq!(
+            vault.sweepable_balance(100, 999).unwrap_err(),
+            error!(FlowError::VaultNotExpired)
+        );
+        for dust_threshold in [0, 50] {
+            assert_eq!(
+                vault.sweepable_balance(dust_threshold, 1_000).unwrap_err(),
+                error!(FlowError::VaultNotSweepable)
+            );
+        }
+
+        let live = Vault { expiry_unix: 0, ..vault.clone() };
+        assert_eq!(
+            live.sweepable_balance(100, i64::MAX).unwrap_err(),
+            error!(FlowError::VaultNotExpired)
+        );
+        for busy in [
+            Vault { shared: true, ..vault.clone() },
+            Vault { pending_amount: 10, ..vault.clone() },
+            Vault { pending_withdraw: 10, ..vault.clone() },
+            Vault { in_progress: true, ..vault.clone() },
+        ] {
+            assert_eq!(
+                busy.sweepable_balance(100, 1_000).unwrap_err(),
+                error!(FlowError::VaultNotSweepable)
+            );
+        }
+    }
+
+    #[test]
+    fn waived_fee_goes_to_the_provider() {
+        let mut split = SettlementSplit { fee_amount: 30, net_to_provider: 970 };
+        assert_eq!(split.waive_fee(), 30);
+        assert_eq!((split.fee_amount, split.net_to_provider), (0, 1_000));
+        assert_eq!(split.payout(1_000, FeeSource::NetFromAmount), 1_000);
+        assert_eq!(split.waive_fee(), 0);
+    }
+
+    #[test]
+    fn vaults_emit_events_unless_disabled() {
+        assert!(Vault::default().emits_events());
+        let quiet = Vault { events_disabled: true, ..Default::default() };
+        assert!(!quiet.emits_events());
+    }
+}
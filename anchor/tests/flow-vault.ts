@@ -6,19 +6,35 @@ import {
   PublicKey,
   SystemProgram,
   Transaction,
+  TransactionInstruction,
   Ed25519Program,
+  LAMPORTS_PER_SOL,
   SYSVAR_INSTRUCTIONS_PUBKEY,
 } from "@solana/web3.js";
 import {
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
+  ExtensionType,
   createMint,
   createAccount,
   mintTo,
   getAccount,
+  getMintLen,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  createInitializeTransferHookInstruction,
+  freezeAccount,
+  approve,
+  getAssociatedTokenAddressSync,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
 } from "@solana/spl-token";
 import { assert } from "chai";
 import nacl from "tweetnacl";
+import { keccak_256 } from "@noble/hashes/sha3";
 
+const MEMO_PROGRAM_ID = new PublicKey(
+  "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr"
+);
 
 async function assertRejects(
   promise: Promise<any>,
@@ -58,7 +74,7 @@ async function assertRejects(
       const txErrorLine = err.message || err.transactionMessage || "";
       const txMatch = txErrorLine.match(/custom program error: 0x(\w+)/);
       if (txMatch && txMatch[1]) {
-        if (txMatch[1] === "2" && errorCode === "InvalidSignature") {
+        if (txMatch[1] === "2" && errorCode === "InvalidVoucher") {
           assert.ok(
             true,
             "Correctly failed in ed25519 program (0x2) as expected for invalid signature"
@@ -94,6 +110,15 @@ describe("flow-vault", () => {
     nativeSpl: { nativeSpl: {} },
     atxpBridge: { atxpBridge: {} },
   };
+  // Mirrors the program's PAUSE_* bits.
+  const pauseFlags = {
+    create: 1 << 0,
+    settle: 1 << 1,
+    withdraw: 1 << 2,
+    deposit: 1 << 3,
+  };
+  const pauseAll =
+    pauseFlags.create | pauseFlags.settle | pauseFlags.withdraw | pauseFlags.deposit;
 
   let mint: PublicKey;
   let admin: Keypair;
@@ -101,10 +126,12 @@ describe("flow-vault", () => {
   let facilitator: Keypair;
   let providerAuthority: Keypair;
   let randomUser: Keypair;
+  let treasury: Keypair;
 
   let agentTokenAccount: PublicKey;
   let providerTokenAccount: PublicKey;
   let randomUserTokenAccount: PublicKey;
+  let feeTokenAccount: PublicKey;
 
   let globalConfigPda: PublicKey;
   let vaultPda: PublicKey;
@@ -112,9 +139,36 @@ describe("flow-vault", () => {
   let providerPda: PublicKey;
 
   const settleThreshold = new BN(100_000); // This value is now set on-chain
-  const feeBps = 100;
+  const feeBps = 250;
   const depositAmount = new BN(2_000_000);
+  // The all-zero session id addresses an owner's default vault.
+  const defaultSession = Array(16).fill(0);
+  const topUpAmount = new BN(50_000);
+  const minDeposit = new BN(500_000);
+  const maxDeposit = new BN(5_000_000);
+  const disputeSeconds = new BN(2);
+  const timelockSeconds = new BN(1);
+
+  const configParams = () => ({
+    settleThreshold,
+    feeBps,
+    feeDestination: treasury.publicKey,
+    minDeposit,
+    maxDeposit,
+    disputeSeconds,
+    minSettleInterval: new BN(0),
+    timelockSeconds,
+    maxFee: new BN(0),
+    referralBps: 0,
+    feeRounding: { floor: {} } as any,
+    registrationFee: new BN(0),
+    maxVaultsPerOwner: 0,
+    clawbackSeconds: new BN(0),
+    dustThreshold: new BN(0),
+  });
   const visaMerchantId = "visa-merchant-x402";
+  const providerName = "x402 Demo Provider";
+  const providerUri = "https://example.com/x402/provider.json";
 
   before(async () => {
 
@@ -123,6 +177,7 @@ describe("flow-vault", () => {
     facilitator = Keypair.generate();
     providerAuthority = Keypair.generate();
     randomUser = Keypair.generate();
+    treasury = Keypair.generate();
 
     const airdropSigs = await Promise.all([
       provider.connection.requestAirdrop(admin.publicKey, 5e9),
@@ -130,6 +185,7 @@ describe("flow-vault", () => {
       provider.connection.requestAirdrop(facilitator.publicKey, 2e9),
       provider.connection.requestAirdrop(providerAuthority.publicKey, 2e9),
       provider.connection.requestAirdrop(randomUser.publicKey, 2e9),
+      provider.connection.requestAirdrop(treasury.publicKey, 1e9),
     ]);
 
     await Promise.all(
@@ -146,8 +202,12 @@ describe("flow-vault", () => {
       6
     );
 
-    [agentTokenAccount, providerTokenAccount, randomUserTokenAccount] =
-      await Promise.all([
+    [
+      agentTokenAccount,
+      providerTokenAccount,
+      randomUserTokenAccount,
+      feeTokenAccount,
+    ] = await Promise.all([
         createAccount(provider.connection, payer.payer, mint, agent.publicKey),
         createAccount(
           provider.connection,
@@ -161,6 +221,7 @@ describe("flow-vault", () => {
           mint,
           randomUser.publicKey
         ),
+        createAccount(provider.connection, payer.payer, mint, treasury.publicKey),
       ]);
 
 
@@ -196,7 +257,7 @@ describe("flow-vault", () => {
 
 
     await program.methods
-      .initializeConfig(settleThreshold, feeBps) // Updated: settleThreshold removed
+      .initializeConfig(configParams())
       .accounts({
         admin: admin.publicKey,
         globalConfig: globalConfigPda,
@@ -207,31 +268,385 @@ describe("flow-vault", () => {
 
     await program.methods
       // Updated: new arguments for bounty features
-      .registerProvider(visaMerchantId, paymentProtocol.nativeSpl)
+      .registerProvider(
+        visaMerchantId,
+        paymentProtocol.nativeSpl,
+        providerName,
+        providerUri,
+        null
+      )
       .accounts({
         authority: providerAuthority.publicKey,
         provider: providerPda,
         destination: providerTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        globalConfig: globalConfigPda,
+        feeDestination: treasury.publicKey,
         systemProgram: SystemProgram.programId,
       } as any)
       .signers([providerAuthority])
       .rpc();
 
     await program.methods
-      .createVault(depositAmount)
+      .createVault(depositAmount, new BN(0), new BN(0), false, 0, defaultSession, true)
       .accounts({
         agent: agent.publicKey,
         vault: vaultPda,
         vaultTokenAccount: vaultTokenAccountPda,
         agentTokenAccount: agentTokenAccount,
         tokenMint: mint,
+        globalConfig: globalConfigPda,
+        ownerVaultCount: ownerVaultCountPda(agent.publicKey),
         tokenProgram: TOKEN_PROGRAM_ID,
+        contribution: null,
         systemProgram: SystemProgram.programId,
       } as any)
       .signers([agent])
       .rpc();
   });
 
+  // Accounts a settlement is made against; defaults to the main suite vault.
+  type SettleTarget = {
+    agent: Keypair;
+    vault: PublicKey;
+    vaultTokenAccount: PublicKey;
+    provider: PublicKey;
+    destination: PublicKey;
+    feeDestination: PublicKey;
+    mint: PublicKey;
+    tokenProgram: PublicKey;
+    // Set to also create the settlement's receipt account.
+    receipt?: PublicKey;
+    // Token accounts for the configured fee splits, in config order.
+    feeRecipients?: PublicKey[];
+    minNetToProvider?: BN;
+    // Set once the vault has switched to a nonce bitmap.
+    nonceBitmap?: PublicKey;
+    // Set to accrue the provider's net amount instead of paying destination.
+    providerEarnings?: PublicKey;
+    // Recorded through the SPL Memo program when set.
+    memo?: string;
+    // The provider's referrer token account, if it has one.
+    referrer?: PublicKey;
+    // Latest Unix time the settlement may land; unset means no deadline.
+    deadlineUnix?: BN;
+    // Names a whitelisted `destination` other than the registered one.
+    destinationOverride?: PublicKey;
+    // The vault owning `destination`, credited with the net amount.
+    downstreamVault?: PublicKey;
+    // Defaults to netting the fee out of the amount.
+    feeFrom?: object;
+    // Provider-owned account paying the fee under `providerPays`.
+    providerFeeAccount?: PublicKey;
+    // Creator's open-vault count, freed if the settlement closes the vault;
+    // defaults to the agent's.
+    ownerVaultCount?: PublicKey;
+    // Set to pay this provider authority's associated token account.
+    providerAuthority?: PublicKey;
+    // Config authority waiving the fee; it must also sign the transaction.
+    feeWaiverAuthority?: PublicKey;
+  };
+
+  function mainTarget(): SettleTarget {
+    return {
+      agent,
+      vault: vaultPda,
+      vaultTokenAccount: vaultTokenAccountPda,
+      provider: providerPda,
+      destination: providerTokenAccount,
+      feeDestination: feeTokenAccount,
+      mint,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+  }
+
+  async function buildSettleTx(
+    settleAmount: BN,
+    nonce: BN,
+    signer?: Keypair,
+    target: SettleTarget = mainTarget()
+  ): Promise<Transaction> {
+    const message = Buffer.concat([
+      Buffer.from("X402_FLOW_SETTLE"),
+      target.vault.toBuffer(),
+      await createdSlot(target.vault),
+      target.provider.toBuffer(),
+      settleAmount.toArrayLike(Buffer, "le", 8),
+      nonce.toArrayLike(Buffer, "le", 8),
+    ]);
+
+    const ed25519Ix = Ed25519Program.createInstructionWithPublicKey({
+      publicKey: target.agent.publicKey.toBytes(),
+      message,
+      signature: nacl.sign.detached(
+        message,
+        (signer ?? target.agent).secretKey
+      ),
+    });
+
+    return new Transaction()
+      .add(ed25519Ix)
+      .add(await buildSettleIx(settleAmount, nonce, target));
+  }
+
+  // The bare settle_batch instruction, without the voucher pre-instruction.
+  async function buildSettleIx(
+    settleAmount: BN,
+    nonce: BN,
+    target: SettleTarget = mainTarget()
+  ): Promise<TransactionInstruction> {
+    return program.methods
+      .settleBatch(
+        settleAmount,
+        nonce,
+        target.minNetToProvider ?? new BN(0),
+        target.memo ?? null,
+        target.deadlineUnix ?? new BN(0),
+        target.destinationOverride ?? null,
+        (target.feeFrom ?? { netFromAmount: {} }) as any
+      )
+      .accounts({
+        facilitator: facilitator.publicKey,
+        agent: target.agent.publicKey,
+        vault: target.vault,
+        vaultTokenAccount: target.vaultTokenAccount,
+        tokenMint: target.mint,
+        globalConfig: globalConfigPda,
+        provider: target.provider,
+        destination: target.destination,
+        feeDestination: target.feeDestination,
+        tokenProgram: target.tokenProgram,
+        instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        receipt: target.receipt ?? null,
+        systemProgram:
+          target.receipt || target.providerAuthority ? SystemProgram.programId : null,
+        nonceBitmap: target.nonceBitmap ?? null,
+        providerEarnings: target.providerEarnings ?? null,
+        memoProgram: target.memo ? MEMO_PROGRAM_ID : null,
+        referrer: target.referrer ?? null,
+        downstreamVault: target.downstreamVault ?? null,
+        providerFeeAccount: target.providerFeeAccount ?? null,
+        ownerVaultCount: target.ownerVaultCount ?? ownerVaultCountPda(target.agent.publicKey),
+        providerAuthority: target.providerAuthority ?? null,
+        providerAta: target.providerAuthority
+          ? getAssociatedTokenAddressSync(
+              target.mint,
+              target.providerAuthority,
+              false,
+              target.tokenProgram
+            )
+          : null,
+        associatedTokenProgram: target.providerAuthority
+          ? ASSOCIATED_TOKEN_PROGRAM_ID
+          : null,
+        feeWaiverAuthority: target.feeWaiverAuthority ?? null,
+      } as any)
+      .remainingAccounts(
+        (target.feeRecipients ?? []).map((pubkey) => ({
+          pubkey,
+          isSigner: false,
+          isWritable: true,
+        }))
+      )
+      .instruction();
+  }
+
+  async function airdrop(...keypairs: Keypair[]) {
+    await Promise.all(
+      keypairs.map(async (kp) =>
+        provider.connection.confirmTransaction(
+          await provider.connection.requestAirdrop(kp.publicKey, 2e9),
+          "confirmed"
+        )
+      )
+    );
+  }
+
+  // Registers a fresh provider with its own destination for the main mint,
+  // optionally attributed to a referrer token account.
+  async function registerTestProvider(
+    referrer: PublicKey | null = null
+  ): Promise<{
+    authority: Keypair;
+    provider: PublicKey;
+    destination: PublicKey;
+  }> {
+    const authority = Keypair.generate();
+    await airdrop(authority);
+    const destination = await createAccount(
+      provider.connection,
+      payer.payer,
+      mint,
+      authority.publicKey
+    );
+    const [providerAccount] = PublicKey.findProgramAddressSync(
+      [Buffer.from("provider"), authority.publicKey.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .registerProvider(null, paymentProtocol.nativeSpl, "", "", referrer)
+      .accounts({
+        authority: authority.publicKey,
+        provider: providerAccount,
+        destination,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        globalConfig: globalConfigPda,
+        feeDestination: treasury.publicKey,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .signers([authority])
+      .rpc();
+
+    return { authority, provider: providerAccount, destination };
+  }
+
+  // Derives a depositor's contribution record in a shared vault.
+  function contributionPda(vault: PublicKey, depositor: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("contribution"), vault.toBuffer(), depositor.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  function ownerVaultCountPda(owner: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("owner_vaults"), owner.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  // The vault's creation slot as signed in vouchers, right after its address.
+  // Zero for a vault that does not exist, so vouchers for it still build.
+  async function createdSlot(vault: PublicKey): Promise<Buffer> {
+    const account = await program.account.vault.fetchNullable(vault);
+    return (account?.createdSlot ?? new BN(0)).toArrayLike(Buffer, "le", 8);
+  }
+
+  // Creates a vault for a fresh agent, settling to the main provider.
+  async function createFundedVault(
+    deposit: BN = depositAmount,
+    expiryUnix: BN = new BN(0),
+    withdrawTimelock: BN = new BN(0),
+    shared: boolean = false,
+    maxSettlements: number = 0,
+    sessionId: number[] = defaultSession,
+    emitEvents: boolean = true
+  ): Promise<SettleTarget & { agentTokenAccount: PublicKey }> {
+    const vaultAgent = Keypair.generate();
+    await airdrop(vaultAgent);
+    const vaultAgentTokenAccount = await createAccount(
+      provider.connection,
+      payer.payer,
+      mint,
+      vaultAgent.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      payer.payer,
+      mint,
+      vaultAgentTokenAccount,
+      admin,
+      10_000_000
+    );
+
+    const sessionSeed = sessionId.some((byte) => byte !== 0) ? [Buffer.from(sessionId)] : [];
+    const [vault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), vaultAgent.publicKey.toBuffer(), ...sessionSeed],
+      program.programId
+    );
+    const [vaultTokenAccount] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault_token_account"), vaultAgent.publicKey.toBuffer(), ...sessionSeed],
+      program.programId
+    );
+
+    await program.methods
+      .createVault(deposit, expiryUnix, withdrawTimelock, shared, maxSettlements, sessionId, emitEvents)
+      .accounts({
+        agent: vaultAgent.publicKey,
+        vault,
+        vaultTokenAccount,
+        agentTokenAccount: vaultAgentTokenAccount,
+        tokenMint: mint,
+        globalConfig: globalConfigPda,
+        ownerVaultCount: ownerVaultCountPda(vaultAgent.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        contribution: shared
+          ? contributionPda(vault, vaultAgent.publicKey)
+          : null,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .signers([vaultAgent])
+      .rpc();
+
+    return {
+      ...mainTarget(),
+      agent: vaultAgent,
+      vault,
+      vaultTokenAccount,
+      agentTokenAccount: vaultAgentTokenAccount,
+    };
+  }
+
+  // Creates a default vault again at the address of a closed one.
+  async function recreateVault(
+    target: SettleTarget & { agentTokenAccount: PublicKey },
+    deposit: BN = minDeposit
+  ) {
+    await program.methods
+      .createVault(deposit, new BN(0), new BN(0), false, 0, defaultSession, true)
+      .accounts({
+        agent: target.agent.publicKey,
+        vault: target.vault,
+        vaultTokenAccount: target.vaultTokenAccount,
+        agentTokenAccount: target.agentTokenAccount,
+        tokenMint: mint,
+        globalConfig: globalConfigPda,
+        ownerVaultCount: ownerVaultCountPda(target.agent.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        contribution: null,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .signers([target.agent])
+      .rpc();
+  }
+
+  // Pauses immediately; unpausing has to wait out the config timelock. By
+  // default everything but withdrawals is paused.
+  async function setGlobalPause(
+    value: boolean,
+    flags: number = pauseFlags.create | pauseFlags.settle | pauseFlags.deposit
+  ) {
+    const configAccounts = {
+      authority: admin.publicKey,
+      globalConfig: globalConfigPda,
+    } as any;
+    if (value) {
+      await program.methods
+        .emergencyPause(flags)
+        .accounts({
+          admin: admin.publicKey,
+          globalConfig: globalConfigPda,
+        } as any)
+        .signers([admin])
+        .rpc();
+      return;
+    }
+    await program.methods
+      .proposeUnpause()
+      .accounts(configAccounts)
+      .signers([admin])
+      .rpc();
+    await new Promise((resolve) =>
+      setTimeout(resolve, (timelockSeconds.toNumber() + 1) * 1000)
+    );
+    await program.methods
+      .applyUnpause()
+      .accounts(configAccounts)
+      .signers([admin])
+      .rpc();
+  }
+
   describe("Initialization Tests", () => {
     it("Initializes global config correctly", async () => {
       const config = await program.account.globalConfig.fetch(globalConfigPda);
@@ -242,6 +657,10 @@ describe("flow-vault", () => {
         settleThreshold.toString()
       );
       assert.equal(config.feeBps, feeBps);
+      assert.ok(config.feeDestination.equals(treasury.publicKey));
+      assert.equal(config.minDeposit.toString(), minDeposit.toString());
+      assert.equal(config.maxDeposit.toString(), maxDeposit.toString());
+      assert.equal(config.disputeSeconds.toString(), disputeSeconds.toString());
       console.log("✅ GlobalConfig verified");
     });
 
@@ -283,6 +702,7 @@ describe("flow-vault", () => {
       const message = Buffer.concat([
         Buffer.from("X402_FLOW_SETTLE"),
         vaultPda.toBuffer(),
+        await createdSlot(vaultPda),
         providerPda.toBuffer(),
         settleAmount.toArrayLike(Buffer, "le", 8),
         nonce.toArrayLike(Buffer, "le", 8),
@@ -298,17 +718,34 @@ describe("flow-vault", () => {
       });
 
       const settleBatchIx = await program.methods
-        .settleBatch(settleAmount, nonce)
+        .settleBatch(settleAmount, nonce, new BN(0), null, new BN(0), null, {
+          netFromAmount: {},
+        } as any)
         .accounts({
           facilitator: facilitator.publicKey,
           agent: agent.publicKey,
           vault: vaultPda,
           vaultTokenAccount: vaultTokenAccountPda,
+          tokenMint: mint,
           globalConfig: globalConfigPda,
           provider: providerPda,
           destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
           tokenProgram: TOKEN_PROGRAM_ID,
           instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          receipt: null,
+          systemProgram: null,
+          nonceBitmap: null,
+          providerEarnings: null,
+          memoProgram: null,
+          referrer: null,
+          downstreamVault: null,
+          providerFeeAccount: null,
+          ownerVaultCount: ownerVaultCountPda(agent.publicKey),
+          providerAuthority: null,
+          providerAta: null,
+          associatedTokenProgram: null,
+          feeWaiverAuthority: null,
         } as any)
         .instruction();
 
@@ -331,18 +768,34 @@ describe("flow-vault", () => {
       assert.equal(event.nonce.toString(), nonce.toString());
       assert.ok(event.vault.equals(vaultPda));
       assert.equal(event.visaMerchantId, visaMerchantId);
+      assert.equal(event.grossAmount.toString(), settleAmount.toString());
+      assert.equal(
+        event.feeAmount.add(event.netToProvider).toString(),
+        settleAmount.toString()
+      );
+      assert.ok(event.timestamp.toNumber() > 0);
       console.log("✅ Settlement event verified");
 
       // Assert on-chain state
       const vault = await program.account.vault.fetch(vaultPda);
       assert.equal(vault.totalSettled.toString(), settleAmount.toString());
       assert.equal(vault.nonce.toString(), nonce.toString());
+      // The reentrancy guard is only held across the transfers.
+      assert.isFalse(vault.inProgress);
 
+      // 250 bps of 350_000 goes to the fee destination, the rest to the provider
+      const expectedFee = settleAmount.muln(feeBps).divn(10_000);
+      assert.equal(event.feeAmount.toString(), expectedFee.toString());
       const providerBalance = await getAccount(
         provider.connection,
         providerTokenAccount
       );
-      assert.equal(providerBalance.amount.toString(), settleAmount.toString());
+      assert.equal(
+        providerBalance.amount.toString(),
+        settleAmount.sub(expectedFee).toString()
+      );
+      const feeBalance = await getAccount(provider.connection, feeTokenAccount);
+      assert.equal(feeBalance.amount.toString(), expectedFee.toString());
       console.log("✅ On-chain state verified");
     });
 
@@ -353,6 +806,7 @@ describe("flow-vault", () => {
       const message = Buffer.concat([
         Buffer.from("X402_FLOW_SETTLE"),
         vaultPda.toBuffer(),
+        await createdSlot(vaultPda),
         providerPda.toBuffer(),
         settleAmount.toArrayLike(Buffer, "le", 8),
         nonce.toArrayLike(Buffer, "le", 8),
@@ -368,17 +822,34 @@ describe("flow-vault", () => {
       });
 
       const settleBatchIx = await program.methods
-        .settleBatch(settleAmount, nonce)
+        .settleBatch(settleAmount, nonce, new BN(0), null, new BN(0), null, {
+          netFromAmount: {},
+        } as any)
         .accounts({
           facilitator: facilitator.publicKey,
           agent: agent.publicKey,
           vault: vaultPda,
           vaultTokenAccount: vaultTokenAccountPda,
+          tokenMint: mint,
           globalConfig: globalConfigPda,
           provider: providerPda,
           destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
           tokenProgram: TOKEN_PROGRAM_ID,
           instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          receipt: null,
+          systemProgram: null,
+          nonceBitmap: null,
+          providerEarnings: null,
+          memoProgram: null,
+          referrer: null,
+          downstreamVault: null,
+          providerFeeAccount: null,
+          ownerVaultCount: ownerVaultCountPda(agent.publicKey),
+          providerAuthority: null,
+          providerAta: null,
+          associatedTokenProgram: null,
+          feeWaiverAuthority: null,
         } as any)
         .instruction();
 
@@ -400,6 +871,7 @@ describe("flow-vault", () => {
       const message = Buffer.concat([
         Buffer.from("X402_FLOW_SETTLE"),
         vaultPda.toBuffer(),
+        await createdSlot(vaultPda),
         providerPda.toBuffer(),
         settleAmount.toArrayLike(Buffer, "le", 8),
         nonce.toArrayLike(Buffer, "le", 8),
@@ -415,17 +887,34 @@ describe("flow-vault", () => {
       });
 
       const settleBatchIx = await program.methods
-        .settleBatch(settleAmount, nonce)
+        .settleBatch(settleAmount, nonce, new BN(0), null, new BN(0), null, {
+          netFromAmount: {},
+        } as any)
         .accounts({
           facilitator: facilitator.publicKey,
           agent: agent.publicKey,
           vault: vaultPda,
           vaultTokenAccount: vaultTokenAccountPda,
+          tokenMint: mint,
           globalConfig: globalConfigPda,
           provider: providerPda,
           destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
           tokenProgram: TOKEN_PROGRAM_ID,
           instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          receipt: null,
+          systemProgram: null,
+          nonceBitmap: null,
+          providerEarnings: null,
+          memoProgram: null,
+          referrer: null,
+          downstreamVault: null,
+          providerFeeAccount: null,
+          ownerVaultCount: ownerVaultCountPda(agent.publicKey),
+          providerAuthority: null,
+          providerAta: null,
+          associatedTokenProgram: null,
+          feeWaiverAuthority: null,
         } as any)
         .instruction();
 
@@ -445,6 +934,7 @@ describe("flow-vault", () => {
       const message = Buffer.concat([
         Buffer.from("X402_FLOW_SETTLE"),
         vaultPda.toBuffer(),
+        await createdSlot(vaultPda),
         providerPda.toBuffer(),
         settleAmount.toArrayLike(Buffer, "le", 8),
         nonce.toArrayLike(Buffer, "le", 8),
@@ -460,17 +950,34 @@ describe("flow-vault", () => {
       });
 
       const settleBatchIx = await program.methods
-        .settleBatch(settleAmount, nonce)
+        .settleBatch(settleAmount, nonce, new BN(0), null, new BN(0), null, {
+          netFromAmount: {},
+        } as any)
         .accounts({
           facilitator: facilitator.publicKey,
           agent: agent.publicKey,
           vault: vaultPda,
           vaultTokenAccount: vaultTokenAccountPda,
+          tokenMint: mint,
           globalConfig: globalConfigPda,
           provider: providerPda,
           destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
           tokenProgram: TOKEN_PROGRAM_ID,
           instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          receipt: null,
+          systemProgram: null,
+          nonceBitmap: null,
+          providerEarnings: null,
+          memoProgram: null,
+          referrer: null,
+          downstreamVault: null,
+          providerFeeAccount: null,
+          ownerVaultCount: ownerVaultCountPda(agent.publicKey),
+          providerAuthority: null,
+          providerAta: null,
+          associatedTokenProgram: null,
+          feeWaiverAuthority: null,
         } as any)
         .instruction();
 
@@ -479,7 +986,7 @@ describe("flow-vault", () => {
 
       await assertRejects(
         promise,
-        "InvalidNonce",
+        "NonceReused",
         "Should reject replay attack"
       );
       console.log("✅ Replay attack prevented");
@@ -492,6 +999,7 @@ describe("flow-vault", () => {
       const message = Buffer.concat([
         Buffer.from("X402_FLOW_SETTLE"),
         vaultPda.toBuffer(),
+        await createdSlot(vaultPda),
         providerPda.toBuffer(),
         settleAmount.toArrayLike(Buffer, "le", 8),
         nonce.toArrayLike(Buffer, "le", 8),
@@ -507,17 +1015,34 @@ describe("flow-vault", () => {
       });
 
       const settleBatchIx = await program.methods
-        .settleBatch(settleAmount, nonce)
+        .settleBatch(settleAmount, nonce, new BN(0), null, new BN(0), null, {
+          netFromAmount: {},
+        } as any)
         .accounts({
           facilitator: facilitator.publicKey,
           agent: agent.publicKey,
           vault: vaultPda,
           vaultTokenAccount: vaultTokenAccountPda,
+          tokenMint: mint,
           globalConfig: globalConfigPda,
           provider: providerPda,
           destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
           tokenProgram: TOKEN_PROGRAM_ID,
           instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          receipt: null,
+          systemProgram: null,
+          nonceBitmap: null,
+          providerEarnings: null,
+          memoProgram: null,
+          referrer: null,
+          downstreamVault: null,
+          providerFeeAccount: null,
+          ownerVaultCount: ownerVaultCountPda(agent.publicKey),
+          providerAuthority: null,
+          providerAta: null,
+          associatedTokenProgram: null,
+          feeWaiverAuthority: null,
         } as any)
         .instruction();
 
@@ -526,7 +1051,7 @@ describe("flow-vault", () => {
 
       await assertRejects(
         promise,
-        "InvalidSignature",
+        "InvalidVoucher",
         "Should reject invalid signature"
       );
       console.log("✅ Invalid signature rejected");
@@ -541,6 +1066,7 @@ describe("flow-vault", () => {
       const message = Buffer.concat([
         Buffer.from("X402_FLOW_SETTLE"),
         vaultPda.toBuffer(),
+        await createdSlot(vaultPda),
         providerPda.toBuffer(),
         settleAmount.toArrayLike(Buffer, "le", 8),
         nonce.toArrayLike(Buffer, "le", 8),
@@ -556,17 +1082,34 @@ describe("flow-vault", () => {
       });
 
       const settleBatchIx = await program.methods
-        .settleBatch(settleAmount, nonce)
+        .settleBatch(settleAmount, nonce, new BN(0), null, new BN(0), null, {
+          netFromAmount: {},
+        } as any)
         .accounts({
           facilitator: facilitator.publicKey,
           agent: agent.publicKey,
           vault: vaultPda,
           vaultTokenAccount: vaultTokenAccountPda,
+          tokenMint: mint,
           globalConfig: globalConfigPda,
           provider: providerPda,
           destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
           tokenProgram: TOKEN_PROGRAM_ID,
           instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          receipt: null,
+          systemProgram: null,
+          nonceBitmap: null,
+          providerEarnings: null,
+          memoProgram: null,
+          referrer: null,
+          downstreamVault: null,
+          providerFeeAccount: null,
+          ownerVaultCount: ownerVaultCountPda(agent.publicKey),
+          providerAuthority: null,
+          providerAta: null,
+          associatedTokenProgram: null,
+          feeWaiverAuthority: null,
         } as any)
         .instruction();
 
@@ -589,6 +1132,7 @@ describe("flow-vault", () => {
       const message = Buffer.concat([
         Buffer.from("X402_FLOW_SETTLE"),
         vaultPda.toBuffer(),
+        await createdSlot(vaultPda),
         providerPda.toBuffer(),
         settleAmount.toArrayLike(Buffer, "le", 8),
         nonce.toArrayLike(Buffer, "le", 8),
@@ -604,17 +1148,34 @@ describe("flow-vault", () => {
       });
 
       const settleBatchIx = await program.methods
-        .settleBatch(settleAmount, nonce)
+        .settleBatch(settleAmount, nonce, new BN(0), null, new BN(0), null, {
+          netFromAmount: {},
+        } as any)
         .accounts({
           facilitator: facilitator.publicKey,
           agent: agent.publicKey,
           vault: vaultPda,
           vaultTokenAccount: vaultTokenAccountPda,
+          tokenMint: mint,
           globalConfig: globalConfigPda,
           provider: providerPda,
           destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
           tokenProgram: TOKEN_PROGRAM_ID,
           instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          receipt: null,
+          systemProgram: null,
+          nonceBitmap: null,
+          providerEarnings: null,
+          memoProgram: null,
+          referrer: null,
+          downstreamVault: null,
+          providerFeeAccount: null,
+          ownerVaultCount: ownerVaultCountPda(agent.publicKey),
+          providerAuthority: null,
+          providerAta: null,
+          associatedTokenProgram: null,
+          feeWaiverAuthority: null,
         } as any)
         .instruction();
 
@@ -623,105 +1184,7046 @@ describe("flow-vault", () => {
 
       await assertRejects(
         promise,
-        "ZeroAmount",
+        "BelowSettleThreshold",
         "Should reject amount below threshold"
       );
       console.log("✅ Below threshold rejected");
     });
   });
 
-  describe("Withdrawal Tests", () => {
-    it("Fails: Wrong user tries to withdraw", async () => {
-      const promise = program.methods
-        .withdraw()
-        .accounts({
-          agent: randomUser.publicKey,
-          vault: vaultPda,
-          vaultTokenAccount: vaultTokenAccountPda,
-          agentTokenAccount: randomUserTokenAccount,
-          tokenProgram: TOKEN_PROGRAM_ID,
-        } as any)
-        .signers([randomUser])
-        .rpc();
+  describe("Fee Routing Tests", () => {
+    it("Fails: Fee destination not owned by the fee recipient", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(new BN(100_000), new BN(4), undefined, {
+            ...mainTarget(),
+            feeDestination: randomUserTokenAccount,
+          }),
+          [facilitator]
+        ),
+        "InvalidFeeDestination",
+        "Should reject a fee destination owned by someone else"
+      );
+      console.log("✅ Foreign fee destination rejected");
+    });
+  });
+
+  describe("Voucher Tests", () => {
+    const settleAmount = new BN(300_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Settles with an ed25519 voucher and no owner signature", async () => {
+      const tx = await buildSettleTx(settleAmount, new BN(1), undefined, target);
+      await provider.sendAndConfirm(tx, [facilitator]);
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.totalSettled.toString(), settleAmount.toString());
+      assert.equal(vault.nonce.toString(), "1");
+      console.log("✅ Voucher settlement succeeded");
+    });
+
+    it("Fails: Voucher signed for a different amount", async () => {
+      const tx = await buildSettleTx(settleAmount, new BN(2), undefined, target);
+      // Swap in a settle instruction whose amount differs from the voucher.
+      tx.instructions[1] = await buildSettleIx(
+        settleAmount.addn(1),
+        new BN(2),
+        target
+      );
 
       await assertRejects(
-        promise,
-        "ConstraintSeeds",
-        "Should reject unauthorized withdrawal"
+        provider.sendAndConfirm(tx, [facilitator]),
+        "InvalidVoucher",
+        "Should reject a tampered amount"
       );
-      console.log("✅ Unauthorized withdrawal prevented");
     });
 
-    it("Withdraws remaining funds successfully", async () => {
-      const vaultBefore = await program.account.vault.fetch(vaultPda);
-      const remainingAmount = vaultBefore.depositAmount.sub(
-        vaultBefore.totalSettled
+    it("Fails: No voucher and no owner signature", async () => {
+      const tx = new Transaction().add(
+        await buildSettleIx(settleAmount, new BN(2), target)
       );
 
-      const tx = await program.methods
-        .withdraw()
+      await assertRejects(
+        provider.sendAndConfirm(tx, [facilitator]),
+        "VoucherMissing",
+        "Should require a voucher when the owner does not sign"
+      );
+    });
+
+    it("Settles without a voucher when the owner co-signs", async () => {
+      const settleIx = await buildSettleIx(settleAmount, new BN(2), target);
+      settleIx.keys
+        .filter((key) => key.pubkey.equals(target.agent.publicKey))
+        .forEach((key) => (key.isSigner = true));
+
+      await provider.sendAndConfirm(new Transaction().add(settleIx), [
+        facilitator,
+        target.agent,
+      ]);
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.nonce.toString(), "2");
+      console.log("✅ Owner-signed settlement succeeded");
+    });
+  });
+
+  describe("Batch Settlement Tests", () => {
+    const settleAmount = new BN(200_000);
+    let first: Awaited<ReturnType<typeof createFundedVault>>;
+    let second: Awaited<ReturnType<typeof createFundedVault>>;
+    let replayed: Awaited<ReturnType<typeof createFundedVault>>;
+
+    before(async () => {
+      [first, second, replayed] = [
+        await createFundedVault(),
+        await createFundedVault(),
+        await createFundedVault(),
+      ];
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, replayed),
+        [facilitator]
+      );
+    });
+
+    const batchIx = (
+      targets: Awaited<ReturnType<typeof createFundedVault>>[],
+      nonces: number[],
+      coSigners: Keypair[] = []
+    ) =>
+      program.methods
+        .settleBatchMany(
+          targets.map(() => settleAmount),
+          nonces.map((n) => new BN(n))
+        )
         .accounts({
-          agent: agent.publicKey,
-          vault: vaultPda,
-          vaultTokenAccount: vaultTokenAccountPda,
-          agentTokenAccount: agentTokenAccount,
+          facilitator: facilitator.publicKey,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          provider: providerPda,
+          destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
           tokenProgram: TOKEN_PROGRAM_ID,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          referrer: null,
         } as any)
-        .signers([agent])
-        .rpc();
+        .remainingAccounts(
+          targets.flatMap((t) => [
+            {
+              pubkey: t.agent.publicKey,
+              isSigner: coSigners.includes(t.agent),
+              isWritable: false,
+            },
+            { pubkey: t.vault, isSigner: false, isWritable: true },
+            { pubkey: t.vaultTokenAccount, isSigner: false, isWritable: true },
+          ])
+        );
 
-      console.log("✅ Withdrawal executed:", tx);
+    it("Fails: Batch longer than the maximum", async () => {
+      const amounts = Array.from({ length: 17 }, () => settleAmount);
+      await assertRejects(
+        program.methods
+          .settleBatchMany(amounts, amounts.map((_, i) => new BN(i + 1)))
+          .accounts({
+            facilitator: facilitator.publicKey,
+            tokenMint: mint,
+            globalConfig: globalConfigPda,
+            provider: providerPda,
+            destination: providerTokenAccount,
+            feeDestination: feeTokenAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+            referrer: null,
+          } as any)
+          .signers([facilitator])
+          .rpc(),
+        "InputTooLarge",
+        "Should cap the batch length"
+      );
+    });
 
-      // Vault should be closed
-      try {
-        await program.account.vault.fetch(vaultPda);
-        assert.fail("Vault should be closed");
-      } catch (err) {
-        assert.ok(err.toString().includes("Account does not exist"));
-      }
+    it("Settles a mixed batch and skips the reused nonce", async () => {
+      const targets = [first, second, replayed];
+      // One voucher plus two co-signing owners keeps the transaction small.
+      const voucherTx = await buildSettleTx(
+        settleAmount,
+        new BN(1),
+        undefined,
+        first
+      );
+      const coSigners = [second.agent, replayed.agent];
+      const method = batchIx(targets, [1, 1, 1], coSigners);
 
-      // Check final balance
-      const agentBalance = await getAccount(
-        provider.connection,
-        agentTokenAccount
+      const tx = new Transaction()
+        .add(voucherTx.instructions[0])
+        .add(await method.instruction());
+      const simulated = await method
+        .preInstructions([voucherTx.instructions[0]])
+        .signers([facilitator, ...coSigners])
+        .simulate();
+      await provider.sendAndConfirm(tx, [facilitator, ...coSigners]);
+
+      // Bits 0 and 1 set: the replayed vault was skipped.
+      const returnLog = simulated.raw.find((log) =>
+        log.startsWith("Program return: ")
       );
-      const expectedBalance = new BN(10_000_000)
-        .sub(depositAmount)
-        .add(remainingAmount);
-      assert.equal(agentBalance.amount.toString(), expectedBalance.toString());
-      console.log("✅ Final balance verified");
+      const bitmap = Buffer.from(returnLog.split(" ").pop(), "base64").readUInt16LE(0);
+      assert.equal(bitmap, 0b011);
+
+      for (const target of [first, second]) {
+        const vault = await program.account.vault.fetch(target.vault);
+        assert.equal(vault.nonce.toString(), "1");
+        assert.equal(vault.totalSettled.toString(), settleAmount.toString());
+      }
+      const skipped = await program.account.vault.fetch(replayed.vault);
+      assert.equal(skipped.totalSettled.toString(), settleAmount.toString());
+      console.log("✅ Mixed batch settled with one skip");
     });
   });
 
-  describe("Admin Tests", () => {
-    it("Fails: Non-admin tries emergency pause", async () => {
-      const promise = program.methods
-        .emergencyPause(true)
+  describe("Merkle Settlement Tests", () => {
+    const amounts = [150_000, 250_000, 350_000, 450_000].map((a) => new BN(a));
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+    let root: Buffer;
+    let leaves: Buffer[];
+    let settlementRoot: PublicKey;
+
+    const leafHash = (vault: PublicKey, index: number, amount: BN) => {
+      const indexBytes = Buffer.alloc(2);
+      indexBytes.writeUInt16LE(index);
+      return Buffer.from(
+        keccak_256(
+          Buffer.concat([
+            Buffer.from([0]),
+            vault.toBuffer(),
+            indexBytes,
+            amount.toArrayLike(Buffer, "le", 8),
+          ])
+        )
+      );
+    };
+    const nodeHash = (left: Buffer, right: Buffer) =>
+      Buffer.from(keccak_256(Buffer.concat([Buffer.from([1]), left, right])));
+
+    // Sibling path for a leaf of the four-leaf tree.
+    const proofFor = (index: number) => [
+      leaves[index ^ 1],
+      index < 2
+        ? nodeHash(leaves[2], leaves[3])
+        : nodeHash(leaves[0], leaves[1]),
+    ];
+
+    const claim = (index: number, amount: BN, proof: Buffer[]) =>
+      program.methods
+        .settleWithProof(
+          Array.from(root),
+          amount,
+          index,
+          proof.map((p) => Array.from(p))
+        )
         .accounts({
-          admin: agent.publicKey,
+          facilitator: facilitator.publicKey,
+          vault: target.vault,
+          vaultTokenAccount: target.vaultTokenAccount,
+          tokenMint: mint,
+          settlementRoot,
           globalConfig: globalConfigPda,
+          provider: providerPda,
+          destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          referrer: null,
         } as any)
-        .signers([agent])
+        .signers([facilitator])
         .rpc();
 
-      await assertRejects(
-        promise,
-        "ConstraintHasOne",
-        "Should reject non-admin pause"
+    before(async () => {
+      target = await createFundedVault();
+      leaves = amounts.map((amount, i) => leafHash(target.vault, i, amount));
+      root = nodeHash(
+        nodeHash(leaves[0], leaves[1]),
+        nodeHash(leaves[2], leaves[3])
+      );
+      [settlementRoot] = PublicKey.findProgramAddressSync(
+        [Buffer.from("settlement_root"), target.vault.toBuffer(), root],
+        program.programId
       );
-      console.log("✅ Non-admin pause rejected");
-    });
 
-    it("Admin triggers emergency pause successfully", async () => {
-      const tx = await program.methods
-        .emergencyPause(true)
+      await program.methods
+        .commitSettlementRoot(Array.from(root), leaves.length)
         .accounts({
-          admin: admin.publicKey,
-          globalConfig: globalConfigPda,
+          agent: target.agent.publicKey,
+          vault: target.vault,
+          provider: providerPda,
+          settlementRoot,
+          systemProgram: SystemProgram.programId,
         } as any)
-        .signers([admin])
+        .signers([target.agent])
         .rpc();
+    });
+
+    it("Claims a leaf with a valid proof", async () => {
+      await claim(2, amounts[2], proofFor(2));
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.totalSettled.toString(), amounts[2].toString());
+      // Proof claims do not consume the voucher nonce.
+      assert.equal(vault.nonce.toString(), "0");
+      console.log("✅ Merkle leaf claimed");
+    });
+
+    it("Fails: Proof with a wrong sibling", async () => {
+      const proof = proofFor(1);
+      proof[0] = leaves[2];
+      await assertRejects(
+        claim(1, amounts[1], proof),
+        "InvalidProof",
+        "Should reject a proof that does not rebuild the root"
+      );
+    });
+
+    it("Fails: Proof deeper than the maximum", async () => {
+      const before = await program.account.settlementRoot.fetch(settlementRoot);
+      const proof = [...proofFor(0), ...Array(7).fill(leaves[0])];
+      await assertRejects(
+        claim(0, amounts[0], proof),
+        "InputTooLarge",
+        "Should cap the proof depth"
+      );
+
+      const after = await program.account.settlementRoot.fetch(settlementRoot);
+      assert.deepEqual(after.claimed, before.claimed);
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.totalSettled.toString(), amounts[2].toString());
+    });
+
+    it("Fails: Claiming the same leaf twice", async () => {
+      await assertRejects(
+        claim(2, amounts[2], proofFor(2)),
+        "LeafAlreadyClaimed",
+        "Should reject a double-claim"
+      );
+    });
+
+    it("Fails: Claiming from a vault re-created at the same address", async () => {
+      const vault = await program.account.vault.fetch(target.vault);
+      await provider.sendAndConfirm(
+        await buildSettleTx(
+          vault.depositAmount.sub(vault.totalSettled),
+          new BN(1),
+          undefined,
+          target
+        ),
+        [facilitator]
+      );
+      assert.isNull(await provider.connection.getAccountInfo(target.vault));
+      await recreateVault(target);
+
+      await assertRejects(
+        claim(0, amounts[0], proofFor(0)),
+        "StaleSettlementRoot",
+        "A closed vault's root should not drain its successor"
+      );
+    });
+  });
+
+  describe("Vault Expiry Tests", () => {
+    const settleAmount = new BN(200_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+    let expiryUnix: number;
 
+    const chainTime = async () =>
+      provider.connection.getBlockTime(
+        await provider.connection.getSlot("confirmed")
+      );
+
+    const refund = () =>
+      program.methods
+        .refundExpired()
+        .accounts({
+          agent: target.agent.publicKey,
+          vault: target.vault,
+          vaultTokenAccount: target.vaultTokenAccount,
+          agentTokenAccount: target.agentTokenAccount,
+          tokenMint: mint,
+          contribution: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([target.agent])
+        .rpc();
+
+    before(async () => {
+      expiryUnix = (await chainTime()) + 4;
+      target = await createFundedVault(depositAmount, new BN(expiryUnix));
+    });
+
+    it("Settles and refuses refunds before expiry", async () => {
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, target),
+        [facilitator]
+      );
+      await assertRejects(
+        refund(),
+        "VaultNotExpired",
+        "Refund should wait for expiry"
+      );
+    });
+
+    it("Rejects settlements and refunds the owner after expiry", async () => {
+      while ((await chainTime()) < expiryUnix) {
+        await new Promise((resolve) => setTimeout(resolve, 1000));
+      }
+
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(2), undefined, target),
+          [facilitator]
+        ),
+        "VaultExpired",
+        "Settlements should stop at expiry"
+      );
+
+      const agentBefore = await getAccount(
+        provider.connection,
+        target.agentTokenAccount
+      );
+      await refund();
+      const agentAfter = await getAccount(
+        provider.connection,
+        target.agentTokenAccount
+      );
+
+      const remaining = depositAmount.sub(settleAmount);
+      assert.equal(
+        (agentAfter.amount - agentBefore.amount).toString(),
+        remaining.toString()
+      );
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(
+        vault.depositAmount.sub(vault.totalSettled).toString(),
+        "0"
+      );
+      console.log("✅ Expired vault refunded");
+    });
+  });
+
+  describe("Dispute Window Tests", () => {
+    const settleAmount = new BN(300_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+    let contested: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const propose = async (
+      t: Awaited<ReturnType<typeof createFundedVault>>,
+      nonce: BN
+    ) => {
+      const voucherIx = (await buildSettleTx(settleAmount, nonce, undefined, t))
+        .instructions[0];
+      const proposeIx = await program.methods
+        .proposeSettlement(settleAmount, nonce)
+        .accounts({
+          facilitator: facilitator.publicKey,
+          agent: t.agent.publicKey,
+          vault: t.vault,
+          globalConfig: globalConfigPda,
+          provider: providerPda,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        } as any)
+        .instruction();
+      return provider.sendAndConfirm(
+        new Transaction().add(voucherIx).add(proposeIx),
+        [facilitator]
+      );
+    };
+
+    const finalize = () =>
+      program.methods
+        .finalizeSettlement()
+        .accounts({
+          facilitator: facilitator.publicKey,
+          vault: target.vault,
+          vaultTokenAccount: target.vaultTokenAccount,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          provider: providerPda,
+          destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          referrer: null,
+        } as any)
+        .signers([facilitator])
+        .rpc();
+
+    before(async () => {
+      target = await createFundedVault();
+      contested = await createFundedVault();
+    });
+
+    it("Fails: Finalizing before the window elapses", async () => {
+      await propose(target, new BN(1));
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.pendingAmount.toString(), settleAmount.toString());
+      assert.ok(vault.pendingProvider.equals(providerPda));
+
+      await assertRejects(
+        finalize(),
+        "DisputeWindowActive",
+        "Should hold funds during the dispute window"
+      );
+    });
+
+    it("Finalizes after the window elapses", async () => {
+      const destinationBefore = await getAccount(
+        provider.connection,
+        providerTokenAccount
+      );
+      await new Promise((resolve) =>
+        setTimeout(resolve, (disputeSeconds.toNumber() + 1) * 1000)
+      );
+      await finalize();
+
+      const destinationAfter = await getAccount(
+        provider.connection,
+        providerTokenAccount
+      );
+      const fee = settleAmount.muln(feeBps).divn(10_000);
+      assert.equal(
+        (destinationAfter.amount - destinationBefore.amount).toString(),
+        settleAmount.sub(fee).toString()
+      );
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.pendingAmount.toString(), "0");
+      assert.equal(vault.totalSettled.toString(), settleAmount.toString());
+      console.log("✅ Settlement finalized after dispute window");
+    });
+
+    it("Owner cancels a pending settlement within the window", async () => {
+      await propose(contested, new BN(1));
+      await program.methods
+        .cancelSettlement()
+        .accounts({
+          agent: contested.agent.publicKey,
+          vault: contested.vault,
+        } as any)
+        .signers([contested.agent])
+        .rpc();
+
+      const vault = await program.account.vault.fetch(contested.vault);
+      assert.equal(vault.pendingAmount.toString(), "0");
+      assert.equal(vault.totalSettled.toString(), "0");
+      // The cancelled voucher's nonce stays consumed.
+      assert.equal(vault.nonce.toString(), "1");
+      console.log("✅ Pending settlement cancelled");
+    });
+  });
+
+  describe("Provider Metadata Tests", () => {
+    const update = (name: string, uri: string, signer = providerAuthority) =>
+      program.methods
+        .updateProviderMetadata(name, uri)
+        .accounts({
+          authority: signer.publicKey,
+          provider: providerPda,
+        } as any)
+        .signers([signer])
+        .rpc();
+
+    it("Stores metadata set at registration", async () => {
+      const providerAccount = await program.account.provider.fetch(providerPda);
+      assert.equal(providerAccount.name, providerName);
+      assert.equal(providerAccount.uri, providerUri);
+    });
+
+    it("Accepts metadata at the length limits", async () => {
+      const name = "n".repeat(32);
+      const uri = "u".repeat(128);
+      await update(name, uri);
+
+      const providerAccount = await program.account.provider.fetch(providerPda);
+      assert.equal(providerAccount.name, name);
+      assert.equal(providerAccount.uri, uri);
+    });
+
+    it("Fails: Name longer than 32 bytes", async () => {
+      await assertRejects(
+        update("n".repeat(33), providerUri),
+        "MetadataTooLong",
+        "Should reject an oversized name"
+      );
+    });
+
+    it("Fails: URI longer than 128 bytes", async () => {
+      await assertRejects(
+        update(providerName, "u".repeat(129)),
+        "MetadataTooLong",
+        "Should reject an oversized URI"
+      );
+    });
+
+    it("Fails: Non-authority updates metadata", async () => {
+      await assertRejects(
+        update(providerName, providerUri, randomUser),
+        "ConstraintHasOne",
+        "Only the provider authority can update metadata"
+      );
+    });
+
+    after(async () => {
+      await update(providerName, providerUri);
+    });
+  });
+
+  describe("Provider Authority Transfer Tests", () => {
+    let rotated: Awaited<ReturnType<typeof registerTestProvider>>;
+    let newAuthority: Keypair;
+
+    before(async () => {
+      rotated = await registerTestProvider();
+      newAuthority = Keypair.generate();
+      await airdrop(newAuthority);
+    });
+
+    it("Fails: Non-authority proposes a transfer", async () => {
+      await assertRejects(
+        program.methods
+          .transferProviderAuthority()
+          .accounts({
+            authority: randomUser.publicKey,
+            provider: rotated.provider,
+            newAuthority: randomUser.publicKey,
+          } as any)
+          .signers([randomUser])
+          .rpc(),
+        "ConstraintHasOne",
+        "Only the current authority can propose a transfer"
+      );
+    });
+
+    it("Fails: Someone other than the proposed key accepts", async () => {
+      await program.methods
+        .transferProviderAuthority()
+        .accounts({
+          authority: rotated.authority.publicKey,
+          provider: rotated.provider,
+          newAuthority: newAuthority.publicKey,
+        } as any)
+        .signers([rotated.authority])
+        .rpc();
+
+      await assertRejects(
+        program.methods
+          .acceptProviderAuthority()
+          .accounts({
+            newAuthority: randomUser.publicKey,
+            provider: rotated.provider,
+          } as any)
+          .signers([randomUser])
+          .rpc(),
+        "InvalidPendingAuthority",
+        "Only the pending authority can accept"
+      );
+    });
+
+    it("Proposed key accepts and takes over", async () => {
+      await program.methods
+        .acceptProviderAuthority()
+        .accounts({
+          newAuthority: newAuthority.publicKey,
+          provider: rotated.provider,
+        } as any)
+        .signers([newAuthority])
+        .rpc();
+
+      const providerAccount = await program.account.provider.fetch(
+        rotated.provider
+      );
+      assert.ok(providerAccount.authority.equals(newAuthority.publicKey));
+      assert.isNull(providerAccount.pendingAuthority);
+
+      // The old key has lost control of the provider.
+      await assertRejects(
+        program.methods
+          .pauseProvider(true)
+          .accounts({
+            authority: rotated.authority.publicKey,
+            provider: rotated.provider,
+          } as any)
+          .signers([rotated.authority])
+          .rpc(),
+        "ConstraintHasOne",
+        "Previous authority should be rejected"
+      );
+      console.log("✅ Provider authority rotated");
+    });
+  });
+
+  describe("Settlement Receipt Tests", () => {
+    const settleAmount = new BN(250_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const receiptPda = (vault: PublicKey, nonce: BN) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("receipt"),
+          vault.toBuffer(),
+          nonce.toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      )[0];
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Creates a receipt with the settlement details", async () => {
+      const nonce = new BN(1);
+      const receipt = receiptPda(target.vault, nonce);
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, nonce, undefined, {
+          ...target,
+          receipt,
+        }),
+        [facilitator]
+      );
+
+      const account = await program.account.settlementReceipt.fetch(receipt);
+      assert.ok(account.vault.equals(target.vault));
+      assert.ok(account.provider.equals(providerPda));
+      assert.equal(account.nonce.toString(), "1");
+      assert.equal(account.amount.toString(), settleAmount.toString());
+      assert.equal(
+        account.fee.toString(),
+        settleAmount.muln(feeBps).divn(10_000).toString()
+      );
+      assert.isAbove(account.timestamp.toNumber(), 0);
+      console.log("✅ Settlement receipt recorded");
+    });
+
+    it("Fails: A second receipt for the same nonce", async () => {
+      const nonce = new BN(1);
+      try {
+        await provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, nonce, undefined, {
+            ...target,
+            receipt: receiptPda(target.vault, nonce),
+          }),
+          [facilitator]
+        );
+        assert.fail("Receipt creation should have failed");
+      } catch (err) {
+        // The system program refuses to re-create the receipt account.
+        const logs: string[] = err.logs ?? err.transactionLogs ?? [];
+        assert.ok(
+          logs.some((log) => log.includes("already in use")),
+          "Expected the receipt init to fail"
+        );
+      }
+    });
+
+    it("Settles without a receipt when none is passed", async () => {
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(2), undefined, target),
+        [facilitator]
+      );
+      const info = await provider.connection.getAccountInfo(
+        receiptPda(target.vault, new BN(2))
+      );
+      assert.isNull(info);
+    });
+  });
+
+  describe("Provider Fee Override Tests", () => {
+    const settleAmount = new BN(400_000);
+    let discounted: Awaited<ReturnType<typeof registerTestProvider>>;
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    before(async () => {
+      discounted = await registerTestProvider();
+      target = await createFundedVault();
+    });
+
+    it("Fails: Override above 10000 bps", async () => {
+      await assertRejects(
+        program.methods
+          .setProviderFee(10_001)
+          .accounts({
+            authority: admin.publicKey,
+            globalConfig: globalConfigPda,
+            provider: discounted.provider,
+          } as any)
+          .signers([admin])
+          .rpc(),
+        "InvalidFeeBps",
+        "Should reject an override above 100%"
+      );
+    });
+
+    it("Fails: Override above the base fee", async () => {
+      await assertRejects(
+        program.methods
+          .setProviderFee(feeBps + 1)
+          .accounts({
+            authority: admin.publicKey,
+            globalConfig: globalConfigPda,
+            provider: discounted.provider,
+          } as any)
+          .signers([admin])
+          .rpc(),
+        "InvalidFeeBps",
+        "Raising a provider's fee has to go through the fee timelock"
+      );
+    });
+
+    it("Fails: Non-admin sets a fee override", async () => {
+      await assertRejects(
+        program.methods
+          .setProviderFee(0)
+          .accounts({
+            authority: discounted.authority.publicKey,
+            globalConfig: globalConfigPda,
+            provider: discounted.provider,
+          } as any)
+          .signers([discounted.authority])
+          .rpc(),
+        "Unauthorized",
+        "Providers cannot set their own fee"
+      );
+    });
+
+    it("Settles at the override rate when one is set", async () => {
+      await program.methods
+        .setProviderFee(50)
+        .accounts({
+          authority: admin.publicKey,
+          globalConfig: globalConfigPda,
+          provider: discounted.provider,
+        } as any)
+        .signers([admin])
+        .rpc();
+
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, {
+          ...target,
+          provider: discounted.provider,
+          destination: discounted.destination,
+        }),
+        [facilitator]
+      );
+
+      const destinationBalance = await getAccount(
+        provider.connection,
+        discounted.destination
+      );
+      const expectedFee = settleAmount.muln(50).divn(10_000);
+      assert.equal(
+        destinationBalance.amount.toString(),
+        settleAmount.sub(expectedFee).toString()
+      );
+      console.log("✅ Override fee applied");
+    });
+
+    it("Falls back to the global rate without an override", async () => {
+      const feeBefore = await getAccount(provider.connection, feeTokenAccount);
+
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(2), undefined, target),
+        [facilitator]
+      );
+
+      const feeAfter = await getAccount(provider.connection, feeTokenAccount);
+      assert.equal(
+        (feeAfter.amount - feeBefore.amount).toString(),
+        settleAmount.muln(feeBps).divn(10_000).toString()
+      );
+      console.log("✅ Global fee applied");
+    });
+  });
+
+  describe("Replay Protection Tests", () => {
+    const settleAmount = new BN(100_000);
+
+    it("Accepts a nonce that skips ahead of the last one", async () => {
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(5)),
+        [facilitator]
+      );
+
+      const vault = await program.account.vault.fetch(vaultPda);
+      assert.equal(vault.nonce.toString(), "5");
+      console.log("✅ Nonce 5 accepted");
+    });
+
+    it("Fails: Replaying the same nonce", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(5)),
+          [facilitator]
+        ),
+        "NonceReused",
+        "Should reject nonce 5 twice"
+      );
+      console.log("✅ Nonce 5 replay rejected");
+    });
+
+    it("Accepts an unused nonce below the last one", async () => {
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(4)),
+        [facilitator]
+      );
+
+      // The highest nonce is unchanged; 4 is recorded in the window.
+      const vault = await program.account.vault.fetch(vaultPda);
+      assert.equal(vault.nonce.toString(), "5");
+      console.log("✅ Out-of-order nonce accepted");
+    });
+
+    it("Accepts the next nonce", async () => {
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(6)),
+        [facilitator]
+      );
+
+      const vault = await program.account.vault.fetch(vaultPda);
+      assert.equal(vault.nonce.toString(), "6");
+      console.log("✅ Nonce 6 accepted");
+    });
+    describe("Out-of-order nonces", () => {
+      let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+      const settle = async (nonce: number) =>
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(nonce), undefined, target),
+          [facilitator]
+        );
+
+      before(async () => {
+        target = await createFundedVault();
+      });
+
+      it("Settles nonces 3, 1, 2 and rejects replaying 2", async () => {
+        await settle(3);
+        await settle(1);
+        await settle(2);
+
+        const vault = await program.account.vault.fetch(target.vault);
+        assert.equal(vault.nonce.toString(), "3");
+        assert.equal(
+          vault.totalSettled.toString(),
+          settleAmount.muln(3).toString()
+        );
+
+        await assertRejects(
+          settle(2),
+          "NonceReused",
+          "Should reject replaying nonce 2"
+        );
+      });
+
+      it("Fails: Unused nonce that fell below the window", async () => {
+        await settle(200);
+        await assertRejects(
+          settle(50),
+          "InvalidNonce",
+          "Nonces more than 128 below the highest are rejected"
+        );
+      });
+    });
+  });
+
+  describe("Top-up Tests", () => {
+    function depositAccounts() {
+      return {
+        agent: agent.publicKey,
+        vault: vaultPda,
+        vaultTokenAccount: vaultTokenAccountPda,
+        agentTokenAccount: agentTokenAccount,
+        tokenMint: mint,
+        globalConfig: globalConfigPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any;
+    }
+
+    it("Accumulates two sequential top-ups", async () => {
+      const vaultBefore = await program.account.vault.fetch(vaultPda);
+      const balanceBefore = vaultBefore.depositAmount.sub(
+        vaultBefore.totalSettled
+      );
+
+      let listener = null;
+      const event = await new Promise<any>((resolve, reject) => {
+        listener = program.addEventListener("vaultToppedUp", (event) => {
+          if (event.newBalance.eq(balanceBefore.add(topUpAmount).add(topUpAmount))) {
+            resolve(event);
+          }
+        });
+        (async () => {
+          for (let i = 0; i < 2; i++) {
+            await program.methods
+              .deposit(topUpAmount)
+              .accounts(depositAccounts())
+              .signers([agent])
+              .rpc();
+          }
+        })().catch(reject);
+      });
+      await program.removeEventListener(listener);
+
+      assert.equal(event.amount.toString(), topUpAmount.toString());
+
+      const vaultAfter = await program.account.vault.fetch(vaultPda);
+      assert.equal(
+        vaultAfter.depositAmount.toString(),
+        vaultBefore.depositAmount.add(topUpAmount.muln(2)).toString()
+      );
+      console.log("✅ Top-ups accumulated");
+    });
+
+    it("Fails: Non-owner tops up someone else's vault", async () => {
+      await assertRejects(
+        program.methods
+          .deposit(topUpAmount)
+          .accounts({
+            ...depositAccounts(),
+            agent: randomUser.publicKey,
+            agentTokenAccount: randomUserTokenAccount,
+          })
+          .signers([randomUser])
+          .rpc(),
+        "ConstraintHasOne",
+        "Should reject a non-owner top-up"
+      );
+    });
+  });
+
+  describe("Withdrawal Tests", () => {
+    it("Fails: Wrong user tries to withdraw", async () => {
+      const promise = program.methods
+        .withdraw(new BN(1))
+        .accounts({
+          agent: randomUser.publicKey,
+          vault: vaultPda,
+          globalConfig: globalConfigPda,
+          vaultTokenAccount: vaultTokenAccountPda,
+          agentTokenAccount: randomUserTokenAccount,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([randomUser])
+        .rpc();
+
+      await assertRejects(
+        promise,
+        "ConstraintHasOne",
+        "Should reject unauthorized withdrawal"
+      );
+      console.log("✅ Unauthorized withdrawal prevented");
+    });
+
+    it("Fails: Withdrawing from a spoofed vault token account", async () => {
+      // Same mint, but not the account derived for this vault.
+      const promise = program.methods
+        .withdraw(new BN(1))
+        .accounts({
+          agent: agent.publicKey,
+          vault: vaultPda,
+          globalConfig: globalConfigPda,
+          vaultTokenAccount: randomUserTokenAccount,
+          agentTokenAccount: agentTokenAccount,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([agent])
+        .rpc();
+
+      await assertRejects(
+        promise,
+        "ConstraintSeeds",
+        "Should reject a token account the vault does not derive"
+      );
+      console.log("✅ Spoofed vault token account rejected");
+    });
+
+    it("Fails: Closing a vault that still holds tokens", async () => {
+      const promise = program.methods
+        .closeVault()
+        .accounts({
+          agent: agent.publicKey,
+          vault: vaultPda,
+          vaultTokenAccount: vaultTokenAccountPda,
+          globalConfig: globalConfigPda,
+          ownerVaultCount: ownerVaultCountPda(agent.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([agent])
+        .rpc();
+
+      await assertRejects(
+        promise,
+        "VaultNotEmpty",
+        "Should reject closing a funded vault"
+      );
+      console.log("✅ Funded vault close rejected");
+    });
+
+    const partialWithdrawal = new BN(100_000);
+
+    function withdrawAccounts() {
+      return {
+        agent: agent.publicKey,
+        vault: vaultPda,
+        globalConfig: globalConfigPda,
+        vaultTokenAccount: vaultTokenAccountPda,
+        agentTokenAccount: agentTokenAccount,
+        tokenMint: mint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any;
+    }
+
+    it("Fails: Withdrawing zero", async () => {
+      await assertRejects(
+        program.methods
+          .withdraw(new BN(0))
+          .accounts(withdrawAccounts())
+          .signers([agent])
+          .rpc(),
+        "ZeroAmount",
+        "Should reject a zero withdrawal"
+      );
+    });
+
+    it("Fails: Withdrawing more than the vault balance", async () => {
+      const vault = await program.account.vault.fetch(vaultPda);
+      const overBalance = vault.depositAmount
+        .sub(vault.totalSettled)
+        .add(new BN(1));
+
+      await assertRejects(
+        program.methods
+          .withdraw(overBalance)
+          .accounts(withdrawAccounts())
+          .signers([agent])
+          .rpc(),
+        "InsufficientFunds",
+        "Should reject an over-balance withdrawal"
+      );
+      console.log("✅ Over-balance withdrawal rejected");
+    });
+
+    it("Withdraws part of the balance", async () => {
+      const vaultBefore = await program.account.vault.fetch(vaultPda);
+
+      await program.methods
+        .withdraw(partialWithdrawal)
+        .accounts(withdrawAccounts())
+        .signers([agent])
+        .rpc();
+
+      const vaultAfter = await program.account.vault.fetch(vaultPda);
+      assert.equal(
+        vaultAfter.depositAmount.toString(),
+        vaultBefore.depositAmount.sub(partialWithdrawal).toString()
+      );
+      assert.equal(
+        vaultAfter.totalSettled.toString(),
+        vaultBefore.totalSettled.toString()
+      );
+      console.log("✅ Partial withdrawal verified");
+    });
+
+    it("Withdraws remaining funds successfully", async () => {
+      const vaultBefore = await program.account.vault.fetch(vaultPda);
+      const remainingAmount = vaultBefore.depositAmount.sub(
+        vaultBefore.totalSettled
+      );
+
+      const tx = await program.methods
+        .withdraw(remainingAmount)
+        .accounts({
+          agent: agent.publicKey,
+          vault: vaultPda,
+          globalConfig: globalConfigPda,
+          vaultTokenAccount: vaultTokenAccountPda,
+          agentTokenAccount: agentTokenAccount,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([agent])
+        .rpc();
+
+      console.log("✅ Withdrawal executed:", tx);
+
+      // Vault stays open with nothing left to settle
+      const vaultAfter = await program.account.vault.fetch(vaultPda);
+      assert.equal(
+        vaultAfter.depositAmount.toString(),
+        vaultAfter.totalSettled.toString()
+      );
+
+      // Check final balance
+      const agentBalance = await getAccount(
+        provider.connection,
+        agentTokenAccount
+      );
+      const expectedBalance = new BN(10_000_000)
+        .sub(depositAmount)
+        .sub(topUpAmount.muln(2))
+        .add(partialWithdrawal)
+        .add(remainingAmount);
+      assert.equal(agentBalance.amount.toString(), expectedBalance.toString());
+      console.log("✅ Final balance verified");
+    });
+
+    it("Closes the emptied vault and refunds rent to the agent", async () => {
+      const lamportsBefore = await provider.connection.getBalance(
+        agent.publicKey
+      );
+
+      await program.methods
+        .closeVault()
+        .accounts({
+          agent: agent.publicKey,
+          vault: vaultPda,
+          vaultTokenAccount: vaultTokenAccountPda,
+          globalConfig: globalConfigPda,
+          ownerVaultCount: ownerVaultCountPda(agent.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([agent])
+        .rpc();
+
+      assert.isNull(await provider.connection.getAccountInfo(vaultPda));
+      assert.isNull(
+        await provider.connection.getAccountInfo(vaultTokenAccountPda)
+      );
+
+      const lamportsAfter = await provider.connection.getBalance(
+        agent.publicKey
+      );
+      assert.isAbove(lamportsAfter, lamportsBefore);
+      console.log("✅ Vault closed and rent refunded");
+    });
+  });
+
+  describe("Token-2022 Tests", () => {
+    let mint2022: PublicKey;
+    let agent2022: Keypair;
+    let provider2022Authority: Keypair;
+    let agent2022TokenAccount: PublicKey;
+    let provider2022TokenAccount: PublicKey;
+    let fee2022TokenAccount: PublicKey;
+    let target2022: SettleTarget;
+    const deposit2022 = new BN(1_000_000);
+
+    before(async () => {
+      agent2022 = Keypair.generate();
+      provider2022Authority = Keypair.generate();
+      await Promise.all(
+        [agent2022, provider2022Authority].map(async (kp) =>
+          provider.connection.confirmTransaction(
+            await provider.connection.requestAirdrop(kp.publicKey, 2e9),
+            "confirmed"
+          )
+        )
+      );
+
+      mint2022 = await createMint(
+        provider.connection,
+        payer.payer,
+        admin.publicKey,
+        null,
+        6,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      agent2022TokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint2022,
+        agent2022.publicKey,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      provider2022TokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint2022,
+        provider2022Authority.publicKey,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      fee2022TokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint2022,
+        treasury.publicKey,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      await mintTo(
+        provider.connection,
+        payer.payer,
+        mint2022,
+        agent2022TokenAccount,
+        admin,
+        5_000_000,
+        [],
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+
+      const [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), agent2022.publicKey.toBuffer()],
+        program.programId
+      );
+      const [vaultTokenAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault_token_account"), agent2022.publicKey.toBuffer()],
+        program.programId
+      );
+      const [providerAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("provider"), provider2022Authority.publicKey.toBuffer()],
+        program.programId
+      );
+      target2022 = {
+        agent: agent2022,
+        vault,
+        vaultTokenAccount,
+        provider: providerAccount,
+        destination: provider2022TokenAccount,
+        feeDestination: fee2022TokenAccount,
+        mint: mint2022,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+      };
+
+      await program.methods
+        .registerProvider(null, paymentProtocol.nativeSpl, "", "", null)
+        .accounts({
+          authority: provider2022Authority.publicKey,
+          provider: providerAccount,
+          destination: provider2022TokenAccount,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          globalConfig: globalConfigPda,
+          feeDestination: treasury.publicKey,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([provider2022Authority])
+        .rpc();
+    });
+
+    it("Creates a vault funded with a Token-2022 mint", async () => {
+      await program.methods
+        .createVault(deposit2022, new BN(0), new BN(0), false, 0, defaultSession, true)
+        .accounts({
+          agent: agent2022.publicKey,
+          vault: target2022.vault,
+          vaultTokenAccount: target2022.vaultTokenAccount,
+          agentTokenAccount: agent2022TokenAccount,
+          tokenMint: mint2022,
+          globalConfig: globalConfigPda,
+          ownerVaultCount: ownerVaultCountPda(agent2022.publicKey),
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          contribution: null,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([agent2022])
+        .rpc();
+
+      const vaultTokenAccountInfo = await getAccount(
+        provider.connection,
+        target2022.vaultTokenAccount,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      assert.equal(
+        vaultTokenAccountInfo.amount.toString(),
+        deposit2022.toString()
+      );
+      console.log("✅ Token-2022 vault created");
+    });
+
+    it("Settles a Token-2022 vault to the provider", async () => {
+      const settleAmount = new BN(250_000);
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, target2022),
+        [facilitator]
+      );
+
+      const vault = await program.account.vault.fetch(target2022.vault);
+      assert.equal(vault.totalSettled.toString(), settleAmount.toString());
+
+      const providerBalance = await getAccount(
+        provider.connection,
+        provider2022TokenAccount,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      const expectedFee = settleAmount.muln(feeBps).divn(10_000);
+      assert.equal(
+        providerBalance.amount.toString(),
+        settleAmount.sub(expectedFee).toString()
+      );
+      console.log("✅ Token-2022 settlement verified");
+    });
+
+    it("Withdraws and closes a Token-2022 vault", async () => {
+      await program.methods
+        .withdraw(new BN(750_000))
+        .accounts({
+          agent: agent2022.publicKey,
+          vault: target2022.vault,
+          globalConfig: globalConfigPda,
+          vaultTokenAccount: target2022.vaultTokenAccount,
+          agentTokenAccount: agent2022TokenAccount,
+          tokenMint: mint2022,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        } as any)
+        .signers([agent2022])
+        .rpc();
+
+      await program.methods
+        .closeVault()
+        .accounts({
+          agent: agent2022.publicKey,
+          vault: target2022.vault,
+          vaultTokenAccount: target2022.vaultTokenAccount,
+          globalConfig: globalConfigPda,
+          ownerVaultCount: ownerVaultCountPda(agent2022.publicKey),
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        } as any)
+        .signers([agent2022])
+        .rpc();
+
+      const agentBalance = await getAccount(
+        provider.connection,
+        agent2022TokenAccount,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      assert.equal(agentBalance.amount.toString(), "4750000");
+      assert.isNull(await provider.connection.getAccountInfo(target2022.vault));
+      console.log("✅ Token-2022 vault withdrawn and closed");
+    });
+
+    // Creates a Token-2022 mint carrying `extension` and tries to open a vault
+    // for it.
+    async function createVaultForExtensionMint(
+      extension: ExtensionType,
+      initExtension: (mint: PublicKey) => TransactionInstruction
+    ) {
+      const extensionMint = Keypair.generate();
+      const mintLen = getMintLen([extension]);
+      const lamports =
+        await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.createAccount({
+            fromPubkey: payer.publicKey,
+            newAccountPubkey: extensionMint.publicKey,
+            space: mintLen,
+            lamports,
+            programId: TOKEN_2022_PROGRAM_ID,
+          }),
+          initExtension(extensionMint.publicKey),
+          createInitializeMintInstruction(
+            extensionMint.publicKey,
+            6,
+            admin.publicKey,
+            null,
+            TOKEN_2022_PROGRAM_ID
+          )
+        ),
+        [extensionMint]
+      );
+
+      const extensionAgent = Keypair.generate();
+      await provider.connection.confirmTransaction(
+        await provider.connection.requestAirdrop(extensionAgent.publicKey, 2e9),
+        "confirmed"
+      );
+      const extensionAgentTokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        extensionMint.publicKey,
+        extensionAgent.publicKey,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+
+      return program.methods
+        .createVault(new BN(1_000_000), new BN(0), new BN(0), false, 0, defaultSession, true)
+        .accounts({
+          agent: extensionAgent.publicKey,
+          vault: PublicKey.findProgramAddressSync(
+            [Buffer.from("vault"), extensionAgent.publicKey.toBuffer()],
+            program.programId
+          )[0],
+          vaultTokenAccount: PublicKey.findProgramAddressSync(
+            [Buffer.from("vault_token_account"), extensionAgent.publicKey.toBuffer()],
+            program.programId
+          )[0],
+          agentTokenAccount: extensionAgentTokenAccount,
+          tokenMint: extensionMint.publicKey,
+          globalConfig: globalConfigPda,
+          ownerVaultCount: ownerVaultCountPda(extensionAgent.publicKey),
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          contribution: null,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([extensionAgent])
+        .rpc();
+    }
+
+    it("Fails: Creating a vault for a transfer-fee mint", async () => {
+      const promise = createVaultForExtensionMint(
+        ExtensionType.TransferFeeConfig,
+        (feeMint) =>
+          createInitializeTransferFeeConfigInstruction(
+            feeMint,
+            admin.publicKey,
+            admin.publicKey,
+            50,
+            BigInt(1_000_000),
+            TOKEN_2022_PROGRAM_ID
+          )
+      );
+
+      await assertRejects(
+        promise,
+        "UnsupportedMintExtension",
+        "Should reject transfer-fee mints"
+      );
+      console.log("✅ Transfer-fee mint rejected");
+    });
+
+    it("Fails: Creating a vault for a transfer-hook mint", async () => {
+      const promise = createVaultForExtensionMint(
+        ExtensionType.TransferHook,
+        (hookMint) =>
+          createInitializeTransferHookInstruction(
+            hookMint,
+            admin.publicKey,
+            program.programId,
+            TOKEN_2022_PROGRAM_ID
+          )
+      );
+
+      await assertRejects(
+        promise,
+        "UnsupportedMintExtension",
+        "Should reject mints whose transfers run a hook"
+      );
+    });
+  });
+
+  describe("Native SOL Vault Tests", () => {
+    let nativeAgent: Keypair;
+    let nativeVaultPda: PublicKey;
+    const nativeDeposit = new BN(LAMPORTS_PER_SOL);
+
+    async function buildNativeSettleTx(settleAmount: BN, nonce: BN) {
+      const message = Buffer.concat([
+        Buffer.from("X402_FLOW_SETTLE"),
+        nativeVaultPda.toBuffer(),
+        await createdSlot(nativeVaultPda),
+        providerPda.toBuffer(),
+        settleAmount.toArrayLike(Buffer, "le", 8),
+        nonce.toArrayLike(Buffer, "le", 8),
+      ]);
+
+      const ed25519Ix = Ed25519Program.createInstructionWithPublicKey({
+        publicKey: nativeAgent.publicKey.toBytes(),
+        message,
+        signature: nacl.sign.detached(message, nativeAgent.secretKey),
+      });
+
+      const settleIx = await program.methods
+        .settleBatchNative(settleAmount, nonce)
+        .accounts({
+          facilitator: facilitator.publicKey,
+          agent: nativeAgent.publicKey,
+          vault: nativeVaultPda,
+          globalConfig: globalConfigPda,
+          provider: providerPda,
+          destination: providerTokenAccount,
+          feeDestination: treasury.publicKey,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          referrer: null,
+        } as any)
+        .instruction();
+
+      return new Transaction().add(ed25519Ix).add(settleIx);
+    }
+
+    before(async () => {
+      nativeAgent = Keypair.generate();
+      await provider.connection.confirmTransaction(
+        await provider.connection.requestAirdrop(nativeAgent.publicKey, 3e9),
+        "confirmed"
+      );
+      [nativeVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("native_vault"), nativeAgent.publicKey.toBuffer()],
+        program.programId
+      );
+      // The deposit bounds apply to lamports too; lift the cap for 1 SOL.
+      await program.methods
+        .updateConfig({ ...configParams(), maxDeposit: new BN(0) })
+        .accounts({ authority: admin.publicKey, globalConfig: globalConfigPda } as any)
+        .signers([admin])
+        .rpc();
+    });
+
+    const createNativeVault = (deposit: BN) =>
+      program.methods
+        .createVaultNative(deposit, new BN(0))
+        .accounts({
+          agent: nativeAgent.publicKey,
+          vault: nativeVaultPda,
+          globalConfig: globalConfigPda,
+          ownerVaultCount: ownerVaultCountPda(nativeAgent.publicKey),
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([nativeAgent])
+        .rpc();
+
+    it("Fails: Native deposit below the minimum", async () => {
+      await assertRejects(
+        createNativeVault(minDeposit.subn(1)),
+        "DepositOutOfBounds",
+        "Native vaults should check the deposit bounds"
+      );
+    });
+
+    it("Deposits SOL into a native vault", async () => {
+      const rentBefore = await provider.connection.getBalance(nativeVaultPda);
+      assert.equal(rentBefore, 0);
+
+      await createNativeVault(nativeDeposit);
+      const owned = await program.account.ownerVaultCount.fetch(
+        ownerVaultCountPda(nativeAgent.publicKey)
+      );
+      assert.equal(owned.count, 1);
+
+      const vault = await program.account.vault.fetch(nativeVaultPda);
+      assert.ok(vault.agent.equals(nativeAgent.publicKey));
+      assert.ok(vault.tokenMint.equals(PublicKey.default));
+      assert.equal(vault.depositAmount.toString(), nativeDeposit.toString());
+
+      const rent = await provider.connection.getMinimumBalanceForRentExemption(
+        program.account.vault.size
+      );
+      const vaultLamports = await provider.connection.getBalance(
+        nativeVaultPda
+      );
+      assert.equal(vaultLamports, rent + nativeDeposit.toNumber());
+      console.log("✅ Native vault funded");
+    });
+
+    it("Settles lamports to the provider's registered destination", async () => {
+      const settleAmount = new BN(LAMPORTS_PER_SOL / 4);
+      const providerLamportsBefore = await provider.connection.getBalance(
+        providerTokenAccount
+      );
+      const treasuryLamportsBefore = await provider.connection.getBalance(
+        treasury.publicKey
+      );
+
+      await provider.sendAndConfirm(
+        await buildNativeSettleTx(settleAmount, new BN(1)),
+        [facilitator]
+      );
+
+      const providerLamportsAfter = await provider.connection.getBalance(
+        providerTokenAccount
+      );
+      const vault = await program.account.vault.fetch(nativeVaultPda);
+      assert.equal(vault.totalSettled.toString(), settleAmount.toString());
+      assert.equal(vault.nonce.toString(), "1");
+      const expectedFee = settleAmount.muln(feeBps).divn(10_000);
+      assert.equal(
+        providerLamportsAfter - providerLamportsBefore,
+        settleAmount.sub(expectedFee).toNumber()
+      );
+      assert.equal(
+        (await provider.connection.getBalance(treasury.publicKey)) -
+          treasuryLamportsBefore,
+        expectedFee.toNumber()
+      );
+      console.log("✅ Native settlement verified");
+    });
+
+    it("Fails: Native settlement reusing a nonce", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildNativeSettleTx(new BN(LAMPORTS_PER_SOL / 4), new BN(1)),
+          [facilitator]
+        ),
+        "NonceReused",
+        "Native path should share replay protection"
+      );
+    });
+
+    it("Withdraws the remaining SOL", async () => {
+      const agentLamportsBefore = await provider.connection.getBalance(
+        nativeAgent.publicKey
+      );
+
+      await program.methods
+        .withdrawNative(new BN((LAMPORTS_PER_SOL * 3) / 4))
+        .accounts({
+          agent: nativeAgent.publicKey,
+          vault: nativeVaultPda,
+          globalConfig: globalConfigPda,
+        } as any)
+        .signers([nativeAgent])
+        .rpc();
+
+      const vault = await program.account.vault.fetch(nativeVaultPda);
+      assert.equal(vault.depositAmount.toString(), vault.totalSettled.toString());
+
+      const agentLamportsAfter = await provider.connection.getBalance(
+        nativeAgent.publicKey
+      );
+      // Gained the 0.75 SOL remainder minus the transaction fee
+      assert.isAbove(
+        agentLamportsAfter - agentLamportsBefore,
+        (LAMPORTS_PER_SOL * 3) / 4 - 10_000
+      );
+      console.log("✅ Native withdrawal verified");
+    });
+
+    it("Closes the emptied native vault and frees its slots", async () => {
+      const before = await program.account.globalConfig.fetch(globalConfigPda);
+
+      await program.methods
+        .closeVaultNative()
+        .accounts({
+          agent: nativeAgent.publicKey,
+          vault: nativeVaultPda,
+          globalConfig: globalConfigPda,
+          ownerVaultCount: ownerVaultCountPda(nativeAgent.publicKey),
+        } as any)
+        .signers([nativeAgent])
+        .rpc();
+
+      assert.isNull(await provider.connection.getAccountInfo(nativeVaultPda));
+      const owned = await program.account.ownerVaultCount.fetch(
+        ownerVaultCountPda(nativeAgent.publicKey)
+      );
+      assert.equal(owned.count, 0);
+      const after = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(
+        before.activeVaults.sub(after.activeVaults).toString(),
+        "1"
+      );
+      console.log("✅ Native vault closed");
+    });
+
+    after(async () => {
+      await program.methods
+        .updateConfig(configParams())
+        .accounts({ authority: admin.publicKey, globalConfig: globalConfigPda } as any)
+        .signers([admin])
+        .rpc();
+    });
+  });
+
+  describe("Deposit Limit Tests", () => {
+    it("Accepts deposits at the configured bounds", async () => {
+      const atMin = await createFundedVault(minDeposit);
+      const atMax = await createFundedVault(maxDeposit);
+
+      const minVault = await program.account.vault.fetch(atMin.vault);
+      const maxVault = await program.account.vault.fetch(atMax.vault);
+      assert.equal(minVault.depositAmount.toString(), minDeposit.toString());
+      assert.equal(maxVault.depositAmount.toString(), maxDeposit.toString());
+      console.log("✅ Boundary deposits accepted");
+    });
+
+    it("Fails: Deposit just below the minimum", async () => {
+      await assertRejects(
+        createFundedVault(minDeposit.subn(1)),
+        "DepositOutOfBounds",
+        "Should reject a deposit below min_deposit"
+      );
+    });
+
+    it("Fails: Deposit just above the maximum", async () => {
+      await assertRejects(
+        createFundedVault(maxDeposit.addn(1)),
+        "DepositOutOfBounds",
+        "Should reject a deposit above max_deposit"
+      );
+    });
+  });
+
+  describe("Provider Pause Tests", () => {
+    const settleAmount = new BN(200_000);
+    let paused: Awaited<ReturnType<typeof registerTestProvider>>;
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const setProviderPause = (value: boolean) =>
+      program.methods
+        .pauseProvider(value)
+        .accounts({
+          authority: paused.authority.publicKey,
+          provider: paused.provider,
+        } as any)
+        .signers([paused.authority])
+        .rpc();
+
+    const pausedTarget = () => ({
+      ...target,
+      provider: paused.provider,
+      destination: paused.destination,
+    });
+
+    before(async () => {
+      paused = await registerTestProvider();
+      target = await createFundedVault();
+    });
+
+    it("Fails: Non-authority pauses a provider", async () => {
+      await assertRejects(
+        program.methods
+          .pauseProvider(true)
+          .accounts({
+            authority: randomUser.publicKey,
+            provider: paused.provider,
+          } as any)
+          .signers([randomUser])
+          .rpc(),
+        "ConstraintHasOne",
+        "Only the provider authority can pause it"
+      );
+    });
+
+    it("Rejects settlements to a paused provider while others settle", async () => {
+      await setProviderPause(true);
+      const providerAccount = await program.account.provider.fetch(
+        paused.provider
+      );
+      assert.isTrue(providerAccount.paused);
+
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(1), undefined, pausedTarget()),
+          [facilitator]
+        ),
+        "ProviderPaused",
+        "Should reject settlement to a paused provider"
+      );
+
+      // The protocol is still live for every other provider.
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, target),
+        [facilitator]
+      );
+      console.log("✅ Provider pause is isolated");
+    });
+
+    it("Global pause takes precedence over provider pause", async () => {
+      await setGlobalPause(true);
+      try {
+        await assertRejects(
+          provider.sendAndConfirm(
+            await buildSettleTx(settleAmount, new BN(2), undefined, pausedTarget()),
+            [facilitator]
+          ),
+          "InstructionPaused",
+          "Global pause should be reported first"
+        );
+      } finally {
+        await setGlobalPause(false);
+      }
+    });
+
+    it("Settles again once the provider unpauses", async () => {
+      await setProviderPause(false);
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(2), undefined, pausedTarget()),
+        [facilitator]
+      );
+
+      const destinationBalance = await getAccount(
+        provider.connection,
+        paused.destination
+      );
+      assert.isTrue(destinationBalance.amount > BigInt(0));
+      console.log("✅ Provider unpaused");
+    });
+  });
+
+  describe("Pause Gating Tests", () => {
+    const settleAmount = new BN(200_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    before(async () => {
+      target = await createFundedVault();
+      await setGlobalPause(true);
+    });
+
+    after(async () => {
+      await setGlobalPause(false);
+    });
+
+    it("Fails: Settling while paused", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(1), undefined, target),
+          [facilitator]
+        ),
+        "InstructionPaused",
+        "Settlement should be blocked while paused"
+      );
+    });
+
+    it("Fails: Creating a vault while paused", async () => {
+      await assertRejects(
+        createFundedVault(),
+        "InstructionPaused",
+        "New vaults should be blocked while paused"
+      );
+    });
+
+    it("Owner can still withdraw while paused", async () => {
+      const withdrawal = new BN(100_000);
+      await program.methods
+        .withdraw(withdrawal)
+        .accounts({
+          agent: target.agent.publicKey,
+          vault: target.vault,
+          globalConfig: globalConfigPda,
+          vaultTokenAccount: target.vaultTokenAccount,
+          agentTokenAccount: target.agentTokenAccount,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([target.agent])
+        .rpc();
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(
+        vault.depositAmount.toString(),
+        depositAmount.sub(withdrawal).toString()
+      );
+      console.log("✅ Withdraw allowed while paused");
+    });
+  });
+
+  describe("Vault Ownership Transfer Tests", () => {
+    let newOwner: Keypair;
+    let newOwnerTokenAccount: PublicKey;
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const withdrawAs = (owner: Keypair, destination: PublicKey) =>
+      program.methods
+        .withdraw(new BN(100_000))
+        .accounts({
+          agent: owner.publicKey,
+          vault: target.vault,
+          globalConfig: globalConfigPda,
+          vaultTokenAccount: target.vaultTokenAccount,
+          agentTokenAccount: destination,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([owner])
+        .rpc();
+
+    before(async () => {
+      newOwner = Keypair.generate();
+      await airdrop(newOwner);
+      newOwnerTokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        newOwner.publicKey
+      );
+      target = await createFundedVault();
+    });
+
+    it("Fails: Someone other than the proposed owner accepts", async () => {
+      await program.methods
+        .transferVaultOwnership()
+        .accounts({
+          agent: target.agent.publicKey,
+          vault: target.vault,
+          newAgent: newOwner.publicKey,
+        } as any)
+        .signers([target.agent])
+        .rpc();
+
+      await assertRejects(
+        program.methods
+          .acceptVaultOwnership()
+          .accounts({
+            newAgent: randomUser.publicKey,
+            vault: target.vault,
+          } as any)
+          .signers([randomUser])
+          .rpc(),
+        "InvalidPendingOwner",
+        "Only the proposed owner can accept"
+      );
+    });
+
+    it("Proposed owner accepts and takes over", async () => {
+      await program.methods
+        .acceptVaultOwnership()
+        .accounts({
+          newAgent: newOwner.publicKey,
+          vault: target.vault,
+        } as any)
+        .signers([newOwner])
+        .rpc();
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.ok(vault.agent.equals(newOwner.publicKey));
+      assert.ok(vault.creator.equals(target.agent.publicKey));
+      assert.isNull(vault.pendingAgent);
+
+      await withdrawAs(newOwner, newOwnerTokenAccount);
+      const balance = await getAccount(provider.connection, newOwnerTokenAccount);
+      assert.equal(balance.amount.toString(), "100000");
+      console.log("✅ Vault ownership transferred");
+    });
+
+    it("Fails: Previous owner withdraws or sets a delegate", async () => {
+      await assertRejects(
+        withdrawAs(target.agent, target.agentTokenAccount),
+        "ConstraintHasOne",
+        "Previous owner should lose withdrawal access"
+      );
+      await assertRejects(
+        program.methods
+          .setDelegate(target.agent.publicKey)
+          .accounts({
+            agent: target.agent.publicKey,
+            vault: target.vault,
+          } as any)
+          .signers([target.agent])
+          .rpc(),
+        "ConstraintHasOne",
+        "Previous owner should lose delegate control"
+      );
+    });
+  });
+
+  describe("Delegate Tests", () => {
+    const settleAmount = new BN(200_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const setDelegate = (delegate: PublicKey, signer: Keypair = target.agent) =>
+      program.methods
+        .setDelegate(delegate)
+        .accounts({
+          agent: signer.publicKey,
+          vault: target.vault,
+        } as any)
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Delegate settles without a voucher", async () => {
+      await setDelegate(facilitator.publicKey);
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.ok(vault.delegate.equals(facilitator.publicKey));
+
+      await provider.sendAndConfirm(
+        new Transaction().add(await buildSettleIx(settleAmount, new BN(1), target)),
+        [facilitator]
+      );
+
+      const settled = await program.account.vault.fetch(target.vault);
+      assert.equal(settled.totalSettled.toString(), settleAmount.toString());
+      console.log("✅ Delegate settlement succeeded");
+    });
+
+    it("Fails: Delegate withdraws or changes the delegate", async () => {
+      const facilitatorTokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        facilitator.publicKey
+      );
+      await assertRejects(
+        program.methods
+          .withdraw(new BN(1))
+          .accounts({
+            agent: facilitator.publicKey,
+            vault: target.vault,
+            globalConfig: globalConfigPda,
+            vaultTokenAccount: target.vaultTokenAccount,
+            agentTokenAccount: facilitatorTokenAccount,
+            tokenMint: mint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          } as any)
+          .signers([facilitator])
+          .rpc(),
+        "ConstraintHasOne",
+        "Delegate should not withdraw"
+      );
+      await assertRejects(
+        setDelegate(randomUser.publicKey, facilitator),
+        "ConstraintHasOne",
+        "Delegate should not change the delegate"
+      );
+    });
+
+    it("Fails: Revoked delegate settles", async () => {
+      await setDelegate(PublicKey.default);
+
+      await assertRejects(
+        provider.sendAndConfirm(
+          new Transaction().add(await buildSettleIx(settleAmount, new BN(2), target)),
+          [facilitator]
+        ),
+        "VoucherMissing",
+        "Revoked delegate should need a voucher again"
+      );
+    });
+  });
+
+  describe("Auto-close Tests", () => {
+    it("Closes a vault drained by its final settlement", async () => {
+      const target = await createFundedVault(minDeposit);
+      const [vaultInfo, tokenInfo, agentBefore] = await Promise.all([
+        provider.connection.getAccountInfo(target.vault),
+        provider.connection.getAccountInfo(target.vaultTokenAccount),
+        provider.connection.getBalance(target.agent.publicKey),
+      ]);
+
+      await provider.sendAndConfirm(
+        await buildSettleTx(minDeposit, new BN(1), undefined, target),
+        [facilitator]
+      );
+
+      assert.isNull(await provider.connection.getAccountInfo(target.vault));
+      assert.isNull(
+        await provider.connection.getAccountInfo(target.vaultTokenAccount)
+      );
+      const agentAfter = await provider.connection.getBalance(
+        target.agent.publicKey
+      );
+      assert.equal(
+        agentAfter - agentBefore,
+        vaultInfo.lamports + tokenInfo.lamports
+      );
+      console.log("✅ Drained vault closed and rent refunded");
+    });
+
+    it("Keeps a vault that still has a balance", async () => {
+      const target = await createFundedVault(minDeposit);
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleThreshold, new BN(1), undefined, target),
+        [facilitator]
+      );
+
+      assert.isNotNull(await provider.connection.getAccountInfo(target.vault));
+    });
+
+    it("Rejects a voucher for a closed vault once its address is reused", async () => {
+      const target = await createFundedVault(minDeposit);
+      const stale = await buildSettleTx(settleThreshold, new BN(2), undefined, target);
+      await provider.sendAndConfirm(
+        await buildSettleTx(minDeposit, new BN(1), undefined, target),
+        [facilitator]
+      );
+      assert.isNull(await provider.connection.getAccountInfo(target.vault));
+
+      await recreateVault(target);
+
+      await assertRejects(
+        provider.sendAndConfirm(stale, [facilitator]),
+        "InvalidVoucher",
+        "Replayed a voucher against a re-created vault"
+      );
+    });
+  });
+
+  describe("Mint Mismatch Tests", () => {
+    let otherMintDestination: PublicKey;
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    before(async () => {
+      const otherMint = await createMint(
+        provider.connection,
+        payer.payer,
+        admin.publicKey,
+        null,
+        6
+      );
+      otherMintDestination = await createAccount(
+        provider.connection,
+        payer.payer,
+        otherMint,
+        providerAuthority.publicKey
+      );
+      target = await createFundedVault();
+    });
+
+    it("Fails: Settling into a destination for another mint", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(new BN(200_000), new BN(1), undefined, {
+            ...target,
+            destination: otherMintDestination,
+          }),
+          [facilitator]
+        ),
+        "MintMismatch",
+        "Should reject a destination for a different mint"
+      );
+      console.log("✅ Mismatched destination mint rejected");
+    });
+  });
+
+  describe("Destination Whitelist Tests", () => {
+    const settleAmount = new BN(200_000);
+    let whitelisted: PublicKey;
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const addDestination = (destination: PublicKey) =>
+      program.methods
+        .addDestination(destination)
+        .accounts({
+          authority: providerAuthority.publicKey,
+          provider: providerPda,
+        } as any)
+        .signers([providerAuthority])
+        .rpc();
+
+    const removeDestination = (
+      destination: PublicKey,
+      signer: Keypair = providerAuthority
+    ) =>
+      program.methods
+        .removeDestination(destination)
+        .accounts({
+          signer: signer.publicKey,
+          provider: providerPda,
+          globalConfig: globalConfigPda,
+        } as any)
+        .signers([signer])
+        .rpc();
+
+    const settleTo = async (destination: PublicKey, nonce: number) =>
+      provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(nonce), undefined, {
+          ...target,
+          destination,
+          destinationOverride: destination,
+        }),
+        [facilitator]
+      );
+
+    before(async () => {
+      whitelisted = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        providerAuthority.publicKey,
+        Keypair.generate()
+      );
+      target = await createFundedVault();
+    });
+
+    it("Fails: Settling to a destination still waiting out its delay", async () => {
+      await addDestination(whitelisted);
+      const providerAccount = await program.account.provider.fetch(providerPda);
+      assert.isAbove(providerAccount.allowedAfter[0].toNumber(), 0);
+
+      await assertRejects(
+        settleTo(whitelisted, 1),
+        "DestinationNotYetAllowed",
+        "A new whitelist entry should wait out the timelock"
+      );
+    });
+
+    it("Settles to a whitelisted destination", async () => {
+      await new Promise((resolve) =>
+        setTimeout(resolve, (timelockSeconds.toNumber() + 1) * 1000)
+      );
+
+      let listener = null;
+      const event = await new Promise<any>(async (resolve, reject) => {
+        listener = program.addEventListener("settlement", resolve);
+        provider
+          .sendAndConfirm(
+            await buildSettleTx(settleAmount, new BN(1), undefined, {
+              ...target,
+              destination: whitelisted,
+              destinationOverride: whitelisted,
+            }),
+            [facilitator]
+          )
+          .catch(reject);
+      });
+      await program.removeEventListener(listener);
+
+      const balance = await getAccount(provider.connection, whitelisted);
+      const fee = settleAmount.muln(feeBps).divn(10_000);
+      assert.equal(balance.amount.toString(), settleAmount.sub(fee).toString());
+      assert.ok(event.destination.equals(whitelisted));
+      console.log("✅ Whitelisted destination paid");
+    });
+
+    it("Fails: Paying a whitelisted destination without naming it", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(2), undefined, {
+            ...target,
+            destination: whitelisted,
+          }),
+          [facilitator]
+        ),
+        "DestinationMismatch",
+        "Without an override the registered destination is paid"
+      );
+    });
+
+    it("Fails: Settling to a destination that is not whitelisted", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(2), undefined, {
+            ...target,
+            destination: randomUserTokenAccount,
+            destinationOverride: randomUserTokenAccount,
+          }),
+          [facilitator]
+        ),
+        "DestinationNotWhitelisted",
+        "Should reject an unapproved destination"
+      );
+    });
+
+    it("Lets the config authority remove only a pending entry", async () => {
+      await assertRejects(
+        removeDestination(whitelisted, admin),
+        "Unauthorized",
+        "An entry past its delay is the provider's to remove"
+      );
+
+      const pending = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        providerAuthority.publicKey,
+        Keypair.generate()
+      );
+      await addDestination(pending);
+      await assertRejects(
+        removeDestination(pending, randomUser),
+        "Unauthorized",
+        "Only the provider, guardian or config authority may remove it"
+      );
+      await removeDestination(pending, admin);
+
+      const providerAccount = await program.account.provider.fetch(providerPda);
+      assert.notOk(
+        providerAccount.allowedDestinations.some((key: PublicKey) => key.equals(pending))
+      );
+      console.log("✅ Pending whitelist entry vetoed");
+    });
+
+    it("Fails: Settling to a removed destination", async () => {
+      await removeDestination(whitelisted);
+
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(2), undefined, {
+            ...target,
+            destination: whitelisted,
+            destinationOverride: whitelisted,
+          }),
+          [facilitator]
+        ),
+        "DestinationNotWhitelisted",
+        "Should reject a destination once it is removed"
+      );
+    });
+  });
+
+  describe("Settlement Cooldown Tests", () => {
+    const settleAmount = new BN(200_000);
+    const minSettleInterval = new BN(2);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const setCooldown = (interval: BN) =>
+      program.methods
+        .updateConfig({ ...configParams(), minSettleInterval: interval })
+        .accounts({
+          authority: admin.publicKey,
+          globalConfig: globalConfigPda,
+        } as any)
+        .signers([admin])
+        .rpc();
+
+    before(async () => {
+      target = await createFundedVault();
+      await setCooldown(minSettleInterval);
+    });
+
+    it("Fails: Settling again before the interval passes", async () => {
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, target),
+        [facilitator]
+      );
+
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(2), undefined, target),
+          [facilitator]
+        ),
+        "SettleTooSoon",
+        "Should reject a settlement inside the cooldown"
+      );
+    });
+
+    it("Settles once the interval has passed", async () => {
+      await new Promise((resolve) =>
+        setTimeout(resolve, (minSettleInterval.toNumber() + 1) * 1000)
+      );
+
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(2), undefined, target),
+        [facilitator]
+      );
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.totalSettled.toString(), settleAmount.muln(2).toString());
+      console.log("✅ Cooldown elapsed and settlement succeeded");
+    });
+
+    after(async () => {
+      await setCooldown(new BN(0));
+    });
+  });
+
+  describe("Fee Split Tests", () => {
+    let partner: Keypair;
+    let partnerTokenAccount: PublicKey;
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const setFeeSplits = (
+      splits: { recipient: PublicKey; shareBps: number }[],
+      signer: Keypair = admin
+    ) =>
+      program.methods
+        .setFeeSplits(splits)
+        .accounts({
+          authority: signer.publicKey,
+          globalConfig: globalConfigPda,
+        } as any)
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      partner = Keypair.generate();
+      partnerTokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        partner.publicKey
+      );
+      target = await createFundedVault();
+    });
+
+    it("Fails: Shares that do not sum to 10000 bps", async () => {
+      await assertRejects(
+        setFeeSplits([
+          { recipient: treasury.publicKey, shareBps: 6_000 },
+          { recipient: partner.publicKey, shareBps: 3_000 },
+        ]),
+        "InvalidFeeSplits",
+        "Should reject shares summing to 90%"
+      );
+    });
+
+    it("Fails: Non-authority sets fee splits", async () => {
+      await assertRejects(
+        setFeeSplits(
+          [{ recipient: randomUser.publicKey, shareBps: 10_000 }],
+          randomUser
+        ),
+        "Unauthorized",
+        "Only the config authority can set fee splits"
+      );
+    });
+
+    it("Splits the fee 60/40 with dust to the first recipient", async () => {
+      await setFeeSplits([
+        { recipient: treasury.publicKey, shareBps: 6_000 },
+        { recipient: partner.publicKey, shareBps: 4_000 },
+      ]);
+
+      // 199_960 * 2.5% = 4_999, which 60/40 floors to 2_999 + 1_999.
+      const settleAmount = new BN(199_960);
+      const treasuryBefore = await getAccount(provider.connection, feeTokenAccount);
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, {
+          ...target,
+          feeRecipients: [feeTokenAccount, partnerTokenAccount],
+        }),
+        [facilitator]
+      );
+
+      const treasuryAfter = await getAccount(provider.connection, feeTokenAccount);
+      const partnerAfter = await getAccount(provider.connection, partnerTokenAccount);
+      assert.equal((treasuryAfter.amount - treasuryBefore.amount).toString(), "3000");
+      assert.equal(partnerAfter.amount.toString(), "1999");
+      console.log("✅ Fee split with dust to the first recipient");
+    });
+
+    it("Fails: Recipient account owned by someone else", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(new BN(200_000), new BN(2), undefined, {
+            ...target,
+            feeRecipients: [feeTokenAccount, randomUserTokenAccount],
+          }),
+          [facilitator]
+        ),
+        "InvalidFeeDestination",
+        "Should reject a recipient account not owned by the split's wallet"
+      );
+    });
+
+    it("Fails: Fewer recipient accounts than splits", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(new BN(200_000), new BN(2), undefined, {
+            ...target,
+            feeRecipients: [feeTokenAccount],
+          }),
+          [facilitator]
+        ),
+        "FeeSplitAccountsMissing",
+        "Should want one recipient account per split"
+      );
+    });
+
+    it("Splits the fee on batch settlements and one-shot payments", async () => {
+      // 200_000 * 2.5% = 5_000, split 3_000 / 2_000.
+      const amount = new BN(200_000);
+      const recipients = [feeTokenAccount, partnerTokenAccount].map((pubkey) => ({
+        pubkey,
+        isSigner: false,
+        isWritable: true,
+      }));
+      const partnerBefore = (await getAccount(provider.connection, partnerTokenAccount)).amount;
+
+      await program.methods
+        .settleBatchMany([amount], [new BN(3)])
+        .accounts({
+          facilitator: facilitator.publicKey,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          provider: providerPda,
+          destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          referrer: null,
+        } as any)
+        .remainingAccounts([
+          { pubkey: target.agent.publicKey, isSigner: true, isWritable: false },
+          { pubkey: target.vault, isSigner: false, isWritable: true },
+          { pubkey: target.vaultTokenAccount, isSigner: false, isWritable: true },
+          ...recipients,
+        ])
+        .signers([facilitator, target.agent])
+        .rpc();
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.lastNonce.toNumber(), 3);
+
+      await mintTo(
+        provider.connection,
+        payer.payer,
+        mint,
+        target.agentTokenAccount,
+        admin,
+        amount.toNumber()
+      );
+      await program.methods
+        .payOnce(amount)
+        .accounts({
+          payer: target.agent.publicKey,
+          payerTokenAccount: target.agentTokenAccount,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          provider: providerPda,
+          destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          referrer: null,
+        } as any)
+        .remainingAccounts(recipients)
+        .signers([target.agent])
+        .rpc();
+
+      const partnerAfter = (await getAccount(provider.connection, partnerTokenAccount)).amount;
+      assert.equal((partnerAfter - partnerBefore).toString(), "4000");
+      console.log("✅ Fee split on batch and one-shot payments");
+    });
+
+    after(async () => {
+      // Route fees back to the single fee destination.
+      await setFeeSplits([]);
+    });
+  });
+
+  describe("Guardian Tests", () => {
+    let guardian: Keypair;
+
+    const emergencyPause = (flags: number, signer: Keypair) =>
+      program.methods
+        .emergencyPause(flags)
+        .accounts({
+          admin: signer.publicKey,
+          globalConfig: globalConfigPda,
+        } as any)
+        .signers([signer])
+        .rpc();
+
+    const setGuardian = (key: PublicKey, signer: Keypair = admin) =>
+      program.methods
+        .setGuardian(key)
+        .accounts({
+          authority: signer.publicKey,
+          globalConfig: globalConfigPda,
+        } as any)
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      guardian = Keypair.generate();
+      await airdrop(guardian);
+    });
+
+    it("Fails: Non-authority sets the guardian", async () => {
+      await assertRejects(
+        setGuardian(randomUser.publicKey, randomUser),
+        "Unauthorized",
+        "Only the config authority can name a guardian"
+      );
+    });
+
+    it("Guardian pauses but cannot unpause", async () => {
+      await setGuardian(guardian.publicKey);
+
+      await emergencyPause(pauseAll, guardian);
+      let config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(config.pauseFlags, pauseAll);
+
+      await assertRejects(
+        emergencyPause(0, guardian),
+        "Unauthorized",
+        "Guardian should not resume the protocol"
+      );
+      await assertRejects(
+        program.methods
+          .proposeUnpause()
+          .accounts({
+            authority: guardian.publicKey,
+            globalConfig: globalConfigPda,
+          } as any)
+          .signers([guardian])
+          .rpc(),
+        "Unauthorized",
+        "Guardian should not start an unpause"
+      );
+
+      await setGlobalPause(false);
+      config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(config.pauseFlags, 0);
+      console.log("✅ Guardian can only halt");
+    });
+
+    after(async () => {
+      await setGuardian(PublicKey.default);
+    });
+  });
+
+  describe("Protocol Stats Tests", () => {
+    it("Tracks totals across create, settle and close", async () => {
+      const settleAmount = new BN(200_000);
+      const fetchConfig = () =>
+        program.account.globalConfig.fetch(globalConfigPda);
+      const before = await fetchConfig();
+
+      const target = await createFundedVault(minDeposit);
+      const opened = await fetchConfig();
+      assert.equal(
+        opened.activeVaults.sub(before.activeVaults).toString(),
+        "1"
+      );
+
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, target),
+        [facilitator]
+      );
+      await program.methods
+        .withdraw(minDeposit.sub(settleAmount))
+        .accounts({
+          agent: target.agent.publicKey,
+          vault: target.vault,
+          globalConfig: globalConfigPda,
+          vaultTokenAccount: target.vaultTokenAccount,
+          agentTokenAccount: target.agentTokenAccount,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([target.agent])
+        .rpc();
+      await program.methods
+        .closeVault()
+        .accounts({
+          agent: target.agent.publicKey,
+          vault: target.vault,
+          vaultTokenAccount: target.vaultTokenAccount,
+          globalConfig: globalConfigPda,
+          ownerVaultCount: ownerVaultCountPda(target.agent.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([target.agent])
+        .rpc();
+
+      const after = await fetchConfig();
+      assert.equal(
+        after.totalSettled.sub(before.totalSettled).toString(),
+        settleAmount.toString()
+      );
+      assert.equal(
+        after.totalFees.sub(before.totalFees).toString(),
+        settleAmount.muln(feeBps).divn(10_000).toString()
+      );
+      assert.equal(
+        after.settlementCount.sub(before.settlementCount).toString(),
+        "1"
+      );
+      assert.equal(after.activeVaults.toString(), before.activeVaults.toString());
+      console.log("✅ Protocol stats updated");
+    });
+
+    it("Counts every settlement in a batch", async () => {
+      const settleAmount = new BN(200_000);
+      const targets = [await createFundedVault(), await createFundedVault()];
+      const before = await program.account.globalConfig.fetch(globalConfigPda);
+
+      await program.methods
+        .settleBatchMany(
+          targets.map(() => settleAmount),
+          targets.map(() => new BN(1))
+        )
+        .accounts({
+          facilitator: facilitator.publicKey,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          provider: providerPda,
+          destination: providerTokenAccount,
+          feeDestination: feeTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          referrer: null,
+        } as any)
+        .remainingAccounts(
+          targets.flatMap((t) => [
+            { pubkey: t.agent.publicKey, isSigner: true, isWritable: false },
+            { pubkey: t.vault, isSigner: false, isWritable: true },
+            { pubkey: t.vaultTokenAccount, isSigner: false, isWritable: true },
+          ])
+        )
+        .signers([facilitator, ...targets.map((t) => t.agent)])
+        .rpc();
+
+      const after = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(
+        after.totalSettled.sub(before.totalSettled).toString(),
+        settleAmount.muln(2).toString()
+      );
+      assert.equal(
+        after.settlementCount.sub(before.settlementCount).toString(),
+        "2"
+      );
+    });
+  });
+
+  describe("Slippage Guard Tests", () => {
+    const settleAmount = new BN(400_000);
+    const netAtCurrentFee = settleAmount.sub(
+      settleAmount.muln(feeBps).divn(10_000)
+    );
+    const configAccounts = () =>
+      ({ authority: admin.publicKey, globalConfig: globalConfigPda } as any);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Settles when the net meets the minimum", async () => {
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, {
+          ...target,
+          minNetToProvider: netAtCurrentFee,
+        }),
+        [facilitator]
+      );
+    });
+
+    it("Fails: A fee bump lands between build and execute", async () => {
+      const tx = await buildSettleTx(settleAmount, new BN(2), undefined, {
+        ...target,
+        minNetToProvider: netAtCurrentFee,
+      });
+
+      await program.methods
+        .proposeFeeIncrease(feeBps + 100, new BN(0), { floor: {} } as any)
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+      await new Promise((resolve) =>
+        setTimeout(resolve, (timelockSeconds.toNumber() + 1) * 1000)
+      );
+      await program.methods
+        .applyFeeIncrease()
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+
+      await assertRejects(
+        provider.sendAndConfirm(tx, [facilitator]),
+        "SlippageExceeded",
+        "Should reject a net below the provider's minimum"
+      );
+      console.log("✅ Slippage guard tripped by the fee bump");
+    });
+
+    after(async () => {
+      await program.methods
+        .updateConfig(configParams())
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+    });
+  });
+
+  describe("Config Timelock Tests", () => {
+    const raisedFeeBps = feeBps + 50;
+    const configAccounts = () =>
+      ({ authority: admin.publicKey, globalConfig: globalConfigPda } as any);
+
+    it("Fails: Raising the fee through update_config", async () => {
+      await assertRejects(
+        program.methods
+          .updateConfig({ ...configParams(), feeBps: raisedFeeBps })
+          .accounts(configAccounts())
+          .signers([admin])
+          .rpc(),
+        "TimelockRequired",
+        "Fee increases should need the timelock"
+      );
+    });
+
+    it("Applies a fee increase only after the timelock", async () => {
+      await program.methods
+        .proposeFeeIncrease(raisedFeeBps, new BN(0), { floor: {} } as any)
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+
+      await assertRejects(
+        program.methods
+          .applyFeeIncrease()
+          .accounts(configAccounts())
+          .signers([admin])
+          .rpc(),
+        "TimelockNotElapsed",
+        "Should not apply before the timelock"
+      );
+
+      await new Promise((resolve) =>
+        setTimeout(resolve, (timelockSeconds.toNumber() + 1) * 1000)
+      );
+      await program.methods
+        .applyFeeIncrease()
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(config.feeBps, raisedFeeBps);
+      console.log("✅ Fee increase applied after the timelock");
+    });
+
+    it("Lowers the fee immediately", async () => {
+      await program.methods
+        .updateConfig(configParams())
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(config.feeBps, feeBps);
+    });
+
+    it("Fails: Applying an unpause before the timelock", async () => {
+      await setGlobalPause(true);
+      await assertRejects(
+        program.methods
+          .emergencyPause(0)
+          .accounts({
+            admin: admin.publicKey,
+            globalConfig: globalConfigPda,
+          } as any)
+          .signers([admin])
+          .rpc(),
+        "TimelockRequired",
+        "Unpausing should need the timelock"
+      );
+
+      await program.methods
+        .proposeUnpause()
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+      await assertRejects(
+        program.methods
+          .applyUnpause()
+          .accounts(configAccounts())
+          .signers([admin])
+          .rpc(),
+        "TimelockNotElapsed",
+        "Should not unpause before the timelock"
+      );
+    });
+
+    after(async () => {
+      await setGlobalPause(false);
+    });
+  });
+
+  describe("Nonce Bitmap Tests", () => {
+    const settleAmount = settleThreshold;
+    let windowTarget: Awaited<ReturnType<typeof createFundedVault>>;
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+    let nonceBitmap: PublicKey;
+
+    // Compute units spent by the settle_batch transaction.
+    async function settleUnits(settleTarget: SettleTarget, nonce: BN) {
+      const sig = await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, nonce, undefined, settleTarget),
+        [facilitator],
+        { commitment: "confirmed" }
+      );
+      const tx = await provider.connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return tx.meta.computeUnitsConsumed;
+    }
+
+    before(async () => {
+      windowTarget = await createFundedVault();
+      target = await createFundedVault();
+      [nonceBitmap] = PublicKey.findProgramAddressSync(
+        [Buffer.from("nonce_bitmap"), target.vault.toBuffer()],
+        program.programId
+      );
+
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(3), undefined, target),
+        [facilitator]
+      );
+      await program.methods
+        .initNonceBitmap()
+        .accounts({
+          agent: target.agent.publicKey,
+          vault: target.vault,
+          nonceBitmap,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([target.agent])
+        .rpc();
+    });
+
+    it("Carries the window's nonces into the bitmap", async () => {
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.isTrue(vault.usesNonceBitmap);
+
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(2), undefined, {
+            ...target,
+            nonceBitmap,
+          }),
+          [facilitator]
+        ),
+        "NonceReused",
+        "Should reject a nonce the window already covered"
+      );
+    });
+
+    it("Settles nonces far apart in any order", async () => {
+      for (const nonce of [50_000, 700, 65_535]) {
+        await provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(nonce), undefined, {
+            ...target,
+            nonceBitmap,
+          }),
+          [facilitator]
+        );
+      }
+
+      const bitmap = await program.account.nonceBitmap.fetch(nonceBitmap);
+      assert.ok(bitmap.vault.equals(target.vault));
+      assert.equal(bitmap.bits[50_000 / 8] & (1 << 50_000 % 8), 1 << 50_000 % 8);
+      console.log("✅ Out-of-order nonces settled through the bitmap");
+    });
+
+    it("Fails: Replaying a bitmap nonce", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(700), undefined, {
+            ...target,
+            nonceBitmap,
+          }),
+          [facilitator]
+        ),
+        "NonceReused",
+        "Should reject a nonce already set in the bitmap"
+      );
+    });
+
+    it("Fails: A nonce past the last slot", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(65_536), undefined, {
+            ...target,
+            nonceBitmap,
+          }),
+          [facilitator]
+        ),
+        "NonceOutOfRange",
+        "Should reject a nonce the bitmap cannot track"
+      );
+    });
+
+    it("Fails: Settling without the bitmap", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(1_000), undefined, target),
+          [facilitator]
+        ),
+        "NonceBitmapRequired",
+        "Should reject a window settlement once the bitmap is in use"
+      );
+    });
+
+    it("Compares compute units against the replay window", async () => {
+      const windowUnits = await settleUnits(windowTarget, new BN(1));
+      const bitmapUnits = await settleUnits(
+        { ...target, nonceBitmap },
+        new BN(1_001)
+      );
+      console.log(
+        `✅ settle_batch CU: window ${windowUnits}, bitmap ${bitmapUnits}`
+      );
+      // The bitmap is zero-copy, so it should cost about the same as the window.
+      assert.isBelow(bitmapUnits, windowUnits + 5_000);
+    });
+  });
+
+  describe("Provider Earnings Tests", () => {
+    const settleAmounts = [new BN(100_000), new BN(250_000), new BN(150_000)];
+    const netOf = (amount: BN) =>
+      amount.sub(amount.muln(feeBps).divn(10_000));
+    let earner: Awaited<ReturnType<typeof registerTestProvider>>;
+    let target: SettleTarget;
+    let earnings: PublicKey;
+
+    before(async () => {
+      earner = await registerTestProvider();
+      [earnings] = PublicKey.findProgramAddressSync(
+        [Buffer.from("provider_earnings"), earner.provider.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .initProviderEarnings()
+        .accounts({
+          authority: earner.authority.publicKey,
+          provider: earner.provider,
+          earnings,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([earner.authority])
+        .rpc();
+
+      target = {
+        ...(await createFundedVault()),
+        provider: earner.provider,
+        destination: earner.destination,
+        providerEarnings: earnings,
+      };
+    });
+
+    const claim = () =>
+      program.methods
+        .claimEarnings()
+        .accounts({
+          authority: earner.authority.publicKey,
+          provider: earner.provider,
+          earnings,
+          destination: earner.destination,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([earner.authority])
+        .rpc();
+
+    it("Accrues several settlements without paying the destination", async () => {
+      for (const [i, amount] of settleAmounts.entries()) {
+        await provider.sendAndConfirm(
+          await buildSettleTx(amount, new BN(i + 1), undefined, target),
+          [facilitator]
+        );
+      }
+
+      const expected = settleAmounts.reduce(
+        (sum, amount) => sum.add(netOf(amount)),
+        new BN(0)
+      );
+      const providerAccount = await program.account.provider.fetch(
+        earner.provider
+      );
+      assert.equal(providerAccount.accrued.toString(), expected.toString());
+      const held = await getAccount(provider.connection, earnings);
+      assert.equal(held.amount.toString(), expected.toString());
+      const destination = await getAccount(
+        provider.connection,
+        earner.destination
+      );
+      assert.equal(destination.amount.toString(), "0");
+    });
+
+    it("Pays the summed earnings out in a single claim", async () => {
+      await claim();
+
+      const expected = settleAmounts.reduce(
+        (sum, amount) => sum.add(netOf(amount)),
+        new BN(0)
+      );
+      const destination = await getAccount(
+        provider.connection,
+        earner.destination
+      );
+      assert.equal(destination.amount.toString(), expected.toString());
+      const providerAccount = await program.account.provider.fetch(
+        earner.provider
+      );
+      assert.equal(providerAccount.accrued.toString(), "0");
+      console.log("✅ Claimed", expected.toString(), "accrued earnings");
+    });
+
+    it("Fails: Claiming with nothing accrued", async () => {
+      await assertRejects(
+        claim(),
+        "NoAccruedEarnings",
+        "Should reject an empty claim"
+      );
+    });
+
+    it("Fails: Claiming as someone else", async () => {
+      const stranger = Keypair.generate();
+      await assertRejects(
+        program.methods
+          .claimEarnings()
+          .accounts({
+            authority: stranger.publicKey,
+            provider: earner.provider,
+            earnings,
+            destination: earner.destination,
+            tokenMint: mint,
+            globalConfig: globalConfigPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          } as any)
+          .signers([stranger])
+          .rpc(),
+        "ConstraintHasOne",
+        "Should reject a signer other than the provider authority"
+      );
+    });
+  });
+
+  describe("Settlement Memo Tests", () => {
+    const settleAmount = settleThreshold;
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    // Logs and CPI program ids of a confirmed settlement.
+    async function settlementTrace(sig: string) {
+      const tx = await provider.connection.getParsedTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return {
+        logs: tx.meta.logMessages,
+        innerPrograms: tx.meta.innerInstructions.flatMap((inner) =>
+          inner.instructions.map((ix) => ix.programId.toBase58())
+        ),
+      };
+    }
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Records a memo when one is passed", async () => {
+      const memo = "INV-2024-0042";
+      const sig = await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, {
+          ...target,
+          memo,
+        }),
+        [facilitator],
+        { commitment: "confirmed" }
+      );
+
+      const { logs, innerPrograms } = await settlementTrace(sig);
+      assert.include(innerPrograms, MEMO_PROGRAM_ID.toBase58());
+      assert.isTrue(
+        logs.some((log) => log.includes(memo)),
+        "Memo should appear in the transaction logs"
+      );
+      console.log("✅ Settlement memo recorded");
+    });
+
+    it("Skips the memo CPI without one", async () => {
+      const sig = await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(2), undefined, target),
+        [facilitator],
+        { commitment: "confirmed" }
+      );
+
+      const { innerPrograms } = await settlementTrace(sig);
+      assert.notInclude(innerPrograms, MEMO_PROGRAM_ID.toBase58());
+    });
+
+    it("Fails: A memo over the limit", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(3), undefined, {
+            ...target,
+            memo: "m".repeat(65),
+          }),
+          [facilitator]
+        ),
+        "MemoTooLong",
+        "Should reject a memo over 64 bytes"
+      );
+    });
+
+    it("Fails: A memo without the memo program", async () => {
+      const tx = await buildSettleTx(settleAmount, new BN(3), undefined, {
+        ...target,
+        memo: "INV-2024-0043",
+      });
+      // Anchor reads the program id in an optional account's slot as None.
+      for (const key of tx.instructions[1].keys) {
+        if (key.pubkey.equals(MEMO_PROGRAM_ID)) {
+          key.pubkey = program.programId;
+        }
+      }
+      await assertRejects(
+        provider.sendAndConfirm(tx, [facilitator]),
+        "MemoProgramRequired",
+        "Should name the missing memo program"
+      );
+    });
+  });
+
+  describe("Idempotent Vault Creation Tests", () => {
+    const deposit = new BN(1_000_000);
+    let creator: Keypair;
+    let creatorTokenAccount: PublicKey;
+    let vault: PublicKey;
+    let vaultTokenAccount: PublicKey;
+
+    const createIdempotent = (amount: BN, signer: Keypair = creator) =>
+      program.methods
+        .createVaultIdempotent(amount, new BN(0), new BN(0), false, 0, defaultSession, true)
+        .accounts({
+          agent: signer.publicKey,
+          vault,
+          vaultTokenAccount,
+          agentTokenAccount: creatorTokenAccount,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          contribution: null,
+          ownerVaultCount: ownerVaultCountPda(signer.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      creator = Keypair.generate();
+      await airdrop(creator);
+      creatorTokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        creator.publicKey
+      );
+      await mintTo(
+        provider.connection,
+        payer.payer,
+        mint,
+        creatorTokenAccount,
+        admin,
+        10_000_000
+      );
+      [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), creator.publicKey.toBuffer()],
+        program.programId
+      );
+      [vaultTokenAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault_token_account"), creator.publicKey.toBuffer()],
+        program.programId
+      );
+    });
+
+    it("Creates a fresh vault", async () => {
+      await createIdempotent(deposit);
+
+      const vaultAccount = await program.account.vault.fetch(vault);
+      assert.ok(vaultAccount.agent.equals(creator.publicKey));
+      assert.equal(vaultAccount.depositAmount.toString(), deposit.toString());
+      const held = await getAccount(provider.connection, vaultTokenAccount);
+      assert.equal(held.amount.toString(), deposit.toString());
+    });
+
+    it("Treats a retry as a no-op", async () => {
+      await createIdempotent(deposit);
+
+      const vaultAccount = await program.account.vault.fetch(vault);
+      assert.equal(vaultAccount.depositAmount.toString(), deposit.toString());
+      const held = await getAccount(provider.connection, vaultTokenAccount);
+      assert.equal(held.amount.toString(), deposit.toString());
+      const record = await program.account.ownerVaultCount.fetch(
+        ownerVaultCountPda(creator.publicKey)
+      );
+      assert.equal(record.count, 1);
+      console.log("✅ Retried create_vault_idempotent left the vault as is");
+    });
+
+    it("Tops a retry with a larger amount up to it", async () => {
+      await createIdempotent(deposit.muln(2));
+
+      const held = await getAccount(provider.connection, vaultTokenAccount);
+      assert.equal(held.amount.toString(), deposit.muln(2).toString());
+    });
+
+    it("Fails: Topping up while deposits are paused", async () => {
+      await setGlobalPause(true, pauseFlags.deposit);
+      try {
+        await assertRejects(
+          createIdempotent(deposit.muln(3)),
+          "InstructionPaused",
+          "A retry's top-up should honour the deposit pause"
+        );
+      } finally {
+        await setGlobalPause(false);
+      }
+    });
+
+    it("Creates a shared session vault with create_vault's options", async () => {
+      const sessionId = Array.from({ length: 16 }, (_, i) => i + 1);
+      const [sessionVault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), creator.publicKey.toBuffer(), Buffer.from(sessionId)],
+        program.programId
+      );
+      const [sessionTokenAccount] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("vault_token_account"),
+          creator.publicKey.toBuffer(),
+          Buffer.from(sessionId),
+        ],
+        program.programId
+      );
+      const create = () =>
+        program.methods
+          .createVaultIdempotent(deposit, new BN(0), new BN(0), true, 5, sessionId, false)
+          .accounts({
+            agent: creator.publicKey,
+            vault: sessionVault,
+            vaultTokenAccount: sessionTokenAccount,
+            agentTokenAccount: creatorTokenAccount,
+            tokenMint: mint,
+            globalConfig: globalConfigPda,
+            contribution: contributionPda(sessionVault, creator.publicKey),
+            ownerVaultCount: ownerVaultCountPda(creator.publicKey),
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          } as any)
+          .signers([creator])
+          .rpc();
+
+      await create();
+      await create();
+
+      const vaultAccount = await program.account.vault.fetch(sessionVault);
+      assert.isTrue(vaultAccount.shared);
+      assert.equal(vaultAccount.maxSettlements, 5);
+      assert.deepEqual(vaultAccount.sessionId, sessionId);
+      assert.isTrue(vaultAccount.eventsDisabled);
+      assert.equal(vaultAccount.totalContributed.toString(), deposit.toString());
+      const contribution = await program.account.contribution.fetch(
+        contributionPda(sessionVault, creator.publicKey)
+      );
+      assert.equal(contribution.amount.toString(), deposit.toString());
+    });
+
+    it("Fails: The vault now belongs to someone else", async () => {
+      const newOwner = Keypair.generate();
+      await airdrop(newOwner);
+      await program.methods
+        .transferVaultOwnership()
+        .accounts({
+          agent: creator.publicKey,
+          vault,
+          newAgent: newOwner.publicKey,
+        } as any)
+        .signers([creator])
+        .rpc();
+      await program.methods
+        .acceptVaultOwnership()
+        .accounts({ newAgent: newOwner.publicKey, vault } as any)
+        .signers([newOwner])
+        .rpc();
+
+      await assertRejects(
+        createIdempotent(deposit),
+        "Unauthorized",
+        "Should reject a vault whose owner is no longer the signer"
+      );
+    });
+  });
+
+  describe("Account Versioning Tests", () => {
+    it("Writes the current version on creation", async () => {
+      const target = await createFundedVault();
+
+      const vault = await program.account.vault.fetch(target.vault);
+      const providerAccount = await program.account.provider.fetch(providerPda);
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(vault.version, 12);
+      assert.equal(providerAccount.version, 6);
+      assert.equal(config.version, 15);
+    });
+
+    it("Leaves a current account untouched on migrate", async () => {
+      const target = await createFundedVault();
+      const before = await provider.connection.getAccountInfo(target.vault);
+
+      await program.methods
+        .migrateAccount()
+        .accounts({
+          payer: provider.wallet.publicKey,
+          account: target.vault,
+          globalConfig: globalConfigPda,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .rpc();
+
+      const after = await provider.connection.getAccountInfo(target.vault);
+      assert.ok(after.data.equals(before.data));
+    });
+
+    it("Fails: Migrating an account of another program", async () => {
+      await assertRejects(
+        program.methods
+          .migrateAccount()
+          .accounts({
+            payer: provider.wallet.publicKey,
+            account: mint,
+            globalConfig: globalConfigPda,
+            systemProgram: SystemProgram.programId,
+          } as any)
+          .rpc(),
+        "ConstraintOwner",
+        "Should only migrate this program's accounts"
+      );
+    });
+  });
+
+  describe("Stored Bump Tests", () => {
+    it("Stores the canonical bumps on creation", async () => {
+      const target = await createFundedVault();
+      const [, vaultBump] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), target.agent.publicKey.toBuffer()],
+        program.programId
+      );
+      const [, configBump] = PublicKey.findProgramAddressSync(
+        [Buffer.from("config")],
+        program.programId
+      );
+
+      const vault = await program.account.vault.fetch(target.vault);
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(vault.bump, vaultBump);
+      assert.equal(config.bump, configBump);
+    });
+
+    it("Settles against the stored bump", async () => {
+      const target = await createFundedVault();
+      const sig = await provider.sendAndConfirm(
+        await buildSettleTx(settleThreshold, new BN(1), undefined, target),
+        [facilitator],
+        { commitment: "confirmed" }
+      );
+
+      const tx = await provider.connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      // Re-deriving the vault and config bumps cost roughly 1.5k CU each
+      // per find_program_address call before they were stored.
+      console.log(
+        "✅ settle_batch with stored bumps:",
+        tx.meta.computeUnitsConsumed,
+        "CU"
+      );
+    });
+  });
+
+  describe("Withdrawal Timelock Tests", () => {
+    const withdrawTimelock = new BN(2);
+    const withdrawAmount = new BN(300_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const vaultAccounts = () =>
+      ({ agent: target.agent.publicKey, vault: target.vault } as any);
+    const withdraw = (amount: BN) =>
+      program.methods
+        .withdraw(amount)
+        .accounts({
+          agent: target.agent.publicKey,
+          vault: target.vault,
+          globalConfig: globalConfigPda,
+          vaultTokenAccount: target.vaultTokenAccount,
+          agentTokenAccount: target.agentTokenAccount,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([target.agent])
+        .rpc();
+
+    before(async () => {
+      target = await createFundedVault(
+        depositAmount,
+        new BN(0),
+        withdrawTimelock
+      );
+    });
+
+    it("Fails: Withdrawing without a request", async () => {
+      await assertRejects(
+        withdraw(withdrawAmount),
+        "WithdrawalLocked",
+        "Should require a request once the timelock is armed"
+      );
+    });
+
+    it("Fails: Withdrawing before the request unlocks", async () => {
+      await program.methods
+        .requestWithdraw(withdrawAmount)
+        .accounts(vaultAccounts())
+        .signers([target.agent])
+        .rpc();
+
+      await assertRejects(
+        withdraw(withdrawAmount),
+        "WithdrawalLocked",
+        "Should reject a withdrawal inside the timelock"
+      );
+    });
+
+    it("Withdraws once the request unlocks", async () => {
+      await new Promise((resolve) =>
+        setTimeout(resolve, (withdrawTimelock.toNumber() + 1) * 1000)
+      );
+      const before = await getAccount(
+        provider.connection,
+        target.agentTokenAccount
+      );
+
+      await withdraw(withdrawAmount);
+
+      const after = await getAccount(
+        provider.connection,
+        target.agentTokenAccount
+      );
+      assert.equal(
+        (after.amount - before.amount).toString(),
+        withdrawAmount.toString()
+      );
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.pendingWithdraw.toString(), "0");
+      console.log("✅ Timelocked withdrawal went through after unlock");
+    });
+
+    it("Cancels a pending request", async () => {
+      await program.methods
+        .requestWithdraw(withdrawAmount)
+        .accounts(vaultAccounts())
+        .signers([target.agent])
+        .rpc();
+      await program.methods
+        .cancelWithdraw()
+        .accounts(vaultAccounts())
+        .signers([target.agent])
+        .rpc();
+      await new Promise((resolve) =>
+        setTimeout(resolve, (withdrawTimelock.toNumber() + 1) * 1000)
+      );
+
+      await assertRejects(
+        withdraw(withdrawAmount),
+        "WithdrawalLocked",
+        "Should reject a withdrawal whose request was cancelled"
+      );
+      await assertRejects(
+        program.methods
+          .cancelWithdraw()
+          .accounts(vaultAccounts())
+          .signers([target.agent])
+          .rpc(),
+        "NoPendingWithdrawal",
+        "Should reject cancelling with nothing pending"
+      );
+    });
+  });
+
+  describe("Fee Cap Tests", () => {
+    // 2.5% of 1_000_000 is 25_000, so a 10_000 cap binds there.
+    const maxFee = new BN(10_000);
+    const configAccounts = () =>
+      ({ authority: admin.publicKey, globalConfig: globalConfigPda } as any);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    async function feeCharged(amount: BN, nonce: BN): Promise<bigint> {
+      const before = await getAccount(provider.connection, feeTokenAccount);
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, nonce, undefined, target),
+        [facilitator]
+      );
+      const after = await getAccount(provider.connection, feeTokenAccount);
+      return after.amount - before.amount;
+    }
+
+    before(async () => {
+      target = await createFundedVault();
+      await program.methods
+        .updateConfig({ ...configParams(), maxFee })
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+    });
+
+    it("Charges the bps fee below the cap", async () => {
+      const amount = new BN(100_000);
+      const fee = await feeCharged(amount, new BN(1));
+      assert.equal(
+        fee.toString(),
+        amount.muln(feeBps).divn(10_000).toString()
+      );
+    });
+
+    it("Caps the fee on a large settlement", async () => {
+      const fee = await feeCharged(new BN(1_000_000), new BN(2));
+      assert.equal(fee.toString(), maxFee.toString());
+      console.log("✅ Fee capped at", maxFee.toString());
+    });
+
+    it("Fails: Raising or removing the cap through update_config", async () => {
+      for (const raised of [maxFee.addn(1), new BN(0)]) {
+        await assertRejects(
+          program.methods
+            .updateConfig({ ...configParams(), maxFee: raised })
+            .accounts(configAccounts())
+            .signers([admin])
+            .rpc(),
+          "TimelockRequired",
+          "A looser cap should need the timelock"
+        );
+      }
+    });
+
+    after(async () => {
+      // Removing the cap is a fee increase, so it waits out the timelock.
+      await program.methods
+        .proposeFeeIncrease(feeBps, new BN(0), { floor: {} } as any)
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+      await new Promise((resolve) =>
+        setTimeout(resolve, (timelockSeconds.toNumber() + 1) * 1000)
+      );
+      await program.methods
+        .applyFeeIncrease()
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+    });
+  });
+
+  describe("Referral Fee Tests", () => {
+    // 20% of the fee goes to the referrer, the rest to the treasury.
+    const referralBps = 2_000;
+    const amount = new BN(1_000_000);
+    const fee = amount.muln(feeBps).divn(10_000);
+    const referralFee = fee.muln(referralBps).divn(10_000);
+    const configAccounts = () =>
+      ({ authority: admin.publicKey, globalConfig: globalConfigPda } as any);
+    let referrerTokenAccount: PublicKey;
+
+    async function balances(
+      destination: PublicKey
+    ): Promise<[bigint, bigint, bigint]> {
+      const accounts = await Promise.all(
+        [destination, referrerTokenAccount, feeTokenAccount].map((account) =>
+          getAccount(provider.connection, account)
+        )
+      );
+      return accounts.map((account) => account.amount) as [
+        bigint,
+        bigint,
+        bigint
+      ];
+    }
+
+    before(async () => {
+      referrerTokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        Keypair.generate().publicKey
+      );
+      await program.methods
+        .updateConfig({ ...configParams(), referralBps })
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+    });
+
+    it("Splits the fee three ways for a referred provider", async () => {
+      const referred = await registerTestProvider(referrerTokenAccount);
+      const providerAccount = await program.account.provider.fetch(
+        referred.provider
+      );
+      assert.equal(
+        providerAccount.referrer.toBase58(),
+        referrerTokenAccount.toBase58()
+      );
+
+      const target = {
+        ...(await createFundedVault()),
+        provider: referred.provider,
+        destination: referred.destination,
+        referrer: referrerTokenAccount,
+      };
+      const before = await balances(referred.destination);
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, new BN(1), undefined, target),
+        [facilitator]
+      );
+      const after = await balances(referred.destination);
+
+      const [net, referral, treasuryFee] = after.map(
+        (balance, i) => (balance - before[i]).toString()
+      );
+      assert.equal(net, amount.sub(fee).toString());
+      assert.equal(referral, referralFee.toString());
+      assert.equal(treasuryFee, fee.sub(referralFee).toString());
+      console.log("✅ Referrer paid", referral, "of a", fee.toString(), "fee");
+    });
+
+    it("Fails: Referred provider settled without its referrer", async () => {
+      const referred = await registerTestProvider(referrerTokenAccount);
+      const target = {
+        ...(await createFundedVault()),
+        provider: referred.provider,
+        destination: referred.destination,
+      };
+
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(amount, new BN(1), undefined, target),
+          [facilitator]
+        ),
+        "ReferrerAccountRequired",
+        "Should not let the referral share fall through to the treasury"
+      );
+    });
+
+    it("Sends the whole fee to the treasury without a referrer", async () => {
+      const unreferred = await registerTestProvider();
+      const target = {
+        ...(await createFundedVault()),
+        provider: unreferred.provider,
+        destination: unreferred.destination,
+      };
+      const before = await balances(unreferred.destination);
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, new BN(1), undefined, target),
+        [facilitator]
+      );
+      const after = await balances(unreferred.destination);
+
+      const [net, referral, treasuryFee] = after.map(
+        (balance, i) => (balance - before[i]).toString()
+      );
+      assert.equal(net, amount.sub(fee).toString());
+      assert.equal(referral, "0");
+      assert.equal(treasuryFee, fee.toString());
+    });
+
+    it("Pays the referrer on batch settlements and one-shot payments", async () => {
+      const referred = await registerTestProvider(referrerTokenAccount);
+      const target = await createFundedVault();
+      const before = await balances(referred.destination);
+
+      await program.methods
+        .settleBatchMany([amount], [new BN(1)])
+        .accounts({
+          facilitator: facilitator.publicKey,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          provider: referred.provider,
+          destination: referred.destination,
+          feeDestination: feeTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          referrer: referrerTokenAccount,
+        } as any)
+        .remainingAccounts([
+          { pubkey: target.agent.publicKey, isSigner: true, isWritable: false },
+          { pubkey: target.vault, isSigner: false, isWritable: true },
+          { pubkey: target.vaultTokenAccount, isSigner: false, isWritable: true },
+        ])
+        .signers([facilitator, target.agent])
+        .rpc();
+
+      await mintTo(
+        provider.connection,
+        payer.payer,
+        mint,
+        target.agentTokenAccount,
+        admin,
+        amount.toNumber()
+      );
+      await program.methods
+        .payOnce(amount)
+        .accounts({
+          payer: target.agent.publicKey,
+          payerTokenAccount: target.agentTokenAccount,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          provider: referred.provider,
+          destination: referred.destination,
+          feeDestination: feeTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          referrer: referrerTokenAccount,
+        } as any)
+        .signers([target.agent])
+        .rpc();
+      const after = await balances(referred.destination);
+
+      const [net, referral, treasuryFee] = after.map(
+        (balance, i) => (balance - before[i]).toString()
+      );
+      assert.equal(net, amount.sub(fee).muln(2).toString());
+      assert.equal(referral, referralFee.muln(2).toString());
+      assert.equal(treasuryFee, fee.sub(referralFee).muln(2).toString());
+      console.log("✅ Referrer paid on batch and one-shot payments");
+    });
+
+    after(async () => {
+      await program.methods
+        .updateConfig(configParams())
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+    });
+  });
+
+  describe("Settle Threshold Tests", () => {
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Fails: One unit below the threshold", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(
+            settleThreshold.subn(1),
+            new BN(1),
+            undefined,
+            target
+          ),
+          [facilitator]
+        ),
+        "BelowSettleThreshold",
+        "Should make the facilitator keep batching"
+      );
+    });
+
+    it("Settles once the batched total reaches the threshold", async () => {
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleThreshold, new BN(1), undefined, target),
+        [facilitator]
+      );
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.totalSettled.toString(), settleThreshold.toString());
+    });
+  });
+
+  describe("Config Validation Tests", () => {
+    const updateConfig = (params: ReturnType<typeof configParams>) =>
+      program.methods
+        .updateConfig(params)
+        .accounts({
+          authority: admin.publicKey,
+          globalConfig: globalConfigPda,
+        } as any)
+        .signers([admin])
+        .rpc();
+
+    it("Accepts the suite's baseline params", async () => {
+      await updateConfig(configParams());
+
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(config.feeBps, feeBps);
+      assert.equal(config.settleThreshold.toString(), settleThreshold.toString());
+    });
+
+    it("Fails: A fee over 100%", async () => {
+      await assertRejects(
+        updateConfig({ ...configParams(), feeBps: 15_000 }),
+        "InvalidFeeBps",
+        "Should reject fee_bps above 10_000"
+      );
+    });
+
+    it("Fails: A zero settlement threshold", async () => {
+      await assertRejects(
+        updateConfig({ ...configParams(), settleThreshold: new BN(0) }),
+        "InvalidThreshold",
+        "Should reject a settle_threshold of zero"
+      );
+    });
+  });
+
+  describe("Instruction Pause Tests", () => {
+    const withdrawal = new BN(100_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const withdraw = () =>
+      program.methods
+        .withdraw(withdrawal)
+        .accounts({
+          agent: target.agent.publicKey,
+          vault: target.vault,
+          globalConfig: globalConfigPda,
+          vaultTokenAccount: target.vaultTokenAccount,
+          agentTokenAccount: target.agentTokenAccount,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([target.agent])
+        .rpc();
+
+    before(async () => {
+      target = await createFundedVault();
+      await setGlobalPause(true, pauseFlags.settle);
+    });
+
+    after(async () => {
+      await setGlobalPause(false);
+    });
+
+    it("Fails: Settling while settlements are paused", async () => {
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(config.pauseFlags, pauseFlags.settle);
+
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleThreshold, new BN(1), undefined, target),
+          [facilitator]
+        ),
+        "InstructionPaused",
+        "Settlement should be blocked by its own flag"
+      );
+    });
+
+    it("Still creates vaults and withdraws with only settle paused", async () => {
+      const created = await createFundedVault();
+      const createdVault = await program.account.vault.fetch(created.vault);
+      assert.equal(
+        createdVault.depositAmount.toString(),
+        depositAmount.toString()
+      );
+
+      await withdraw();
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(
+        vault.depositAmount.toString(),
+        depositAmount.sub(withdrawal).toString()
+      );
+      console.log("✅ Create and withdraw open during a settle-only pause");
+    });
+
+    it("Fails: Withdrawing once the withdraw flag is added", async () => {
+      await setGlobalPause(true, pauseFlags.withdraw);
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(config.pauseFlags, pauseFlags.settle | pauseFlags.withdraw);
+
+      await assertRejects(
+        withdraw(),
+        "InstructionPaused",
+        "Withdrawals should be blocked by their own flag"
+      );
+    });
+  });
+
+  describe("Provider Close Tests", () => {
+    const closeProvider = (
+      closing: Awaited<ReturnType<typeof registerTestProvider>>
+    ) =>
+      program.methods
+        .closeProvider()
+        .accounts({
+          authority: closing.authority.publicKey,
+          provider: closing.provider,
+        } as any)
+        .signers([closing.authority])
+        .rpc();
+
+    it("Closes an idle provider and refunds its rent", async () => {
+      const closing = await registerTestProvider();
+      const rent = await provider.connection.getBalance(closing.provider);
+      const before = await provider.connection.getBalance(
+        closing.authority.publicKey
+      );
+
+      await closeProvider(closing);
+
+      assert.isNull(
+        await program.account.provider.fetchNullable(closing.provider)
+      );
+      const after = await provider.connection.getBalance(
+        closing.authority.publicKey
+      );
+      // The authority also paid the transaction fee.
+      assert.isTrue(after > before + rent - 10_000);
+      console.log("✅ Provider closed, rent refunded:", rent);
+    });
+
+    it("Fails: Closing with unclaimed earnings", async () => {
+      const earner = await registerTestProvider();
+      const [earnings] = PublicKey.findProgramAddressSync(
+        [Buffer.from("provider_earnings"), earner.provider.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .initProviderEarnings()
+        .accounts({
+          authority: earner.authority.publicKey,
+          provider: earner.provider,
+          earnings,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([earner.authority])
+        .rpc();
+      const target = {
+        ...(await createFundedVault()),
+        provider: earner.provider,
+        destination: earner.destination,
+        providerEarnings: earnings,
+      };
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleThreshold, new BN(1), undefined, target),
+        [facilitator]
+      );
+
+      await assertRejects(
+        closeProvider(earner),
+        "UnclaimedEarnings",
+        "Accrued earnings would be stranded"
+      );
+      const providerAccount = await program.account.provider.fetch(
+        earner.provider
+      );
+      assert.isTrue(providerAccount.accrued.gtn(0));
+    });
+
+    it("Fails: Non-authority closes a provider", async () => {
+      const closing = await registerTestProvider();
+      await assertRejects(
+        program.methods
+          .closeProvider()
+          .accounts({
+            authority: randomUser.publicKey,
+            provider: closing.provider,
+          } as any)
+          .signers([randomUser])
+          .rpc(),
+        "ConstraintHasOne",
+        "Only the provider authority can close it"
+      );
+    });
+  });
+
+  describe("Vault Status Tests", () => {
+    const getStatus = (vault: PublicKey) =>
+      program.methods
+        .getVaultStatus()
+        .accounts({ vault, globalConfig: globalConfigPda } as any)
+        .view();
+
+    it("Returns the vault's balance and status", async () => {
+      const target = await createFundedVault();
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleThreshold, new BN(1), undefined, target),
+        [facilitator]
+      );
+
+      const status = await getStatus(target.vault);
+      assert.equal(
+        status.balance.toString(),
+        depositAmount.sub(settleThreshold).toString()
+      );
+      assert.ok(status.owner.equals(target.agent.publicKey));
+      assert.isNull(status.provider);
+      assert.equal(status.expiryUnix.toNumber(), 0);
+      assert.isFalse(status.expired);
+      assert.isFalse(status.paused);
+      assert.equal(status.lastNonce.toNumber(), 1);
+      console.log("✅ Vault status:", status.balance.toString());
+    });
+
+    it("Reports an expired vault", async () => {
+      const chainTime = async () =>
+        provider.connection.getBlockTime(
+          await provider.connection.getSlot("confirmed")
+        );
+      const expiryUnix = (await chainTime()) + 2;
+      const target = await createFundedVault(depositAmount, new BN(expiryUnix));
+      while ((await chainTime()) < expiryUnix) {
+        await new Promise((resolve) => setTimeout(resolve, 500));
+      }
+
+      const status = await getStatus(target.vault);
+      assert.equal(status.expiryUnix.toNumber(), expiryUnix);
+      assert.isTrue(status.expired);
+    });
+  });
+
+  describe("Settlement Deadline Tests", () => {
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const chainTime = async () =>
+      provider.connection.getBlockTime(
+        await provider.connection.getSlot("confirmed")
+      );
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Settles before the deadline", async () => {
+      const deadlineUnix = new BN((await chainTime()) + 60);
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleThreshold, new BN(1), undefined, {
+          ...target,
+          deadlineUnix,
+        }),
+        [facilitator]
+      );
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.nonce.toNumber(), 1);
+    });
+
+    it("Fails: Settling after the deadline", async () => {
+      const deadlineUnix = new BN((await chainTime()) + 1);
+      while ((await chainTime()) <= deadlineUnix.toNumber()) {
+        await new Promise((resolve) => setTimeout(resolve, 500));
+      }
+
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleThreshold, new BN(2), undefined, {
+            ...target,
+            deadlineUnix,
+          }),
+          [facilitator]
+        ),
+        "DeadlineExceeded",
+        "A stale settlement should not land"
+      );
+      console.log("✅ Stale settlement rejected");
+    });
+  });
+
+  describe("Destination Change Tests", () => {
+    let changing: Awaited<ReturnType<typeof registerTestProvider>>;
+    let newDestination: PublicKey;
+
+    const propose = (destination: PublicKey) =>
+      program.methods
+        .proposeDestination()
+        .accounts({
+          authority: changing.authority.publicKey,
+          provider: changing.provider,
+          destination,
+          globalConfig: globalConfigPda,
+        } as any)
+        .signers([changing.authority])
+        .rpc();
+
+    const apply = () =>
+      program.methods
+        .applyDestination()
+        .accounts({
+          authority: changing.authority.publicKey,
+          provider: changing.provider,
+        } as any)
+        .signers([changing.authority])
+        .rpc();
+
+    const veto = (signer: Keypair) =>
+      program.methods
+        .vetoDestination()
+        .accounts({
+          signer: signer.publicKey,
+          provider: changing.provider,
+          globalConfig: globalConfigPda,
+        } as any)
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      changing = await registerTestProvider();
+      newDestination = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        changing.authority.publicKey,
+        Keypair.generate()
+      );
+    });
+
+    it("Keeps paying the old destination until the delay is over", async () => {
+      await propose(newDestination);
+      let providerAccount = await program.account.provider.fetch(
+        changing.provider
+      );
+      assert.ok(providerAccount.pendingDestination.equals(newDestination));
+
+      await assertRejects(
+        apply(),
+        "DestinationChangePending",
+        "The change should wait out the delay"
+      );
+      await assertRejects(
+        propose(randomUserTokenAccount),
+        "DestinationChangePending",
+        "A second change should not replace the pending one"
+      );
+
+      const target = {
+        ...(await createFundedVault()),
+        provider: changing.provider,
+        destination: changing.destination,
+      };
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleThreshold, new BN(1), undefined, target),
+        [facilitator]
+      );
+      const oldBalance = await getAccount(
+        provider.connection,
+        changing.destination
+      );
+      assert.isTrue(oldBalance.amount > BigInt(0));
+
+      await new Promise((resolve) =>
+        setTimeout(resolve, (timelockSeconds.toNumber() + 1) * 1000)
+      );
+      await apply();
+      providerAccount = await program.account.provider.fetch(
+        changing.provider
+      );
+      assert.ok(providerAccount.destination.equals(newDestination));
+      assert.isNull(providerAccount.pendingDestination);
+      console.log("✅ Destination changed after the delay");
+    });
+
+    it("Vetoes a pending change", async () => {
+      await propose(changing.destination);
+
+      await assertRejects(
+        veto(randomUser),
+        "Unauthorized",
+        "Only the provider, guardian or config authority may veto"
+      );
+      await veto(admin);
+
+      const providerAccount = await program.account.provider.fetch(
+        changing.provider
+      );
+      assert.isNull(providerAccount.pendingDestination);
+      assert.ok(providerAccount.destination.equals(newDestination));
+      await assertRejects(
+        apply(),
+        "NoPendingDestinationChange",
+        "A vetoed change cannot be applied"
+      );
+    });
+  });
+
+  describe("Shared Vault Tests", () => {
+    // The creator puts in 1_200_000 and a co-funder 600_000, a 2:1 split.
+    const creatorDeposit = new BN(1_200_000);
+    const coFunding = new BN(600_000);
+    const settleAmount = new BN(300_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+    let coFunder: Keypair;
+    let coFunderTokenAccount: PublicKey;
+    let expiryUnix: number;
+
+    const chainTime = async () =>
+      provider.connection.getBlockTime(
+        await provider.connection.getSlot("confirmed")
+      );
+
+    const refund = (depositor: PublicKey, tokenAccount: PublicKey) =>
+      program.methods
+        .refundExpired()
+        .accounts({
+          agent: coFunder.publicKey,
+          vault: target.vault,
+          contribution: contributionPda(target.vault, depositor),
+          vaultTokenAccount: target.vaultTokenAccount,
+          agentTokenAccount: tokenAccount,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([coFunder])
+        .rpc();
+
+    before(async () => {
+      expiryUnix = (await chainTime()) + 8;
+      target = await createFundedVault(
+        creatorDeposit,
+        new BN(expiryUnix),
+        new BN(0),
+        true
+      );
+      coFunder = Keypair.generate();
+      await airdrop(coFunder);
+      coFunderTokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        coFunder.publicKey
+      );
+      await mintTo(
+        provider.connection,
+        payer.payer,
+        mint,
+        coFunderTokenAccount,
+        admin,
+        coFunding.toNumber()
+      );
+    });
+
+    it("Records each contributor's stake", async () => {
+      await program.methods
+        .contribute(coFunding)
+        .accounts({
+          depositor: coFunder.publicKey,
+          vault: target.vault,
+          contribution: contributionPda(target.vault, coFunder.publicKey),
+          vaultTokenAccount: target.vaultTokenAccount,
+          depositorTokenAccount: coFunderTokenAccount,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([coFunder])
+        .rpc();
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.isTrue(vault.shared);
+      assert.equal(
+        vault.totalContributed.toString(),
+        creatorDeposit.add(coFunding).toString()
+      );
+      const creatorStake = await program.account.contribution.fetch(
+        contributionPda(target.vault, target.agent.publicKey)
+      );
+      const coFunderStake = await program.account.contribution.fetch(
+        contributionPda(target.vault, coFunder.publicKey)
+      );
+      assert.equal(creatorStake.amount.toString(), creatorDeposit.toString());
+      assert.equal(coFunderStake.amount.toString(), coFunding.toString());
+    });
+
+    it("Settles from the pooled balance", async () => {
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, target),
+        [facilitator]
+      );
+
+      const status = await program.methods
+        .getVaultStatus()
+        .accounts({ vault: target.vault, globalConfig: globalConfigPda } as any)
+        .view();
+      assert.equal(
+        status.balance.toString(),
+        creatorDeposit.add(coFunding).sub(settleAmount).toString()
+      );
+    });
+
+    it("Fails: Owner withdraws from a shared vault", async () => {
+      await assertRejects(
+        program.methods
+          .withdraw(new BN(1))
+          .accounts({
+            agent: target.agent.publicKey,
+            vault: target.vault,
+            globalConfig: globalConfigPda,
+            vaultTokenAccount: target.vaultTokenAccount,
+            agentTokenAccount: target.agentTokenAccount,
+            tokenMint: mint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          } as any)
+          .signers([target.agent])
+          .rpc(),
+        "SharedVault",
+        "The owner should not drain co-funded deposits"
+      );
+    });
+
+    it("Refunds each contributor their share after expiry", async () => {
+      while ((await chainTime()) < expiryUnix) {
+        await new Promise((resolve) => setTimeout(resolve, 1000));
+      }
+      // 1_500_000 is left, split 2:1.
+      const remaining = creatorDeposit.add(coFunding).sub(settleAmount);
+
+      const coFunderBefore = await getAccount(
+        provider.connection,
+        coFunderTokenAccount
+      );
+      await refund(coFunder.publicKey, coFunderTokenAccount);
+      const coFunderAfter = await getAccount(
+        provider.connection,
+        coFunderTokenAccount
+      );
+      assert.equal(
+        (coFunderAfter.amount - coFunderBefore.amount).toString(),
+        remaining.divn(3).toString()
+      );
+
+      await assertRejects(
+        refund(target.agent.publicKey, coFunderTokenAccount),
+        "Unauthorized",
+        "A refund may only pay its contributor"
+      );
+      const creatorBefore = await getAccount(
+        provider.connection,
+        target.agentTokenAccount
+      );
+      await refund(target.agent.publicKey, target.agentTokenAccount);
+      const creatorAfter = await getAccount(
+        provider.connection,
+        target.agentTokenAccount
+      );
+      assert.equal(
+        (creatorAfter.amount - creatorBefore.amount).toString(),
+        remaining.muln(2).divn(3).toString()
+      );
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.depositAmount.toString(), settleAmount.toString());
+      await assertRejects(
+        refund(coFunder.publicKey, coFunderTokenAccount),
+        "ZeroAmount",
+        "A contributor is refunded only once"
+      );
+      console.log("✅ Expiry refunds split 2:1");
+    });
+  });
+
+  describe("Emergency Drain Tests", () => {
+    const recoveryWallet = Keypair.generate();
+    const configAccounts = () =>
+      ({ authority: admin.publicKey, globalConfig: globalConfigPda } as any);
+    let recoveryAccount: PublicKey;
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const drain = () =>
+      program.methods
+        .emergencyDrain()
+        .accounts({
+          authority: admin.publicKey,
+          globalConfig: globalConfigPda,
+          vault: target.vault,
+          vaultTokenAccount: target.vaultTokenAccount,
+          recoveryAccount,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([admin])
+        .rpc();
+
+    before(async () => {
+      recoveryAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        recoveryWallet.publicKey
+      );
+      await program.methods
+        .setRecoveryDestination(recoveryWallet.publicKey)
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+      target = await createFundedVault();
+    });
+
+    it("Fails: Draining while withdrawals are open", async () => {
+      await assertRejects(
+        drain(),
+        "WithdrawalsNotPaused",
+        "Drains are for paused incidents only"
+      );
+    });
+
+    it("Sweeps the vault to the recovery account while paused", async () => {
+      await setGlobalPause(true, pauseAll);
+      try {
+        await drain();
+      } finally {
+        await setGlobalPause(false);
+      }
+
+      const recovered = await getAccount(provider.connection, recoveryAccount);
+      assert.equal(recovered.amount.toString(), depositAmount.toString());
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.isTrue(vault.drained);
+
+      await assertRejects(
+        program.methods
+          .withdraw(new BN(1))
+          .accounts({
+            agent: target.agent.publicKey,
+            vault: target.vault,
+            globalConfig: globalConfigPda,
+            vaultTokenAccount: target.vaultTokenAccount,
+            agentTokenAccount: target.agentTokenAccount,
+            tokenMint: mint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          } as any)
+          .signers([target.agent])
+          .rpc(),
+        "VaultDrained",
+        "The owner should not withdraw after a drain"
+      );
+      console.log("✅ Vault drained to recovery:", recovered.amount.toString());
+    });
+
+    after(async () => {
+      await program.methods
+        .setRecoveryDestination(PublicKey.default)
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+    });
+  });
+
+  describe("Settlement Capacity Tests", () => {
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    const settleWithEvent = async (amount: BN, nonce: BN) => {
+      let listener = null;
+      const event = await new Promise<any>(async (resolve, reject) => {
+        listener = program.addEventListener("settlement", resolve);
+        provider
+          .sendAndConfirm(
+            await buildSettleTx(amount, nonce, undefined, target),
+            [facilitator]
+          )
+          .catch(reject);
+      });
+      await program.removeEventListener(listener);
+      return event;
+    };
+
+    it("Reports the remaining balance and capacity after each settlement", async () => {
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+
+      const first = await settleWithEvent(new BN(1_000_000), new BN(1));
+      const left = depositAmount.sub(new BN(1_000_000));
+      assert.equal(first.remainingBalance.toString(), left.toString());
+      assert.equal(first.remainingBeforeCap.toString(), left.toString());
+
+      // Leave less than the threshold behind: nothing more can be settled.
+      const second = left.sub(config.settleThreshold).addn(1);
+      const event = await settleWithEvent(second, new BN(2));
+      const rest = left.sub(second);
+      assert.equal(event.remainingBalance.toString(), rest.toString());
+      assert.equal(event.remainingBeforeCap.toString(), "0");
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(
+        vault.depositAmount.sub(vault.totalSettled).toString(),
+        rest.toString()
+      );
+      console.log("✅ Settlement capacity reported");
+    });
+  });
+
+  describe("Config Close Tests", () => {
+    // The suite shares one config and never closes every vault, so closing
+    // it successfully is covered by the `require_no_active_vaults` unit test.
+    const closeConfig = (authority: Keypair) =>
+      program.methods
+        .closeConfig()
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig: globalConfigPda,
+        } as any)
+        .signers([authority])
+        .rpc();
+
+    it("Fails: Closing the config as a non-authority", async () => {
+      await assertRejects(
+        closeConfig(randomUser),
+        "Unauthorized",
+        "Should reject a close from outside the authority"
+      );
+    });
+
+    it("Fails: Closing the config while vaults are active", async () => {
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.ok(config.activeVaults.gtn(0));
+      await assertRejects(
+        closeConfig(admin),
+        "VaultsStillActive",
+        "Should keep the config while vaults still need it"
+      );
+
+      const after = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(after.activeVaults.toString(), config.activeVaults.toString());
+      console.log("✅ Config close refused with active vaults");
+    });
+  });
+
+  describe("Subscription Tests", () => {
+    const interval = new BN(2);
+    const amountPerInterval = new BN(150_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    // A scheduled pull carries no voucher and no owner signature.
+    const pull = async (nonce: BN) =>
+      provider.sendAndConfirm(
+        new Transaction().add(
+          await buildSettleIx(amountPerInterval, nonce, target)
+        ),
+        [facilitator]
+      );
+
+    before(async () => {
+      target = await createFundedVault();
+      await program.methods
+        .setSubscription(providerPda, interval, amountPerInterval)
+        .accounts({ agent: target.agent.publicKey, vault: target.vault } as any)
+        .signers([target.agent])
+        .rpc();
+    });
+
+    it("Fails: Pulling more than the amount per interval", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          new Transaction().add(
+            await buildSettleIx(amountPerInterval.addn(1), new BN(1), target)
+          ),
+          [facilitator]
+        ),
+        "SubscriptionAmountExceeded",
+        "Should cap a scheduled pull"
+      );
+    });
+
+    it("Pulls the first interval without a voucher", async () => {
+      await pull(new BN(1));
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.totalSettled.toString(), amountPerInterval.toString());
+      assert.ok(vault.nextAllowedTs.gt(new BN(0)));
+      console.log("✅ Scheduled pull settled");
+    });
+
+    it("Fails: Pulling again inside the interval", async () => {
+      await assertRejects(
+        pull(new BN(2)),
+        "IntervalNotElapsed",
+        "Should allow one pull per interval"
+      );
+    });
+
+    it("Pulls again once the interval rolls over", async () => {
+      await new Promise((resolve) =>
+        setTimeout(resolve, (interval.toNumber() + 1) * 1000)
+      );
+      await pull(new BN(2));
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(
+        vault.totalSettled.toString(),
+        amountPerInterval.muln(2).toString()
+      );
+      console.log("✅ Next interval pulled");
+    });
+
+    it("Fails: Pulling for a provider other than the subscriber", async () => {
+      const other = await registerTestProvider();
+      await assertRejects(
+        provider.sendAndConfirm(
+          new Transaction().add(
+            await buildSettleIx(amountPerInterval, new BN(3), {
+              ...target,
+              provider: other.provider,
+              destination: other.destination,
+            })
+          ),
+          [facilitator]
+        ),
+        "VoucherMissing",
+        "Should only waive the voucher for the subscribed provider"
+      );
+    });
+  });
+
+  describe("Commitment Tests", () => {
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const commit = (amount: BN) =>
+      program.methods
+        .commitAmount(amount)
+        .accounts({ agent: target.agent.publicKey, vault: target.vault } as any)
+        .signers([target.agent])
+        .rpc();
+
+    const settle = async (amount: BN, nonce: BN) =>
+      provider.sendAndConfirm(
+        await buildSettleTx(amount, nonce, undefined, target),
+        [facilitator]
+      );
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Settles within the committed amount", async () => {
+      await commit(new BN(300_000));
+      await settle(new BN(200_000), new BN(1));
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.committed.toString(), "300000");
+      assert.equal(vault.totalSettled.toString(), "200000");
+      console.log("✅ Committed settlement executed");
+    });
+
+    it("Fails: Settling past the committed amount", async () => {
+      await assertRejects(
+        settle(new BN(150_000), new BN(2)),
+        "CommitmentExceeded",
+        "Should cap settlements at the commitment"
+      );
+    });
+
+    it("Settles more after an incremental commitment", async () => {
+      await commit(new BN(100_000));
+      await settle(new BN(150_000), new BN(2));
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.committed.toString(), "400000");
+      assert.equal(vault.totalSettled.toString(), "350000");
+      console.log("✅ Incremental commitment settled");
+    });
+
+    it("Fails: Committing as someone other than the owner", async () => {
+      await assertRejects(
+        program.methods
+          .commitAmount(new BN(1))
+          .accounts({ agent: randomUser.publicKey, vault: target.vault } as any)
+          .signers([randomUser])
+          .rpc(),
+        "ConstraintHasOne",
+        "Should only let the owner commit"
+      );
+    });
+  });
+
+  describe("Fee Tier Tests", () => {
+    const configAccounts = () =>
+      ({ authority: admin.publicKey, globalConfig: globalConfigPda } as any);
+    const tiers = [
+      { minAmount: new BN(500_000), feeBps: 100 },
+      { minAmount: new BN(1_000_000), feeBps: 50 },
+    ];
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    async function feeCharged(amount: BN, nonce: BN): Promise<bigint> {
+      const before = await getAccount(provider.connection, feeTokenAccount);
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, nonce, undefined, target),
+        [facilitator]
+      );
+      const after = await getAccount(provider.connection, feeTokenAccount);
+      return after.amount - before.amount;
+    }
+
+    before(async () => {
+      await program.methods
+        .setFeeTiers(tiers)
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+      target = await createFundedVault();
+    });
+
+    it("Fails: Tiers out of order", async () => {
+      await assertRejects(
+        program.methods
+          .setFeeTiers([...tiers].reverse())
+          .accounts(configAccounts())
+          .signers([admin])
+          .rpc(),
+        "InvalidFeeTiers",
+        "Should require tiers sorted by amount"
+      );
+    });
+
+    it("Fails: A tier above the base fee", async () => {
+      await assertRejects(
+        program.methods
+          .setFeeTiers([{ minAmount: new BN(1), feeBps: feeBps + 1 }])
+          .accounts(configAccounts())
+          .signers([admin])
+          .rpc(),
+        "InvalidFeeTiers",
+        "Should only let tiers discount the fee"
+      );
+    });
+
+    it("Charges the base fee just below the first tier", async () => {
+      const amount = new BN(499_999);
+      const fee = await feeCharged(amount, new BN(1));
+      assert.equal(fee.toString(), amount.muln(feeBps).divn(10_000).toString());
+    });
+
+    it("Charges each tier's rate from its boundary", async () => {
+      assert.equal((await feeCharged(new BN(500_000), new BN(2))).toString(), "5000");
+      assert.equal((await feeCharged(new BN(1_000_000), new BN(3))).toString(), "5000");
+      console.log("✅ Tiered fees applied");
+    });
+
+    after(async () => {
+      await program.methods
+        .setFeeTiers([])
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+    });
+  });
+
+  describe("Relayed Withdrawal Tests", () => {
+    const amount = new BN(300_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    // The owner signs the approval off-chain; the facilitator relays it.
+    async function relayTx(
+      approvedRecipient: PublicKey,
+      recipient: PublicKey,
+      nonce: BN
+    ): Promise<Transaction> {
+      const message = Buffer.concat([
+        Buffer.from("X402_FLOW_WITHDRAW"),
+        target.vault.toBuffer(),
+        await createdSlot(target.vault),
+        amount.toArrayLike(Buffer, "le", 8),
+        approvedRecipient.toBuffer(),
+        nonce.toArrayLike(Buffer, "le", 8),
+      ]);
+      const ed25519Ix = Ed25519Program.createInstructionWithPublicKey({
+        publicKey: target.agent.publicKey.toBytes(),
+        message,
+        signature: nacl.sign.detached(message, target.agent.secretKey),
+      });
+      const relayIx = await program.methods
+        .relayWithdraw(amount, nonce)
+        .accounts({
+          relayer: facilitator.publicKey,
+          agent: target.agent.publicKey,
+          vault: target.vault,
+          globalConfig: globalConfigPda,
+          vaultTokenAccount: target.vaultTokenAccount,
+          recipient,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        } as any)
+        .instruction();
+      return new Transaction().add(ed25519Ix).add(relayIx);
+    }
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Withdraws to the approved recipient through a relayer", async () => {
+      const before = await getAccount(provider.connection, target.agentTokenAccount);
+      await provider.sendAndConfirm(
+        await relayTx(target.agentTokenAccount, target.agentTokenAccount, new BN(1)),
+        [facilitator]
+      );
+
+      const after = await getAccount(provider.connection, target.agentTokenAccount);
+      assert.equal((after.amount - before.amount).toString(), amount.toString());
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.depositAmount.toString(), depositAmount.sub(amount).toString());
+      assert.equal(vault.nonce.toString(), "1");
+      console.log("✅ Relayed withdrawal executed");
+    });
+
+    it("Fails: Replaying an approval", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await relayTx(target.agentTokenAccount, target.agentTokenAccount, new BN(1)),
+          [facilitator]
+        ),
+        "NonceReused",
+        "Should only honour an approval once"
+      );
+    });
+
+    it("Fails: Relaying to a recipient the owner did not approve", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await relayTx(target.agentTokenAccount, randomUserTokenAccount, new BN(2)),
+          [facilitator]
+        ),
+        "InvalidVoucher",
+        "Should bind the approval to its recipient"
+      );
+    });
+  });
+
+  describe("Frozen Destination Tests", () => {
+    const settleAmount = new BN(200_000);
+    let freezableMint: PublicKey;
+    let providerAuthority: Keypair;
+    let earnings: PublicKey;
+    let target: SettleTarget;
+
+    before(async () => {
+      const vaultAgent = Keypair.generate();
+      providerAuthority = Keypair.generate();
+      await airdrop(vaultAgent, providerAuthority);
+
+      // The main mint has no freeze authority.
+      freezableMint = await createMint(
+        provider.connection,
+        payer.payer,
+        admin.publicKey,
+        admin.publicKey,
+        6
+      );
+      const agentTokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        freezableMint,
+        vaultAgent.publicKey
+      );
+      const destination = await createAccount(
+        provider.connection,
+        payer.payer,
+        freezableMint,
+        providerAuthority.publicKey
+      );
+      const feeDestination = await createAccount(
+        provider.connection,
+        payer.payer,
+        freezableMint,
+        treasury.publicKey
+      );
+      await mintTo(
+        provider.connection,
+        payer.payer,
+        freezableMint,
+        agentTokenAccount,
+        admin,
+        depositAmount.toNumber()
+      );
+
+      const [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), vaultAgent.publicKey.toBuffer()],
+        program.programId
+      );
+      const [vaultTokenAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault_token_account"), vaultAgent.publicKey.toBuffer()],
+        program.programId
+      );
+      const [providerAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("provider"), providerAuthority.publicKey.toBuffer()],
+        program.programId
+      );
+      [earnings] = PublicKey.findProgramAddressSync(
+        [Buffer.from("provider_earnings"), providerAccount.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .registerProvider(null, paymentProtocol.nativeSpl, "", "", null)
+        .accounts({
+          authority: providerAuthority.publicKey,
+          provider: providerAccount,
+          destination,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          globalConfig: globalConfigPda,
+          feeDestination: treasury.publicKey,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([providerAuthority])
+        .rpc();
+      await program.methods
+        .initProviderEarnings()
+        .accounts({
+          authority: providerAuthority.publicKey,
+          provider: providerAccount,
+          earnings,
+          tokenMint: freezableMint,
+          globalConfig: globalConfigPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([providerAuthority])
+        .rpc();
+      await program.methods
+        .createVault(depositAmount, new BN(0), new BN(0), false, 0, defaultSession, true)
+        .accounts({
+          agent: vaultAgent.publicKey,
+          vault,
+          vaultTokenAccount,
+          agentTokenAccount,
+          tokenMint: freezableMint,
+          globalConfig: globalConfigPda,
+          ownerVaultCount: ownerVaultCountPda(vaultAgent.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          contribution: null,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([vaultAgent])
+        .rpc();
+
+      await freezeAccount(
+        provider.connection,
+        payer.payer,
+        destination,
+        freezableMint,
+        admin
+      );
+
+      target = {
+        agent: vaultAgent,
+        vault,
+        vaultTokenAccount,
+        provider: providerAccount,
+        destination,
+        feeDestination,
+        mint: freezableMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+    });
+
+    it("Fails: Paying a frozen destination", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(1), undefined, target),
+          [facilitator]
+        ),
+        "DestinationUnavailable",
+        "Should name the frozen destination instead of failing in the CPI"
+      );
+    });
+
+    it("Accrues to the provider's earnings while the destination is frozen", async () => {
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, {
+          ...target,
+          providerEarnings: earnings,
+        }),
+        [facilitator]
+      );
+
+      const net = settleAmount.sub(settleAmount.muln(feeBps).divn(10_000));
+      const earningsAccount = await getAccount(provider.connection, earnings);
+      assert.equal(earningsAccount.amount.toString(), net.toString());
+      const providerAccount = await program.account.provider.fetch(target.provider);
+      assert.equal(providerAccount.accrued.toString(), net.toString());
+      console.log("✅ Frozen destination settlement accrued");
+    });
+  });
+
+  describe("Vault Chaining Tests", () => {
+    const settleAmount = new BN(400_000);
+    let payee: Awaited<ReturnType<typeof registerTestProvider>>;
+    let upstream: Awaited<ReturnType<typeof createFundedVault>>;
+    let downstream: Awaited<ReturnType<typeof createFundedVault>>;
+    let chained: SettleTarget;
+
+    before(async () => {
+      payee = await registerTestProvider();
+      [upstream, downstream] = [
+        await createFundedVault(),
+        await createFundedVault(),
+      ];
+      // The provider opts in to being paid into the downstream vault.
+      await program.methods
+        .addDestination(downstream.vaultTokenAccount)
+        .accounts({
+          authority: payee.authority.publicKey,
+          provider: payee.provider,
+        } as any)
+        .signers([payee.authority])
+        .rpc();
+      await new Promise((resolve) =>
+        setTimeout(resolve, (timelockSeconds.toNumber() + 1) * 1000)
+      );
+      chained = {
+        ...upstream,
+        provider: payee.provider,
+        destination: downstream.vaultTokenAccount,
+        destinationOverride: downstream.vaultTokenAccount,
+        downstreamVault: downstream.vault,
+      };
+    });
+
+    it("Fails: Crediting the vault being settled from", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(1), undefined, {
+            ...chained,
+            downstreamVault: upstream.vault,
+          }),
+          [facilitator]
+        ),
+        "DownstreamVaultIsSource",
+        "Should not credit the source vault back"
+      );
+    });
+
+    it("Fails: Crediting a vault that does not own the destination", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(1), undefined, {
+            ...chained,
+            downstreamVault: mainTarget().vault,
+          }),
+          [facilitator]
+        ),
+        "DownstreamVaultMismatch",
+        "Should only credit the vault the payment lands in"
+      );
+    });
+
+    it("Fails: Crediting a vault the settlement does not pay", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleAmount, new BN(1), undefined, {
+            ...chained,
+            providerAuthority: payee.authority.publicKey,
+          }),
+          [facilitator]
+        ),
+        "DownstreamVaultNotPaid",
+        "Should not credit a vault when the payment goes to the provider's ATA"
+      );
+    });
+
+    it("Settles from one vault straight into another", async () => {
+      const before = await getAccount(provider.connection, downstream.vaultTokenAccount);
+      await provider.sendAndConfirm(
+        await buildSettleTx(settleAmount, new BN(1), undefined, chained),
+        [facilitator]
+      );
+
+      const net = settleAmount.sub(settleAmount.muln(feeBps).divn(10_000));
+      const after = await getAccount(provider.connection, downstream.vaultTokenAccount);
+      assert.equal((after.amount - before.amount).toString(), net.toString());
+      const vault = await program.account.vault.fetch(downstream.vault);
+      assert.equal(vault.depositAmount.toString(), depositAmount.add(net).toString());
+      const source = await program.account.vault.fetch(upstream.vault);
+      assert.equal(source.totalSettled.toString(), settleAmount.toString());
+      console.log("✅ Vault-to-vault settlement credited");
+    });
+  });
+
+  describe("Fee Rounding Tests", () => {
+    const configAccounts = () =>
+      ({ authority: admin.publicKey, globalConfig: globalConfigPda } as any);
+    // 250 bps of these leaves 0.1 and 0.5 of a base unit over.
+    const tenthOver = new BN(100_004);
+    const halfOver = new BN(100_020);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+    let nonce = 0;
+
+    const setRounding = (feeRounding: object) =>
+      program.methods
+        .updateConfig({ ...configParams(), feeRounding } as any)
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+
+    // Rounding that takes more is a fee increase, so it waits out the
+    // timelock like a higher fee_bps.
+    async function raiseRounding(feeRounding: object) {
+      await program.methods
+        .proposeFeeIncrease(feeBps, new BN(0), feeRounding as any)
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+      await new Promise((resolve) =>
+        setTimeout(resolve, (timelockSeconds.toNumber() + 1) * 1000)
+      );
+      await program.methods
+        .applyFeeIncrease()
+        .accounts(configAccounts())
+        .signers([admin])
+        .rpc();
+    }
+
+    async function feeCharged(amount: BN): Promise<bigint> {
+      const before = await getAccount(provider.connection, feeTokenAccount);
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, new BN(++nonce), undefined, target),
+        [facilitator]
+      );
+      const after = await getAccount(provider.connection, feeTokenAccount);
+      return after.amount - before.amount;
+    }
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Floors the fee by default", async () => {
+      assert.equal((await feeCharged(tenthOver)).toString(), "2500");
+    });
+
+    it("Fails: Rounding fees up through update_config", async () => {
+      await assertRejects(
+        setRounding({ ceil: {} }),
+        "TimelockRequired",
+        "Floor to Ceil should need the timelock"
+      );
+      await assertRejects(
+        setRounding({ nearest: {} }),
+        "TimelockRequired",
+        "Floor to Nearest should need the timelock"
+      );
+    });
+
+    it("Rounds the fee up with Ceil", async () => {
+      await raiseRounding({ ceil: {} });
+      assert.equal((await feeCharged(tenthOver)).toString(), "2501");
+    });
+
+    it("Rounds the fee to the nearest unit with Nearest", async () => {
+      await setRounding({ nearest: {} });
+      assert.equal((await feeCharged(tenthOver)).toString(), "2500");
+      assert.equal((await feeCharged(halfOver)).toString(), "2501");
+      console.log("✅ Fee rounding modes applied");
+    });
+
+    it("Fails: Moving from Nearest back to Ceil without the timelock", async () => {
+      await assertRejects(
+        setRounding({ ceil: {} }),
+        "TimelockRequired",
+        "Nearest to Ceil should need the timelock"
+      );
+    });
+
+    after(async () => {
+      await setRounding({ floor: {} });
+    });
+  });
+
+  describe("Settlement Limit Tests", () => {
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const settle = async (amount: BN, nonce: BN) =>
+      provider.sendAndConfirm(
+        await buildSettleTx(amount, nonce, undefined, target),
+        [facilitator]
+      );
+
+    before(async () => {
+      target = await createFundedVault(depositAmount, new BN(0), new BN(0), false, 2);
+    });
+
+    it("Settles up to the vault's limit", async () => {
+      await settle(new BN(200_000), new BN(1));
+      await settle(new BN(200_000), new BN(2));
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.maxSettlements, 2);
+      assert.equal(vault.settlementCount, 2);
+      console.log("✅ Settled up to the limit");
+    });
+
+    it("Fails: Settling past the limit", async () => {
+      await assertRejects(
+        settle(new BN(200_000), new BN(3)),
+        "SettlementLimitReached",
+        "Should stop settlements at the limit"
+      );
+    });
+
+    it("Owner can still withdraw the remainder", async () => {
+      const vaultBefore = await program.account.vault.fetch(target.vault);
+      const remainder = vaultBefore.depositAmount.sub(vaultBefore.totalSettled);
+      await program.methods
+        .withdraw(remainder)
+        .accounts({
+          agent: target.agent.publicKey,
+          vault: target.vault,
+          globalConfig: globalConfigPda,
+          vaultTokenAccount: target.vaultTokenAccount,
+          agentTokenAccount: target.agentTokenAccount,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([target.agent])
+        .rpc();
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.depositAmount.toString(), vault.totalSettled.toString());
+      console.log("✅ Remainder withdrawn after the limit");
+    });
+  });
+
+  describe("Batch Provider Registration Tests", () => {
+    const providerPdaFor = (authority: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("provider"), authority.toBuffer()],
+        program.programId
+      )[0];
+
+    it("Registers new providers and skips an existing one", async () => {
+      const existing = await registerTestProvider();
+      const fresh = [Keypair.generate(), Keypair.generate()];
+      const entries = [{ authority: existing.authority, destination: existing.destination }];
+      for (const authority of fresh) {
+        const destination = await createAccount(
+          provider.connection,
+          payer.payer,
+          mint,
+          authority.publicKey
+        );
+        entries.push({ authority, destination });
+      }
+
+      const method = program.methods
+        .registerProvidersBatch(paymentProtocol.nativeSpl)
+        .accounts({
+          payer: payer.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          globalConfig: globalConfigPda,
+          feeDestination: treasury.publicKey,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .remainingAccounts(
+          entries.flatMap(({ authority, destination }) => [
+            { pubkey: authority.publicKey, isSigner: true, isWritable: false },
+            { pubkey: providerPdaFor(authority.publicKey), isSigner: false, isWritable: true },
+            { pubkey: destination, isSigner: false, isWritable: false },
+          ])
+        )
+        .signers(entries.map(({ authority }) => authority));
+      const simulated = await method.simulate();
+      await method.rpc();
+
+      // Bit 0 clear: the existing provider was skipped.
+      const returnLog = simulated.raw.find((log) =>
+        log.startsWith("Program return: ")
+      );
+      const bitmap = Buffer.from(returnLog.split(" ").pop(), "base64").readUInt8(0);
+      assert.equal(bitmap, 0b110);
+
+      for (const { authority, destination } of entries.slice(1)) {
+        const created = await program.account.provider.fetch(
+          providerPdaFor(authority.publicKey)
+        );
+        assert.ok(created.authority.equals(authority.publicKey));
+        assert.ok(created.destination.equals(destination));
+        assert.equal(created.version, 6);
+      }
+      console.log("✅ Batch registered two providers and skipped one");
+    });
+
+    it("Fails: Registering for an authority that did not sign", async () => {
+      const authority = Keypair.generate();
+      const destination = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        authority.publicKey
+      );
+      await assertRejects(
+        program.methods
+          .registerProvidersBatch(paymentProtocol.nativeSpl)
+          .accounts({
+            payer: payer.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            globalConfig: globalConfigPda,
+            feeDestination: treasury.publicKey,
+            systemProgram: SystemProgram.programId,
+          } as any)
+          .remainingAccounts([
+            { pubkey: authority.publicKey, isSigner: false, isWritable: false },
+            { pubkey: providerPdaFor(authority.publicKey), isSigner: false, isWritable: true },
+            { pubkey: destination, isSigner: false, isWritable: false },
+          ])
+          .rpc(),
+        "AccountNotSigner",
+        "Should require each authority's signature"
+      );
+    });
+  });
+
+  describe("Registration Fee Tests", () => {
+    const registrationFee = new BN(100_000_000);
+
+    const setRegistrationFee = (fee: BN) =>
+      program.methods
+        .updateConfig({ ...configParams(), registrationFee: fee })
+        .accounts({ authority: admin.publicKey, globalConfig: globalConfigPda } as any)
+        .signers([admin])
+        .rpc();
+
+    async function register(authority: Keypair) {
+      const destination = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        authority.publicKey
+      );
+      const [providerAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("provider"), authority.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .registerProvider(null, paymentProtocol.nativeSpl, "", "", null)
+        .accounts({
+          authority: authority.publicKey,
+          provider: providerAccount,
+          destination,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          globalConfig: globalConfigPda,
+          feeDestination: treasury.publicKey,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([authority])
+        .rpc();
+      return providerAccount;
+    }
+
+    it("Registers without a charge while the fee is zero", async () => {
+      const authority = Keypair.generate();
+      await airdrop(authority);
+      const before = await provider.connection.getBalance(treasury.publicKey);
+      await register(authority);
+
+      const after = await provider.connection.getBalance(treasury.publicKey);
+      assert.equal(after, before);
+      console.log("✅ Zero-fee registration charged nothing");
+    });
+
+    it("Collects the registration fee", async () => {
+      await setRegistrationFee(registrationFee);
+      const authority = Keypair.generate();
+      await airdrop(authority);
+      const before = await provider.connection.getBalance(treasury.publicKey);
+      const providerAccount = await register(authority);
+
+      const after = await provider.connection.getBalance(treasury.publicKey);
+      assert.equal(after - before, registrationFee.toNumber());
+      const created = await program.account.provider.fetch(providerAccount);
+      assert.ok(created.authority.equals(authority.publicKey));
+      console.log("✅ Registration fee collected");
+    });
+
+    it("Fails: Authority cannot cover the registration fee", async () => {
+      // Airdrops leave the authority with 2 SOL.
+      await setRegistrationFee(new BN(3_000_000_000));
+      const authority = Keypair.generate();
+      await airdrop(authority);
+      await assertRejects(
+        register(authority),
+        "InsufficientRegistrationFee",
+        "Should refuse a registration the authority cannot pay for"
+      );
+    });
+
+    after(async () => {
+      await setRegistrationFee(new BN(0));
+    });
+  });
+
+  describe("Provider Fee Source Tests", () => {
+    const amount = new BN(200_000);
+    const fee = amount.muln(feeBps).divn(10_000);
+    let payee: Awaited<ReturnType<typeof registerTestProvider>>;
+    let target: SettleTarget;
+    let providerFeeAccount: PublicKey;
+
+    // A provider-owned token account with the provider PDA approved to spend it.
+    async function approvedFeeAccount(
+      balance: number,
+      allowance: number = 1_000_000
+    ): Promise<PublicKey> {
+      const account = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        payee.authority.publicKey,
+        Keypair.generate()
+      );
+      if (balance > 0) {
+        await mintTo(provider.connection, payer.payer, mint, account, admin, balance);
+      }
+      await approve(
+        provider.connection,
+        payer.payer,
+        account,
+        payee.provider,
+        payee.authority,
+        allowance
+      );
+      return account;
+    }
+
+    async function balances() {
+      const [destination, feeSource, fees] = await Promise.all(
+        [payee.destination, providerFeeAccount, feeTokenAccount].map((account) =>
+          getAccount(provider.connection, account)
+        )
+      );
+      return { destination: destination.amount, feeSource: feeSource.amount, fees: fees.amount };
+    }
+
+    before(async () => {
+      payee = await registerTestProvider();
+      target = {
+        ...(await createFundedVault()),
+        provider: payee.provider,
+        destination: payee.destination,
+      };
+      providerFeeAccount = await approvedFeeAccount(1_000_000);
+    });
+
+    it("Nets the fee out of the amount by default", async () => {
+      const before = await balances();
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, new BN(1), undefined, target),
+        [facilitator]
+      );
+
+      const after = await balances();
+      assert.equal((after.destination - before.destination).toString(), amount.sub(fee).toString());
+      assert.equal((after.fees - before.fees).toString(), fee.toString());
+      assert.equal(after.feeSource, before.feeSource);
+      console.log("✅ Fee netted from the settled amount");
+    });
+
+    it("Pays the whole amount when the provider covers the fee", async () => {
+      const before = await balances();
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, new BN(2), undefined, {
+          ...target,
+          feeFrom: { providerPays: {} },
+          providerFeeAccount,
+        }),
+        [facilitator]
+      );
+
+      const after = await balances();
+      assert.equal((after.destination - before.destination).toString(), amount.toString());
+      assert.equal((after.fees - before.fees).toString(), fee.toString());
+      assert.equal((before.feeSource - after.feeSource).toString(), fee.toString());
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.totalSettled.toString(), amount.muln(2).toString());
+      console.log("✅ Provider-funded fee left the gross to the destination");
+    });
+
+    it("Fails: Provider fee account cannot cover the fee", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(amount, new BN(3), undefined, {
+            ...target,
+            feeFrom: { providerPays: {} },
+            providerFeeAccount: await approvedFeeAccount(0),
+          }),
+          [facilitator]
+        ),
+        "InsufficientFeeFunds",
+        "Should check the provider can pay the fee"
+      );
+    });
+
+    it("Fails: Fee account not owned by the provider authority", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(amount, new BN(3), undefined, {
+            ...target,
+            feeFrom: { providerPays: {} },
+            providerFeeAccount: randomUserTokenAccount,
+          }),
+          [facilitator]
+        ),
+        "InvalidFeeAccountOwner",
+        "Should only pull the fee from the provider's own account"
+      );
+    });
+
+    it("Fails: Provider-paid fee without a fee account", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(amount, new BN(3), undefined, {
+            ...target,
+            feeFrom: { providerPays: {} },
+          }),
+          [facilitator]
+        ),
+        "ProviderFeeAccountRequired",
+        "Should need the account the fee is pulled from"
+      );
+    });
+
+    it("Fails: Fee account without a large enough approval", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(amount, new BN(3), undefined, {
+            ...target,
+            feeFrom: { providerPays: {} },
+            providerFeeAccount: await approvedFeeAccount(1_000_000, fee.toNumber() - 1),
+          }),
+          [facilitator]
+        ),
+        "FeeAccountNotApproved",
+        "Should check the provider PDA may spend the fee"
+      );
+    });
+  });
+
+  describe("Session Vault Tests", () => {
+    const sessionId = Array.from(Keypair.generate().publicKey.toBytes().subarray(0, 16));
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    before(async () => {
+      target = await createFundedVault(
+        depositAmount,
+        new BN(0),
+        new BN(0),
+        false,
+        0,
+        sessionId
+      );
+    });
+
+    it("Derives the vault from the owner and session id", async () => {
+      const [derived] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), target.agent.publicKey.toBuffer(), Buffer.from(sessionId)],
+        program.programId
+      );
+      assert.ok(target.vault.equals(derived));
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.deepEqual(Array.from(vault.sessionId), sessionId);
+      console.log("✅ Session vault found from its id");
+    });
+
+    it("Settles against the session vault", async () => {
+      await provider.sendAndConfirm(
+        await buildSettleTx(new BN(200_000), new BN(1), undefined, target),
+        [facilitator]
+      );
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.totalSettled.toString(), "200000");
+      console.log("✅ Session vault settled");
+    });
+  });
+
+  describe("Withdrawal Accounting Tests", () => {
+    const amount = new BN(200_000);
+    const fee = amount.muln(feeBps).divn(10_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Reports lifetime fees on withdraw", async () => {
+      for (const nonce of [1, 2]) {
+        await provider.sendAndConfirm(
+          await buildSettleTx(amount, new BN(nonce), undefined, target),
+          [facilitator]
+        );
+      }
+      const remainder = depositAmount.sub(amount.muln(2));
+
+      let listener = null;
+      const event = await new Promise<any>(async (resolve, reject) => {
+        listener = program.addEventListener("withdrawn", resolve);
+        program.methods
+          .withdraw(remainder)
+          .accounts({
+            agent: target.agent.publicKey,
+            vault: target.vault,
+            globalConfig: globalConfigPda,
+            vaultTokenAccount: target.vaultTokenAccount,
+            agentTokenAccount: target.agentTokenAccount,
+            tokenMint: mint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          } as any)
+          .signers([target.agent])
+          .rpc()
+          .catch(reject);
+      });
+      await program.removeEventListener(listener);
+
+      assert.equal(event.lifetimeFeesPaid.toString(), fee.muln(2).toString());
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.lifetimeFeesPaid.toString(), fee.muln(2).toString());
+      console.log("✅ Withdrawal reported lifetime fees");
+    });
+  });
+
+  describe("Config Authority Rotation Tests", () => {
+    const newAdmin = Keypair.generate();
+
+    const propose = (from: Keypair, to: PublicKey) =>
+      program.methods
+        .updateAuthority()
+        .accounts({
+          authority: from.publicKey,
+          globalConfig: globalConfigPda,
+          newAuthority: to,
+        } as any)
+        .signers([from])
+        .rpc();
+
+    const accept = (by: Keypair) =>
+      program.methods
+        .acceptAuthority()
+        .accounts({ newAuthority: by.publicKey, globalConfig: globalConfigPda } as any)
+        .signers([by])
+        .rpc();
+
+    it("Keeps the old authority in control until the proposal is accepted", async () => {
+      await propose(admin, newAdmin.publicKey);
+
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.ok(config.admin.equals(admin.publicKey));
+      assert.ok(config.pendingAdmin.equals(newAdmin.publicKey));
+
+      await program.methods
+        .cancelAuthorityUpdate()
+        .accounts({ authority: admin.publicKey, globalConfig: globalConfigPda } as any)
+        .signers([admin])
+        .rpc();
+      const cancelled = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.isNull(cancelled.pendingAdmin);
+      console.log("✅ Pending rotation cancelled by the current authority");
+    });
+
+    it("Fails: A key that was not proposed accepting", async () => {
+      await propose(admin, newAdmin.publicKey);
+      await assertRejects(
+        accept(randomUser),
+        "InvalidPendingAuthority",
+        "Should only let the pending key accept"
+      );
+    });
+
+    it("Rotates the authority once the new key accepts", async () => {
+      await accept(newAdmin);
+
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.ok(config.admin.equals(newAdmin.publicKey));
+      assert.isNull(config.pendingAdmin);
+      await assertRejects(
+        propose(admin, admin.publicKey),
+        "Unauthorized",
+        "The retired key should lose control"
+      );
+      console.log("✅ Config authority rotated");
+    });
+
+    after(async () => {
+      await propose(newAdmin, admin.publicKey);
+      await accept(admin);
+    });
+  });
+
+  describe("Provider Token Program Tests", () => {
+    async function register(tokenProgram: PublicKey) {
+      const authority = Keypair.generate();
+      await airdrop(authority);
+      const destination = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        authority.publicKey
+      );
+      const [providerAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("provider"), authority.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .registerProvider(null, paymentProtocol.nativeSpl, "", "", null)
+        .accounts({
+          authority: authority.publicKey,
+          provider: providerAccount,
+          destination,
+          tokenProgram,
+          globalConfig: globalConfigPda,
+          feeDestination: treasury.publicKey,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([authority])
+        .rpc();
+      return providerAccount;
+    }
+
+    it("Registers a destination owned by the matching token program", async () => {
+      const providerAccount = await register(TOKEN_PROGRAM_ID);
+      const registered = await program.account.provider.fetch(providerAccount);
+      assert.equal(registered.version, 6);
+      console.log("✅ Legacy destination registered under the legacy program");
+    });
+
+    it("Fails: Legacy token account registered for Token-2022", async () => {
+      await assertRejects(
+        register(TOKEN_2022_PROGRAM_ID),
+        "TokenProgramMismatch",
+        "Should refuse a destination from another token program"
+      );
+    });
+  });
+
+  describe("Settlement Simulation Tests", () => {
+    const amount = new BN(500_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const simulate = (settleAmount: BN, nonce: BN) =>
+      program.methods
+        .simulateSettle(settleAmount, nonce)
+        .accounts({
+          vault: target.vault,
+          globalConfig: globalConfigPda,
+          provider: target.provider,
+          nonceBitmap: null,
+        } as any)
+        .view();
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Previews exactly what the settlement then does", async () => {
+      const preview = await simulate(amount, new BN(1));
+      const unchanged = await program.account.vault.fetch(target.vault);
+      assert.equal(unchanged.totalSettled.toNumber(), 0);
+
+      const feesBefore = await getAccount(provider.connection, feeTokenAccount);
+      const destinationBefore = await getAccount(provider.connection, target.destination);
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, new BN(1), undefined, target),
+        [facilitator]
+      );
+      const feesAfter = await getAccount(provider.connection, feeTokenAccount);
+      const destinationAfter = await getAccount(provider.connection, target.destination);
+      const vault = await program.account.vault.fetch(target.vault);
+
+      assert.equal(preview.gross.toString(), amount.toString());
+      assert.equal(preview.fee.toString(), (feesAfter.amount - feesBefore.amount).toString());
+      assert.equal(
+        preview.net.toString(),
+        (destinationAfter.amount - destinationBefore.amount).toString()
+      );
+      assert.equal(
+        preview.resultingBalance.toString(),
+        vault.depositAmount.sub(vault.totalSettled).toString()
+      );
+      assert.isFalse(preview.wouldCrossThreshold);
+      console.log("✅ Preview matched the settlement");
+    });
+
+    it("Flags a settlement that leaves less than the threshold", async () => {
+      const remaining = depositAmount.sub(amount).sub(settleThreshold).addn(1);
+      const preview = await simulate(remaining, new BN(2));
+      assert.isTrue(preview.wouldCrossThreshold);
+    });
+
+    it("Fails: Previewing a settlement that would be refused", async () => {
+      await assertRejects(
+        simulate(amount, new BN(1)),
+        "NonceReused",
+        "Should run the same nonce check as settle_batch"
+      );
+    });
+  });
+
+  describe("Vault Cap Tests", () => {
+    const cap = 2;
+    const owner = Keypair.generate();
+    let ownerTokenAccount: PublicKey;
+
+    const setVaultCap = (maxVaultsPerOwner: number) =>
+      program.methods
+        .updateConfig({ ...configParams(), maxVaultsPerOwner })
+        .accounts({ authority: admin.publicKey, globalConfig: globalConfigPda } as any)
+        .signers([admin])
+        .rpc();
+
+    function sessionAccounts(session: number) {
+      const sessionId = Array(16).fill(0);
+      sessionId[15] = session;
+      const [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), owner.publicKey.toBuffer(), Buffer.from(sessionId)],
+        program.programId
+      );
+      const [vaultTokenAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault_token_account"), owner.publicKey.toBuffer(), Buffer.from(sessionId)],
+        program.programId
+      );
+      return { sessionId, vault, vaultTokenAccount };
+    }
+
+    const openSession = (session: number) => {
+      const { sessionId, vault, vaultTokenAccount } = sessionAccounts(session);
+      return program.methods
+        .createVault(depositAmount, new BN(0), new BN(0), false, 0, sessionId, true)
+        .accounts({
+          agent: owner.publicKey,
+          vault,
+          vaultTokenAccount,
+          agentTokenAccount: ownerTokenAccount,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          ownerVaultCount: ownerVaultCountPda(owner.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          contribution: null,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([owner])
+        .rpc();
+    };
+
+    const openCount = async () =>
+      (await program.account.ownerVaultCount.fetch(ownerVaultCountPda(owner.publicKey))).count;
+
+    before(async () => {
+      await airdrop(owner);
+      ownerTokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        owner.publicKey
+      );
+      await mintTo(
+        provider.connection,
+        payer.payer,
+        mint,
+        ownerTokenAccount,
+        admin,
+        10_000_000
+      );
+      await setVaultCap(cap);
+    });
+
+    after(async () => {
+      await setVaultCap(0);
+    });
+
+    it("Creates vaults up to the per-owner cap", async () => {
+      for (let session = 1; session <= cap; session++) {
+        await openSession(session);
+      }
+      assert.equal(await openCount(), cap);
+      console.log("✅ Owner opened vaults up to the cap");
+    });
+
+    it("Fails: Creating a vault past the cap", async () => {
+      await assertRejects(
+        openSession(cap + 1),
+        "TooManyVaults",
+        "Should reject a vault past the per-owner cap"
+      );
+      console.log("✅ Vault past the cap rejected");
+    });
+
+    it("Frees a slot once a vault is closed", async () => {
+      const { vault, vaultTokenAccount } = sessionAccounts(1);
+      await program.methods
+        .withdraw(depositAmount)
+        .accounts({
+          agent: owner.publicKey,
+          vault,
+          globalConfig: globalConfigPda,
+          vaultTokenAccount,
+          agentTokenAccount: ownerTokenAccount,
+          tokenMint: mint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([owner])
+        .rpc();
+      await program.methods
+        .closeVault()
+        .accounts({
+          agent: owner.publicKey,
+          vault,
+          vaultTokenAccount,
+          globalConfig: globalConfigPda,
+          ownerVaultCount: ownerVaultCountPda(owner.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([owner])
+        .rpc();
+      assert.equal(await openCount(), cap - 1);
+
+      await openSession(cap + 1);
+      assert.equal(await openCount(), cap);
+      console.log("✅ Closing a vault let the owner open another");
+    });
+  });
+
+  describe("Voucher Accrual Tests", () => {
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const accrue = (amount: BN) =>
+      program.methods
+        .accrue(amount)
+        .accounts({ agent: target.agent.publicKey, vault: target.vault } as any)
+        .signers([target.agent])
+        .rpc();
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Fails: Settling before enough vouchers have accrued", async () => {
+      await accrue(settleThreshold.subn(1));
+
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleThreshold.muln(2), new BN(1), undefined, target),
+          [facilitator]
+        ),
+        "BelowSettleThreshold",
+        "Should wait for accrued vouchers to reach the threshold"
+      );
+      console.log("✅ Settlement held back below the accrued threshold");
+    });
+
+    it("Settles once accrued vouchers reach the threshold", async () => {
+      await accrue(new BN(1));
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.accumulatedVouchers.toString(), settleThreshold.toString());
+
+      // The gate is on accrued demand, so the settlement itself may be
+      // smaller than the threshold.
+      const amount = settleThreshold.divn(2);
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, new BN(2), undefined, target),
+        [facilitator]
+      );
+      const after = await program.account.vault.fetch(target.vault);
+      assert.equal(after.totalSettled.toString(), amount.toString());
+      console.log("✅ Settled against accrued vouchers");
+    });
+
+    it("Fails: Settling again once the accrued surplus is spent", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(settleThreshold, new BN(3), undefined, target),
+          [facilitator]
+        ),
+        "BelowSettleThreshold",
+        "Should count already settled vouchers against the accrued total"
+      );
+      console.log("✅ Settled vouchers no longer count toward the threshold");
+    });
+  });
+
+  describe("Associated Token Payout Tests", () => {
+    const amount = new BN(200_000);
+    const authority = Keypair.generate();
+    let providerAccount: PublicKey;
+    let registeredDestination: PublicKey;
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    before(async () => {
+      await airdrop(authority);
+      // A plain token account rather than the ATA, so the ATA starts out
+      // missing.
+      registeredDestination = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        authority.publicKey,
+        Keypair.generate()
+      );
+      [providerAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("provider"), authority.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .registerProvider(null, paymentProtocol.nativeSpl, "", "", null)
+        .accounts({
+          authority: authority.publicKey,
+          provider: providerAccount,
+          destination: registeredDestination,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          globalConfig: globalConfigPda,
+          feeDestination: treasury.publicKey,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([authority])
+        .rpc();
+      target = await createFundedVault();
+    });
+
+    it("Settles to an associated token account created on the fly", async () => {
+      const ata = getAssociatedTokenAddressSync(mint, authority.publicKey);
+      assert.isNull(await provider.connection.getAccountInfo(ata));
+
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, new BN(1), undefined, {
+          ...target,
+          provider: providerAccount,
+          destination: registeredDestination,
+          providerAuthority: authority.publicKey,
+        }),
+        [facilitator]
+      );
+
+      const account = await getAccount(provider.connection, ata);
+      assert.ok(account.owner.equals(authority.publicKey));
+      assert.ok(account.mint.equals(mint));
+      const fee = amount.muln(feeBps).divn(10_000);
+      assert.equal(account.amount.toString(), amount.sub(fee).toString());
+      const registered = await getAccount(provider.connection, registeredDestination);
+      assert.equal(registered.amount.toString(), "0");
+      console.log("✅ Provider authority's ATA created and paid");
+    });
+
+    it("Fails: Deriving the ATA from another authority", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(amount, new BN(2), undefined, {
+            ...target,
+            provider: providerAccount,
+            destination: registeredDestination,
+            providerAuthority: providerAuthority.publicKey,
+          }),
+          [facilitator]
+        ),
+        "ProviderAuthorityMismatch",
+        "Should only pay the provider authority's own ATA"
+      );
+      console.log("✅ ATA of a different authority rejected");
+    });
+
+    it("Fails: Paying both the earnings account and the ATA", async () => {
+      const [earnings] = PublicKey.findProgramAddressSync(
+        [Buffer.from("provider_earnings"), providerAccount.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .initProviderEarnings()
+        .accounts({
+          authority: authority.publicKey,
+          provider: providerAccount,
+          earnings,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([authority])
+        .rpc();
+
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(amount, new BN(2), undefined, {
+            ...target,
+            provider: providerAccount,
+            destination: registeredDestination,
+            providerEarnings: earnings,
+            providerAuthority: authority.publicKey,
+          }),
+          [facilitator]
+        ),
+        "ConflictingPayees",
+        "Should pay only one of the two"
+      );
+    });
+  });
+
+  describe("Event Sequence Tests", () => {
+    // Collects the `seq` of every event of the given kinds that one call emits.
+    async function seqsOf(
+      eventNames: string[],
+      call: () => Promise<string>
+    ): Promise<number[]> {
+      const seqs: number[] = [];
+      const listeners = eventNames.map((name) =>
+        program.addEventListener(name as any, (event: any) =>
+          seqs.push(event.seq.toNumber())
+        )
+      );
+      await call();
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+      await Promise.all(listeners.map((id) => program.removeEventListener(id)));
+      return seqs;
+    }
+
+    it("Numbers every event one past the previous", async () => {
+      const target = await createFundedVault();
+      const start = (await program.account.globalConfig.fetch(globalConfigPda)).eventSeq.toNumber();
+
+      const seqs = [
+        ...(await seqsOf(["vaultDelegateUpdated"], () =>
+          program.methods
+            .setDelegate(facilitator.publicKey)
+            .accounts({ agent: target.agent.publicKey, vault: target.vault } as any)
+            .signers([target.agent])
+            .rpc()
+        )),
+        ...(await seqsOf(["settlement"], async () =>
+          provider.sendAndConfirm(
+            await buildSettleTx(new BN(200_000), new BN(1), undefined, target),
+            [facilitator]
+          )
+        )),
+        ...(await seqsOf(["amountCommitted"], () =>
+          program.methods
+            .commitAmount(new BN(500_000))
+            .accounts({ agent: target.agent.publicKey, vault: target.vault } as any)
+            .signers([target.agent])
+            .rpc()
+        )),
+        ...(await seqsOf(["withdrawn"], () =>
+          program.methods
+            .withdraw(new BN(100_000))
+            .accounts({
+              agent: target.agent.publicKey,
+              vault: target.vault,
+              globalConfig: globalConfigPda,
+              vaultTokenAccount: target.vaultTokenAccount,
+              agentTokenAccount: target.agentTokenAccount,
+              tokenMint: mint,
+              tokenProgram: TOKEN_PROGRAM_ID,
+            } as any)
+            .signers([target.agent])
+            .rpc()
+        )),
+      ];
+
+      assert.deepEqual(seqs, [start + 1, start + 2, start + 3, start + 4]);
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(config.eventSeq.toNumber(), start + 4);
+      console.log("✅ Event seq advanced by one per event");
+    });
+  });
+
+  describe("One-Shot Payment Tests", () => {
+    const amount = new BN(400_000);
+    const fee = amount.muln(feeBps).divn(10_000);
+    const payerKey = Keypair.generate();
+    let payerTokenAccount: PublicKey;
+    let payee: Awaited<ReturnType<typeof registerTestProvider>>;
+
+    before(async () => {
+      await airdrop(payerKey);
+      payerTokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        payerKey.publicKey
+      );
+      await mintTo(
+        provider.connection,
+        payer.payer,
+        mint,
+        payerTokenAccount,
+        admin,
+        10_000_000
+      );
+      payee = await registerTestProvider();
+    });
+
+    const payOnce = (payAmount: BN) =>
+      program.methods
+        .payOnce(payAmount)
+        .accounts({
+          payer: payerKey.publicKey,
+          payerTokenAccount,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          provider: payee.provider,
+          destination: payee.destination,
+          feeDestination: feeTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          referrer: null,
+        } as any)
+        .signers([payerKey])
+        .rpc();
+
+    it("Pays the provider net of fee without leaving an account behind", async () => {
+      const before = await provider.connection.getProgramAccounts(program.programId);
+      const feeBefore = (await getAccount(provider.connection, feeTokenAccount)).amount;
+
+      await payOnce(amount);
+
+      const payerAccount = await getAccount(provider.connection, payerTokenAccount);
+      assert.equal(payerAccount.amount.toString(), new BN(10_000_000).sub(amount).toString());
+      const destination = await getAccount(provider.connection, payee.destination);
+      assert.equal(destination.amount.toString(), amount.sub(fee).toString());
+      const feeAfter = (await getAccount(provider.connection, feeTokenAccount)).amount;
+      assert.equal((feeAfter - feeBefore).toString(), fee.toString());
+
+      const [vaultPdaForPayer] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), payerKey.publicKey.toBuffer()],
+        program.programId
+      );
+      assert.isNull(await provider.connection.getAccountInfo(vaultPdaForPayer));
+      const after = await provider.connection.getProgramAccounts(program.programId);
+      assert.equal(after.length, before.length);
+      console.log("✅ One-shot payment settled with nothing left open");
+    });
+
+    it("Fails: Paying below the settlement threshold", async () => {
+      await assertRejects(
+        payOnce(settleThreshold.subn(1)),
+        "BelowSettleThreshold",
+        "Should apply the threshold to one-shot payments"
+      );
+      console.log("✅ One-shot payment below the threshold rejected");
+    });
+  });
+
+  describe("Clawback Tests", () => {
+    const amount = new BN(400_000);
+    const net = amount.sub(amount.muln(feeBps).divn(10_000));
+    const returned = new BN(100_000);
+    let earner: Awaited<ReturnType<typeof registerTestProvider>>;
+    let earnings: PublicKey;
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const setClawbackWindow = (seconds: number) =>
+      program.methods
+        .updateConfig({ ...configParams(), clawbackSeconds: new BN(seconds) })
+        .accounts({ authority: admin.publicKey, globalConfig: globalConfigPda } as any)
+        .signers([admin])
+        .rpc();
+
+    const receiptFor = (nonce: number) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("receipt"),
+          target.vault.toBuffer(),
+          new BN(nonce).toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      )[0];
+
+    // Accrues a receipted settlement to the provider's earnings.
+    const settleToEarnings = async (nonce: number) =>
+      provider.sendAndConfirm(
+        await buildSettleTx(amount, new BN(nonce), undefined, {
+          ...target,
+          provider: earner.provider,
+          destination: earner.destination,
+          providerEarnings: earnings,
+          receipt: receiptFor(nonce),
+        }),
+        [facilitator]
+      );
+
+    const clawback = (nonce: number, signer: Keypair) =>
+      program.methods
+        .clawback(returned)
+        .accounts({
+          authority: signer.publicKey,
+          provider: earner.provider,
+          earnings,
+          receipt: receiptFor(nonce),
+          vault: target.vault,
+          vaultTokenAccount: target.vaultTokenAccount,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      earner = await registerTestProvider();
+      [earnings] = PublicKey.findProgramAddressSync(
+        [Buffer.from("provider_earnings"), earner.provider.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .initProviderEarnings()
+        .accounts({
+          authority: earner.authority.publicKey,
+          provider: earner.provider,
+          earnings,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([earner.authority])
+        .rpc();
+      target = await createFundedVault();
+    });
+
+    after(async () => {
+      await setClawbackWindow(0);
+    });
+
+    it("Returns a settlement to its vault within the window", async () => {
+      await settleToEarnings(1);
+      await setClawbackWindow(60);
+      const configBefore = await program.account.globalConfig.fetch(globalConfigPda);
+
+      await clawback(1, admin);
+
+      const configAfter = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(
+        configBefore.totalSettled.sub(configAfter.totalSettled).toString(),
+        returned.toString()
+      );
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.totalSettled.toString(), amount.sub(returned).toString());
+      const vaultTokens = await getAccount(provider.connection, target.vaultTokenAccount);
+      assert.equal(
+        vaultTokens.amount.toString(),
+        depositAmount.sub(amount).add(returned).toString()
+      );
+      const providerAccount = await program.account.provider.fetch(earner.provider);
+      assert.equal(providerAccount.accrued.toString(), net.sub(returned).toString());
+      const receipt = await program.account.settlementReceipt.fetch(receiptFor(1));
+      assert.equal(receipt.clawedBack.toString(), returned.toString());
+      console.log("✅ Clawback returned funds to the originating vault");
+    });
+
+    it("Fails: Clawing back once the window has closed", async () => {
+      await settleToEarnings(2);
+      await setClawbackWindow(1);
+      await new Promise((resolve) => setTimeout(resolve, 2000));
+
+      await assertRejects(
+        clawback(2, admin),
+        "ClawbackWindowClosed",
+        "Should refuse an authority clawback after the window"
+      );
+      console.log("✅ Late clawback rejected");
+    });
+
+    it("Lets the provider return funds after the window", async () => {
+      await clawback(2, earner.authority);
+
+      const receipt = await program.account.settlementReceipt.fetch(receiptFor(2));
+      assert.equal(receipt.clawedBack.toString(), returned.toString());
+      console.log("✅ Provider returned funds voluntarily");
+    });
+
+    it("Fails: Clawing back a settlement the provider has since claimed", async () => {
+      await program.methods
+        .claimEarnings()
+        .accounts({
+          authority: earner.authority.publicKey,
+          provider: earner.provider,
+          earnings,
+          destination: earner.destination,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([earner.authority])
+        .rpc();
+      // A later accrual refills the ledger the old receipts were paid from.
+      await new Promise((resolve) => setTimeout(resolve, 1500));
+      await settleToEarnings(3);
+
+      await assertRejects(
+        clawback(1, earner.authority),
+        "ReceiptAlreadyClaimed",
+        "A claimed settlement should not be refunded from newer earnings"
+      );
+      await clawback(3, earner.authority);
+      console.log("✅ Only unclaimed settlements can be clawed back");
+    });
+  });
+
+  describe("Token Program Pinning Tests", () => {
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Records the token program at creation", async () => {
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.ok(vault.tokenProgram.equals(TOKEN_PROGRAM_ID));
+      console.log("✅ Vault pinned to the legacy token program");
+    });
+
+    it("Fails: Settling through a different token program", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(new BN(200_000), new BN(1), undefined, {
+            ...target,
+            tokenProgram: TOKEN_2022_PROGRAM_ID,
+          }),
+          [facilitator]
+        ),
+        "InvalidTokenProgram",
+        "Should only settle through the vault's own token program"
+      );
+      console.log("✅ Swapped token program rejected");
+    });
+  });
+
+  describe("Dust Sweep Tests", () => {
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+    let expiryUnix: number;
+    const keeper = Keypair.generate();
+
+    const chainTime = async () =>
+      provider.connection.getBlockTime(
+        await provider.connection.getSlot("confirmed")
+      );
+
+    const setDustThreshold = (threshold: BN) =>
+      program.methods
+        .updateConfig({ ...configParams(), dustThreshold: threshold })
+        .accounts({ authority: admin.publicKey, globalConfig: globalConfigPda } as any)
+        .signers([admin])
+        .rpc();
+
+    const sweep = (swept: SettleTarget, ownerVaultCount?: PublicKey) =>
+      program.methods
+        .sweepDust()
+        .accounts({
+          keeper: keeper.publicKey,
+          vault: swept.vault,
+          vaultTokenAccount: swept.vaultTokenAccount,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          feeTokenAccount,
+          feeDestination: treasury.publicKey,
+          ownerVaultCount: ownerVaultCount ?? ownerVaultCountPda(swept.agent.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([keeper])
+        .rpc();
+
+    before(async () => {
+      await airdrop(keeper);
+      expiryUnix = (await chainTime()) + 2;
+      target = await createFundedVault(depositAmount, new BN(expiryUnix));
+      await setDustThreshold(depositAmount.addn(1));
+    });
+
+    it("Fails: Sweeping a live vault", async () => {
+      await assertRejects(
+        sweep(mainTarget()),
+        "VaultNotExpired",
+        "A vault without an expiry is never swept"
+      );
+      await assertRejects(
+        sweep(target),
+        "VaultNotExpired",
+        "A vault is not swept before it expires"
+      );
+      console.log("✅ Live vaults left alone");
+    });
+
+    it("Sweeps an expired dust vault to the fee destination", async () => {
+      while ((await chainTime()) < expiryUnix) {
+        await new Promise((resolve) => setTimeout(resolve, 500));
+      }
+
+      await assertRejects(
+        sweep(target, ownerVaultCountPda(keeper.publicKey)),
+        "ConstraintSeeds",
+        "The creator's vault count cannot be swapped out"
+      );
+
+      const countPda = ownerVaultCountPda(target.agent.publicKey);
+      const countBefore = (await program.account.ownerVaultCount.fetch(countPda)).count;
+      const feeBefore = await getAccount(provider.connection, feeTokenAccount);
+      const keeperBefore = await provider.connection.getBalance(keeper.publicKey);
+      await sweep(target);
+      const feeAfter = await getAccount(provider.connection, feeTokenAccount);
+      const keeperAfter = await provider.connection.getBalance(keeper.publicKey);
+
+      assert.equal(
+        (feeAfter.amount - feeBefore.amount).toString(),
+        depositAmount.toString()
+      );
+      assert.isAbove(keeperAfter, keeperBefore);
+      assert.isNull(await provider.connection.getAccountInfo(target.vault));
+      assert.isNull(await provider.connection.getAccountInfo(target.vaultTokenAccount));
+      const countAfter = (await program.account.ownerVaultCount.fetch(countPda)).count;
+      assert.equal(countAfter, countBefore - 1);
+      console.log("✅ Dust swept and the vault closed");
+    });
+
+    after(async () => {
+      await setDustThreshold(new BN(0));
+    });
+  });
+
+  describe("Create Vault Error Tests", () => {
+    // Creates a default-session vault for a fresh owner, optionally passing
+    // the owner's contribution account.
+    async function createVault(
+      withdrawTimelock: BN,
+      shared: boolean,
+      withContribution: boolean
+    ) {
+      const owner = Keypair.generate();
+      await airdrop(owner);
+      const ownerTokenAccount = await createAccount(
+        provider.connection,
+        payer.payer,
+        mint,
+        owner.publicKey
+      );
+      await mintTo(
+        provider.connection,
+        payer.payer,
+        mint,
+        ownerTokenAccount,
+        admin,
+        depositAmount.toNumber()
+      );
+      const [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), owner.publicKey.toBuffer()],
+        program.programId
+      );
+      const [vaultTokenAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault_token_account"), owner.publicKey.toBuffer()],
+        program.programId
+      );
+      return program.methods
+        .createVault(depositAmount, new BN(0), withdrawTimelock, shared, 0, defaultSession, true)
+        .accounts({
+          agent: owner.publicKey,
+          vault,
+          vaultTokenAccount,
+          agentTokenAccount: ownerTokenAccount,
+          tokenMint: mint,
+          globalConfig: globalConfigPda,
+          ownerVaultCount: ownerVaultCountPda(owner.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          contribution: withContribution
+            ? contributionPda(vault, owner.publicKey)
+            : null,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers([owner])
+        .rpc();
+    }
+
+    it("Fails: Negative withdrawal timelock", async () => {
+      await assertRejects(
+        createVault(new BN(-1), false, false),
+        "InvalidWithdrawTimelock",
+        "Should refuse a negative timelock"
+      );
+    });
+
+    it("Fails: Shared vault without the creator's contribution", async () => {
+      await assertRejects(
+        createVault(new BN(0), true, false),
+        "ContributionRequired",
+        "Should need the contribution account for a shared vault"
+      );
+    });
+
+    it("Fails: Contribution account for a vault that is not shared", async () => {
+      await assertRejects(
+        createVault(new BN(0), false, true),
+        "UnexpectedContribution",
+        "Should only take a contribution account for a shared vault"
+      );
+    });
+  });
+
+  describe("Fee Waiver Tests", () => {
+    const amount = new BN(200_000);
+    const fee = amount.muln(feeBps).divn(10_000);
+    let target: Awaited<ReturnType<typeof createFundedVault>>;
+
+    const destinationBalance = async () =>
+      (await getAccount(provider.connection, target.destination)).amount;
+
+    before(async () => {
+      target = await createFundedVault();
+    });
+
+    it("Pays the full amount when the authority waives the fee", async () => {
+      const destinationBefore = await destinationBalance();
+      const feesBefore = (await getAccount(provider.connection, feeTokenAccount)).amount;
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, new BN(1), undefined, {
+          ...target,
+          feeWaiverAuthority: admin.publicKey,
+        }),
+        [facilitator, admin]
+      );
+
+      const feesAfter = (await getAccount(provider.connection, feeTokenAccount)).amount;
+      assert.equal((await destinationBalance()) - destinationBefore, BigInt(amount.toString()));
+      assert.equal(feesAfter, feesBefore);
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.equal(vault.lifetimeFeesPaid.toString(), "0");
+      console.log("✅ Fee waived for one settlement");
+    });
+
+    it("Charges the normal fee without a waiver", async () => {
+      const destinationBefore = await destinationBalance();
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, new BN(2), undefined, target),
+        [facilitator]
+      );
+      assert.equal(
+        (await destinationBalance()) - destinationBefore,
+        BigInt(amount.sub(fee).toString())
+      );
+    });
+
+    it("Fails: Non-authority waives the fee", async () => {
+      await assertRejects(
+        provider.sendAndConfirm(
+          await buildSettleTx(amount, new BN(3), undefined, {
+            ...target,
+            feeWaiverAuthority: randomUser.publicKey,
+          }),
+          [facilitator, randomUser]
+        ),
+        "Unauthorized",
+        "Only the config authority may waive a fee"
+      );
+      console.log("✅ Waiver from a non-authority rejected");
+    });
+  });
+
+  describe("Fee Destination Aliasing Tests", () => {
+    const amount = new BN(200_000);
+    let target: SettleTarget;
+
+    before(async () => {
+      const payee = await registerTestProvider();
+      // The provider whitelists the fee destination as one of its own.
+      await program.methods
+        .addDestination(feeTokenAccount)
+        .accounts({
+          authority: payee.authority.publicKey,
+          provider: payee.provider,
+        } as any)
+        .signers([payee.authority])
+        .rpc();
+      await new Promise((resolve) =>
+        setTimeout(resolve, (timelockSeconds.toNumber() + 1) * 1000)
+      );
+      target = {
+        ...(await createFundedVault()),
+        provider: payee.provider,
+        destination: feeTokenAccount,
+        destinationOverride: feeTokenAccount,
+      };
+    });
+
+    it("Credits the gross exactly once when the destination is the fee destination", async () => {
+      const before = await getAccount(provider.connection, feeTokenAccount);
+      const vaultBefore = await getAccount(provider.connection, target.vaultTokenAccount);
+      await provider.sendAndConfirm(
+        await buildSettleTx(amount, new BN(1), undefined, target),
+        [facilitator]
+      );
+
+      const after = await getAccount(provider.connection, feeTokenAccount);
+      const vaultAfter = await getAccount(provider.connection, target.vaultTokenAccount);
+      assert.equal((after.amount - before.amount).toString(), amount.toString());
+      assert.equal((vaultBefore.amount - vaultAfter.amount).toString(), amount.toString());
+      console.log("✅ Aliased destination credited once with the gross");
+    });
+  });
+
+  describe("Event Opt-Out Tests", () => {
+    const amount = new BN(200_000);
+
+    // Counts the events of the given kinds one call emits for `vault`.
+    async function eventsFor(
+      vault: PublicKey,
+      eventNames: string[],
+      call: () => Promise<unknown>
+    ): Promise<number> {
+      let count = 0;
+      const listeners = eventNames.map((name) =>
+        program.addEventListener(name as any, (event: any) => {
+          if (event.vault.equals(vault)) count += 1;
+        })
+      );
+      await call();
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+      await Promise.all(listeners.map((id) => program.removeEventListener(id)));
+      return count;
+    }
+
+    const settleAndWithdraw = (target: Awaited<ReturnType<typeof createFundedVault>>) =>
+      eventsFor(target.vault, ["settlement", "withdrawn"], async () => {
+        await provider.sendAndConfirm(
+          await buildSettleTx(amount, new BN(1), undefined, target),
+          [facilitator]
+        );
+        await program.methods
+          .withdraw(amount)
+          .accounts({
+            agent: target.agent.publicKey,
+            vault: target.vault,
+            globalConfig: globalConfigPda,
+            vaultTokenAccount: target.vaultTokenAccount,
+            agentTokenAccount: target.agentTokenAccount,
+            tokenMint: mint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          } as any)
+          .signers([target.agent])
+          .rpc();
+      });
+
+    it("Emits settlement and withdrawal events by default", async () => {
+      const target = await createFundedVault();
+      assert.equal(await settleAndWithdraw(target), 2);
+      console.log("✅ Events emitted for a default vault");
+    });
+
+    it("Skips the events but still updates state when disabled", async () => {
+      const target = await createFundedVault(
+        depositAmount,
+        new BN(0),
+        new BN(0),
+        false,
+        0,
+        defaultSession,
+        false
+      );
+      assert.equal(await settleAndWithdraw(target), 0);
+
+      const vault = await program.account.vault.fetch(target.vault);
+      assert.isTrue(vault.eventsDisabled);
+      assert.equal(vault.totalSettled.toString(), amount.toString());
+      assert.equal(vault.depositAmount.toString(), depositAmount.sub(amount).toString());
+      console.log("✅ Events skipped for an opted-out vault");
+    });
+  });
+
+  describe("Config Governance Tests", () => {
+    let members: Keypair[];
+
+    const updateConfig = (signers: Keypair[]) =>
+      program.methods
+        .updateConfig(configParams())
+        .accounts({
+          authority: signers[0].publicKey,
+          globalConfig: globalConfigPda,
+        } as any)
+        .remainingAccounts(
+          signers.slice(1).map((kp) => ({
+            pubkey: kp.publicKey,
+            isSigner: true,
+            isWritable: false,
+          }))
+        )
+        .signers(signers)
+        .rpc();
+
+    const setSigners = (
+      signers: Keypair[],
+      keys: PublicKey[],
+      threshold: number
+    ) =>
+      program.methods
+        .setConfigSigners(keys, threshold)
+        .accounts({
+          authority: signers[0].publicKey,
+          globalConfig: globalConfigPda,
+        } as any)
+        .remainingAccounts(
+          signers.slice(1).map((kp) => ({
+            pubkey: kp.publicKey,
+            isSigner: true,
+            isWritable: false,
+          }))
+        )
+        .signers(signers)
+        .rpc();
+
+    before(async () => {
+      members = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+    });
+
+    it("Admin updates the config in single-admin mode", async () => {
+      await updateConfig([admin]);
+      await assertRejects(
+        updateConfig([randomUser]),
+        "Unauthorized",
+        "Non-admin should not update the config"
+      );
+    });
+
+    it("2-of-3 multisig rejects a single signer and accepts two", async () => {
+      await setSigners(
+        [admin],
+        members.map((kp) => kp.publicKey),
+        2
+      );
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(config.signerThreshold, 2);
+      assert.equal(config.signerCount, 3);
+
+      await assertRejects(
+        updateConfig([members[0]]),
+        "Unauthorized",
+        "One of three signers is not enough"
+      );
+      await assertRejects(
+        updateConfig([admin]),
+        "Unauthorized",
+        "The admin alone is not enough once a multisig is set"
+      );
+      await updateConfig([members[0], members[2]]);
+      console.log("✅ 2-of-3 multisig enforced");
+    });
+
+    after(async () => {
+      // Return to single-admin mode for the remaining suites.
+      await setSigners([members[0], members[1]], [], 0);
+    });
+  });
+
+  describe("Admin Tests", () => {
+    it("Fails: Non-admin tries emergency pause", async () => {
+      const promise = program.methods
+        .emergencyPause(pauseAll)
+        .accounts({
+          admin: agent.publicKey,
+          globalConfig: globalConfigPda,
+        } as any)
+        .signers([agent])
+        .rpc();
+
+      await assertRejects(
+        promise,
+        "Unauthorized",
+        "Should reject non-admin pause"
+      );
+      console.log("✅ Non-admin pause rejected");
+    });
+
+    it("Admin triggers emergency pause successfully", async () => {
+      let listener = null;
+      const [event, tx] = await new Promise<[any, string]>(
+        (resolve, reject) => {
+          let sig: string;
+          listener = program.addEventListener("pauseUpdated", (event) => {
+            resolve([event, sig]);
+          });
+          program.methods
+            .emergencyPause(pauseAll)
+            .accounts({
+              admin: admin.publicKey,
+              globalConfig: globalConfigPda,
+            } as any)
+            .signers([admin])
+            .rpc()
+            .then((s) => (sig = s))
+            .catch(reject);
+        }
+      );
+      await program.removeEventListener(listener);
+
+      assert.ok(event.admin.equals(admin.publicKey));
+      assert.isTrue(event.paused);
+      assert.equal(event.pauseFlags, pauseAll);
+      assert.ok(event.timestamp.toNumber() > 0);
+
+      const config = await program.account.globalConfig.fetch(globalConfigPda);
+      assert.equal(config.pauseFlags, pauseAll);
       console.log("✅ Emergency pause triggered:", tx);
     });
   });